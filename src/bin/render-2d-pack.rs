@@ -0,0 +1,151 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, process::exit};
+
+use render_2d::loader::{atlas::{AtlasBuilder, AtlasChannel}, options::{LoadOptions, NameCase, SymlinkPolicy}, texture::load_images_with_slices_from_fs, vfs::OsFs};
+
+const USAGE: &str = "usage: render-2d-pack [options] <input dir>...
+
+Packs every image below the input directories into atlas pages and writes them
+next to a metadata file that can be loaded with load_packed_atlas. Nine slices
+from '.slice' files and '.9.png' images are stored in the metadata, their images
+are never trimmed or rotated.
+
+options:
+    -o, --output <file>     metadata file to write, pages are written next to it (default: atlas.atlas)
+    --padding <px>          transparent pixels between images (default: 0)
+    --max-size <px>         maximum width and height of a page (default: unlimited)
+    --trim                  remove transparent borders before packing
+    --rotate                allow images to be rotated 90 degrees to fit
+    --no-dedup              pack identical images separately
+    --channel <name>        pack companion maps found by suffix, one of normal (_n), emissive (_e) or mask (_m)
+    --include <glob>        only pack files matching the pattern, can be repeated (default: **/*.[pP][nN][gG])
+    --exclude <glob>        skip files and directories matching the pattern, can be repeated
+    --max-depth <n>         maximum number of directories to descend into
+    --skip-symlinks         ignore symlinked files and directories
+    --strip-extension       remove file extensions from sprite names
+    --prefix <text>         prefix added to every sprite name
+    --lowercase             lowercase sprite names
+    --uppercase             uppercase sprite names
+    -h, --help              print this message";
+
+struct Arguments{
+    inputs: Vec<PathBuf>,
+    output: PathBuf,
+    builder: AtlasBuilder,
+    options: LoadOptions,
+}
+
+fn parse_arguments(args: impl Iterator<Item = String>) -> Result<Arguments,String>{
+    let mut args = args.peekable();
+    let mut inputs = Vec::new();
+    let mut output = PathBuf::from("atlas.atlas");
+    let mut builder = AtlasBuilder::new();
+    let mut options = LoadOptions::new();
+    let mut includes = Vec::new();
+
+    while let Some(arg) = args.next(){
+        let mut value = |name: &str|args.next().ok_or_else(||format!("missing value for {}",name));
+        match arg.as_str(){
+            "-h" | "--help" => {
+                println!("{}",USAGE);
+                exit(0);
+            },
+            "-o" | "--output" => output = PathBuf::from(value(&arg)?),
+            "--padding" => builder = builder.with_padding(parse_number(&arg, &value(&arg)?)?),
+            "--max-size" => builder = builder.with_max_size(parse_number(&arg, &value(&arg)?)?),
+            "--trim" => builder = builder.with_trim(true),
+            "--rotate" => builder = builder.with_rotation(true),
+            "--no-dedup" => builder = builder.with_deduplicate(false),
+            "--channel" => builder = builder.with_channel(parse_channel(&value(&arg)?)?),
+            "--include" => includes.push(value(&arg)?),
+            "--exclude" => options = options.with_exclude(&value(&arg)?),
+            "--max-depth" => options = options.with_max_depth(parse_number(&arg, &value(&arg)?)? as usize),
+            "--skip-symlinks" => options = options.with_symlinks(SymlinkPolicy::Skip),
+            "--strip-extension" => options = options.with_strip_extension(true),
+            "--prefix" => options = options.with_name_prefix(value(&arg)?),
+            "--lowercase" => options = options.with_name_case(NameCase::Lower),
+            "--uppercase" => options = options.with_name_case(NameCase::Upper),
+            x if x.starts_with('-') => return Err(format!("unknown option {}",x)),
+            x => inputs.push(PathBuf::from(x)),
+        }
+    }
+
+    if inputs.is_empty(){
+        return Err(String::from("no input directories given"));
+    }
+    if !includes.is_empty(){
+        options = options.with_include(&includes.iter().map(|x|x.as_str()).collect::<Vec<_>>());
+    }
+
+    Ok(Arguments{
+        inputs,
+        output,
+        builder,
+        options
+    })
+}
+
+fn parse_channel(value: &str) -> Result<AtlasChannel,String>{
+    match value{
+        "normal" => Ok(AtlasChannel::normal()),
+        "emissive" => Ok(AtlasChannel::emissive()),
+        "mask" => Ok(AtlasChannel::mask()),
+        x => Err(format!("unknown channel {}",x))
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<u32,String>{
+    value.parse().map_err(|_|format!("invalid value {} for {}",value,name))
+}
+
+fn run(arguments: Arguments) -> Result<(),String>{
+    let mut builder = arguments.builder;
+    //the file every sprite name came from, a name can only be packed once
+    let mut sources: HashMap<String,PathBuf> = HashMap::new();
+    for input in &arguments.inputs{
+        if !input.is_dir(){
+            return Err(format!("{} is not a directory",input.display()));
+        }
+        let loaded = load_images_with_slices_from_fs(&OsFs::new(input), "", &arguments.options)?;
+        for ((name, image), path) in loaded.images.into_iter().zip(loaded.paths){
+            let path = input.join(path);
+            if let Some(other) = sources.get(&name){
+                return Err(format!("duplicate sprite name {} from {} and {}",name,other.display(),path.display()));
+            }
+            sources.insert(name.clone(), path);
+            builder.add_image(name, image);
+        }
+        for (name, definition) in loaded.slices{
+            builder.add_slice(name, definition);
+        }
+    }
+    if builder.is_empty(){
+        return Err(String::from("no images found"));
+    }
+
+    let packed = builder.pack()?;
+    let stats = packed.stats;
+    let metadata = packed.save(&arguments.output)?;
+
+    let dir = arguments.output.parent().unwrap_or_else(||Path::new(""));
+    for page in &metadata.pages{
+        println!("wrote {} ({}x{})",dir.join(&page.file).display(),page.width,page.height);
+    }
+    println!("wrote {}",arguments.output.display());
+    println!("{} images, {} unique, {} pixels saved by deduplication",stats.image_count,stats.unique_count,stats.saved_pixels);
+    Ok(())
+}
+
+fn main(){
+    let arguments = match parse_arguments(std::env::args().skip(1)){
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}",e,USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = run(arguments){
+        eprintln!("error: {}",e);
+        exit(1);
+    }
+}
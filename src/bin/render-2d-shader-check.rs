@@ -0,0 +1,161 @@
+use std::{path::PathBuf, process::exit};
+
+use render_2d::{loader::vfs::OsFs, resource::shader::{glsl::{InterfaceBlock, InterfaceVariable, ProgramInterface, SUPPORTED_VERSIONS, validate_program}, preprocessor::ShaderVariant}};
+
+const USAGE: &str = "usage: render-2d-shader-check [options] <vertex> <fragment>...
+
+Parses and type checks shader programs without a gpu, resolving includes like
+the runtime loaders do. Every vertex shader is followed by its fragment shader.
+
+options:
+    --root <dir>            directory the shader paths and includes are relative to (default: .)
+    -D <name>[=<value>]     define a macro in both stages, can be repeated
+    --interface             print the attributes, uniforms and blocks of every valid program
+    -h, --help              print this message";
+
+struct Arguments{
+    root: PathBuf,
+    programs: Vec<(String,String)>,
+    variant: ShaderVariant,
+    interface: bool,
+}
+
+fn parse_arguments(args: impl Iterator<Item = String>) -> Result<Arguments,String>{
+    let mut args = args.peekable();
+    let mut root = PathBuf::from(".");
+    let mut paths = Vec::new();
+    let mut variant = ShaderVariant::new();
+    let mut interface = false;
+
+    while let Some(arg) = args.next(){
+        let mut value = |name: &str|args.next().ok_or_else(||format!("missing value for {}",name));
+        match arg.as_str(){
+            "-h" | "--help" => {
+                println!("{}",USAGE);
+                exit(0);
+            },
+            "--root" => root = PathBuf::from(value(&arg)?),
+            "-D" => variant = parse_define(variant, &value(&arg)?),
+            "--interface" => interface = true,
+            x if x.starts_with("-D") => variant = parse_define(variant, &x[2..]),
+            x if x.starts_with('-') => return Err(format!("unknown option {}",x)),
+            x => paths.push(String::from(x)),
+        }
+    }
+
+    if paths.is_empty(){
+        return Err(String::from("no shaders given"));
+    }
+    if paths.len() % 2 != 0{
+        return Err(format!("{} has no fragment shader, shaders are given as vertex and fragment pairs",paths.last().unwrap()));
+    }
+    let programs = paths.chunks(2).map(|x|(x[0].clone(), x[1].clone())).collect();
+
+    Ok(Arguments{
+        root,
+        programs,
+        variant,
+        interface,
+    })
+}
+
+fn parse_define(variant: ShaderVariant, define: &str) -> ShaderVariant{
+    match define.split_once('='){
+        Some((name, value)) => variant.with_value(name, value),
+        None => variant.with_define(define),
+    }
+}
+
+fn print_variable(kind: &str, variable: &InterfaceVariable){
+    let mut line = format!("    {} {} {:?}",kind,variable.name,variable.data_type);
+    if let Some(n) = variable.array_size{
+        line.push_str(&format!("[{}]",n));
+    }
+    if let Some(location) = variable.location{
+        line.push_str(&format!(" location={}",location));
+    }
+    if let Some(binding) = variable.binding{
+        line.push_str(&format!(" binding={}",binding));
+    }
+    if !variable.referenced{
+        line.push_str(" (unused)");
+    }
+    println!("{}",line);
+}
+
+fn print_block(block: &InterfaceBlock){
+    let mut line = format!("    block {}",block.name);
+    if block.std140{
+        line.push_str(&format!(" std140 {} bytes",block.data_size));
+    }
+    if let Some(binding) = block.binding{
+        line.push_str(&format!(" binding={}",binding));
+    }
+    if !block.referenced{
+        line.push_str(" (unused)");
+    }
+    println!("{}",line);
+    for (name, member) in &block.members{
+        if block.std140{
+            println!("        {} {:?} offset={}",name,member.data_type,member.offset);
+        }
+        else{
+            println!("        {} {:?}",name,member.data_type);
+        }
+    }
+}
+
+fn print_interface(interface: &ProgramInterface){
+    for attribute in interface.attributes(){
+        print_variable("attribute", attribute);
+    }
+    for varying in &interface.vertex.outputs{
+        print_variable("varying", varying);
+    }
+    for output in &interface.fragment.outputs{
+        print_variable("output", output);
+    }
+    for uniform in interface.uniforms(){
+        print_variable("uniform", &uniform);
+    }
+    for block in interface.uniform_blocks(){
+        print_block(&block);
+    }
+}
+
+fn run(arguments: Arguments) -> usize{
+    let fs = OsFs::new(&arguments.root);
+    let mut failed = 0;
+    for (vertex, fragment) in &arguments.programs{
+        match validate_program(&fs, vertex, fragment, &arguments.variant){
+            Ok(interface) => {
+                println!("ok {} {} (GLSL {})",vertex,fragment,interface.vertex.version);
+                if arguments.interface{
+                    print_interface(&interface);
+                }
+            },
+            Err(e) => {
+                failed += 1;
+                println!("failed {} {}",vertex,fragment);
+                eprintln!("{}",e.trim_end());
+            }
+        }
+    }
+    failed
+}
+
+fn main(){
+    let arguments = match parse_arguments(std::env::args().skip(1)){
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}\n\nsupported GLSL versions: {:?}",e,USAGE,SUPPORTED_VERSIONS);
+            exit(2);
+        }
+    };
+
+    let failed = run(arguments);
+    if failed > 0{
+        eprintln!("{} program(s) failed validation",failed);
+        exit(1);
+    }
+}
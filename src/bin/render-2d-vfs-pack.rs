@@ -0,0 +1,92 @@
+use std::{path::PathBuf, process::exit};
+
+use render_2d::loader::vfs::{OsFs, PackFs, PackWriter, VirtualFs};
+
+const USAGE: &str = "usage: render-2d-vfs-pack [options] <input dir>...
+
+Stores every file below the input directories in a single pack that can be
+mounted with PackFs. Later inputs replace files with the same path.
+
+options:
+    -o, --output <file>     pack file to write (default: assets.pack)
+    --prefix <path>         directory the following inputs are stored under (default: the root)
+    --list                  print every path stored in the pack
+    -h, --help              print this message";
+
+struct Arguments{
+    inputs: Vec<(PathBuf,String)>,
+    output: PathBuf,
+    list: bool,
+}
+
+fn parse_arguments(args: impl Iterator<Item = String>) -> Result<Arguments,String>{
+    let mut args = args.peekable();
+    let mut inputs = Vec::new();
+    let mut output = PathBuf::from("assets.pack");
+    let mut prefix = String::new();
+    let mut list = false;
+
+    while let Some(arg) = args.next(){
+        let mut value = |name: &str|args.next().ok_or_else(||format!("missing value for {}",name));
+        match arg.as_str(){
+            "-h" | "--help" => {
+                println!("{}",USAGE);
+                exit(0);
+            },
+            "-o" | "--output" => output = PathBuf::from(value(&arg)?),
+            "--prefix" => prefix = value(&arg)?,
+            "--list" => list = true,
+            x if x.starts_with('-') => return Err(format!("unknown option {}",x)),
+            x => inputs.push((PathBuf::from(x), prefix.clone())),
+        }
+    }
+
+    if inputs.is_empty(){
+        return Err(String::from("no input directories given"));
+    }
+
+    Ok(Arguments{
+        inputs,
+        output,
+        list,
+    })
+}
+
+fn run(arguments: Arguments) -> Result<(),String>{
+    let mut writer = PackWriter::new();
+    for (input, prefix) in &arguments.inputs{
+        if !input.is_dir(){
+            return Err(format!("{} is not a directory",input.display()));
+        }
+        writer.add_dir(&OsFs::new(input), "", prefix)?;
+    }
+    writer.write_to_file(&arguments.output)?;
+
+    //reading the pack back checks it can be mounted
+    let pack = PackFs::open(&arguments.output)?;
+    let mut count = 0;
+    for path in pack.paths(){
+        if arguments.list{
+            println!("{}",path);
+        }
+        pack.read(path)?;
+        count += 1;
+    }
+    println!("wrote {} ({} files)",arguments.output.display(),count);
+    Ok(())
+}
+
+fn main(){
+    let arguments = match parse_arguments(std::env::args().skip(1)){
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}",e,USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = run(arguments){
+        eprintln!("error: {}",e);
+        exit(1);
+    }
+}
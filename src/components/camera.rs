@@ -0,0 +1,295 @@
+use crate::window::WindowResource;
+
+///
+/// Which way the y axis of the world points on screen
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum YAxis{
+    ///Like screen coordinates, y grows downwards
+    #[default]
+    Down,
+    ///Like math coordinates, y grows upwards
+    Up,
+}
+
+///
+/// Area of the window a camera draws to, as fractions of the window size with (0,0) at the top left corner
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Viewport{
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Viewport{
+    fn default() -> Self {
+        Self{
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+}
+
+///
+/// Size of the window in physical pixels and how many physical pixels make up a logical one
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct ScreenSize{
+    pub width: f32,
+    pub height: f32,
+    pub scale_factor: f32,
+}
+
+impl ScreenSize{
+    ///The current size and scale factor of the window
+    pub fn from_window(window: &WindowResource) -> Self{
+        let [width, height] = window.size();
+        Self{
+            width: width as f32,
+            height: height as f32,
+            scale_factor: window.scale_factor() as f32,
+        }
+    }
+}
+
+///
+/// An orthographic 2d camera, stored as a world resource and applied by the CameraSystem
+/// At a zoom of 1 a world unit covers one logical pixel, so the view keeps its size on high dpi screens
+/// Screen positions are in physical pixels from the top left corner of the window, like the cursor positions winit reports
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Camera2D{
+    ///World position shown at the center of the viewport
+    pub position: [f32;2],
+    ///Larger values show a smaller part of the world
+    pub zoom: f32,
+    ///Rotation of the camera in radians, the world appears rotated the other way
+    pub rotation: f32,
+    pub viewport: Viewport,
+    pub y_axis: YAxis,
+}
+
+impl Default for Camera2D{
+    fn default() -> Self {
+        Self{
+            position: [0.0, 0.0],
+            zoom: 1.0,
+            rotation: 0.0,
+            viewport: Viewport::default(),
+            y_axis: YAxis::Down,
+        }
+    }
+}
+
+impl Camera2D{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    pub fn with_position(mut self, x: f32, y: f32) -> Self{
+        self.position = [x, y];
+        self
+    }
+
+    pub fn with_zoom(mut self, zoom: f32) -> Self{
+        self.zoom = zoom;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self{
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_viewport(mut self, viewport: Viewport) -> Self{
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn with_y_axis(mut self, y_axis: YAxis) -> Self{
+        self.y_axis = y_axis;
+        self
+    }
+
+    ///
+    /// The viewport in physical pixels as [x, y, width, height], with y measured from the top of the window
+    pub fn viewport_rect(&self, screen: &ScreenSize) -> [f32;4]{
+        [
+            self.viewport.x * screen.width,
+            self.viewport.y * screen.height,
+            self.viewport.width * screen.width,
+            self.viewport.height * screen.height,
+        ]
+    }
+
+    ///Physical pixels a world unit covers
+    fn pixels_per_unit(&self, screen: &ScreenSize) -> f32{
+        self.zoom * screen.scale_factor
+    }
+
+    ///Flips the y of offsets between the world and the screen, which always points down
+    fn y_sign(&self) -> f32{
+        match self.y_axis{
+            YAxis::Down => 1.0,
+            YAxis::Up => -1.0,
+        }
+    }
+
+    ///
+    /// Column major matrix from world positions to clip space, as FrameUniforms::view_projection expects
+    /// Depth is passed through unchanged, so the draw order depth of sprites keeps working
+    pub fn view_projection(&self, screen: &ScreenSize) -> [f32;16]{
+        let [_, _, width, height] = self.viewport_rect(screen);
+        let (sin, cos) = self.rotation.sin_cos();
+        let scale = self.pixels_per_unit(screen);
+        let scale_x = 2.0 * scale / width.max(1.0);
+        //clip space y points up while the screen points down
+        let scale_y = -self.y_sign() * 2.0 * scale / height.max(1.0);
+
+        let (a, b) = (scale_x * cos, scale_x * sin);
+        let (c, d) = (-scale_y * sin, scale_y * cos);
+        let [x, y] = self.position;
+        [
+            a, c, 0.0, 0.0,
+            b, d, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            -(a * x + b * y), -(c * x + d * y), 0.0, 1.0,
+        ]
+    }
+
+    ///
+    /// The position in physical window pixels a world position is drawn at
+    pub fn world_to_screen(&self, world: [f32;2], screen: &ScreenSize) -> [f32;2]{
+        let [x, y, width, height] = self.viewport_rect(screen);
+        let (sin, cos) = self.rotation.sin_cos();
+        let scale = self.pixels_per_unit(screen);
+        let dx = world[0] - self.position[0];
+        let dy = world[1] - self.position[1];
+        let view_x = (dx * cos + dy * sin) * scale;
+        let view_y = (-dx * sin + dy * cos) * scale * self.y_sign();
+        [x + width / 2.0 + view_x, y + height / 2.0 + view_y]
+    }
+
+    ///
+    /// The world position drawn at a position in physical window pixels, for example the cursor
+    pub fn screen_to_world(&self, position: [f32;2], screen: &ScreenSize) -> [f32;2]{
+        let [x, y, width, height] = self.viewport_rect(screen);
+        let (sin, cos) = self.rotation.sin_cos();
+        let scale = self.pixels_per_unit(screen);
+        let view_x = (position[0] - x - width / 2.0) / scale;
+        let view_y = (position[1] - y - height / 2.0) / scale * self.y_sign();
+        [
+            self.position[0] + view_x * cos - view_y * sin,
+            self.position[1] + view_x * sin + view_y * cos,
+        ]
+    }
+
+    ///True if the physical window position lies inside the viewport of the camera
+    pub fn contains_screen_point(&self, position: [f32;2], screen: &ScreenSize) -> bool{
+        let [x, y, width, height] = self.viewport_rect(screen);
+        position[0] >= x && position[0] < x + width && position[1] >= y && position[1] < y + height
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn assert_near(a: [f32;2], b: [f32;2]){
+        assert!((a[0] - b[0]).abs() < 1e-3 && (a[1] - b[1]).abs() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    fn screen() -> ScreenSize{
+        ScreenSize{
+            width: 1600.0,
+            height: 900.0,
+            scale_factor: 2.0,
+        }
+    }
+
+    fn cameras() -> Vec<Camera2D>{
+        let viewport = Viewport{x: 0.5, y: 0.25, width: 0.5, height: 0.75};
+        let mut out = Vec::new();
+        for y_axis in [YAxis::Down, YAxis::Up]{
+            out.push(Camera2D::new().with_y_axis(y_axis));
+            out.push(Camera2D::new().with_y_axis(y_axis).with_position(120.0, -40.0).with_zoom(2.5).with_rotation(0.6));
+            out.push(Camera2D::new().with_y_axis(y_axis).with_position(-8.0, 3.0).with_zoom(0.5).with_rotation(-2.0).with_viewport(viewport));
+        }
+        out
+    }
+
+    ///Column major matrix times (x, y, 0, 1), divided by w
+    fn to_ndc(matrix: &[f32;16], [x, y]: [f32;2]) -> [f32;2]{
+        let w = matrix[3] * x + matrix[7] * y + matrix[15];
+        [(matrix[0] * x + matrix[4] * y + matrix[12]) / w, (matrix[1] * x + matrix[5] * y + matrix[13]) / w]
+    }
+
+    ///The physical window position gl maps the ndc of the viewport to
+    fn ndc_to_screen(camera: &Camera2D, screen: &ScreenSize, [x, y]: [f32;2]) -> [f32;2]{
+        let [left, top, width, height] = camera.viewport_rect(screen);
+        [left + (x + 1.0) / 2.0 * width, top + (1.0 - y) / 2.0 * height]
+    }
+
+    #[test]
+    fn screen_to_world_inverts_world_to_screen(){
+        let screen = screen();
+        for camera in cameras(){
+            for point in [[0.0, 0.0], [13.5, -7.25], [-300.0, 220.0]]{
+                let on_screen = camera.world_to_screen(point, &screen);
+                assert_near(camera.screen_to_world(on_screen, &screen), point);
+            }
+            for position in [[0.0, 0.0], [812.0, 450.0], [1599.0, 10.0]]{
+                let world = camera.screen_to_world(position, &screen);
+                assert_near(camera.world_to_screen(world, &screen), position);
+            }
+        }
+    }
+
+    #[test]
+    fn matrix_maps_world_points_to_where_world_to_screen_puts_them(){
+        let screen = screen();
+        for camera in cameras(){
+            let matrix = camera.view_projection(&screen);
+            for point in [[0.0, 0.0], [13.5, -7.25], [-300.0, 220.0]]{
+                let ndc = to_ndc(&matrix, point);
+                assert_near(ndc_to_screen(&camera, &screen, ndc), camera.world_to_screen(point, &screen));
+            }
+        }
+    }
+
+    #[test]
+    fn matrix_passes_depth_through(){
+        let matrix = Camera2D::new().with_rotation(1.0).with_zoom(3.0).view_projection(&screen());
+        assert_eq!([matrix[2], matrix[6], matrix[10], matrix[14]], [0.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn camera_position_is_at_the_viewport_center(){
+        let screen = screen();
+        for camera in cameras(){
+            let [x, y, width, height] = camera.viewport_rect(&screen);
+            assert_near(camera.world_to_screen(camera.position, &screen), [x + width / 2.0, y + height / 2.0]);
+        }
+    }
+
+    #[test]
+    fn zoom_and_scale_factor_set_the_pixels_per_unit(){
+        let screen = screen();
+        let camera = Camera2D::new().with_zoom(2.0);
+        let origin = camera.world_to_screen([0.0, 0.0], &screen);
+        //a unit covers zoom logical pixels, which are scale_factor physical pixels each
+        assert_near(camera.world_to_screen([1.0, 0.0], &screen), [origin[0] + 4.0, origin[1]]);
+        assert_near(camera.world_to_screen([0.0, 1.0], &screen), [origin[0], origin[1] + 4.0]);
+        assert_near(camera.with_y_axis(YAxis::Up).world_to_screen([0.0, 1.0], &screen), [origin[0], origin[1] - 4.0]);
+    }
+
+    #[test]
+    fn viewport_contains_only_its_own_pixels(){
+        let screen = screen();
+        let camera = Camera2D::new().with_viewport(Viewport{x: 0.5, y: 0.0, width: 0.5, height: 1.0});
+        assert!(camera.contains_screen_point([800.0, 0.0], &screen));
+        assert!(camera.contains_screen_point([1599.0, 899.0], &screen));
+        assert!(!camera.contains_screen_point([799.0, 10.0], &screen));
+        assert!(!camera.contains_screen_point([1600.0, 10.0], &screen));
+    }
+}
@@ -1,7 +1,382 @@
-use crate::resource::{ResourceKey, sprite::uv_sprite::UvSprite};
+use crate::resource::{ResourceKey, animation::{AnimationMode, SpriteAnimation}};
 
+pub mod camera;
+
+///
+/// Draws the sprite resource the key refers to, sprites are stored as dyn Sprite so any sprite type can be used
+/// Without a material the sprite is drawn with the shader of the draw system
+/// Sprites are drawn by layer, then by z-index, see resource::batch::order
 #[derive(Default)]
 pub struct SpriteFilter{
-    sprite: UvSprite,
+    sprite: Option<ResourceKey>,
+    material: Option<ResourceKey>,
+    layer: i32,
+    z_index: i32,
+    size: Option<[f32;2]>,
+}
+
+impl SpriteFilter{
+    pub fn new(sprite: ResourceKey) -> Self{
+        Self{
+            sprite: Some(sprite),
+            material: None,
+            layer: 0,
+            z_index: 0,
+            size: None,
+        }
+    }
+
+    ///
+    /// Draws the sprite at the size in pixels instead of its source size, before the transform is applied
+    /// Nine slice sprites keep their borders and only stretch or tile the rest
+    pub fn with_size(mut self, width: f32, height: f32) -> Self{
+        self.size = Some([width, height]);
+        self
+    }
+
+    ///The size the sprite is drawn at, None for the source size of the sprite
+    pub fn size(&self) -> Option<[f32;2]>{
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: Option<[f32;2]>){
+        self.size = size;
+    }
+
+    ///Render layer the sprite is drawn in, layers are drawn from the lowest up and configured with RenderLayers
+    pub fn with_layer(mut self, layer: i32) -> Self{
+        self.layer = layer;
+        self
+    }
+
+    ///Order inside the layer, higher values are drawn in front
+    pub fn with_z_index(mut self, z_index: i32) -> Self{
+        self.z_index = z_index;
+        self
+    }
+
+    pub fn layer(&self) -> i32{
+        self.layer
+    }
+
+    pub fn set_layer(&mut self, layer: i32){
+        self.layer = layer;
+    }
+
+    pub fn z_index(&self) -> i32{
+        self.z_index
+    }
+
+    pub fn set_z_index(&mut self, z_index: i32){
+        self.z_index = z_index;
+    }
+
+    ///Draws the sprite with the material the key refers to, see loader::material::add_material
+    pub fn with_material(mut self, material: ResourceKey) -> Self{
+        self.material = Some(material);
+        self
+    }
+
+    pub fn material(&self) -> Option<ResourceKey>{
+        self.material
+    }
+
+    pub fn set_material(&mut self, material: Option<ResourceKey>){
+        self.material = material;
+    }
+
+    pub fn sprite(&self) -> Option<ResourceKey>{
+        self.sprite
+    }
+
+    pub fn set_sprite(&mut self, sprite: Option<ResourceKey>){
+        self.sprite = sprite;
+    }
+}
+
+
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum AnimationEvent{
+    ///A frame with a tag was entered
+    Tag(String),
+    ///An animation in Once mode reached the end of its last frame
+    Finished,
+}
+
+///
+/// Plays a SpriteAnimation resource, the animation system advances it and updates the SpriteFilter of the entity
+pub struct AnimatedSprite{
+    clip: ResourceKey,
+    frame: usize,
+    frame_time: f32,
+    forward: bool,
+    playing: bool,
+    finished: bool,
+    speed: f32,
+    events: Vec<AnimationEvent>,
+}
+
+impl AnimatedSprite{
+    ///Creates a playing animation at the start of the clip, the key has to refer to a SpriteAnimation
+    pub fn new(clip: ResourceKey) -> Self{
+        Self{
+            clip,
+            frame: 0,
+            frame_time: 0.0,
+            forward: true,
+            playing: true,
+            finished: false,
+            speed: 1.0,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn clip(&self) -> ResourceKey{
+        self.clip
+    }
+
+    ///Switches to another clip, restarting from its first frame
+    pub fn set_clip(&mut self, clip: ResourceKey){
+        self.clip = clip;
+        self.rewind();
+    }
+
+    pub fn play(&mut self){
+        if self.finished{
+            self.rewind();
+        }
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self){
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool{
+        self.playing
+    }
+
+    pub fn is_finished(&self) -> bool{
+        self.finished
+    }
+
+    ///Playback speed multiplier, negative values are treated as 0
+    pub fn set_speed(&mut self, speed: f32){
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn speed(&self) -> f32{
+        self.speed
+    }
+
+    pub fn frame(&self) -> usize{
+        self.frame
+    }
+
+    fn rewind(&mut self){
+        self.frame = 0;
+        self.frame_time = 0.0;
+        self.forward = true;
+        self.finished = false;
+    }
+
+    ///
+    /// Jumps to the time in seconds from the start of the clip, no events are raised for the skipped frames
+    pub fn seek(&mut self, animation: &SpriteAnimation, time: f32){
+        self.rewind();
+        let mut events = std::mem::take(&mut self.events);
+        self.step(animation, time.max(0.0));
+        std::mem::swap(&mut self.events, &mut events);
+    }
+
+    ///Removes and returns the events raised since the last call
+    pub fn drain_events(&mut self) -> Vec<AnimationEvent>{
+        std::mem::take(&mut self.events)
+    }
+
+    ///
+    /// Advances the animation by delta seconds scaled by the speed, returns the sprite of the current frame
+    pub fn advance(&mut self, animation: &SpriteAnimation, delta: f32) -> Option<ResourceKey>{
+        if self.playing && !self.finished{
+            self.step(animation, delta * self.speed);
+        }
+        animation.frames().get(self.frame).map(|x|x.sprite)
+    }
+
+    fn step(&mut self, animation: &SpriteAnimation, delta: f32){
+        let frames = animation.frames();
+        if frames.is_empty(){
+            return;
+        }
+        self.frame = self.frame.min(frames.len() - 1);
+
+        //skips whole cycles so a long frame time doesn't loop over every frame
+        let cycle = match animation.mode(){
+            AnimationMode::PingPong if frames.len() > 1 => 2.0 * animation.duration() - frames[0].duration - frames[frames.len() - 1].duration,
+            _ => animation.duration(),
+        };
+        let mut remaining = self.frame_time + delta;
+        if animation.mode() != AnimationMode::Once && cycle > 0.0 && remaining > cycle * 2.0{
+            remaining %= cycle;
+        }
+
+        while !self.finished && remaining >= frames[self.frame].duration{
+            remaining -= frames[self.frame].duration;
+            match self.next_frame(animation){
+                Some(x) => {
+                    self.frame = x;
+                    if let Some(tag) = &frames[x].tag{
+                        self.events.push(AnimationEvent::Tag(tag.clone()));
+                    }
+                },
+                None => {
+                    self.finished = true;
+                    self.playing = false;
+                    remaining = frames[self.frame].duration;
+                    self.events.push(AnimationEvent::Finished);
+                }
+            }
+            if cycle <= 0.0{
+                //every frame has a zero duration, showing the next one is as far as it can go
+                break;
+            }
+        }
+        self.frame_time = remaining;
+    }
+
+    fn next_frame(&mut self, animation: &SpriteAnimation) -> Option<usize>{
+        let last = animation.len() - 1;
+        match animation.mode(){
+            AnimationMode::Loop => Some(if self.frame >= last { 0 } else { self.frame + 1 }),
+            AnimationMode::Once => if self.frame >= last { None } else { Some(self.frame + 1) },
+            AnimationMode::PingPong => {
+                if last == 0{
+                    return Some(0);
+                }
+                if self.forward && self.frame >= last{
+                    self.forward = false;
+                }
+                else if !self.forward && self.frame == 0{
+                    self.forward = true;
+                }
+                Some(if self.forward { self.frame + 1 } else { self.frame - 1 })
+            }
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests{
+    use crate::resource::Resources;
+
+    use super::*;
+
+    //frames only need keys, any resource type provides them
+    fn keys(count: usize) -> Vec<ResourceKey>{
+        let mut resources = Resources::new();
+        resources.register_type::<usize>();
+        (0..count).map(|x|resources.add_resource(x, format!("frame_{}",x))).collect()
+    }
+
+    fn clip(mode: AnimationMode) -> (Vec<ResourceKey>, SpriteAnimation){
+        let keys = keys(3);
+        let animation = keys.iter().fold(SpriteAnimation::new(mode), |out, x|out.with_frame(*x, 1.0));
+        (keys, animation)
+    }
+
+    fn frames(sprite: &mut AnimatedSprite, animation: &SpriteAnimation, steps: usize, delta: f32) -> Vec<usize>{
+        (0..steps).map(|_|{
+            sprite.advance(animation, delta);
+            sprite.frame()
+        }).collect()
+    }
+
+    #[test]
+    fn ping_pong_reverses_at_both_ends(){
+        let (keys, animation) = clip(AnimationMode::PingPong);
+        let mut sprite = AnimatedSprite::new(keys[0]);
+        assert_eq!(frames(&mut sprite, &animation, 8, 1.0), vec![1, 2, 1, 0, 1, 2, 1, 0]);
+        assert!(sprite.drain_events().is_empty());
+    }
+
+    #[test]
+    fn large_deltas_skip_frames_and_cycles(){
+        let (keys, animation) = clip(AnimationMode::Loop);
+        let mut sprite = AnimatedSprite::new(keys[0]);
+        assert_eq!(sprite.advance(&animation, 10.5), Some(keys[1]));
+        //the rest of the delta is kept for the current frame
+        assert_eq!(sprite.advance(&animation, 0.5), Some(keys[2]));
+
+        //a ping pong cycle is 4 frames long, 9.5 seconds is two cycles and 1.5 frames
+        let (keys, animation) = clip(AnimationMode::PingPong);
+        let mut sprite = AnimatedSprite::new(keys[0]);
+        assert_eq!(sprite.advance(&animation, 9.5), Some(keys[1]));
+        assert_eq!(frames(&mut sprite, &animation, 2, 1.0), vec![2, 1]);
+
+        let (keys, animation) = clip(AnimationMode::Once);
+        let mut sprite = AnimatedSprite::new(keys[0]);
+        assert_eq!(sprite.advance(&animation, 100.0), Some(keys[2]));
+        assert!(sprite.is_finished());
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame(){
+        let (keys, animation) = clip(AnimationMode::Once);
+        let mut sprite = AnimatedSprite::new(keys[0]);
+        assert_eq!(sprite.advance(&animation, 2.5), Some(keys[2]));
+        assert!(!sprite.is_finished());
+        assert!(sprite.drain_events().is_empty());
+
+        assert_eq!(sprite.advance(&animation, 0.6), Some(keys[2]));
+        assert!(sprite.is_finished());
+        assert!(!sprite.is_playing());
+        assert_eq!(sprite.drain_events(), vec![AnimationEvent::Finished]);
+
+        //a finished animation stays put until it's played again
+        assert_eq!(sprite.advance(&animation, 5.0), Some(keys[2]));
+        assert!(sprite.drain_events().is_empty());
+        sprite.play();
+        assert_eq!(sprite.advance(&animation, 0.0), Some(keys[0]));
+    }
+
+    #[test]
+    fn tags_are_raised_across_a_wrap(){
+        let keys = keys(3);
+        let animation = SpriteAnimation::new(AnimationMode::Loop)
+            .with_tagged_frame(keys[0], 1.0, String::from("start"))
+            .with_frame(keys[1], 1.0)
+            .with_tagged_frame(keys[2], 1.0, String::from("end"));
+        let mut sprite = AnimatedSprite::new(keys[0]);
+
+        //the first frame is already shown, it only raises its tag when it's entered again
+        assert_eq!(sprite.advance(&animation, 3.0), Some(keys[0]));
+        assert_eq!(sprite.drain_events(), vec![AnimationEvent::Tag(String::from("end")), AnimationEvent::Tag(String::from("start"))]);
+        sprite.advance(&animation, 0.5);
+        assert!(sprite.drain_events().is_empty());
+    }
+
+    #[test]
+    fn seek_clamps_and_raises_no_events(){
+        let keys = keys(3);
+        let animation = SpriteAnimation::new(AnimationMode::Loop)
+            .with_frame(keys[0], 1.0)
+            .with_tagged_frame(keys[1], 1.0, String::from("hit"))
+            .with_frame(keys[2], 1.0);
+        let mut sprite = AnimatedSprite::new(keys[0]);
+
+        sprite.seek(&animation, 1.5);
+        assert_eq!(sprite.frame(), 1);
+        sprite.seek(&animation, -5.0);
+        assert_eq!(sprite.frame(), 0);
+        sprite.seek(&animation, 100.0);
+        assert_eq!(sprite.frame(), 1);
+        assert!(sprite.drain_events().is_empty());
+
+        let (keys, animation) = clip(AnimationMode::Once);
+        let mut sprite = AnimatedSprite::new(keys[0]);
+        sprite.seek(&animation, 100.0);
+        assert_eq!(sprite.frame(), 2);
+        assert!(sprite.is_finished());
+        assert!(sprite.drain_events().is_empty());
+    }
+}
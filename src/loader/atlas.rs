@@ -0,0 +1,967 @@
+use std::{collections::{HashMap, HashSet, hash_map::DefaultHasher}, hash::{Hash, Hasher}, path::Path};
+
+use lodepng::{Bitmap, RGBA};
+
+use crate::resource::{sprite::nine_slice::{NineSliceDefinition, NineSliceInsets}, texture::{GlTexture, atlas::{AtlasPage, AtlasRect, AtlasRegion, AtlasStats, TextureAtlas}}};
+
+use super::{nine_slice::{parse_mode, mode_name}, processing::ImagePipeline, texture::{decode_image, into_raw_bytes}};
+
+///Size of a page and the rects placed on it, as (image index, rect, rotated)
+type PageLayout = (u32,Vec<(usize,AtlasRect,bool)>);
+
+///
+/// A single packed texture of an atlas
+pub struct PackedPage{
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<RGBA>,
+    ///Pixels of each companion channel, using the same layout as the page
+    pub companions: Vec<(String,Vec<RGBA>)>,
+}
+
+///
+/// The result of packing images into an atlas, before anything is uploaded to the gpu
+pub struct PackedAtlas{
+    pub pages: Vec<PackedPage>,
+    pub regions: Vec<(String,AtlasRegion)>,
+    ///Nine slice definitions of the packed images that have one, see AtlasBuilder::with_slices
+    pub slices: Vec<(String,NineSliceDefinition)>,
+    pub stats: AtlasStats,
+}
+
+impl PackedAtlas{
+    ///
+    /// Uploads the packed pixels into new textures and creates the atlas for them
+    pub fn upload(self) -> TextureAtlas{
+        let pages = self.pages.into_iter().map(|page|{
+            let mut texture = GlTexture::create_empty();
+            texture.set_data(page.width, page.height, into_raw_bytes(page.pixels));
+            let companions = page.companions.into_iter().map(|(channel, pixels)|{
+                (channel, GlTexture::from_data(page.width, page.height, into_raw_bytes(pixels)))
+            }).collect();
+            AtlasPage{
+                texture,
+                width: page.width,
+                height: page.height,
+                companions
+            }
+        }).collect();
+
+        let mut atlas = TextureAtlas::from_pages(pages);
+        atlas.set_stats(self.stats);
+        for (name, region) in self.regions{
+            atlas.insert_region(name, region);
+        }
+        atlas
+    }
+
+    ///
+    /// Writes every page as a png next to the metadata file, named after it as '<name>_<page>.png'
+    /// Companion pages are named '<name>_<page>_<channel>.png'
+    /// The metadata can be loaded at runtime with load_packed_atlas, without packing the images again
+    pub fn save(&self, metadata_path: &Path) -> Result<AtlasMetadata,String>{
+        let stem = metadata_path.file_stem().ok_or_else(||format!("Invalid metadata path {}",metadata_path.display()))?.to_string_lossy();
+        let dir = metadata_path.parent().unwrap_or_else(||Path::new(""));
+
+        let mut metadata = AtlasMetadata{
+            pages: Vec::new(),
+            regions: self.regions.clone(),
+            slices: self.slices.clone(),
+        };
+        for (index, page) in self.pages.iter().enumerate(){
+            let file = format!("{}_{}.png",stem,index);
+            lodepng::encode32_file(dir.join(&file), &page.pixels, page.width as usize, page.height as usize)
+            .map_err(|e|format!("Failed to write {}: {}",dir.join(&file).display(),e))?;
+            let mut companions = Vec::new();
+            for (channel, pixels) in &page.companions{
+                let file = format!("{}_{}_{}.png",stem,index,channel);
+                lodepng::encode32_file(dir.join(&file), pixels, page.width as usize, page.height as usize)
+                .map_err(|e|format!("Failed to write {}: {}",dir.join(&file).display(),e))?;
+                companions.push((channel.clone(), file));
+            }
+            metadata.pages.push(PageMetadata{
+                file,
+                width: page.width,
+                height: page.height,
+                companions
+            });
+        }
+
+        std::fs::write(metadata_path, metadata.to_text()).map_err(|e|format!("Failed to write {}: {}",metadata_path.display(),e))?;
+        Ok(metadata)
+    }
+}
+
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct PageMetadata{
+    ///Path of the page image relative to the metadata file
+    pub file: String,
+    pub width: u32,
+    pub height: u32,
+    ///Channel name and file of each companion page
+    pub companions: Vec<(String,String)>,
+}
+
+///
+/// Description of a packed atlas stored next to its page images
+///
+/// The text format starts with the line 'render-2d-atlas 1', followed by one line per page
+/// 'page <width> <height> <file>', each optionally followed by 'companion <channel> <file>' lines, and one line per image
+/// 'region <page> <x> <y> <width> <height> <rotated> <offset x> <offset y> <source width> <source height> <name>'
+/// Nine slice sprites follow their region with 'slice <left> <top> <right> <bottom> <center mode> <edge mode> <name>'
+#[derive(Clone,Debug,Default)]
+pub struct AtlasMetadata{
+    pub pages: Vec<PageMetadata>,
+    pub regions: Vec<(String,AtlasRegion)>,
+    pub slices: Vec<(String,NineSliceDefinition)>,
+}
+
+const METADATA_HEADER: &str = "render-2d-atlas 1";
+
+impl AtlasMetadata{
+    pub fn to_text(&self) -> String{
+        let mut out = String::from(METADATA_HEADER);
+        out.push('\n');
+        for page in &self.pages{
+            out.push_str(&format!("page {} {} {}\n",page.width,page.height,page.file));
+            for (channel, file) in &page.companions{
+                out.push_str(&format!("companion {} {}\n",channel,file));
+            }
+        }
+        for (name, r) in &self.regions{
+            out.push_str(&format!("region {} {} {} {} {} {} {} {} {} {} {}\n",
+                r.page, r.rect.x, r.rect.y, r.rect.width, r.rect.height, r.rotated as u8,
+                r.offset_x, r.offset_y, r.source_width, r.source_height, name));
+        }
+        for (name, slice) in &self.slices{
+            let insets = &slice.insets;
+            out.push_str(&format!("slice {} {} {} {} {} {} {}\n",
+                insets.left, insets.top, insets.right, insets.bottom, mode_name(slice.center), mode_name(slice.edges), name));
+        }
+        out
+    }
+
+    pub fn parse(text: &str) -> Result<Self,String>{
+        let mut lines = text.lines().enumerate().filter(|(_,x)|!x.trim().is_empty());
+        match lines.next(){
+            Some((_, x)) if x.trim() == METADATA_HEADER => {},
+            _ => return Err(String::from("Invalid atlas metadata, missing header"))
+        }
+
+        let mut out = Self::default();
+        for (line, text) in lines{
+            let error = |e: &str|format!("Invalid atlas metadata at line {}: {}",line + 1,e);
+            let (kind, rest) = text.split_once(' ').ok_or_else(||error("expected an entry"))?;
+            match kind{
+                "page" => {
+                    let parts = rest.splitn(3, ' ').collect::<Vec<_>>();
+                    if parts.len() != 3{
+                        return Err(error("expected width, height and file"));
+                    }
+                    out.pages.push(PageMetadata{
+                        width: parts[0].parse().map_err(|_|error("invalid width"))?,
+                        height: parts[1].parse().map_err(|_|error("invalid height"))?,
+                        file: String::from(parts[2]),
+                        companions: Vec::new(),
+                    });
+                },
+                "companion" => {
+                    let (channel, file) = rest.split_once(' ').ok_or_else(||error("expected channel and file"))?;
+                    let page = out.pages.last_mut().ok_or_else(||error("companion has to follow a page"))?;
+                    page.companions.push((String::from(channel), String::from(file)));
+                },
+                "region" => {
+                    let parts = rest.splitn(11, ' ').collect::<Vec<_>>();
+                    if parts.len() != 11{
+                        return Err(error("expected 10 numbers and a name"));
+                    }
+                    let mut numbers = [0u32;10];
+                    for (i, x) in parts[..10].iter().enumerate(){
+                        numbers[i] = x.parse().map_err(|_|error("invalid number"))?;
+                    }
+                    if numbers[0] as usize >= out.pages.len(){
+                        return Err(error("region refers to a page that isn't declared"));
+                    }
+                    out.regions.push((String::from(parts[10]), AtlasRegion{
+                        page: numbers[0] as usize,
+                        rect: AtlasRect::new(numbers[1], numbers[2], numbers[3], numbers[4]),
+                        rotated: numbers[5] != 0,
+                        offset_x: numbers[6],
+                        offset_y: numbers[7],
+                        source_width: numbers[8],
+                        source_height: numbers[9],
+                    }));
+                },
+                "slice" => {
+                    let parts = rest.splitn(7, ' ').collect::<Vec<_>>();
+                    if parts.len() != 7{
+                        return Err(error("expected 4 insets, 2 modes and a name"));
+                    }
+                    let mut insets = [0.0f32;4];
+                    for (i, x) in parts[..4].iter().enumerate(){
+                        insets[i] = x.parse().ok().filter(|x: &f32|*x >= 0.0).ok_or_else(||error("invalid inset"))?;
+                    }
+                    let center = parse_mode(parts[4]).ok_or_else(||error("expected stretch or tile"))?;
+                    let edges = parse_mode(parts[5]).ok_or_else(||error("expected stretch or tile"))?;
+                    if !out.regions.iter().any(|(x, _)|x == parts[6]){
+                        return Err(error("slice refers to a region that isn't declared"));
+                    }
+                    out.slices.push((String::from(parts[6]), NineSliceDefinition{
+                        insets: NineSliceInsets::new(insets[0], insets[1], insets[2], insets[3]),
+                        center,
+                        edges,
+                    }));
+                },
+                x => return Err(error(&format!("unknown entry {}",x)))
+            }
+        }
+        Ok(out)
+    }
+}
+
+///
+/// An image ready to be packed, possibly trimmed
+#[derive(Clone)]
+struct PreparedImage{
+    width: u32,
+    height: u32,
+    pixels: Vec<RGBA>,
+    offset_x: u32,
+    offset_y: u32,
+}
+
+///
+/// A companion map packed with the same layout as the regular images, such as normal or emissive maps
+/// Companion images are found by name, 'hero_n.png' is the normal map of 'hero.png' when the suffix is '_n'
+#[derive(Clone,Debug)]
+pub struct AtlasChannel{
+    pub name: String,
+    pub suffix: String,
+    ///Color used for images without a companion image
+    pub default: RGBA,
+}
+
+impl AtlasChannel{
+    pub fn new(name: &str, suffix: &str, default: RGBA) -> Self{
+        Self{
+            name: String::from(name),
+            suffix: String::from(suffix),
+            default
+        }
+    }
+
+    ///Normal maps with the suffix '_n', defaulting to a flat normal
+    pub fn normal() -> Self{
+        Self::new("normal", "_n", RGBA{r: 128, g: 128, b: 255, a: 255})
+    }
+
+    ///Emissive maps with the suffix '_e', defaulting to no emission
+    pub fn emissive() -> Self{
+        Self::new("emissive", "_e", RGBA{r: 0, g: 0, b: 0, a: 255})
+    }
+
+    ///Mask maps with the suffix '_m', defaulting to a fully set mask
+    pub fn mask() -> Self{
+        Self::new("mask", "_m", RGBA{r: 255, g: 255, b: 255, a: 255})
+    }
+
+    ///The normal, emissive and mask channels
+    pub fn builtin() -> [Self;3]{
+        [Self::normal(), Self::emissive(), Self::mask()]
+    }
+
+    ///
+    /// If the name belongs to a companion image of this channel, returns the name of the image it belongs to
+    pub(crate) fn base_name(&self, name: &str) -> Option<String>{
+        let file_start = name.rfind('/').map_or(0, |x|x + 1);
+        let (stem, extension) = match name[file_start..].rfind('.'){
+            Some(x) if x > 0 => name.split_at(file_start + x),
+            _ => (name, "")
+        };
+        stem.strip_suffix(self.suffix.as_str())
+        .filter(|x|x.len() > file_start)
+        .map(|x|format!("{}{}",x,extension))
+    }
+}
+
+///
+/// Collects named images and packs them into one or more texture atlas pages
+pub struct AtlasBuilder{
+    images: Vec<(String,Bitmap<RGBA>)>,
+    slices: Vec<(String,NineSliceDefinition)>,
+    channels: Vec<AtlasChannel>,
+    pipeline: ImagePipeline,
+    deduplicate: bool,
+    padding: u32,
+    max_size: Option<u32>,
+    trim: bool,
+    allow_rotation: bool,
+}
+
+impl Default for AtlasBuilder{
+    fn default() -> Self {
+        Self{
+            images: Vec::new(),
+            slices: Vec::new(),
+            channels: Vec::new(),
+            pipeline: ImagePipeline::new(),
+            deduplicate: true,
+            padding: 0,
+            max_size: None,
+            trim: false,
+            allow_rotation: false,
+        }
+    }
+}
+
+impl AtlasBuilder{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    ///
+    /// When enabled, images with identical pixels are only packed once and every name shares the same rect
+    /// Enabled by default
+    pub fn with_deduplicate(mut self, deduplicate: bool) -> Self{
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    ///Transparent pixels kept between packed images, avoids bleeding when sampling with filtering
+    pub fn with_padding(mut self, padding: u32) -> Self{
+        self.padding = padding;
+        self
+    }
+
+    ///
+    /// Limits the size of each page, images that don't fit are placed on additional pages
+    /// Pages are always square powers of two, so the limit is rounded down to one
+    pub fn with_max_size(mut self, max_size: u32) -> Self{
+        self.max_size = Some(max_size);
+        self
+    }
+
+    ///When enabled, fully transparent rows and columns are removed from the edges of each image before packing
+    pub fn with_trim(mut self, trim: bool) -> Self{
+        self.trim = trim;
+        self
+    }
+
+    ///When enabled, images may be rotated 90 degrees clockwise if that lets them fit on a page
+    pub fn with_rotation(mut self, allow_rotation: bool) -> Self{
+        self.allow_rotation = allow_rotation;
+        self
+    }
+
+    ///
+    /// Packs a companion channel alongside the images, the regular images drive the placement
+    /// and every companion page gets the same layout, so the uvs of a sprite are valid for all of them
+    pub fn with_channel(mut self, channel: AtlasChannel) -> Self{
+        self.channels.push(channel);
+        self
+    }
+
+    ///
+    /// Runs every image through the pipeline before packing
+    /// Companion maps only go through the processors that change the layout, such as scaling
+    pub fn with_pipeline(mut self, pipeline: ImagePipeline) -> Self{
+        self.pipeline = pipeline;
+        self
+    }
+
+    pub fn with_images(mut self, images: Vec<(String,Bitmap<RGBA>)>) -> Self{
+        self.images.extend(images);
+        self
+    }
+
+    pub fn add_image(&mut self, name: String, image: Bitmap<RGBA>){
+        self.images.push((name, image));
+    }
+
+    ///
+    /// Nine slice definitions of the images with the same name, stored with the packed atlas
+    /// These images are never trimmed or rotated, since their insets are measured on the whole image
+    pub fn with_slices(mut self, slices: Vec<(String,NineSliceDefinition)>) -> Self{
+        self.slices.extend(slices);
+        self
+    }
+
+    pub fn add_slice(&mut self, name: String, definition: NineSliceDefinition){
+        self.slices.push((name, definition));
+    }
+
+    ///
+    /// Decodes the png data and adds it under the name
+    pub fn add_encoded(&mut self, name: String, bytes: &[u8]) -> Result<(),String>{
+        let image = decode_image(bytes).map_err(|e|format!("{} ({})",e,name))?;
+        self.add_image(name, image);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize{
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.images.is_empty()
+    }
+
+    ///
+    /// Packs the images and uploads the result into new textures
+    pub fn build(self) -> Result<TextureAtlas,String>{
+        Ok(self.pack()?.upload())
+    }
+
+    ///
+    /// Packs the images into square power of two pages, without touching the gpu
+    pub fn pack(mut self) -> Result<PackedAtlas,String>{
+        if !self.pipeline.is_empty(){
+            if self.pipeline.changes_layout(){
+                if let Some((name, _)) = self.slices.iter().find(|(x, _)|self.images.iter().any(|(y, _)|x == y)){
+                    return Err(format!("{} has nine slice insets, which can't be combined with the layout changing processors {:?}",name,self.pipeline));
+                }
+            }
+            let channels = &self.channels;
+            let pipeline = &self.pipeline;
+            self.images = std::mem::take(&mut self.images).into_iter().map(|(name, image)|{
+                let is_companion = channels.iter().any(|x|x.base_name(&name).is_some());
+                let image = if is_companion { pipeline.process_layout(image) } else { pipeline.process(image) };
+                image.map(|x|(name.clone(), x)).map_err(|e|format!("Failed to process {}: {}",name,e))
+            }).collect::<Result<Vec<_>,_>>()?;
+        }
+
+        let images = &self.images;
+        if images.is_empty(){
+            return Err(String::from("Can't create an atlas without any images"));
+        }
+        if let Some((name, image)) = images.iter().find(|(_,x)|x.buffer.len() != x.width * x.height){
+            return Err(format!("Invalid image {}, expected {} pixels but got {}",name,image.width * image.height,image.buffer.len()));
+        }
+
+        //companions[(i, c)] is the image of channel c belonging to image i
+        let mut companions = HashMap::new();
+        let mut bases = Vec::new();
+        {
+            let by_name = images.iter().enumerate().map(|(i,(name,_))|(name.as_str(), i)).collect::<HashMap<_,_>>();
+            for (index, (name, image)) in images.iter().enumerate(){
+                let companion = self.channels.iter().enumerate().find_map(|(c, channel)|channel.base_name(name).map(|x|(c, x)));
+                match companion{
+                    Some((channel, base_name)) => {
+                        let base = *by_name.get(base_name.as_str()).ok_or_else(||format!("Companion image {} has no image named {}",name,base_name))?;
+                        let base_image = &images[base].1;
+                        if base_image.width != image.width || base_image.height != image.height{
+                            return Err(format!("Companion image {} is {}x{} but {} is {}x{}",name,image.width,image.height,base_name,base_image.width,base_image.height));
+                        }
+                        companions.insert((base, channel), index);
+                    },
+                    None => bases.push(index)
+                }
+            }
+        }
+
+        //source[i] is the index of the image whose pixels are packed in place of image i
+        let source = if self.deduplicate{
+            let groups = bases.iter().map(|x|{
+                let mut group = vec![Some(&images[*x].1)];
+                group.extend((0..self.channels.len()).map(|c|companions.get(&(*x, c)).map(|i|&images[*i].1)));
+                (*x, group)
+            }).collect::<Vec<_>>();
+            find_duplicates(&groups)
+        }
+        else{
+            bases.iter().map(|x|(*x, *x)).collect()
+        };
+
+        //images with nine slices, and the ones their pixels are shared with, are kept whole and unrotated
+        let whole = bases.iter().filter(|x|self.slices.iter().any(|(name, _)|*name == images[**x].0)).map(|x|source[x]).collect::<HashSet<_>>();
+
+        let mut prepared = HashMap::new();
+        for index in bases.iter().copied().filter(|x|source[x] == *x){
+            let image = &images[index].1;
+            let base = if self.trim && !whole.contains(&index) { trim_image(image) } else { untrimmed_image(image) };
+            let channels = self.channels.iter().enumerate().map(|(c, channel)|{
+                match companions.get(&(index, c)){
+                    Some(x) => crop_image(&images[*x].1, &base),
+                    None => PreparedImage{
+                        pixels: vec![channel.default;(base.width * base.height) as usize],
+                        ..base.clone()
+                    }
+                }
+            }).collect::<Vec<_>>();
+            prepared.insert(index, (base, channels));
+        }
+
+        let mut order = prepared.keys().copied().collect::<Vec<_>>();
+        //tallest first, the index keeps the layout reproducible for images of equal size
+        order.sort_by(|a,b|{
+            let (pa, pb) = (&prepared[a].0, &prepared[b].0);
+            pb.height.cmp(&pa.height).then(pb.width.cmp(&pa.width)).then(a.cmp(b))
+        });
+
+        let sizes = order.iter().map(|x|(*x, prepared[x].0.width + self.padding, prepared[x].0.height + self.padding)).collect::<Vec<_>>();
+        let layout = self.layout_pages(&sizes, images, &whole)?;
+
+        let mut pages = Vec::with_capacity(layout.len());
+        let mut placed = HashMap::new();
+        for (page_index, (size, rects)) in layout.into_iter().enumerate(){
+            let mut pixels = vec![RGBA{..Default::default()};(size*size) as usize];
+            let mut channel_pixels = vec![pixels.clone();self.channels.len()];
+            for (index, rect, rotated) in rects{
+                let (image, channels) = &prepared[&index];
+                let rect = AtlasRect::new(rect.x, rect.y, rect.width - self.padding, rect.height - self.padding);
+                blit(image, &rect, rotated, &mut pixels, size);
+                for (channel, out) in channels.iter().zip(channel_pixels.iter_mut()){
+                    blit(channel, &rect, rotated, out, size);
+                }
+                placed.insert(index, (page_index, rect, rotated));
+            }
+            pages.push(PackedPage{
+                width: size,
+                height: size,
+                pixels,
+                companions: self.channels.iter().map(|x|x.name.clone()).zip(channel_pixels).collect(),
+            });
+        }
+
+        let mut stats = AtlasStats{
+            image_count: bases.len(),
+            unique_count: order.len(),
+            saved_pixels: 0,
+            atlas_pixels: pages.iter().map(|x|(x.width * x.height) as usize).sum(),
+        };
+
+        let mut regions = Vec::with_capacity(bases.len());
+        let mut images = self.images.into_iter().map(Some).collect::<Vec<_>>();
+        for index in bases{
+            let (name, image) = images[index].take().unwrap();
+            if source[&index] != index{
+                stats.saved_pixels += image.width * image.height;
+            }
+            let (page, rect, rotated) = placed[&source[&index]];
+            let prepared = &prepared[&source[&index]].0;
+            regions.push((name, AtlasRegion{
+                page,
+                rect,
+                rotated,
+                offset_x: prepared.offset_x,
+                offset_y: prepared.offset_y,
+                source_width: image.width as u32,
+                source_height: image.height as u32,
+            }));
+        }
+
+        let slices = self.slices.into_iter().filter(|(name, _)|regions.iter().any(|(x, _)|x == name)).collect();
+        Ok(PackedAtlas{
+            pages,
+            regions,
+            slices,
+            stats,
+        })
+    }
+
+    ///
+    /// Places the padded sizes on as few pages as the max size allows
+    /// Returns the size of each page together with the placed rects and whether they were rotated
+    /// Images in fixed are never rotated
+    fn layout_pages(&self, sizes: &[(usize,u32,u32)], images: &[(String,Bitmap<RGBA>)], fixed: &HashSet<usize>) -> Result<Vec<PageLayout>,String>{
+        let max_size = self.max_size.map(|x|if x.count_ones() == 1 { x } else { get_minimum_containing_square(x) >> 1 });
+        if let Some(max) = max_size{
+            let fits = |w: u32, h: u32|w <= max && h <= max;
+            if let Some((index, w, h)) = sizes.iter().find(|(_,w,h)|!fits(*w, *h)){
+                return Err(format!("Image {} ({}x{} with padding) doesn't fit in an atlas of at most {}x{}",images[*index].0,w,h,max,max));
+            }
+        }
+
+        let mut remaining = sizes.to_vec();
+        let mut pages = Vec::new();
+        while !remaining.is_empty(){
+            let largest = remaining.iter().map(|(_,w,h)|*w.max(h)).max().unwrap();
+            let mut size = get_minimum_containing_square(largest);
+
+            loop{
+                let mut root = ImageNode::Empty{width: size, height: size};
+                let mut rotated = Vec::new();
+                let mut unplaced = Vec::new();
+                for (index, w, h) in &remaining{
+                    if root.try_insert(*index, *w, *h){
+                        continue;
+                    }
+                    if self.allow_rotation && w != h && !fixed.contains(index) && root.try_insert(*index, *h, *w){
+                        rotated.push(*index);
+                        continue;
+                    }
+                    unplaced.push((*index, *w, *h));
+                }
+
+                let at_limit = max_size.is_some_and(|max|size >= max);
+                if unplaced.is_empty() || at_limit{
+                    let mut rects = Vec::new();
+                    root.collect_rects(0, 0, &mut rects);
+                    if rects.is_empty(){
+                        return Err(String::from("Failed to place any image on an atlas page"));
+                    }
+                    pages.push((size, rects.into_iter().map(|(index, rect)|(index, rect, rotated.contains(&index))).collect()));
+                    remaining = unplaced;
+                    break;
+                }
+                size <<= 1;
+            }
+        }
+        Ok(pages)
+    }
+}
+
+fn untrimmed_image(image: &Bitmap<RGBA>) -> PreparedImage{
+    PreparedImage{
+        width: image.width as u32,
+        height: image.height as u32,
+        pixels: image.buffer.clone(),
+        offset_x: 0,
+        offset_y: 0,
+    }
+}
+
+///
+/// Removes fully transparent rows and columns from the edges of the image
+/// A fully transparent image is reduced to a single pixel
+fn trim_image(image: &Bitmap<RGBA>) -> PreparedImage{
+    let (width, height) = (image.width, image.height);
+    let mut min = (width, height);
+    let mut max = (0, 0);
+    for y in 0..height{
+        for x in 0..width{
+            if image.buffer[x + y * width].a != 0{
+                min = (min.0.min(x), min.1.min(y));
+                max = (max.0.max(x), max.1.max(y));
+            }
+        }
+    }
+
+    if min.0 > max.0{
+        return PreparedImage{
+            width: 1,
+            height: 1,
+            pixels: vec![RGBA{..Default::default()}],
+            offset_x: 0,
+            offset_y: 0,
+        };
+    }
+
+    let trimmed_width = max.0 - min.0 + 1;
+    let mut pixels = Vec::with_capacity(trimmed_width * (max.1 - min.1 + 1));
+    for y in min.1..=max.1{
+        pixels.extend_from_slice(&image.buffer[min.0 + y * width..=max.0 + y * width]);
+    }
+    PreparedImage{
+        width: trimmed_width as u32,
+        height: (max.1 - min.1 + 1) as u32,
+        pixels,
+        offset_x: min.0 as u32,
+        offset_y: min.1 as u32,
+    }
+}
+
+///
+/// Cuts the same area out of a companion image as was kept of the trimmed image it belongs to
+fn crop_image(image: &Bitmap<RGBA>, trimmed: &PreparedImage) -> PreparedImage{
+    let mut pixels = Vec::with_capacity((trimmed.width * trimmed.height) as usize);
+    for y in trimmed.offset_y..trimmed.offset_y + trimmed.height{
+        let start = (trimmed.offset_x + y * image.width as u32) as usize;
+        pixels.extend_from_slice(&image.buffer[start..start + trimmed.width as usize]);
+    }
+    PreparedImage{
+        pixels,
+        ..trimmed.clone()
+    }
+}
+
+///
+/// Copies the image into the page at the rect, rotated images are turned 90 degrees clockwise
+fn blit(image: &PreparedImage, rect: &AtlasRect, rotated: bool, out: &mut [RGBA], size: u32){
+    if rotated{
+        for py in 0..image.height{
+            for px in 0..image.width{
+                let dx = rect.x + (image.height - 1 - py);
+                let dy = rect.y + px;
+                out[(dx + dy * size) as usize] = image.pixels[(px + py * image.width) as usize];
+            }
+        }
+    }
+    else{
+        for py in 0..image.height{
+            let src = (py * image.width) as usize;
+            let dst = (rect.x + (rect.y + py) * size) as usize;
+            out[dst..dst + image.width as usize].copy_from_slice(&image.pixels[src..src + image.width as usize]);
+        }
+    }
+}
+
+///
+/// Groups images by their pixels, each group being an image together with its companions
+/// Returns a map from every image to the first image with identical pixels in all channels
+fn find_duplicates(groups: &[(usize,Vec<Option<&Bitmap<RGBA>>>)]) -> HashMap<usize,usize>{
+    let mut seen: HashMap<u64,Vec<usize>> = HashMap::new();
+    let mut source = HashMap::with_capacity(groups.len());
+
+    for (position, (index, group)) in groups.iter().enumerate(){
+        let mut hasher = DefaultHasher::new();
+        for image in group{
+            match image{
+                Some(image) => {
+                    image.width.hash(&mut hasher);
+                    image.height.hash(&mut hasher);
+                    image.buffer.hash(&mut hasher);
+                },
+                None => 0usize.hash(&mut hasher)
+            }
+        }
+
+        let candidates = seen.entry(hasher.finish()).or_default();
+        //the hash only narrows the search, the pixels are compared to rule out collisions
+        let original = candidates.iter().copied().find(|x|{
+            groups[*x].1.iter().zip(group.iter()).all(|(a,b)|match (a,b){
+                (Some(a), Some(b)) => a.width == b.width && a.height == b.height && a.buffer == b.buffer,
+                (None, None) => true,
+                _ => false
+            })
+        });
+
+        match original{
+            Some(x) => {
+                source.insert(*index, groups[x].0);
+            },
+            None => {
+                candidates.push(position);
+                source.insert(*index, *index);
+            }
+        }
+    }
+    source
+}
+
+fn get_minimum_containing_square(size: u32) -> u32{
+    if size.count_ones() == 1{
+        size
+    }
+    else{
+        let z = size.leading_zeros();
+        1u32 << (32u32 - z)
+    }
+}
+
+///
+/// Node in the binary tree used to pack images into an atlas
+/// Each occupied node splits the remaining space into the region to the right (left) and the region below (down) the image
+pub enum ImageNode {
+    Occupied{
+        index: usize,
+        width: u32,
+        height: u32,
+        left: Box<ImageNode>,
+        down: Box<ImageNode>
+    },
+    Empty{
+        width: u32,
+        height: u32,
+    }
+}
+
+impl ImageNode{
+    fn try_insert(&mut self, index: usize, target_width: u32, target_height: u32) -> bool{
+        match self {
+            ImageNode::Occupied { left, down, .. } => {
+                left.try_insert(index, target_width, target_height) || down.try_insert(index, target_width, target_height)
+            }
+            ImageNode::Empty { width, height } => {
+                if *width >= target_width && *height >= target_height{
+                    let remaining_width = *width - target_width;
+                    let remaining_height = *height - target_height;
+
+                    *self = ImageNode::Occupied{
+                        down: Box::new(ImageNode::Empty{width: *width, height: remaining_height}),
+                        left: Box::new(ImageNode::Empty{width: remaining_width, height: target_height}),
+                        index,
+                        width: target_width,
+                        height: target_height,
+                    };
+
+                    true
+                }
+                else{
+                    false
+                }
+            }
+        }
+    }
+
+    fn collect_rects(&self, x: u32, y: u32, out: &mut Vec<(usize,AtlasRect)>){
+        match self{
+            ImageNode::Occupied { index, width, height, left, down } => {
+                out.push((*index, AtlasRect::new(x, y, *width, *height)));
+
+                left.collect_rects(x + *width, y, out);
+                down.collect_rects(x, y + *height, out);
+            },
+            ImageNode::Empty { .. } => {
+
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::resource::sprite::nine_slice::SliceMode;
+
+    fn image(width: usize, height: usize, color: u8) -> Bitmap<RGBA>{
+        Bitmap{
+            buffer: vec![RGBA{r: color, g: color, b: color, a: 255};width * height],
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn find_duplicates_maps_to_the_first_identical_image(){
+        let (a, b, c) = (image(2, 2, 10), image(2, 2, 20), image(2, 2, 10));
+        let groups = vec![(0, vec![Some(&a)]), (1, vec![Some(&b)]), (2, vec![Some(&c)])];
+        let source = find_duplicates(&groups);
+        assert_eq!(source[&0], 0);
+        assert_eq!(source[&1], 1);
+        assert_eq!(source[&2], 0);
+    }
+
+    #[test]
+    fn find_duplicates_compares_companions_and_sizes(){
+        let (a, b) = (image(2, 2, 10), image(2, 2, 10));
+        let (normal_a, normal_b) = (image(2, 2, 1), image(2, 2, 2));
+        let groups = vec![(3, vec![Some(&a), Some(&normal_a)]), (7, vec![Some(&b), Some(&normal_b)])];
+        let source = find_duplicates(&groups);
+        assert_eq!(source[&7], 7);
+
+        let groups = vec![(3, vec![Some(&a), None]), (7, vec![Some(&b), Some(&normal_b)])];
+        assert_eq!(find_duplicates(&groups)[&7], 7);
+
+        //same pixel count and color, different shape
+        let (wide, tall) = (image(4, 1, 10), image(1, 4, 10));
+        let groups = vec![(0, vec![Some(&wide)]), (1, vec![Some(&tall)])];
+        assert_eq!(find_duplicates(&groups)[&1], 1);
+    }
+
+    #[test]
+    fn identical_images_share_one_region(){
+        let packed = AtlasBuilder::new()
+            .with_images(vec![
+                (String::from("a"), image(4, 4, 10)),
+                (String::from("b"), image(4, 4, 20)),
+                (String::from("c"), image(4, 4, 10)),
+                (String::from("d"), image(4, 4, 10)),
+            ])
+            .pack()
+            .unwrap();
+
+        let region = |name: &str|packed.regions.iter().find(|(x, _)|x == name).unwrap().1;
+        assert_eq!(region("a"), region("c"));
+        assert_eq!(region("a"), region("d"));
+        assert_ne!(region("a").rect, region("b").rect);
+        assert_eq!(packed.stats.image_count, 4);
+        assert_eq!(packed.stats.unique_count, 2);
+        assert_eq!(packed.stats.saved_pixels, 2 * 16);
+    }
+
+    #[test]
+    fn deduplication_can_be_disabled(){
+        let packed = AtlasBuilder::new()
+            .with_deduplicate(false)
+            .with_images(vec![(String::from("a"), image(4, 4, 10)), (String::from("b"), image(4, 4, 10))])
+            .pack()
+            .unwrap();
+        assert_ne!(packed.regions[0].1.rect, packed.regions[1].1.rect);
+        assert_eq!(packed.stats.unique_count, 2);
+        assert_eq!(packed.stats.saved_pixels, 0);
+    }
+
+    fn padded(width: usize, height: usize) -> Bitmap<RGBA>{
+        let mut out = image(width, height, 10);
+        for pixel in out.buffer.iter_mut().take(width){
+            pixel.a = 0;
+        }
+        out
+    }
+
+    #[test]
+    fn sliced_images_are_not_trimmed_or_rotated(){
+        let packed = AtlasBuilder::new()
+            .with_trim(true)
+            .with_rotation(true)
+            .with_images(vec![(String::from("panel"), padded(8, 2)), (String::from("icon"), padded(8, 2)), (String::from("big"), image(16, 16, 1))])
+            .with_slices(vec![(String::from("panel"), NineSliceDefinition::new(NineSliceInsets::new(1.0, 0.0, 1.0, 0.0))), (String::from("missing"), NineSliceDefinition::new(NineSliceInsets::default()))])
+            .with_deduplicate(false)
+            .pack()
+            .unwrap();
+
+        let region = |name: &str|packed.regions.iter().find(|(x, _)|x == name).unwrap().1;
+        let panel = region("panel");
+        assert!(!panel.rotated);
+        assert_eq!((panel.rect.width, panel.rect.height, panel.offset_y), (8, 2, 0));
+        assert_eq!(region("icon").offset_y, 1);
+        assert_eq!(packed.slices.len(), 1);
+        assert_eq!(packed.slices[0].0, "panel");
+    }
+
+    #[test]
+    fn duplicates_of_sliced_images_keep_the_whole_image(){
+        let packed = AtlasBuilder::new()
+            .with_trim(true)
+            .with_images(vec![(String::from("icon"), padded(4, 4)), (String::from("panel"), padded(4, 4))])
+            .with_slices(vec![(String::from("panel"), NineSliceDefinition::new(NineSliceInsets::new(1.0, 1.0, 1.0, 1.0)))])
+            .pack()
+            .unwrap();
+        assert_eq!(packed.stats.unique_count, 1);
+        assert!(packed.regions.iter().all(|(_, x)|x.rect.height == 4 && x.offset_y == 0));
+    }
+
+    #[test]
+    fn slices_survive_the_metadata_format(){
+        let packed = AtlasBuilder::new()
+            .with_images(vec![(String::from("ui/panel frame"), image(8, 8, 1)), (String::from("icon"), image(2, 2, 2))])
+            .with_slices(vec![(String::from("ui/panel frame"), NineSliceDefinition{
+                insets: NineSliceInsets::new(1.0, 2.5, 3.0, 0.0),
+                center: SliceMode::Tile,
+                edges: SliceMode::Stretch,
+            })])
+            .pack()
+            .unwrap();
+        let metadata = AtlasMetadata{
+            pages: vec![PageMetadata{file: String::from("atlas_0.png"), width: 16, height: 16, companions: Vec::new()}],
+            regions: packed.regions.clone(),
+            slices: packed.slices.clone(),
+        };
+
+        let parsed = AtlasMetadata::parse(&metadata.to_text()).unwrap();
+        assert_eq!(parsed.regions, metadata.regions);
+        assert_eq!(parsed.slices, metadata.slices);
+    }
+
+    #[test]
+    fn invalid_slice_records_are_rejected(){
+        let header = "render-2d-atlas 1\npage 4 4 a.png\nregion 0 0 0 4 4 0 0 0 4 4 panel\n";
+        assert!(AtlasMetadata::parse(&format!("{}slice 1 1 1 1 stretch tile panel",header)).is_ok());
+        assert!(AtlasMetadata::parse(&format!("{}slice 1 1 1 1 stretch tile other",header)).is_err());
+        assert!(AtlasMetadata::parse(&format!("{}slice 1 1 1 -1 stretch tile panel",header)).is_err());
+        assert!(AtlasMetadata::parse(&format!("{}slice 1 1 1 1 repeat tile panel",header)).is_err());
+        assert!(AtlasMetadata::parse(&format!("{}slice 1 1 1 stretch tile",header)).is_err());
+    }
+
+    #[test]
+    fn sliced_images_reject_layout_processors(){
+        use crate::loader::processing::{FlipVertical, ImagePipeline};
+        let result = AtlasBuilder::new()
+            .with_pipeline(ImagePipeline::new().with(FlipVertical))
+            .with_images(vec![(String::from("panel"), image(4, 4, 1))])
+            .with_slices(vec![(String::from("panel"), NineSliceDefinition::new(NineSliceInsets::new(1.0, 1.0, 1.0, 1.0)))])
+            .pack();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,36 @@
+use crate::resource::{ResourceKey, Resources, material::Material, shader::GlShader};
+
+pub fn register_material(resources: &mut Resources){
+    resources.register_type::<Material>();
+}
+
+///
+/// Validates the material against its shader and adds it to resources under the given name
+/// Sprites refer to it with SpriteFilter::with_material
+pub fn add_material(resources: &mut Resources, material: Material, name: String) -> Result<ResourceKey,String>{
+    material.validate().map_err(|e|format!("{} ({})",e,name))?;
+    register_material(resources);
+    Ok(resources.add_resource(material, name))
+}
+
+///
+/// Creates a material drawing with the shader registered under the name, for example one of resource::shader::builtin
+pub fn material_from_shader(resources: &Resources, shader: &str) -> Result<Material,String>{
+    match resources.find_resource_key(shader){
+        Some(x) if x.is::<GlShader>() => Ok(Material::new(resources.get_resource::<GlShader>(&x).clone())),
+        Some(_) => Err(format!("Resource {} isn't a shader",shader)),
+        None => Err(format!("There is no shader named {}",shader)),
+    }
+}
+
+///
+/// Fetches a material by name, returns None if there is no resource with the name or it isn't a material
+pub fn find_material<'a>(resources: &'a Resources, name: &str) -> Option<&'a Material>{
+    let key = resources.find_resource_key(name)?;
+    if key.is::<Material>(){
+        Some(resources.get_resource::<Material>(&key))
+    }
+    else{
+        None
+    }
+}
@@ -1,2 +1,9 @@
 pub mod texture;
-pub mod sprite;
\ No newline at end of file
+pub mod sprite;
+pub mod options;
+pub mod atlas;
+pub mod vfs;
+pub mod processing;
+pub mod nine_slice;
+pub mod shader;
+pub mod material;
\ No newline at end of file
@@ -0,0 +1,213 @@
+use lodepng::{Bitmap, RGBA};
+
+use crate::resource::sprite::nine_slice::{NineSliceDefinition, NineSliceInsets, SliceMode};
+
+///Extension of the sidecar file describing the nine slice borders of the image with the same file stem
+pub const SLICE_EXTENSION: &str = "slice";
+
+///
+/// Returns true if the file follows the android nine patch convention, ending in '.9.png'
+pub fn is_nine_patch(path: &str) -> bool{
+    path.to_lowercase().ends_with(".9.png")
+}
+
+///
+/// Removes the '.9' marker from the path of a nine patch, so 'ui/panel.9.png' becomes 'ui/panel.png'
+pub fn strip_nine_patch_marker(path: &str) -> String{
+    match path.len().checked_sub(".9.png".len()){
+        Some(x) if is_nine_patch(path) => format!("{}{}",&path[..x],&path[x + 2..]),
+        _ => String::from(path)
+    }
+}
+
+///
+/// Returns the path of the sidecar file for the image at the path, 'ui/panel.png' has 'ui/panel.slice'
+pub fn sidecar_path(path: &str) -> String{
+    let file_start = path.rfind('/').map_or(0, |x|x + 1);
+    match path[file_start..].rfind('.'){
+        Some(dot) if dot > 0 => format!("{}.{}",&path[..file_start + dot],SLICE_EXTENSION),
+        _ => format!("{}.{}",path,SLICE_EXTENSION)
+    }
+}
+
+///
+/// Parses a sidecar file, made of 'key = value' lines with '#' comments
+/// 'insets = <left> <top> <right> <bottom>' is required, 'center' and 'edges' are optional and either 'stretch' or 'tile'
+pub fn parse_slice_file(text: &str) -> Result<NineSliceDefinition,String>{
+    let mut insets = None;
+    let mut center = SliceMode::Stretch;
+    let mut edges = SliceMode::Stretch;
+
+    for (line, text) in text.lines().enumerate(){
+        let text = text.split('#').next().unwrap().trim();
+        if text.is_empty(){
+            continue;
+        }
+        let error = |e: &str|format!("Invalid slice file at line {}: {}",line + 1,e);
+        let (key, value) = text.split_once('=').ok_or_else(||error("expected key = value"))?;
+        match key.trim(){
+            "insets" => {
+                let numbers = value.split_whitespace()
+                    .map(|x|x.parse::<f32>().map_err(|_|error("invalid inset")))
+                    .collect::<Result<Vec<_>,_>>()?;
+                if numbers.len() != 4 || numbers.iter().any(|x|*x < 0.0){
+                    return Err(error("expected left, top, right and bottom insets"));
+                }
+                insets = Some(NineSliceInsets::new(numbers[0], numbers[1], numbers[2], numbers[3]));
+            },
+            "center" => center = parse_mode(value.trim()).ok_or_else(||error("expected stretch or tile"))?,
+            "edges" => edges = parse_mode(value.trim()).ok_or_else(||error("expected stretch or tile"))?,
+            x => return Err(error(&format!("unknown key {}",x)))
+        }
+    }
+
+    Ok(NineSliceDefinition{
+        insets: insets.ok_or_else(||String::from("Invalid slice file, missing insets"))?,
+        center,
+        edges,
+    })
+}
+
+pub(crate) fn parse_mode(value: &str) -> Option<SliceMode>{
+    match value{
+        "stretch" => Some(SliceMode::Stretch),
+        "tile" => Some(SliceMode::Tile),
+        _ => None
+    }
+}
+
+///The name parse_mode reads the mode from
+pub(crate) fn mode_name(mode: SliceMode) -> &'static str{
+    match mode{
+        SliceMode::Stretch => "stretch",
+        SliceMode::Tile => "tile",
+    }
+}
+
+///
+/// Decodes an android nine patch, returning the image without its 1 pixel marker border and the insets it describes
+/// The black pixels of the top and left border mark the stretchable columns and rows, anything outside them is a fixed border
+/// The padding markers of the bottom and right border are ignored
+pub fn decode_nine_patch(image: &Bitmap<RGBA>) -> Result<(Bitmap<RGBA>,NineSliceInsets),String>{
+    if image.width < 3 || image.height < 3{
+        return Err(format!("A {}x{} image is too small to be a nine patch",image.width,image.height));
+    }
+
+    let is_marker = |x: usize, y: usize|{
+        let pixel = image.buffer[x + y * image.width];
+        pixel.a == 255 && pixel.r == 0 && pixel.g == 0 && pixel.b == 0
+    };
+    let width = image.width - 2;
+    let height = image.height - 2;
+    let columns = (0..width).filter(|x|is_marker(x + 1, 0)).collect::<Vec<_>>();
+    let rows = (0..height).filter(|y|is_marker(0, y + 1)).collect::<Vec<_>>();
+
+    //only a single stretchable span per axis is supported, so everything between the outer markers stretches
+    let (left, right) = match (columns.first(), columns.last()){
+        (Some(first), Some(last)) => (*first, width - 1 - last),
+        _ => return Err(String::from("Nine patch has no stretch markers in its top border"))
+    };
+    let (top, bottom) = match (rows.first(), rows.last()){
+        (Some(first), Some(last)) => (*first, height - 1 - last),
+        _ => return Err(String::from("Nine patch has no stretch markers in its left border"))
+    };
+
+    let mut buffer = Vec::with_capacity(width * height);
+    for y in 1..=height{
+        let row = y * image.width;
+        buffer.extend_from_slice(&image.buffer[row + 1..row + 1 + width]);
+    }
+    Ok((
+        Bitmap{
+            buffer,
+            width,
+            height
+        },
+        NineSliceInsets::new(left as f32, top as f32, right as f32, bottom as f32)
+    ))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn parses_slice_files(){
+        let text = "# panel borders\ninsets = 4 5 6 7\n\ncenter = tile # repeat the pattern\n";
+        let definition = parse_slice_file(text).unwrap();
+        assert_eq!(definition.insets, NineSliceInsets::new(4.0, 5.0, 6.0, 7.0));
+        assert_eq!(definition.center, SliceMode::Tile);
+        assert_eq!(definition.edges, SliceMode::Stretch);
+    }
+
+    #[test]
+    fn slice_file_errors_name_the_line(){
+        let error = |text: &str|parse_slice_file(text).unwrap_err();
+        assert_eq!(error("# nothing\n"), "Invalid slice file, missing insets");
+        assert_eq!(error("insets 1 2 3 4"), "Invalid slice file at line 1: expected key = value");
+        assert_eq!(error("# a\ninsets = 1 2 3"), "Invalid slice file at line 2: expected left, top, right and bottom insets");
+        assert_eq!(error("insets = 1 2 3 -4"), "Invalid slice file at line 1: expected left, top, right and bottom insets");
+        assert_eq!(error("insets = 1 2 x 4"), "Invalid slice file at line 1: invalid inset");
+        assert_eq!(error("insets = 1 2 3 4\nedges = wrap"), "Invalid slice file at line 2: expected stretch or tile");
+        assert_eq!(error("insets = 1 2 3 4\nscale = 2"), "Invalid slice file at line 2: unknown key scale");
+    }
+
+    fn marker() -> RGBA{
+        RGBA{r: 0, g: 0, b: 0, a: 255}
+    }
+
+    //a 5x4 image inside the marker border, each pixel holds its index in the content
+    fn nine_patch(columns: &[usize], rows: &[usize]) -> Bitmap<RGBA>{
+        let (width, height) = (7, 6);
+        let mut buffer = vec![RGBA{r: 0, g: 0, b: 0, a: 0};width * height];
+        for y in 0..4{
+            for x in 0..5{
+                buffer[x + 1 + (y + 1) * width] = RGBA{r: (x + y * 5) as u8, g: 0, b: 0, a: 255};
+            }
+        }
+        for x in columns{
+            buffer[x + 1] = marker();
+        }
+        for y in rows{
+            buffer[(y + 1) * width] = marker();
+        }
+        Bitmap{
+            buffer,
+            width,
+            height
+        }
+    }
+
+    #[test]
+    fn nine_patch_insets_come_from_the_outer_markers(){
+        let (content, insets) = decode_nine_patch(&nine_patch(&[1, 2], &[1])).unwrap();
+        assert_eq!(insets, NineSliceInsets::new(1.0, 1.0, 2.0, 2.0));
+        assert_eq!((content.width, content.height), (5, 4));
+        assert_eq!(content.buffer.iter().map(|x|x.r as usize).collect::<Vec<_>>(), (0..20).collect::<Vec<_>>());
+
+        //gaps between markers still stretch
+        let (_, insets) = decode_nine_patch(&nine_patch(&[0, 4], &[0, 3])).unwrap();
+        assert_eq!(insets, NineSliceInsets::new(0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn nine_patches_need_markers_on_both_axes(){
+        assert!(decode_nine_patch(&nine_patch(&[], &[1])).unwrap_err().contains("top border"));
+        assert!(decode_nine_patch(&nine_patch(&[1], &[])).unwrap_err().contains("left border"));
+        let tiny = Bitmap{buffer: vec![marker();4], width: 2, height: 2};
+        assert!(decode_nine_patch(&tiny).unwrap_err().contains("too small"));
+    }
+
+    #[test]
+    fn nine_patch_and_sidecar_paths(){
+        assert!(is_nine_patch("ui/panel.9.PNG"));
+        assert!(!is_nine_patch("ui/panel9.png"));
+        assert_eq!(strip_nine_patch_marker("ui/panel.9.png"), "ui/panel.png");
+        assert_eq!(strip_nine_patch_marker("ui/panel.9.PNG"), "ui/panel.PNG");
+        assert_eq!(strip_nine_patch_marker("ui/panel.png"), "ui/panel.png");
+
+        assert_eq!(sidecar_path("ui/panel.png"), "ui/panel.slice");
+        assert_eq!(sidecar_path("ui.v2/panel"), "ui.v2/panel.slice");
+        assert_eq!(sidecar_path(".hidden"), ".hidden.slice");
+    }
+}
@@ -0,0 +1,405 @@
+use std::path::{Component, Path};
+
+use super::processing::ImagePipeline;
+
+///
+/// How symbolic links are treated when walking a directory
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum SymlinkPolicy{
+    ///Symlinked files and directories are loaded as if they were regular entries
+    Follow,
+    ///Symlinked entries are ignored
+    Skip,
+}
+
+///
+/// Case normalization applied to generated sprite names
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum NameCase{
+    Preserve,
+    Lower,
+    Upper,
+}
+
+///
+/// A glob pattern matched against '/' separated paths relative to the load root
+/// `*` matches any run of characters within a path segment, `?` matches a single character,
+/// `[abc]`, `[a-z]` and `[!abc]` match a single character of the class and a `**` segment matches any number of directories
+#[derive(Clone,Debug)]
+pub struct GlobPattern{
+    source: String,
+    segments: Vec<String>,
+}
+
+impl GlobPattern{
+    pub fn new(pattern: &str) -> Self{
+        let source = pattern.replace('\\', "/");
+        let segments = source.split('/').filter(|x|!x.is_empty()).map(String::from).collect();
+        Self{
+            source,
+            segments
+        }
+    }
+
+    pub fn as_str(&self) -> &str{
+        &self.source
+    }
+
+    pub fn matches(&self, path: &str) -> bool{
+        let path = path.split('/').filter(|x|!x.is_empty()).collect::<Vec<_>>();
+        match_segments(&self.segments, &path)
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool{
+    match pattern.split_first(){
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip|match_segments(rest, &path[skip..]))
+        },
+        Some((first, rest)) => {
+            match path.split_first(){
+                Some((segment, path_rest)) => match_wildcard(first.as_bytes(), segment.as_bytes()) && match_segments(rest, path_rest),
+                None => false
+            }
+        }
+    }
+}
+
+fn match_wildcard(pattern: &[u8], text: &[u8]) -> bool{
+    match pattern.split_first(){
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip|match_wildcard(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && match_wildcard(rest, &text[1..]),
+        Some((b'[', rest)) => match (text.first(), match_class(rest)){
+            (Some(c), Some((class, negated, rest))) => class_contains(class, *c) != negated && match_wildcard(rest, &text[1..]),
+            (None, Some(_)) => false,
+            //an unclosed bracket is matched literally
+            (_, None) => text.first() == Some(&b'[') && match_wildcard(rest, &text[1..]),
+        },
+        Some((c, rest)) => text.first() == Some(c) && match_wildcard(rest, &text[1..]),
+    }
+}
+
+///
+/// Splits a character class after its opening bracket into its contents, whether it is negated and the rest of the pattern
+/// A ']' right after the opening bracket is part of the class
+fn match_class(pattern: &[u8]) -> Option<(&[u8],bool,&[u8])>{
+    let (negated, body) = match pattern.first(){
+        Some(b'!') | Some(b'^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    let end = body.iter().skip(1).position(|x|*x == b']')? + 1;
+    Some((&body[..end], negated, &body[end + 1..]))
+}
+
+fn class_contains(class: &[u8], c: u8) -> bool{
+    let mut i = 0;
+    while i < class.len(){
+        if i + 2 < class.len() && class[i + 1] == b'-'{
+            if class[i] <= c && c <= class[i + 2]{
+                return true;
+            }
+            i += 3;
+        }
+        else{
+            if class[i] == c{
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+///
+/// Options controlling which files are picked up when loading a directory, and how the loaded images are named
+/// The default loads every png below the root, whatever the case of its extension, following symlinks and skipping hidden entries and common editor backup files
+#[derive(Clone,Debug)]
+pub struct LoadOptions{
+    pub include: Vec<GlobPattern>,
+    pub exclude: Vec<GlobPattern>,
+    pub max_depth: Option<usize>,
+    pub symlinks: SymlinkPolicy,
+    pub strip_extension: bool,
+    pub name_prefix: String,
+    pub name_case: NameCase,
+    ///Image processing applied to files matching the pattern, in the order the rules were added
+    pub processing: Vec<(GlobPattern,ImagePipeline)>,
+}
+
+impl Default for LoadOptions{
+    fn default() -> Self {
+        Self{
+            include: vec![GlobPattern::new("**/*.[pP][nN][gG]")],
+            exclude: vec![
+                GlobPattern::new("**/.*"),
+                GlobPattern::new("**/.*/**"),
+                GlobPattern::new("**/*~"),
+                GlobPattern::new("**/*.bak"),
+                GlobPattern::new("**/*.tmp"),
+            ],
+            max_depth: None,
+            symlinks: SymlinkPolicy::Follow,
+            strip_extension: false,
+            name_prefix: String::new(),
+            name_case: NameCase::Preserve,
+            processing: Vec::new(),
+        }
+    }
+}
+
+impl LoadOptions{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    ///Replaces the include patterns, a file has to match at least one of them to be loaded
+    pub fn with_include(mut self, patterns: &[&str]) -> Self{
+        self.include = patterns.iter().map(|x|GlobPattern::new(x)).collect();
+        self
+    }
+
+    ///Adds an exclude pattern, files and directories matching any of them are skipped
+    pub fn with_exclude(mut self, pattern: &str) -> Self{
+        self.exclude.push(GlobPattern::new(pattern));
+        self
+    }
+
+    pub fn without_default_excludes(mut self) -> Self{
+        self.exclude.clear();
+        self
+    }
+
+    ///Limits how many directories deep the loader recurses, 0 only loads files directly in the root
+    pub fn with_max_depth(mut self, depth: usize) -> Self{
+        self.max_depth = Some(depth);
+        self
+    }
+
+    pub fn with_symlinks(mut self, policy: SymlinkPolicy) -> Self{
+        self.symlinks = policy;
+        self
+    }
+
+    pub fn with_strip_extension(mut self, strip: bool) -> Self{
+        self.strip_extension = strip;
+        self
+    }
+
+    pub fn with_name_prefix(mut self, prefix: String) -> Self{
+        self.name_prefix = prefix;
+        self
+    }
+
+    pub fn with_name_case(mut self, case: NameCase) -> Self{
+        self.name_case = case;
+        self
+    }
+
+    ///
+    /// Processes every loaded file matching the pattern with the pipeline, for example 'ui/**' for a whole directory
+    /// When several rules match a file their pipelines run one after the other
+    pub fn with_processing(mut self, pattern: &str, pipeline: ImagePipeline) -> Self{
+        self.processing.push((GlobPattern::new(pattern), pipeline));
+        self
+    }
+
+    ///Returns the combined pipeline of every processing rule matching the relative path
+    pub fn pipeline_for(&self, relative: &str) -> ImagePipeline{
+        let mut out = ImagePipeline::new();
+        for (pattern, pipeline) in &self.processing{
+            if pattern.matches(relative){
+                out.extend(pipeline);
+            }
+        }
+        out
+    }
+
+    ///Returns true if the file at the relative path should be loaded
+    pub fn accepts_file(&self, relative: &str) -> bool{
+        self.include.iter().any(|x|x.matches(relative)) && !self.is_excluded(relative)
+    }
+
+    ///Returns true if the loader should recurse into the directory at the relative path, found at the given depth
+    pub fn accepts_dir(&self, relative: &str, depth: usize) -> bool{
+        self.max_depth.is_none_or(|max|depth <= max) && !self.is_excluded(relative)
+    }
+
+    fn is_excluded(&self, relative: &str) -> bool{
+        self.exclude.iter().any(|x|x.matches(relative))
+    }
+
+    ///Creates the resource name for a file from its '/' separated path relative to the load root
+    pub fn create_name(&self, relative: &str) -> String{
+        let mut name = relative;
+        if self.strip_extension{
+            let file_start = name.rfind('/').map_or(0, |x|x + 1);
+            if let Some(dot) = name[file_start..].rfind('.'){
+                if dot > 0{
+                    name = &name[..file_start + dot];
+                }
+            }
+        }
+
+        let name = format!("{}{}", self.name_prefix, name);
+        match self.name_case{
+            NameCase::Preserve => name,
+            NameCase::Lower => name.to_lowercase(),
+            NameCase::Upper => name.to_uppercase(),
+        }
+    }
+}
+
+///
+/// Creates a '/' separated path of the path relative to the root, returns None if the path isn't inside the root
+/// The result is the same on every platform
+pub fn relative_path(path: &Path, root: &Path) -> Option<String>{
+    let relative = path.strip_prefix(root).ok()?;
+    let mut out = String::new();
+    for component in relative.components(){
+        match component{
+            Component::Normal(x) => {
+                if !out.is_empty(){
+                    out.push('/');
+                }
+                out.push_str(&x.to_string_lossy());
+            },
+            Component::CurDir => {},
+            _ => return None
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn star_stays_within_a_segment(){
+        let pattern = GlobPattern::new("*.png");
+        assert!(pattern.matches("hero.png"));
+        assert!(pattern.matches(".png"));
+        assert!(!pattern.matches("ui/hero.png"));
+        assert!(!pattern.matches("hero.png.bak"));
+    }
+
+    #[test]
+    fn double_star_matches_any_depth(){
+        let pattern = GlobPattern::new("**/*.png");
+        assert!(pattern.matches("hero.png"));
+        assert!(pattern.matches("ui/buttons/ok.png"));
+        assert!(!pattern.matches("ui/buttons/ok.jpg"));
+
+        let pattern = GlobPattern::new("ui/**");
+        assert!(pattern.matches("ui"));
+        assert!(pattern.matches("ui/a/b/c.png"));
+        assert!(!pattern.matches("other/ui/c.png"));
+
+        let pattern = GlobPattern::new("a/**/b/*.png");
+        assert!(pattern.matches("a/b/x.png"));
+        assert!(pattern.matches("a/x/y/b/x.png"));
+        assert!(!pattern.matches("a/x/y/c/x.png"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character(){
+        let pattern = GlobPattern::new("frame_??.png");
+        assert!(pattern.matches("frame_01.png"));
+        assert!(!pattern.matches("frame_1.png"));
+        assert!(!pattern.matches("frame_001.png"));
+    }
+
+    #[test]
+    fn character_classes(){
+        let pattern = GlobPattern::new("frame_[0-9].png");
+        assert!(pattern.matches("frame_3.png"));
+        assert!(!pattern.matches("frame_a.png"));
+
+        let pattern = GlobPattern::new("[abc]*.png");
+        assert!(pattern.matches("b_tile.png"));
+        assert!(!pattern.matches("d_tile.png"));
+
+        let pattern = GlobPattern::new("[!_]*.png");
+        assert!(pattern.matches("tile.png"));
+        assert!(!pattern.matches("_tile.png"));
+
+        let pattern = GlobPattern::new("[]x].png");
+        assert!(pattern.matches("].png"));
+        assert!(pattern.matches("x.png"));
+
+        //an unclosed class is a literal bracket
+        let pattern = GlobPattern::new("[ab.png");
+        assert!(pattern.matches("[ab.png"));
+        assert!(!pattern.matches("a.png"));
+    }
+
+    #[test]
+    fn backslashes_are_separators(){
+        let pattern = GlobPattern::new("ui\\*.png");
+        assert_eq!(pattern.as_str(), "ui/*.png");
+        assert!(pattern.matches("ui/ok.png"));
+    }
+
+    #[test]
+    fn default_options_skip_hidden_and_backup_files(){
+        let options = LoadOptions::new();
+        assert!(options.accepts_file("ui/ok.png"));
+        assert!(!options.accepts_file(".hidden.png"));
+        assert!(!options.accepts_file(".git/icon.png"));
+        assert!(!options.accepts_file("ui/ok.png~"));
+        assert!(!options.accepts_file("ok.txt"));
+        assert!(!options.accepts_dir(".git", 0));
+    }
+
+    #[test]
+    fn default_options_match_any_extension_case(){
+        let options = LoadOptions::new();
+        assert!(options.accepts_file("ui/OK.PNG"));
+        assert!(options.accepts_file("ui/ok.Png"));
+        assert!(!options.accepts_file("ui/ok.pngx"));
+        assert_eq!(options.symlinks, SymlinkPolicy::Follow);
+    }
+
+    #[test]
+    fn max_depth_limits_directories(){
+        let options = LoadOptions::new().with_max_depth(1);
+        assert!(options.accepts_dir("a", 0));
+        assert!(options.accepts_dir("a/b", 1));
+        assert!(!options.accepts_dir("a/b/c", 2));
+        assert!(LoadOptions::new().accepts_dir("a/b/c/d", 100));
+    }
+
+    #[test]
+    fn names_keep_the_relative_path_by_default(){
+        assert_eq!(LoadOptions::new().create_name("ui/Ok.png"), "ui/Ok.png");
+    }
+
+    #[test]
+    fn names_strip_only_the_file_extension(){
+        let options = LoadOptions::new().with_strip_extension(true);
+        assert_eq!(options.create_name("ui/ok.png"), "ui/ok");
+        assert_eq!(options.create_name("ui.v2/ok"), "ui.v2/ok");
+        assert_eq!(options.create_name("a/.hidden"), "a/.hidden");
+        assert_eq!(options.create_name("ok.9.png"), "ok.9");
+    }
+
+    #[test]
+    fn names_get_prefix_and_case(){
+        let options = LoadOptions::new()
+            .with_strip_extension(true)
+            .with_name_prefix(String::from("Game/"))
+            .with_name_case(NameCase::Lower);
+        assert_eq!(options.create_name("UI/Ok.PNG"), "game/ui/ok");
+        let options = LoadOptions::new().with_name_case(NameCase::Upper);
+        assert_eq!(options.create_name("ui/ok.png"), "UI/OK.PNG");
+    }
+
+    #[test]
+    fn relative_paths_use_forward_slashes(){
+        let root = Path::new("assets");
+        assert_eq!(relative_path(&Path::new("assets").join("ui").join("ok.png"), root).as_deref(), Some("ui/ok.png"));
+        assert_eq!(relative_path(Path::new("other/ok.png"), root), None);
+    }
+}
@@ -0,0 +1,294 @@
+use std::{fmt::Debug, sync::Arc};
+
+use lodepng::{Bitmap, RGBA};
+
+///
+/// A step applied to decoded images before they are turned into textures
+pub trait ImageProcessor: Send + Sync{
+    fn name(&self) -> &str;
+
+    fn process(&self, image: Bitmap<RGBA>) -> Result<Bitmap<RGBA>,String>;
+
+    ///True if the processor moves or resizes pixels, these are also applied to companion maps so they keep matching
+    fn changes_layout(&self) -> bool{
+        false
+    }
+}
+
+///
+/// An ordered list of image processors
+#[derive(Clone,Default)]
+pub struct ImagePipeline{
+    processors: Vec<Arc<dyn ImageProcessor>>,
+}
+
+impl Debug for ImagePipeline{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.processors.iter().map(|x|x.name())).finish()
+    }
+}
+
+impl ImagePipeline{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    pub fn with(mut self, processor: impl ImageProcessor + 'static) -> Self{
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    pub fn push(&mut self, processor: Arc<dyn ImageProcessor>){
+        self.processors.push(processor);
+    }
+
+    ///Appends every processor of the other pipeline after the processors of this one
+    pub fn extend(&mut self, other: &ImagePipeline){
+        self.processors.extend(other.processors.iter().cloned());
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.processors.is_empty()
+    }
+
+    pub fn process(&self, mut image: Bitmap<RGBA>) -> Result<Bitmap<RGBA>,String>{
+        for processor in &self.processors{
+            image = processor.process(image).map_err(|e|format!("{} failed: {}",processor.name(),e))?;
+        }
+        Ok(image)
+    }
+
+    ///True if any processor moves or resizes pixels
+    pub fn changes_layout(&self) -> bool{
+        self.processors.iter().any(|x|x.changes_layout())
+    }
+
+    ///Only runs the processors that change the layout of the image
+    pub fn process_layout(&self, mut image: Bitmap<RGBA>) -> Result<Bitmap<RGBA>,String>{
+        for processor in self.processors.iter().filter(|x|x.changes_layout()){
+            image = processor.process(image).map_err(|e|format!("{} failed: {}",processor.name(),e))?;
+        }
+        Ok(image)
+    }
+}
+
+///
+/// Multiplies the color channels by alpha
+pub struct PremultiplyAlpha;
+
+impl ImageProcessor for PremultiplyAlpha{
+    fn name(&self) -> &str {
+        "premultiply alpha"
+    }
+
+    fn process(&self, mut image: Bitmap<RGBA>) -> Result<Bitmap<RGBA>,String> {
+        for pixel in image.buffer.iter_mut(){
+            let a = pixel.a as u32;
+            pixel.r = ((pixel.r as u32 * a + 127) / 255) as u8;
+            pixel.g = ((pixel.g as u32 * a + 127) / 255) as u8;
+            pixel.b = ((pixel.b as u32 * a + 127) / 255) as u8;
+        }
+        Ok(image)
+    }
+}
+
+///
+/// Makes every pixel of the key color fully transparent, ignoring its alpha
+pub struct ColorKey{
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl ColorKey{
+    ///The classic magenta key, #FF00FF
+    pub fn magenta() -> Self{
+        Self{
+            r: 255,
+            g: 0,
+            b: 255
+        }
+    }
+}
+
+impl ImageProcessor for ColorKey{
+    fn name(&self) -> &str {
+        "color key"
+    }
+
+    fn process(&self, mut image: Bitmap<RGBA>) -> Result<Bitmap<RGBA>,String> {
+        for pixel in image.buffer.iter_mut(){
+            if pixel.r == self.r && pixel.g == self.g && pixel.b == self.b{
+                *pixel = RGBA{r: 0, g: 0, b: 0, a: 0};
+            }
+        }
+        Ok(image)
+    }
+}
+
+///
+/// Reverses the order of the rows, turning a top left origin into gl's bottom left origin
+/// Meant for standalone textures, atlas uvs assume the rows of each image are in their original order
+pub struct FlipVertical;
+
+impl ImageProcessor for FlipVertical{
+    fn name(&self) -> &str {
+        "flip vertical"
+    }
+
+    fn process(&self, mut image: Bitmap<RGBA>) -> Result<Bitmap<RGBA>,String> {
+        let width = image.width;
+        for y in 0..image.height / 2{
+            let (top, bottom) = image.buffer.split_at_mut((image.height - 1 - y) * width);
+            top[y * width..(y + 1) * width].swap_with_slice(&mut bottom[..width]);
+        }
+        Ok(image)
+    }
+
+    fn changes_layout(&self) -> bool {
+        true
+    }
+}
+
+///
+/// Scales the image up by a whole factor using nearest neighbour sampling, keeping pixel art crisp
+pub struct IntegerScale(pub u32);
+
+impl ImageProcessor for IntegerScale{
+    fn name(&self) -> &str {
+        "integer scale"
+    }
+
+    fn process(&self, image: Bitmap<RGBA>) -> Result<Bitmap<RGBA>,String> {
+        let factor = self.0 as usize;
+        if factor == 0{
+            return Err(String::from("scale factor has to be at least 1"));
+        }
+        if factor == 1{
+            return Ok(image);
+        }
+
+        let width = image.width * factor;
+        let height = image.height * factor;
+        let mut buffer = Vec::with_capacity(width * height);
+        for y in 0..height{
+            let row = (y / factor) * image.width;
+            buffer.extend((0..width).map(|x|image.buffer[row + x / factor]));
+        }
+        Ok(Bitmap{
+            buffer,
+            width,
+            height
+        })
+    }
+
+    fn changes_layout(&self) -> bool {
+        true
+    }
+}
+
+///
+/// Converts the colors to their luminance, keeping alpha
+pub struct Grayscale;
+
+impl ImageProcessor for Grayscale{
+    fn name(&self) -> &str {
+        "grayscale"
+    }
+
+    fn process(&self, mut image: Bitmap<RGBA>) -> Result<Bitmap<RGBA>,String> {
+        for pixel in image.buffer.iter_mut(){
+            //rec. 709 luma weights
+            let luma = (pixel.r as f32 * 0.2126 + pixel.g as f32 * 0.7152 + pixel.b as f32 * 0.0722).round() as u8;
+            pixel.r = luma;
+            pixel.g = luma;
+            pixel.b = luma;
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn pixel(v: u8) -> RGBA{
+        RGBA{r: v, g: v, b: v, a: 255}
+    }
+
+    //each pixel holds its own index, so moved pixels can be told apart
+    fn image(width: usize, height: usize) -> Bitmap<RGBA>{
+        Bitmap{
+            buffer: (0..width * height).map(|x|pixel(x as u8)).collect(),
+            width,
+            height
+        }
+    }
+
+    fn values(image: &Bitmap<RGBA>) -> Vec<u8>{
+        image.buffer.iter().map(|x|x.r).collect()
+    }
+
+    #[test]
+    fn flip_vertical_reverses_rows(){
+        let flipped = FlipVertical.process(image(2, 4)).unwrap();
+        assert_eq!(values(&flipped), vec![6, 7, 4, 5, 2, 3, 0, 1]);
+
+        //the middle row of an odd height stays in place
+        let flipped = FlipVertical.process(image(3, 3)).unwrap();
+        assert_eq!(values(&flipped), vec![6, 7, 8, 3, 4, 5, 0, 1, 2]);
+
+        let flipped = FlipVertical.process(image(2, 1)).unwrap();
+        assert_eq!(values(&flipped), vec![0, 1]);
+    }
+
+    #[test]
+    fn integer_scale_repeats_pixels(){
+        let scaled = IntegerScale(2).process(image(2, 2)).unwrap();
+        assert_eq!((scaled.width, scaled.height), (4, 4));
+        assert_eq!(values(&scaled), vec![
+            0, 0, 1, 1,
+            0, 0, 1, 1,
+            2, 2, 3, 3,
+            2, 2, 3, 3,
+        ]);
+
+        assert_eq!(values(&IntegerScale(1).process(image(3, 1)).unwrap()), vec![0, 1, 2]);
+        assert!(IntegerScale(0).process(image(1, 1)).is_err());
+    }
+
+    #[test]
+    fn premultiply_alpha_rounds_to_nearest(){
+        let image = Bitmap{
+            buffer: vec![
+                RGBA{r: 255, g: 1, b: 3, a: 128},
+                RGBA{r: 200, g: 100, b: 0, a: 0},
+                RGBA{r: 10, g: 20, b: 30, a: 255},
+            ],
+            width: 3,
+            height: 1
+        };
+        let out = PremultiplyAlpha.process(image).unwrap();
+        //255*128/255 = 128, 1*128/255 = 0.502 and 3*128/255 = 1.506 round up
+        assert_eq!(out.buffer[0], RGBA{r: 128, g: 1, b: 2, a: 128});
+        assert_eq!(out.buffer[1], RGBA{r: 0, g: 0, b: 0, a: 0});
+        assert_eq!(out.buffer[2], RGBA{r: 10, g: 20, b: 30, a: 255});
+    }
+
+    #[test]
+    fn process_layout_only_runs_layout_changes(){
+        let pipeline = ImagePipeline::new()
+            .with(ColorKey{r: 0, g: 0, b: 0})
+            .with(FlipVertical)
+            .with(Grayscale);
+        assert!(pipeline.changes_layout());
+        assert!(!ImagePipeline::new().with(PremultiplyAlpha).changes_layout());
+
+        //the color key would clear the first pixel, only the flip may run
+        let out = pipeline.process_layout(image(1, 2)).unwrap();
+        assert_eq!(out.buffer, vec![pixel(1), pixel(0)]);
+
+        let out = pipeline.process(image(1, 2)).unwrap();
+        assert_eq!(out.buffer, vec![pixel(1), RGBA{r: 0, g: 0, b: 0, a: 0}]);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{path::Path, time::SystemTime};
+
+use crate::{loader::vfs::{OsFs, VirtualFs}, resource::{ResourceKey, Resources, shader::{GlShader, block::{FRAME_UNIFORMS, FRAME_UNIFORMS_BINDING, FrameUniforms, UniformBlock}, builtin::BUILTIN_SHADERS, preprocessor::{PreprocessedSource, ShaderVariant, ShaderVariants, preprocess}}}};
+
+pub fn register_shaders(resources: &mut Resources){
+    resources.register_type::<GlShader>();
+    resources.register_type::<ShaderVariants>();
+    resources.register_type::<UniformBlock<FrameUniforms>>();
+}
+
+///
+/// Compiles the shaders shipped with the crate and adds them under their names from resource::shader::builtin
+/// The shared FrameUniforms block is added as well, under FRAME_UNIFORMS
+/// Needs a current gl context, the sprite draw system uses these by default
+pub fn register_builtin_shaders(resources: &mut Resources) -> Result<(),String>{
+    register_shaders(resources);
+    resources.add_resource(UniformBlock::new(FRAME_UNIFORMS_BINDING, &FrameUniforms::default()), String::from(FRAME_UNIFORMS));
+    for builtin in BUILTIN_SHADERS{
+        let shader = builtin.compile()?;
+        resources.add_resource(shader, String::from(builtin.name));
+    }
+    Ok(())
+}
+
+///
+/// Preprocesses and compiles the shader from the two source files and adds it to resources under the given name
+/// Includes are resolved relative to the directory of each file
+pub fn load_shader(vertex_path: &Path, fragment_path: &Path, name: String, resources: &mut Resources) -> Result<ResourceKey,String>{
+    let vertex = preprocess_file(vertex_path)?;
+    let fragment = preprocess_file(fragment_path)?;
+    let shader = GlShader::from_preprocessed(&vertex, &fragment)?;
+    Ok(resources.add_resource(shader, name))
+}
+
+///
+/// Same as load_shader, but the sources are read from a virtual filesystem
+pub fn load_shader_from_fs(fs: &dyn VirtualFs, vertex_path: &str, fragment_path: &str, name: String, resources: &mut Resources) -> Result<ResourceKey,String>{
+    load_shader_variant_from_fs(fs, vertex_path, fragment_path, &ShaderVariant::new(), name, resources)
+}
+
+///
+/// Same as load_shader_from_fs, with the defines of the variant injected into both stages
+pub fn load_shader_variant_from_fs(fs: &dyn VirtualFs, vertex_path: &str, fragment_path: &str, variant: &ShaderVariant, name: String, resources: &mut Resources) -> Result<ResourceKey,String>{
+    let vertex = preprocess(fs, vertex_path, variant)?;
+    let fragment = preprocess(fs, fragment_path, variant)?;
+    let shader = GlShader::from_preprocessed(&vertex, &fragment)?;
+    Ok(resources.add_resource(shader, name))
+}
+
+///
+/// Compiles the shader and adds it to resources under the given name, the sources aren't preprocessed
+pub fn load_shader_from_source(vertex: &str, fragment: &str, name: String, resources: &mut Resources) -> Result<ResourceKey,String>{
+    let shader = GlShader::from_source(vertex, fragment)?;
+    Ok(resources.add_resource(shader, name))
+}
+
+///
+/// Adds a shader whose variants are compiled on demand with ShaderVariants::get
+pub fn add_shader_variants(vertex_path: &str, fragment_path: &str, name: String, resources: &mut Resources) -> ResourceKey{
+    resources.add_resource(ShaderVariants::new(vertex_path, fragment_path), name)
+}
+
+fn preprocess_file(path: &Path) -> Result<PreprocessedSource,String>{
+    let parent = path.parent().unwrap_or_else(||Path::new(""));
+    let file_name = path.file_name().ok_or_else(||String::from("Invalid Path, Path has no file name"))?;
+    preprocess(&OsFs::new(parent), &file_name.to_string_lossy(), &ShaderVariant::new())
+}
+
+///Watched files with the time they were last modified
+type FileTimes = Vec<(String,Option<SystemTime>)>;
+
+struct WatchedShader{
+    shader: GlShader,
+    vertex_path: String,
+    fragment_path: String,
+    variant: ShaderVariant,
+    ///Every file the shader was built from, including its includes, with the time it was last modified
+    files: FileTimes,
+}
+
+///
+/// What a ShaderWatcher::poll did
+#[derive(Clone,Debug,Default)]
+pub struct ShaderReload{
+    ///Number of shaders recompiled successfully
+    pub reloaded: usize,
+    ///The path that failed to reload with the error, both stages are named when linking failed
+    pub errors: Vec<(String,String)>,
+}
+
+///
+/// Watches the source files of shaders and recompiles them when they change
+/// Reloaded programs are swapped into the existing GlShader, so every clone of it sees the new program
+/// Files are checked by their modification time when poll is called, filesystems without one are never reloaded
+pub struct ShaderWatcher{
+    fs: Box<dyn VirtualFs>,
+    shaders: Vec<WatchedShader>,
+}
+
+impl ShaderWatcher{
+    pub fn new(fs: impl VirtualFs + 'static) -> Self{
+        Self{
+            fs: Box::new(fs),
+            shaders: Vec::new(),
+        }
+    }
+
+    ///
+    /// Loads the shader from the watched filesystem, adds it to resources under the given name and watches it
+    pub fn load(&mut self, vertex_path: &str, fragment_path: &str, variant: &ShaderVariant, name: String, resources: &mut Resources) -> Result<ResourceKey,String>{
+        let vertex = preprocess(self.fs.as_ref(), vertex_path, variant)?;
+        let fragment = preprocess(self.fs.as_ref(), fragment_path, variant)?;
+        let shader = GlShader::from_preprocessed(&vertex, &fragment)?;
+        self.shaders.push(WatchedShader{
+            shader: shader.clone(),
+            vertex_path: String::from(vertex_path),
+            fragment_path: String::from(fragment_path),
+            variant: variant.clone(),
+            files: self.modified_times(&vertex, &fragment),
+        });
+        Ok(resources.add_resource(shader, name))
+    }
+
+    pub fn len(&self) -> usize{
+        self.shaders.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.shaders.is_empty()
+    }
+
+    ///
+    /// Recompiles every shader with a changed source file
+    /// Failures leave the previous program active and are returned once, the shader is retried after its files change again
+    pub fn poll(&mut self) -> ShaderReload{
+        let mut out = ShaderReload::default();
+        for index in 0..self.shaders.len(){
+            let watched = &self.shaders[index];
+            let changed = watched.files.iter().any(|(path, time)|self.fs.modified(path) != *time);
+            if !changed{
+                continue;
+            }
+
+            match self.reload(watched){
+                Ok(files) => {
+                    self.shaders[index].files = files;
+                    out.reloaded += 1;
+                },
+                Err((files, path, e)) => {
+                    out.errors.push((path, e));
+                    self.shaders[index].files = files;
+                }
+            }
+        }
+        out
+    }
+
+    ///
+    /// On failure returns the refreshed file times, the path that failed and the error
+    fn reload(&self, watched: &WatchedShader) -> Result<FileTimes,(FileTimes,String,String)>{
+        //the stored times are refreshed even on failure, so a broken file is only reported once per change
+        let failed = |path: String, e: String|(watched.files.iter().map(|(path,_)|(path.clone(), self.fs.modified(path))).collect::<Vec<_>>(), path, e);
+        let vertex = preprocess(self.fs.as_ref(), &watched.vertex_path, &watched.variant).map_err(|e|failed(watched.vertex_path.clone(), e))?;
+        let fragment = preprocess(self.fs.as_ref(), &watched.fragment_path, &watched.variant).map_err(|e|failed(watched.fragment_path.clone(), e))?;
+        watched.shader.reload(&vertex, &fragment).map_err(|e|failed(format!("{}, {}",watched.vertex_path,watched.fragment_path), e))?;
+        Ok(self.modified_times(&vertex, &fragment))
+    }
+
+    fn modified_times(&self, vertex: &PreprocessedSource, fragment: &PreprocessedSource) -> FileTimes{
+        let mut files = FileTimes::new();
+        for file in vertex.files().chain(fragment.files()){
+            if !files.iter().any(|(x,_)|x == file){
+                files.push((String::from(file), self.fs.modified(file)));
+            }
+        }
+        files
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use std::{cell::RefCell, collections::BTreeMap, rc::Rc, time::Duration};
+
+    use crate::{loader::vfs::VfsEntry, resource::shader::ShaderDataType};
+
+    use super::*;
+
+    //files with a modification time the test can bump
+    #[derive(Clone,Default)]
+    struct TimedFs(Rc<RefCell<BTreeMap<String,(String,u64)>>>);
+
+    impl TimedFs{
+        fn write(&self, path: &str, source: &str){
+            let mut files = self.0.borrow_mut();
+            let time = files.get(path).map_or(0, |x|x.1 + 1);
+            files.insert(String::from(path), (String::from(source), time));
+        }
+    }
+
+    impl VirtualFs for TimedFs{
+        fn read(&self, path: &str) -> Result<Vec<u8>,String>{
+            self.0.borrow().get(path).map(|x|x.0.clone().into_bytes()).ok_or_else(||format!("{} not found",path))
+        }
+
+        fn exists(&self, path: &str) -> bool{
+            self.0.borrow().contains_key(path)
+        }
+
+        fn is_dir(&self, _path: &str) -> bool{
+            false
+        }
+
+        fn read_dir(&self, _path: &str) -> Result<Vec<VfsEntry>,String>{
+            Ok(Vec::new())
+        }
+
+        fn modified(&self, path: &str) -> Option<SystemTime>{
+            self.0.borrow().get(path).map(|x|SystemTime::UNIX_EPOCH + Duration::from_secs(x.1))
+        }
+    }
+
+    #[test]
+    fn failed_reloads_are_returned_once(){
+        let fs = TimedFs::default();
+        fs.write("sprite.vert", "#version 450\nvoid main(){}");
+        fs.write("sprite.frag", "#version 450\n#include \"common.glsl\"\nvoid main(){}");
+        fs.write("common.glsl", "float x;");
+
+        //a shader without a gl program, it is never compiled since preprocessing fails
+        let shader = GlShader::with_reflected_uniforms(&[("u_color", ShaderDataType::Vec4F, 1)]);
+        let mut watcher = ShaderWatcher::new(fs.clone());
+        let vertex = preprocess(&fs, "sprite.vert", &ShaderVariant::new()).unwrap();
+        let fragment = preprocess(&fs, "sprite.frag", &ShaderVariant::new()).unwrap();
+        let files = watcher.modified_times(&vertex, &fragment);
+        watcher.shaders.push(WatchedShader{
+            shader: shader.clone(),
+            vertex_path: String::from("sprite.vert"),
+            fragment_path: String::from("sprite.frag"),
+            variant: ShaderVariant::new(),
+            files,
+        });
+
+        let result = watcher.poll();
+        assert_eq!((result.reloaded, result.errors.len()), (0, 0));
+
+        fs.write("common.glsl", "#include \"missing.glsl\"");
+        let result = watcher.poll();
+        assert_eq!(result.reloaded, 0);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].0, "sprite.frag");
+        assert!(result.errors[0].1.contains("included at common.glsl:1"), "{}", result.errors[0].1);
+
+        //nothing changed since, so the error isn't reported again and the old program stays
+        assert!(watcher.poll().errors.is_empty());
+        assert!(shader.uniform("u_color").is_some());
+    }
+}
@@ -1,5 +1,28 @@
-use crate::resource::{Resources, sprite::Sprite};
+use crate::resource::{ResourceKey, Resources, animation::SpriteAnimation, sprite::Sprite};
 
 pub fn register_sprite(resources: &mut Resources){
     resources.register_type::<dyn Sprite>();
-}
\ No newline at end of file
+    resources.register_type::<SpriteAnimation>();
+}
+
+///
+/// Adds any sprite type to resources so it can be fetched by name as a dyn Sprite
+pub fn add_sprite(resources: &mut Resources, sprite: impl Sprite + 'static, name: String) -> ResourceKey{
+    add_boxed_sprite(resources, Box::new(sprite), name)
+}
+
+pub fn add_boxed_sprite(resources: &mut Resources, sprite: Box<dyn Sprite>, name: String) -> ResourceKey{
+    resources.add_boxed_resource::<dyn Sprite>(sprite, name)
+}
+
+///
+/// Fetches a sprite by key, regardless of its concrete type
+pub fn get_sprite<'a>(resources: &'a Resources, key: &ResourceKey) -> &'a dyn Sprite{
+    resources.get_boxed_resource::<dyn Sprite>(key)
+}
+
+///
+/// Fetches a sprite by name, returns None if there is no resource with the name or it isn't a sprite
+pub fn find_sprite<'a>(resources: &'a Resources, name: &str) -> Option<&'a dyn Sprite>{
+    resources.find_boxed_resource::<dyn Sprite>(name)
+}
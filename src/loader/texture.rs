@@ -1,8 +1,8 @@
-use std::{collections::HashMap, env::current_dir, mem::forget, os::windows::process, path::Path};
+use std::{collections::HashMap, env::current_dir, mem::forget, path::{Path, PathBuf}};
 
 use lodepng::{Bitmap, RGBA};
 
-use crate::resource::{ResourceKey, Resources, sprite::uv_sprite::UvSprite, texture::GlTexture};
+use crate::{loader::{atlas::{AtlasBuilder, AtlasChannel, AtlasMetadata}, nine_slice::{decode_nine_patch, is_nine_patch, parse_slice_file, sidecar_path, strip_nine_patch_marker}, options::{LoadOptions, SymlinkPolicy, relative_path}, processing::ImagePipeline, sprite::{add_sprite, register_sprite}, vfs::{OsFs, VirtualFs}}, resource::{ResourceKey, Resources, sprite::nine_slice::{NineSliceDefinition, NineSliceSprite}, texture::{GlTexture, atlas::{AtlasPage, TextureAtlas}}}};
 ///
 /// loads a png into resources, naming it to the relative path to the current working dir
 pub fn load_texture(file_path: &Path, resources: &mut Resources) -> Result<ResourceKey,String>{
@@ -10,222 +10,308 @@ pub fn load_texture(file_path: &Path, resources: &mut Resources) -> Result<Resou
         return Err(String::from("Invalid Path, Path is pointing to a directory"));
     }
 
-   let result = lodepng::decode32_file(file_path);
-   
-   if result.is_err(){
-       let err = result.unwrap_err();
-       return Err(format!("Failed to load file {}",err));
-   }
+   let parent = file_path.parent().unwrap_or_else(||Path::new(""));
+   let file_name = file_path.file_name().ok_or_else(||String::from("Invalid Path, Path has no file name"))?;
+   let data = read_image(&OsFs::new(parent), &file_name.to_string_lossy())?;
+   load_texture_from_image(data, create_sprite_name(file_path, current_dir().unwrap().as_path()), resources)
+}
+
+///
+/// Same as load_texture, but the decoded image is run through the pipeline before it is uploaded
+pub fn load_texture_with_pipeline(file_path: &Path, pipeline: &ImagePipeline, resources: &mut Resources) -> Result<ResourceKey,String>{
+    if file_path.is_dir(){
+        return Err(String::from("Invalid Path, Path is pointing to a directory"));
+    }
+
+    let parent = file_path.parent().unwrap_or_else(||Path::new(""));
+    let file_name = file_path.file_name().ok_or_else(||String::from("Invalid Path, Path has no file name"))?;
+    let data = read_image(&OsFs::new(parent), &file_name.to_string_lossy())?;
+    let data = pipeline.process(data).map_err(|e|format!("Failed to process {}: {}",file_path.display(),e))?;
+    load_texture_from_image(data, create_sprite_name(file_path, current_dir().unwrap().as_path()), resources)
+}
+
+///
+/// loads a png from a virtual filesystem into resources, naming it to its path in the filesystem
+pub fn load_texture_from_fs(fs: &dyn VirtualFs, path: &str, resources: &mut Resources) -> Result<ResourceKey,String>{
+    if fs.is_dir(path){
+        return Err(String::from("Invalid Path, Path is pointing to a directory"));
+    }
 
-   let data = result.unwrap();
-   let width = data.width as u32;
-   let height = data.height as u32;
-   let mut buffer = data.buffer;
-   
-   let len = buffer.len()*4;
-   let capasity = buffer.capacity()*4;
-   let buffer = buffer.as_mut_ptr() as *mut u8;
+    let data = read_image(fs, path)?;
+    load_texture_from_image(data, String::from(path), resources)
+}
+
+///
+/// loads an encoded png, for example from include_bytes!, into resources under the given name
+pub fn load_texture_from_bytes(bytes: &[u8], name: String, resources: &mut Resources) -> Result<ResourceKey,String>{
+    let data = decode_image(bytes)?;
+    load_texture_from_image(data, name, resources)
+}
 
-   std::mem::forget(buffer);
+///
+/// uploads an already decoded image into resources under the given name
+pub fn load_texture_from_image(image: Bitmap<RGBA>, name: String, resources: &mut Resources) -> Result<ResourceKey,String>{
+    if image.buffer.len() != image.width * image.height{
+        return Err(format!("Invalid image {}, expected {} pixels but got {}",name,image.width * image.height,image.buffer.len()));
+    }
 
-   let new = unsafe {Vec::from_raw_parts(buffer,len,capasity)};
+    let width = image.width as u32;
+    let height = image.height as u32;
 
-   let texture = GlTexture::from_data(width,height, new);
-   Ok(resources.add_resource(texture,create_sprite_name(file_path, current_dir().unwrap().as_path())))
+    let texture = GlTexture::from_data(width,height, into_raw_bytes(image.buffer));
+    Ok(resources.add_resource(texture,name))
+}
+
+///
+/// decodes an encoded png into rgba pixels
+pub fn decode_image(bytes: &[u8]) -> Result<Bitmap<RGBA>,String>{
+    lodepng::decode32(bytes).map_err(|e|format!("Failed to decode image {}",e))
 }
 
 pub fn register_textures(resources: &mut Resources){
     resources.register_type::<GlTexture>();
+    resources.register_type::<TextureAtlas>();
+    register_sprite(resources);
 }
 
 
+///Name the atlas loaded by load_as_atlas and its variants is registered under
+pub const DEFAULT_ATLAS_NAME: &str = "spritesheet";
+
 ///
 /// Loads every image from the root and constructs a texture atlas with additional sprites
-/// The atlas will be named 'spritesheet', and the sprites will be named in correlation to the filepath of each subsequent image relative to the current working dir
-pub fn load_as_atlas(root: &Path, resources: &mut Resources){
-    let mut images = Vec::new();
-    load_dir(root, root, &mut images);
+/// The atlas will be named DEFAULT_ATLAS_NAME ('spritesheet'), and the sprites will be named in correlation to the filepath of each subsequent image relative to the root
+/// Returns the key of the registered atlas
+pub fn load_as_atlas(root: &Path, resources: &mut Resources) -> Result<ResourceKey,String>{
+    load_as_atlas_with_options(root, &LoadOptions::default(), resources)
+}
 
-    create_atlas(images);
+///
+/// Same as load_as_atlas, but the files that are loaded and the names they get are controlled by the options
+pub fn load_as_atlas_with_options(root: &Path, options: &LoadOptions, resources: &mut Resources) -> Result<ResourceKey,String>{
+    load_as_atlas_from_fs(&OsFs::new(root), "", options, resources)
 }
 
-fn load_dir(root: &Path, path: &Path, images: &mut Vec<(String,Bitmap<RGBA>)>){
-    let entries = path.read_dir().unwrap();
-    for entry in entries{
-        let entry = entry.unwrap();
-        if entry.path().is_dir(){
-            load_dir(root, entry.path().as_path(), images);
-        }
-        else{
-            load_image(root, entry.path().as_path(), images);
+///
+/// Same as load_as_atlas_with_options, but the images are read from the directory dir of a virtual filesystem
+/// Images with a '.slice' sidecar file or following the '.9.png' convention are registered as nine slice sprites
+/// Companion maps of the built-in channels, like 'hero_n.png' next to 'hero.png', are packed into the matching channel of the atlas
+pub fn load_as_atlas_from_fs(fs: &dyn VirtualFs, dir: &str, options: &LoadOptions, resources: &mut Resources) -> Result<ResourceKey,String>{
+    let loaded = load_images_with_slices_from_fs(fs, dir, options)?;
+    let mut builder = AtlasBuilder::new();
+    //only channels that have a companion get one, the others would just be filled with their default
+    for channel in AtlasChannel::builtin(){
+        let names = loaded.images.iter().map(|(x,_)|x);
+        if names.clone().filter_map(|x|channel.base_name(x)).any(|x|names.clone().any(|y|*y == x)){
+            builder = builder.with_channel(channel);
         }
     }
+    let atlas = builder.with_images(loaded.images).with_slices(loaded.slices.clone()).build()?;
+    register_atlas_with_slices(atlas, DEFAULT_ATLAS_NAME, &loaded.slices, resources)
 }
 
-fn load_image(root: &Path, path: &Path, images: &mut Vec<(String,Bitmap<RGBA>)>){
-    let name = create_sprite_name(path, root);
-    images.push((name,lodepng::decode32_file(path).unwrap()));
+///
+/// Packs already decoded images into an atlas, registered the same way as load_as_atlas but under the given name
+pub fn load_atlas_from_images(images: Vec<(String,Bitmap<RGBA>)>, name: &str, resources: &mut Resources) -> Result<ResourceKey,String>{
+    let atlas = AtlasBuilder::new().with_images(images).build()?;
+    Ok(register_atlas(atlas, name, resources))
 }
 
+///
+/// Decodes the named png data, for example from include_bytes!, and packs it into an atlas registered the same way as load_as_atlas but under the given name
+pub fn load_atlas_from_bytes(images: &[(&str,&[u8])], name: &str, resources: &mut Resources) -> Result<ResourceKey,String>{
+    let mut builder = AtlasBuilder::new();
+    for (name, bytes) in images{
+        builder.add_encoded(String::from(*name), bytes)?;
+    }
+    Ok(register_atlas(builder.build()?, name, resources))
+}
 
-fn create_atlas(mut images: Vec<(String,Bitmap<RGBA>)>){
-    images.sort_by(|a,b|{
-        match b.1.height.partial_cmp(&a.1.width){
-            Some(x) => match x{
-                std::cmp::Ordering::Equal => b.1.width.partial_cmp(&a.1.height).unwrap(),
-                x => x
-            },
-            _ => todo!(),
+///
+/// Loads an atlas written by render-2d-pack or PackedAtlas::save, registered the same way as load_as_atlas
+/// The atlas is named after the metadata path without its extension, 'ui/atlas.atlas' becomes 'ui/atlas'
+/// The page images are read relative to the directory of the metadata file
+/// Sprites with a slice record are registered as nine slice sprites, the same as register_atlas_with_slices
+pub fn load_packed_atlas(fs: &dyn VirtualFs, metadata_path: &str, resources: &mut Resources) -> Result<ResourceKey,String>{
+    let text = String::from_utf8(fs.read(metadata_path)?).map_err(|_|format!("Invalid atlas metadata {}: not utf8",metadata_path))?;
+    let metadata = AtlasMetadata::parse(&text).map_err(|e|format!("{} ({})",e,metadata_path))?;
+
+    let dir = metadata_path.rfind('/').map_or("", |x|&metadata_path[..x]);
+    let mut pages = Vec::with_capacity(metadata.pages.len());
+    for page in &metadata.pages{
+        let image = read_image(fs, &join_path(dir, &page.file))?;
+        if image.width as u32 != page.width || image.height as u32 != page.height{
+            return Err(format!("Atlas page {} is {}x{} but the metadata says {}x{}",page.file,image.width,image.height,page.width,page.height));
         }
-    });
-
-    let mut size = get_minimum_containing_square(images[0].1.width.max(images[1].1.height) as u32);
-    let mut root = ImageNode::Empty{width: size, height: size};
-    
-    for x in images{
-        let mut accum = x;
-        while let Some(x) = root.try_insert(accum) {
-            accum = x;
-            
-            size <<= 1;
-            root.grow_width(size);
-            root.grow_height(size);
+        let mut companions = HashMap::new();
+        for (channel, file) in &page.companions{
+            let companion = read_image(fs, &join_path(dir, file))?;
+            if companion.width != image.width || companion.height != image.height{
+                return Err(format!("Companion page {} doesn't match the size of {}",file,page.file));
+            }
+            companions.insert(channel.clone(), GlTexture::from_data(page.width, page.height, into_raw_bytes(companion.buffer)));
         }
+        pages.push(AtlasPage{
+            texture: GlTexture::from_data(page.width, page.height, into_raw_bytes(image.buffer)),
+            width: page.width,
+            height: page.height,
+            companions,
+        });
     }
-    let mut atlas_data = vec![RGBA{..Default::default()};(size*size) as usize];
-    let mut sprites = HashMap::new();
-    let mut output_texture = GlTexture::create_empty();
-    root.write_image(0, 0, &mut atlas_data, &mut sprites, &output_texture, size);
-
-    atlas_data.shrink_to_fit();
-    let len = atlas_data.len()*4;
-    let cap = atlas_data.capacity()*4;
-    let buf = atlas_data.as_mut_ptr() as *mut u8;
-    forget(atlas_data);
-
-    let raw_data = unsafe{Vec::from_raw_parts(buf,len,cap)};
-    
-    output_texture.set_data(size, size, raw_data);
-    //lodepng::encode32_file(current_dir().unwrap().join("atlas.png"), &atlas_data, size as usize, size as usize).unwrap();
-}
-
-fn get_minimum_containing_square(size: u32) -> u32{
-    if size.count_ones() == 1{
-        size
-    }
-    else{
-        let z = size.leading_zeros();
-        1u32 << (32u32 - z)
+
+    let mut atlas = TextureAtlas::from_pages(pages);
+    for (name, region) in metadata.regions{
+        atlas.insert_region(name, region);
     }
+    let name = match metadata_path.rfind('.'){
+        Some(x) if x > dir.len() => &metadata_path[..x],
+        _ => metadata_path
+    };
+    register_atlas_with_slices(atlas, name, &metadata.slices, resources)
 }
 
-fn create_sprite_name(path: &Path,root: &Path) -> String{
-    String::from(path.to_str().unwrap())
-    .replace(root.to_str().unwrap(), "")
-    .replace('\\', "/")
-}
-
-pub enum ImageNode {
-    Occupied{
-        image: (String,Bitmap<RGBA>),
-        left: Box<ImageNode>,
-        down: Box<ImageNode>
-    },
-    Empty{
-        width: u32,
-        height: u32,
+///
+/// Registers every sprite of the atlas under its name as a dyn Sprite, and the atlas itself under the given name
+pub fn register_atlas(atlas: TextureAtlas, name: &str, resources: &mut Resources) -> ResourceKey{
+    for (sprite_name, sprite) in atlas.sprites(){
+        add_sprite(resources, sprite.clone(), String::from(sprite_name));
     }
+    resources.add_resource(atlas, String::from(name))
 }
 
-impl ImageNode{
-    fn try_insert(&mut self, target: (String, Bitmap<RGBA>)) -> Option<(String,Bitmap<RGBA>)>{
-        match self {
-            ImageNode::Occupied { image:_, left, down } => {
-                let a = left.try_insert(target);
-                if a.is_some(){
-                    down.try_insert(a.unwrap())
-                }
-                else {
-                    None
-                }
-            }
-            ImageNode::Empty { width, height } => {
-                if *width >= target.1.width as u32 && *height >= target.1.height as u32{
-                    let remaining_width = *width - target.1.width as u32;
-                    let remaining_height = *height - target.1.height as u32;
-
-                    *self = ImageNode::Occupied{
-                        down: Box::new(ImageNode::Empty{width: target.1.width as u32, height: remaining_height}),
-                        left: Box::new(ImageNode::Empty{width: remaining_width, height: target.1.height as u32}),
-                        image: target
-                    };
-
-                    None
-                }
-                else{
-                    Some(target)        
-                }
+///
+/// Same as register_atlas, but sprites with a nine slice definition are registered as a NineSliceSprite
+pub fn register_atlas_with_slices(atlas: TextureAtlas, name: &str, slices: &[(String,NineSliceDefinition)], resources: &mut Resources) -> Result<ResourceKey,String>{
+    for (sprite_name, sprite) in atlas.sprites(){
+        match slices.iter().find(|(x,_)|x == sprite_name){
+            Some((_, definition)) => {
+                let sprite = NineSliceSprite::from_definition(sprite.clone(), definition).map_err(|e|format!("{} ({})",e,sprite_name))?;
+                add_sprite(resources, sprite, String::from(sprite_name));
+            },
+            None => {
+                add_sprite(resources, sprite.clone(), String::from(sprite_name));
             }
         }
     }
+    Ok(resources.add_resource(atlas, String::from(name)))
+}
 
-    fn grow_height(&mut self, amt: u32){
-        match self {
-            ImageNode::Occupied { image, left, down } => {
-                let nh = amt - image.1.height as u32;
-                left.grow_width(nh);
-                down.grow_width(nh);
-            }
-            ImageNode::Empty { width:_, height } => {
-                *height = (*height).max(amt);
+///
+/// Decodes every image under the root accepted by the options, paired with the name created for it
+pub fn load_images(root: &Path, options: &LoadOptions) -> Result<Vec<(String,Bitmap<RGBA>)>,String>{
+    load_images_from_fs(&OsFs::new(root), "", options)
+}
+
+///
+/// Decodes every image in the directory dir of the filesystem accepted by the options, paired with the name created for it
+/// Names are relative to dir
+pub fn load_images_from_fs(fs: &dyn VirtualFs, dir: &str, options: &LoadOptions) -> Result<Vec<(String,Bitmap<RGBA>)>,String>{
+    load_images_with_slices_from_fs(fs, dir, options).map(|x|x.images)
+}
+
+///
+/// Same as load_images_from_fs, but also returns the nine slice definitions found for the images, paired with the image name
+/// Nine patches have their marker border removed and the '.9' dropped from their name
+pub fn load_images_with_slices_from_fs(fs: &dyn VirtualFs, dir: &str, options: &LoadOptions) -> Result<LoadedImages,String>{
+    let mut loaded = LoadedImages::default();
+    let mut visited = Vec::new();
+    load_dir(fs, dir, "", 0, options, &mut visited, &mut loaded)?;
+    Ok(loaded)
+}
+
+///
+/// Decoded images paired with their names, and the nine slice definitions of the ones that have one
+#[derive(Default)]
+pub struct LoadedImages{
+    pub images: Vec<(String,Bitmap<RGBA>)>,
+    ///The filesystem path each image was read from, in the same order as images
+    pub paths: Vec<String>,
+    pub slices: Vec<(String,NineSliceDefinition)>,
+}
+
+fn load_dir(fs: &dyn VirtualFs, path: &str, relative: &str, depth: usize, options: &LoadOptions, visited: &mut Vec<PathBuf>, loaded: &mut LoadedImages) -> Result<(),String>{
+    if options.symlinks == SymlinkPolicy::Follow{
+        //guards against symlinks pointing back up the tree
+        if let Some(canonical) = fs.canonical_path(path){
+            if visited.contains(&canonical){
+                return Ok(());
             }
+            visited.push(canonical);
         }
     }
 
-    fn grow_width(&mut self, amt: u32){
-        match self {
-            ImageNode::Occupied { image, left, down } => {
-                let nw = amt - image.1.width as u32;
-                left.grow_width(nw);
-                down.grow_width(nw);
+    for entry in fs.read_dir(path)?{
+        if entry.is_symlink && options.symlinks == SymlinkPolicy::Skip{
+            continue;
+        }
+
+        let entry_path = join_path(path, &entry.name);
+        let entry_relative = join_path(relative, &entry.name);
+
+        if entry.is_dir{
+            if options.accepts_dir(&entry_relative, depth + 1){
+                load_dir(fs, &entry_path, &entry_relative, depth + 1, options, visited, loaded)?;
             }
-            ImageNode::Empty { width, height:_ } => {
-                *width = (*width).max(amt);
+        }
+        else if options.accepts_file(&entry_relative){
+            let mut image = read_image(fs, &entry_path)?;
+            let mut slice = None;
+            //the marker border is removed before processing, so layout changing processors don't see it
+            if is_nine_patch(&entry_path){
+                let (content, insets) = decode_nine_patch(&image).map_err(|e|format!("{} ({})",e,entry_path))?;
+                image = content;
+                slice = Some(NineSliceDefinition::new(insets));
             }
+            let sidecar = sidecar_path(&strip_nine_patch_marker(&entry_path));
+            if fs.exists(&sidecar){
+                let text = String::from_utf8(fs.read(&sidecar)?).map_err(|_|format!("Invalid slice file {}: not utf8",sidecar))?;
+                slice = Some(parse_slice_file(&text).map_err(|e|format!("{} ({})",e,sidecar))?);
+            }
+
+            //companion maps of the built-in channels next to their image only get the layout changes, like AtlasBuilder::with_pipeline
+            let is_companion = AtlasChannel::builtin().iter().filter_map(|x|x.base_name(&entry_path)).any(|x|fs.exists(&x));
+            let pipeline = options.pipeline_for(&entry_relative);
+            //the insets are measured on the unprocessed image, moving or resizing its pixels would make them wrong
+            if slice.is_some() && pipeline.changes_layout(){
+                return Err(format!("{} has nine slice insets, which can't be combined with the layout changing processors {:?}",entry_path,pipeline));
+            }
+            let image = if is_companion { pipeline.process_layout(image) } else { pipeline.process(image) };
+            let image = image.map_err(|e|format!("Failed to process {}: {}",entry_path,e))?;
+            let name = options.create_name(&strip_nine_patch_marker(&entry_relative));
+            if let Some(slice) = slice{
+                loaded.slices.push((name.clone(), slice));
+            }
+            loaded.images.push((name, image));
+            loaded.paths.push(entry_path);
         }
     }
+    Ok(())
+}
 
-    fn write_image(self, x: u32, y: u32, out: &mut Vec<RGBA>, sprites: &mut HashMap<String,UvSprite>, texture: &GlTexture, size: u32){
-        match self{
-            ImageNode::Occupied { image, left, down } => {
-
-                for px in 0..image.1.width as u32{
-                    for py in 0..image.1.height as u32{
-                        out[((px + x) + (py + y) * size) as usize] = image.1.buffer[(px + py * image.1.width as u32) as usize].clone();
-                    }
-                }
-
-                
-                let fsize = size as f32;
-                
-                let half_pixel = 1.0/(fsize*16.0);
+fn join_path(dir: &str, name: &str) -> String{
+    if dir.is_empty(){
+        String::from(name)
+    }
+    else{
+        format!("{}/{}",dir,name)
+    }
+}
 
-                let min_x = (x as f32)/fsize;
-                let min_y = (y as f32)/fsize;
-                let w = (image.1.width as f32)/fsize;
-                let h = (image.1.height as f32)/fsize;
-                let max_x = min_x + w;
-                let max_y = min_y + h;
+fn read_image(fs: &dyn VirtualFs, path: &str) -> Result<Bitmap<RGBA>,String>{
+    let bytes = fs.read(path)?;
+    lodepng::decode32(&bytes).map_err(|e|format!("Failed to load file {}: {}",path,e))
+}
 
+pub(crate) fn into_raw_bytes(mut data: Vec<RGBA>) -> Vec<u8>{
+    data.shrink_to_fit();
+    let len = data.len()*4;
+    let cap = data.capacity()*4;
+    let buf = data.as_mut_ptr() as *mut u8;
+    forget(data);
 
-                sprites.insert(image.0, UvSprite::new(min_x + half_pixel, min_y + half_pixel, max_x - half_pixel, max_y - half_pixel, texture.clone()));
+    unsafe{Vec::from_raw_parts(buf,len,cap)}
+}
 
-                left.write_image(x + image.1.width as u32, y, out, sprites, texture, size);
-                down.write_image(x, y + image.1.height as u32, out, sprites, texture, size);
-            },
-            ImageNode::Empty { width:_, height:_ } => {
-                
-            },
-        }
-    }
-}
\ No newline at end of file
+fn create_sprite_name(path: &Path,root: &Path) -> String{
+    relative_path(path, root).unwrap_or_else(||path.to_string_lossy().replace('\\', "/"))
+}
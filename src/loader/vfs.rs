@@ -0,0 +1,625 @@
+use std::{collections::BTreeMap, fs::File, io::{Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}, sync::Mutex, time::SystemTime};
+
+///
+/// A single entry returned when listing a directory of a virtual filesystem
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct VfsEntry{
+    pub name: String,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+}
+
+///
+/// A source of asset files
+/// Paths are '/' separated and relative to the root of the filesystem, the root itself is the empty string
+pub trait VirtualFs{
+    fn read(&self, path: &str) -> Result<Vec<u8>,String>;
+
+    fn exists(&self, path: &str) -> bool;
+
+    fn is_dir(&self, path: &str) -> bool;
+
+    ///Lists the entries directly inside the directory, sorted by name
+    fn read_dir(&self, path: &str) -> Result<Vec<VfsEntry>,String>;
+
+    ///A path uniquely identifying the directory, used to detect symlink cycles when walking the filesystem
+    fn canonical_path(&self, _path: &str) -> Option<PathBuf>{
+        None
+    }
+
+    ///When the file was last modified, None if the filesystem doesn't track it
+    fn modified(&self, _path: &str) -> Option<SystemTime>{
+        None
+    }
+}
+
+///
+/// Joins the path with '/' and resolves "." and ".." segments
+/// ".." never goes above the root, so a path can't reach files outside of the filesystem
+fn normalize(path: &str) -> String{
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split(['/', '\\']){
+        match segment{
+            "" | "." => {},
+            ".." => {
+                segments.pop();
+            },
+            x => segments.push(x),
+        }
+    }
+    segments.join("/")
+}
+
+fn join(dir: &str, name: &str) -> String{
+    if dir.is_empty(){
+        String::from(name)
+    }
+    else{
+        format!("{}/{}",dir,name)
+    }
+}
+
+///
+/// Files from a directory on the os filesystem
+pub struct OsFs{
+    root: PathBuf,
+}
+
+impl OsFs{
+    pub fn new(root: &Path) -> Self{
+        Self{
+            root: root.to_path_buf()
+        }
+    }
+
+    pub fn root(&self) -> &Path{
+        &self.root
+    }
+
+    fn resolve(&self, path: &str) -> PathBuf{
+        let mut out = self.root.clone();
+        out.extend(normalize(path).split('/').filter(|x|!x.is_empty()));
+        out
+    }
+}
+
+impl VirtualFs for OsFs{
+    fn read(&self, path: &str) -> Result<Vec<u8>,String> {
+        let full = self.resolve(path);
+        std::fs::read(&full).map_err(|e|format!("Failed to read file {}: {}",full.display(),e))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.resolve(path).exists()
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        self.resolve(path).is_dir()
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<VfsEntry>,String> {
+        let full = self.resolve(path);
+        let mut out = Vec::new();
+        let entries = full.read_dir().map_err(|e|format!("Failed to read directory {}: {}",full.display(),e))?;
+        for entry in entries{
+            let entry = entry.map_err(|e|format!("Failed to read directory {}: {}",full.display(),e))?;
+            let file_type = entry.file_type().map_err(|e|format!("Failed to read {}: {}",entry.path().display(),e))?;
+            out.push(VfsEntry{
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: entry.path().is_dir(),
+                is_symlink: file_type.is_symlink(),
+            });
+        }
+        //read_dir order is platform dependent, sorting keeps atlas layouts reproducible
+        out.sort_by(|a,b|a.name.cmp(&b.name));
+        Ok(out)
+    }
+
+    fn canonical_path(&self, path: &str) -> Option<PathBuf> {
+        self.resolve(path).canonicalize().ok()
+    }
+
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        std::fs::metadata(self.resolve(path)).and_then(|x|x.modified()).ok()
+    }
+}
+
+///
+/// Lists the direct children of dir from a sorted set of file paths
+fn list_paths<'a>(paths: impl Iterator<Item = &'a str>, dir: &str) -> Result<Vec<VfsEntry>,String>{
+    let dir = normalize(dir);
+    let prefix = if dir.is_empty(){ String::new() } else { format!("{}/",dir) };
+    let mut out: Vec<VfsEntry> = Vec::new();
+    let mut found = dir.is_empty();
+
+    for path in paths{
+        let rest = match path.strip_prefix(prefix.as_str()){
+            Some(x) => x,
+            None => continue
+        };
+        found = true;
+        let (name, is_dir) = match rest.find('/'){
+            Some(x) => (&rest[..x], true),
+            None => (rest, false)
+        };
+        if out.last().is_none_or(|x|x.name != name){
+            out.push(VfsEntry{
+                name: String::from(name),
+                is_dir,
+                is_symlink: false
+            });
+        }
+    }
+
+    if found{
+        out.sort_by(|a,b|a.name.cmp(&b.name));
+        Ok(out)
+    }
+    else{
+        Err(format!("Failed to read directory {}: no such directory",dir))
+    }
+}
+
+fn has_dir<'a>(mut paths: impl Iterator<Item = &'a str>, dir: &str) -> bool{
+    let dir = normalize(dir);
+    dir.is_empty() || paths.any(|x|x.len() > dir.len() && x.starts_with(dir.as_str()) && x.as_bytes()[dir.len()] == b'/')
+}
+
+///
+/// Files kept in memory, useful for embedded or downloaded assets
+#[derive(Default)]
+pub struct MemoryFs{
+    files: BTreeMap<String,Vec<u8>>,
+}
+
+impl MemoryFs{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    pub fn insert(&mut self, path: &str, data: Vec<u8>){
+        self.files.insert(normalize(path), data);
+    }
+
+    pub fn with_file(mut self, path: &str, data: Vec<u8>) -> Self{
+        self.insert(path, data);
+        self
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<Vec<u8>>{
+        self.files.remove(&normalize(path))
+    }
+}
+
+impl VirtualFs for MemoryFs{
+    fn read(&self, path: &str) -> Result<Vec<u8>,String> {
+        self.files.get(&normalize(path)).cloned().ok_or_else(||format!("Failed to read file {}: no such file",path))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(&normalize(path)) || self.is_dir(path)
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        has_dir(self.files.keys().map(|x|x.as_str()), path)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<VfsEntry>,String> {
+        list_paths(self.files.keys().map(|x|x.as_str()), path)
+    }
+}
+
+const PACK_MAGIC: &[u8;8] = b"R2DPACK\0";
+const PACK_VERSION: u32 = 1;
+
+///
+/// Location of a file inside the data section of a pack
+#[derive(Clone,Copy)]
+struct PackEntry{
+    offset: u64,
+    size: u64,
+}
+
+enum PackData{
+    File(Mutex<File>),
+    Memory(Vec<u8>),
+}
+
+///
+/// Files stored in a single uncompressed pack, as written by PackWriter
+///
+/// The layout is the magic "R2DPACK\0", a little endian u32 version and u32 entry count,
+/// followed by the entries as (u32 path length, utf8 path, u64 offset, u64 size) and the file data.
+/// Offsets are relative to the start of the data section
+pub struct PackFs{
+    entries: BTreeMap<String,PackEntry>,
+    data_start: u64,
+    data: PackData,
+}
+
+impl PackFs{
+    ///Opens a pack file, only the index is read up front
+    pub fn open(path: &Path) -> Result<Self,String>{
+        let mut file = File::open(path).map_err(|e|format!("Failed to open pack {}: {}",path.display(),e))?;
+        let size = file.metadata().map_err(|e|format!("Failed to open pack {}: {}",path.display(),e))?.len();
+        let (entries, data_start) = Self::read_index(&mut file, size).map_err(|e|format!("Invalid pack {}: {}",path.display(),e))?;
+        Ok(Self{
+            entries,
+            data_start,
+            data: PackData::File(Mutex::new(file)),
+        })
+    }
+
+    ///Reads a pack from memory, for example from include_bytes!
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self,String>{
+        let (entries, data_start) = Self::read_index(&mut std::io::Cursor::new(&bytes), bytes.len() as u64).map_err(|e|format!("Invalid pack: {}",e))?;
+        Ok(Self{
+            entries,
+            data_start,
+            data: PackData::Memory(bytes),
+        })
+    }
+
+    ///
+    /// Reads the index of a pack of the given size in bytes, every length and entry is checked against it
+    /// so a corrupt or truncated pack is rejected before anything is allocated from it
+    fn read_index(reader: &mut impl Read, size: u64) -> Result<(BTreeMap<String,PackEntry>,u64),String>{
+        let mut magic = [0u8;8];
+        read_exact(reader, &mut magic)?;
+        if &magic != PACK_MAGIC{
+            return Err(String::from("wrong magic number"));
+        }
+        let version = read_u32(reader)?;
+        if version != PACK_VERSION{
+            return Err(format!("unsupported version {}",version));
+        }
+
+        let count = read_u32(reader)?;
+        let mut position = 16u64;
+        let mut entries = BTreeMap::new();
+        for _ in 0..count{
+            let len = read_u32(reader)? as u64;
+            let end = position.checked_add(4 + len + 16).filter(|x|*x <= size).ok_or_else(||String::from("index is out of bounds"))?;
+            let mut name = vec![0u8;len as usize];
+            read_exact(reader, &mut name)?;
+            let name = String::from_utf8(name).map_err(|_|String::from("path is not valid utf8"))?;
+            let offset = read_u64(reader)?;
+            let entry_size = read_u64(reader)?;
+            position = end;
+            entries.insert(name, PackEntry{
+                offset,
+                size: entry_size
+            });
+        }
+
+        let data_size = size - position;
+        for (name, entry) in &entries{
+            if entry.offset.checked_add(entry.size).is_none_or(|x|x > data_size){
+                return Err(format!("entry {} is out of bounds",name));
+            }
+        }
+        Ok((entries, position))
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str>{
+        self.entries.keys().map(|x|x.as_str())
+    }
+}
+
+impl VirtualFs for PackFs{
+    fn read(&self, path: &str) -> Result<Vec<u8>,String> {
+        let entry = *self.entries.get(&normalize(path)).ok_or_else(||format!("Failed to read file {}: no such file",path))?;
+        //read_index checked every entry against the size of the pack
+        let start = self.data_start + entry.offset;
+        match &self.data{
+            PackData::File(file) => {
+                let mut file = file.lock().map_err(|_|format!("Failed to read file {}: pack is poisoned",path))?;
+                let mut out = vec![0u8;entry.size as usize];
+                file.seek(SeekFrom::Start(start)).map_err(|e|format!("Failed to read file {}: {}",path,e))?;
+                file.read_exact(&mut out).map_err(|e|format!("Failed to read file {}: {}",path,e))?;
+                Ok(out)
+            },
+            PackData::Memory(bytes) => {
+                bytes.get(start as usize..(start + entry.size) as usize)
+                .map(|x|x.to_vec())
+                .ok_or_else(||format!("Failed to read file {}: entry is out of bounds",path))
+            }
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.entries.contains_key(&normalize(path)) || self.is_dir(path)
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        has_dir(self.paths(), path)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<VfsEntry>,String> {
+        list_paths(self.paths(), path)
+    }
+}
+
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> Result<(),String>{
+    reader.read_exact(buf).map_err(|e|e.to_string())
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32,String>{
+    let mut buf = [0u8;4];
+    read_exact(reader, &mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64,String>{
+    let mut buf = [0u8;8];
+    read_exact(reader, &mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+///
+/// Builds pack files readable by PackFs
+#[derive(Default)]
+pub struct PackWriter{
+    files: BTreeMap<String,Vec<u8>>,
+}
+
+impl PackWriter{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    pub fn add_file(&mut self, path: &str, data: Vec<u8>){
+        self.files.insert(normalize(path), data);
+    }
+
+    ///
+    /// Adds every file of the virtual filesystem below dir, stored under prefix
+    pub fn add_dir(&mut self, fs: &dyn VirtualFs, dir: &str, prefix: &str) -> Result<(),String>{
+        for entry in fs.read_dir(dir)?{
+            let path = join(dir, &entry.name);
+            let target = join(prefix, &entry.name);
+            if entry.is_dir{
+                self.add_dir(fs, &path, &target)?;
+            }
+            else{
+                self.add_file(&target, fs.read(&path)?);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> Result<(),String>{
+        let mut header = Vec::new();
+        header.extend_from_slice(PACK_MAGIC);
+        header.extend_from_slice(&PACK_VERSION.to_le_bytes());
+        header.extend_from_slice(&(self.files.len() as u32).to_le_bytes());
+
+        let mut offset = 0u64;
+        for (path, data) in &self.files{
+            header.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            header.extend_from_slice(path.as_bytes());
+            header.extend_from_slice(&offset.to_le_bytes());
+            header.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            offset += data.len() as u64;
+        }
+
+        out.write_all(&header).map_err(|e|format!("Failed to write pack: {}",e))?;
+        for data in self.files.values(){
+            out.write_all(data).map_err(|e|format!("Failed to write pack: {}",e))?;
+        }
+        Ok(())
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> Result<(),String>{
+        let mut file = File::create(path).map_err(|e|format!("Failed to create pack {}: {}",path.display(),e))?;
+        self.write(&mut file)
+    }
+}
+
+///
+/// Several filesystems mounted on top of each other
+/// Files in later mounts override files with the same path in earlier mounts, which lets mods replace base assets
+#[derive(Default)]
+pub struct MountedFs{
+    mounts: Vec<Box<dyn VirtualFs>>,
+}
+
+impl MountedFs{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    ///Mounts a filesystem with a higher priority than every existing mount
+    pub fn mount(&mut self, fs: Box<dyn VirtualFs>){
+        self.mounts.push(fs);
+    }
+
+    pub fn with_mount(mut self, fs: Box<dyn VirtualFs>) -> Self{
+        self.mount(fs);
+        self
+    }
+
+    fn by_priority(&self) -> impl Iterator<Item = &Box<dyn VirtualFs>>{
+        self.mounts.iter().rev()
+    }
+}
+
+impl VirtualFs for MountedFs{
+    fn read(&self, path: &str) -> Result<Vec<u8>,String> {
+        match self.by_priority().find(|x|x.exists(path) && !x.is_dir(path)){
+            Some(x) => x.read(path),
+            None => Err(format!("Failed to read file {}: no such file",path))
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.mounts.iter().any(|x|x.exists(path))
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        self.mounts.iter().any(|x|x.is_dir(path))
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<VfsEntry>,String> {
+        let mut merged: BTreeMap<String,VfsEntry> = BTreeMap::new();
+        let mut found = false;
+        for fs in self.by_priority(){
+            if !fs.is_dir(path){
+                continue;
+            }
+            found = true;
+            for entry in fs.read_dir(path)?{
+                merged.entry(entry.name.clone()).or_insert(entry);
+            }
+        }
+
+        if found{
+            Ok(merged.into_values().collect())
+        }
+        else{
+            Err(format!("Failed to read directory {}: no such directory",path))
+        }
+    }
+
+    fn canonical_path(&self, path: &str) -> Option<PathBuf> {
+        self.by_priority().find(|x|x.is_dir(path)).and_then(|x|x.canonical_path(path))
+    }
+
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        self.by_priority().find(|x|x.exists(path) && !x.is_dir(path)).and_then(|x|x.modified(path))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn names(entries: Vec<VfsEntry>) -> Vec<String>{
+        entries.into_iter().map(|x|x.name).collect()
+    }
+
+    fn sample() -> MemoryFs{
+        MemoryFs::new()
+            .with_file("sprites/player.png", vec![1, 2, 3])
+            .with_file("sprites/ui/button.png", vec![4])
+            .with_file("shaders/sprite.vert", b"void main(){}".to_vec())
+    }
+
+    #[test]
+    fn normalize_resolves_dots_without_leaving_the_root(){
+        assert_eq!(normalize("a/./b//c"), "a/b/c");
+        assert_eq!(normalize("a\\b\\c"), "a/b/c");
+        assert_eq!(normalize("a/b/../c"), "a/c");
+        assert_eq!(normalize("../../secret.txt"), "secret.txt");
+        assert_eq!(normalize("a/../../b"), "b");
+        assert_eq!(normalize("/"), "");
+    }
+
+    #[test]
+    fn memory_fs_reads_and_lists(){
+        let fs = sample();
+        assert_eq!(fs.read("sprites/player.png").unwrap(), vec![1, 2, 3]);
+        assert_eq!(fs.read("./sprites/../sprites/ui/button.png").unwrap(), vec![4]);
+        assert!(fs.read("sprites").is_err());
+        assert!(fs.is_dir("sprites/ui") && !fs.is_dir("sprites/player.png"));
+        assert!(fs.exists("shaders") && !fs.exists("missing"));
+        assert_eq!(names(fs.read_dir("").unwrap()), ["shaders", "sprites"]);
+        assert_eq!(names(fs.read_dir("sprites").unwrap()), ["player.png", "ui"]);
+        assert!(fs.read_dir("sprites").unwrap()[1].is_dir);
+        assert!(fs.read_dir("missing").is_err());
+    }
+
+    #[test]
+    fn memory_fs_keeps_paths_inside_the_root(){
+        let fs = MemoryFs::new().with_file("../outside.txt", vec![7]);
+        assert_eq!(fs.read("outside.txt").unwrap(), vec![7]);
+        assert_eq!(fs.read("sprites/../../outside.txt").unwrap(), vec![7]);
+    }
+
+    #[test]
+    fn pack_round_trip(){
+        let mut writer = PackWriter::new();
+        writer.add_dir(&sample(), "", "base").unwrap();
+        writer.add_file("empty.bin", Vec::new());
+        let mut bytes = Vec::new();
+        writer.write(&mut bytes).unwrap();
+
+        let pack = PackFs::from_bytes(bytes).unwrap();
+        assert_eq!(pack.paths().collect::<Vec<_>>(), ["base/shaders/sprite.vert", "base/sprites/player.png", "base/sprites/ui/button.png", "empty.bin"]);
+        assert_eq!(pack.read("base/sprites/player.png").unwrap(), vec![1, 2, 3]);
+        assert_eq!(pack.read("base/shaders/sprite.vert").unwrap(), b"void main(){}");
+        assert!(pack.read("empty.bin").unwrap().is_empty());
+        assert_eq!(names(pack.read_dir("base/sprites").unwrap()), ["player.png", "ui"]);
+        assert!(pack.is_dir("base") && !pack.exists("sprites"));
+    }
+
+    #[test]
+    fn pack_file_round_trip(){
+        let path = std::env::temp_dir().join(format!("render-2d-vfs-test-{}.pack",std::process::id()));
+        let mut writer = PackWriter::new();
+        writer.add_dir(&sample(), "sprites", "").unwrap();
+        writer.write_to_file(&path).unwrap();
+        let pack = PackFs::open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let pack = pack.unwrap();
+        assert_eq!(pack.read("player.png").unwrap(), vec![1, 2, 3]);
+        assert_eq!(pack.read("ui/button.png").unwrap(), vec![4]);
+    }
+
+    #[test]
+    fn corrupt_packs_are_rejected(){
+        let mut writer = PackWriter::new();
+        writer.add_file("a.png", vec![1, 2, 3, 4]);
+        let mut bytes = Vec::new();
+        writer.write(&mut bytes).unwrap();
+
+        //truncated data
+        assert!(PackFs::from_bytes(bytes[..bytes.len() - 1].to_vec()).is_err());
+        //truncated index
+        assert!(PackFs::from_bytes(bytes[..20].to_vec()).is_err());
+
+        //huge path length
+        let mut huge_path = bytes.clone();
+        huge_path[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(PackFs::from_bytes(huge_path).is_err());
+
+        //offset + size overflows
+        let mut overflow = bytes.clone();
+        let offset = 16 + 4 + "a.png".len();
+        overflow[offset..offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(PackFs::from_bytes(overflow).is_err());
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(PackFs::from_bytes(wrong_magic).is_err());
+
+        assert!(PackFs::from_bytes(bytes).is_ok());
+    }
+
+    #[test]
+    fn later_mounts_override_earlier_ones(){
+        let base = MemoryFs::new()
+            .with_file("sprites/player.png", vec![1])
+            .with_file("sprites/enemy.png", vec![2]);
+        let modded = MemoryFs::new()
+            .with_file("sprites/player.png", vec![10])
+            .with_file("sprites/extra.png", vec![3]);
+        let fs = MountedFs::new().with_mount(Box::new(base)).with_mount(Box::new(modded));
+
+        assert_eq!(fs.read("sprites/player.png").unwrap(), vec![10]);
+        assert_eq!(fs.read("sprites/enemy.png").unwrap(), vec![2]);
+        assert_eq!(fs.read("sprites/extra.png").unwrap(), vec![3]);
+        assert_eq!(names(fs.read_dir("sprites").unwrap()), ["enemy.png", "extra.png", "player.png"]);
+        assert!(fs.read("missing.png").is_err());
+        assert!(fs.read_dir("missing").is_err());
+    }
+
+    #[test]
+    fn a_directory_does_not_hide_a_file_of_a_lower_mount(){
+        let base = MemoryFs::new().with_file("data", vec![1]);
+        let modded = MemoryFs::new().with_file("data/inner.txt", vec![2]);
+        let fs = MountedFs::new().with_mount(Box::new(base)).with_mount(Box::new(modded));
+        assert_eq!(fs.read("data").unwrap(), vec![1]);
+    }
+}
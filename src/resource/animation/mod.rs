@@ -0,0 +1,185 @@
+use crate::resource::{ResourceKey, Resources, sprite::Sprite};
+
+///
+/// How an animation continues once it reaches its last frame
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum AnimationMode{
+    ///Starts over from the first frame
+    Loop,
+    ///Plays backwards to the first frame, then forwards again
+    PingPong,
+    ///Stops on the last frame
+    Once,
+}
+
+#[derive(Clone,Debug)]
+pub struct AnimationFrame{
+    ///Key of the sprite resource shown during the frame
+    pub sprite: ResourceKey,
+    ///Duration of the frame in seconds
+    pub duration: f32,
+    ///Frames with a tag raise an event when they are entered
+    pub tag: Option<String>,
+}
+
+///
+/// An ordered list of sprite frames making up an animation clip
+#[derive(Clone,Debug)]
+pub struct SpriteAnimation{
+    frames: Vec<AnimationFrame>,
+    mode: AnimationMode,
+}
+
+impl SpriteAnimation{
+    pub fn new(mode: AnimationMode) -> Self{
+        Self{
+            frames: Vec::new(),
+            mode
+        }
+    }
+
+    pub fn with_frame(mut self, sprite: ResourceKey, duration: f32) -> Self{
+        self.push_frame(AnimationFrame{
+            sprite,
+            duration,
+            tag: None
+        });
+        self
+    }
+
+    pub fn with_tagged_frame(mut self, sprite: ResourceKey, duration: f32, tag: String) -> Self{
+        self.push_frame(AnimationFrame{
+            sprite,
+            duration,
+            tag: Some(tag)
+        });
+        self
+    }
+
+    pub fn push_frame(&mut self, frame: AnimationFrame){
+        self.frames.push(frame);
+    }
+
+    ///Tags the frame at the index, returns false if there is no such frame
+    pub fn set_tag(&mut self, frame: usize, tag: Option<String>) -> bool{
+        match self.frames.get_mut(frame){
+            Some(x) => {
+                x.tag = tag;
+                true
+            },
+            None => false
+        }
+    }
+
+    ///
+    /// Creates a clip from sprites named by the convention '<prefix><number>', such as 'walk_00' to 'walk_11'
+    /// The number may be followed by a file extension, frames are ordered by their number
+    /// Only resources stored as dyn Sprite are used
+    pub fn from_numbered_sprites(resources: &Resources, prefix: &str, frame_duration: f32, mode: AnimationMode) -> Result<Self,String>{
+        let mut numbered = resources.resource_names().into_iter()
+            .filter_map(|name|{
+                let rest = name.strip_prefix(prefix)?;
+                let digits = rest.split('.').next().unwrap();
+                if digits.is_empty() || !digits.bytes().all(|x|x.is_ascii_digit()){
+                    return None;
+                }
+                //only a single extension may follow the number
+                if rest.len() > digits.len() && rest[digits.len() + 1..].contains('.'){
+                    return None;
+                }
+                //other resources, such as the atlas of the sprites, may share the naming scheme
+                let key = resources.find_resource_key(name).filter(|x|x.is::<dyn Sprite>())?;
+                Some((digits.parse::<u64>().ok()?, key))
+            })
+            .collect::<Vec<_>>();
+        if numbered.is_empty(){
+            return Err(format!("No sprites named {}<number> found",prefix));
+        }
+        numbered.sort();
+
+        let mut out = Self::new(mode);
+        for (_, key) in numbered{
+            out = out.with_frame(key, frame_duration);
+        }
+        Ok(out)
+    }
+
+    pub fn frames(&self) -> &[AnimationFrame]{
+        &self.frames
+    }
+
+    pub fn mode(&self) -> AnimationMode{
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: AnimationMode){
+        self.mode = mode;
+    }
+
+    pub fn len(&self) -> usize{
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.frames.is_empty()
+    }
+
+    ///Duration of a single pass through every frame, in seconds
+    pub fn duration(&self) -> f32{
+        self.frames.iter().map(|x|x.duration).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::resource::{sprite::{SpriteQuad, UvRect}, texture::GlTexture};
+
+    use super::*;
+
+    //textures delete themselves through gl when dropped, which needs a context
+    struct TestSprite(&'static GlTexture);
+
+    impl Sprite for TestSprite{
+        fn quad(&self) -> SpriteQuad<'_> {
+            SpriteQuad{
+                texture: self.0,
+                uv: UvRect::default(),
+                width: 1.0,
+                height: 1.0,
+                pivot_x: 0.0,
+                pivot_y: 0.0,
+                rotated: false,
+                offset_x: 0.0,
+                offset_y: 0.0,
+                source_width: 1.0,
+                source_height: 1.0,
+            }
+        }
+    }
+
+    fn add_sprite(resources: &mut Resources, name: &str) -> ResourceKey{
+        resources.add_boxed_resource::<dyn Sprite>(Box::new(TestSprite(Box::leak(Box::default()))), String::from(name))
+    }
+
+    #[test]
+    fn numbered_sprites_are_ordered_by_number(){
+        let mut resources = Resources::new();
+        resources.register_type::<dyn Sprite>();
+        resources.register_type::<u32>();
+        let ten = add_sprite(&mut resources, "walk_10.png");
+        let two = add_sprite(&mut resources, "walk_2.png");
+        let one = add_sprite(&mut resources, "walk_1");
+        add_sprite(&mut resources, "walk_x.png");
+        add_sprite(&mut resources, "walk_3.png.bak");
+        add_sprite(&mut resources, "run_4.png");
+        resources.add_resource(5u32, String::from("walk_5"));
+
+        let animation = SpriteAnimation::from_numbered_sprites(&resources, "walk_", 0.1, AnimationMode::Loop).unwrap();
+        let frames = animation.frames().iter().map(|x|x.sprite).collect::<Vec<_>>();
+        assert_eq!(frames, vec![one, two, ten]);
+        assert_eq!(animation.mode(), AnimationMode::Loop);
+        assert!((animation.duration() - 0.3).abs() < 1e-6);
+
+        assert!(SpriteAnimation::from_numbered_sprites(&resources, "jump_", 0.1, AnimationMode::Loop).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use std::mem::size_of;
+
+use super::buffer::{BufferUsage, GlBuffer};
+
+pub mod order;
+
+///Number of quads a batch holds before it has to be flushed, keeps every index within u16
+pub const MAX_BATCH_QUADS: usize = 4096;
+
+///
+/// A vertex of the built-in sprite layout, a_position (0), a_uv (1) and a_color (2)
+/// The z of the position is the depth of the sprite, see order::draw_depth
+#[repr(C)]
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct SpriteVertex{
+    pub position: [f32;3],
+    pub uv: [f32;2],
+    pub color: [f32;4],
+}
+
+struct RawVertexArray{
+    id: gl::types::GLuint,
+}
+
+unsafe impl Send for RawVertexArray{}
+unsafe impl Sync for RawVertexArray{}
+
+impl RawVertexArray{
+    fn new() -> Self{
+        let id = unsafe{
+            let mut out = 0;
+            gl::CreateVertexArrays(1, &mut out);
+            out
+        };
+
+        Self{
+            id
+        }
+    }
+}
+
+impl Drop for RawVertexArray{
+    fn drop(&mut self) {
+        unsafe{
+            gl::DeleteVertexArrays(1, &self.id);
+        }
+    }
+}
+
+///
+/// Collects quads into a streaming vertex buffer and draws them with a single call when flushed
+/// The batch doesn't track shaders or textures, whatever is bound when flush is called is drawn with
+/// so the caller flushes before changing state, see SpriteDrawSystem
+pub struct SpriteBatch{
+    vertex_array: RawVertexArray,
+    vertex_buffer: GlBuffer,
+    ///Kept alive for the vertex array, which reads the indices from it
+    _index_buffer: GlBuffer,
+    vertices: Vec<SpriteVertex>,
+    draw_calls: usize,
+}
+
+impl SpriteBatch{
+    ///Creates the buffers and vertex array, needs a current gl context
+    pub fn new() -> Self{
+        let mut indices = Vec::with_capacity(MAX_BATCH_QUADS * 6);
+        for quad in 0..MAX_BATCH_QUADS as u16{
+            let first = quad * 4;
+            indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+        }
+        let index_buffer = GlBuffer::new();
+        index_buffer.set_data(as_bytes(&indices), BufferUsage::Static);
+        let vertex_buffer = GlBuffer::with_size(MAX_BATCH_QUADS * 4 * size_of::<SpriteVertex>(), BufferUsage::Stream);
+
+        let vertex_array = RawVertexArray::new();
+        unsafe{
+            gl::VertexArrayVertexBuffer(vertex_array.id, 0, vertex_buffer.id(), 0, size_of::<SpriteVertex>() as i32);
+            gl::VertexArrayElementBuffer(vertex_array.id, index_buffer.id());
+            for (location, components, offset) in [(0, 3, 0), (1, 2, 12), (2, 4, 20)]{
+                gl::EnableVertexArrayAttrib(vertex_array.id, location);
+                gl::VertexArrayAttribFormat(vertex_array.id, location, components, gl::FLOAT, gl::FALSE, offset);
+                gl::VertexArrayAttribBinding(vertex_array.id, location, 0);
+            }
+        }
+
+        Self{
+            vertex_array,
+            vertex_buffer,
+            _index_buffer: index_buffer,
+            vertices: Vec::with_capacity(MAX_BATCH_QUADS * 4),
+            draw_calls: 0,
+        }
+    }
+
+    ///Number of quads waiting to be drawn
+    pub fn len(&self) -> usize{
+        self.vertices.len() / 4
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.vertices.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.len() >= MAX_BATCH_QUADS
+    }
+
+    ///
+    /// Adds a quad with its corners in the order top left, top right, bottom right, bottom left
+    /// A full batch is flushed first, drawing the quads added before with the current state
+    pub fn push_quad(&mut self, corners: [SpriteVertex;4]){
+        if self.is_full(){
+            self.flush();
+        }
+        self.vertices.extend_from_slice(&corners);
+    }
+
+    ///
+    /// Uploads the collected quads and draws them with the bound shader and textures, then empties the batch
+    pub fn flush(&mut self){
+        if self.vertices.is_empty(){
+            return;
+        }
+        //respecifying the storage lets the driver hand out fresh memory instead of waiting for the previous draw
+        self.vertex_buffer.set_data(as_bytes(&self.vertices), BufferUsage::Stream);
+        unsafe{
+            gl::BindVertexArray(self.vertex_array.id);
+            gl::DrawElements(gl::TRIANGLES, (self.len() * 6) as i32, gl::UNSIGNED_SHORT, std::ptr::null());
+            gl::BindVertexArray(0);
+        }
+        self.vertices.clear();
+        self.draw_calls += 1;
+    }
+
+    ///Number of flushes that drew something since the last call
+    pub fn take_draw_calls(&mut self) -> usize{
+        std::mem::take(&mut self.draw_calls)
+    }
+}
+
+impl Default for SpriteBatch{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn as_bytes<T: Copy>(data: &[T]) -> &[u8]{
+    unsafe{std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))}
+}
@@ -0,0 +1,212 @@
+use std::cmp::Ordering;
+
+use crate::resource::ResourceKey;
+
+///
+/// Whether sprites of a layer are ordered by their y position on top of their z-index
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum YSort{
+    #[default]
+    Disabled,
+    ///For worlds whose y axis points down, sprites with a larger y are drawn in front
+    Down,
+    ///For worlds whose y axis points up, sprites with a smaller y are drawn in front
+    Up,
+}
+
+///
+/// How the sprites of a render layer are ordered and drawn
+/// Transparent layers are drawn back to front with blending, opaque layers front to back with depth testing
+/// so covered pixels are rejected before they are shaded, which is only correct for sprites without transparent pixels
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct LayerSettings{
+    pub y_sort: YSort,
+    pub opaque: bool,
+}
+
+impl LayerSettings{
+    pub fn transparent() -> Self{
+        Self::default()
+    }
+
+    pub fn opaque() -> Self{
+        Self{
+            opaque: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_y_sort(mut self, y_sort: YSort) -> Self{
+        self.y_sort = y_sort;
+        self
+    }
+}
+
+///
+/// Where a sprite is drawn relative to the others, lower keys are drawn first and end up behind
+/// Ordered by layer, z-index and y, ties are broken by material and texture so equal sprites end up in the same batch
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct DrawKey{
+    pub layer: i32,
+    pub z_index: i32,
+    ///Y position the layer sorts by, 0 for layers without y sorting
+    pub y: f32,
+    pub material: Option<ResourceKey>,
+    pub texture: gl::types::GLuint,
+}
+
+impl DrawKey{
+    ///
+    /// Builds the key of a sprite at the y position, which only counts if the layer sorts by it
+    pub fn new(layer: i32, z_index: i32, y: f32, settings: &LayerSettings, material: Option<ResourceKey>, texture: gl::types::GLuint) -> Self{
+        let y = match settings.y_sort{
+            YSort::Disabled => 0.0,
+            //total_cmp puts negative NaNs first, every NaN is turned into the positive one that sorts last
+            _ if y.is_nan() => f32::NAN,
+            YSort::Down => y,
+            YSort::Up => -y,
+        };
+        Self{
+            layer,
+            z_index,
+            y,
+            material,
+            texture,
+        }
+    }
+
+    ///Total order, NaN positions are drawn last
+    pub fn compare(&self, other: &Self) -> Ordering{
+        self.layer.cmp(&other.layer)
+            .then(self.z_index.cmp(&other.z_index))
+            .then(self.y.total_cmp(&other.y))
+            .then(self.material.cmp(&other.material))
+            .then(self.texture.cmp(&other.texture))
+    }
+}
+
+///
+/// Settings of every layer, layers without their own settings use the default one
+#[derive(Clone,Debug,Default)]
+pub struct RenderLayers{
+    layers: Vec<(i32,LayerSettings)>,
+    default: LayerSettings,
+}
+
+impl RenderLayers{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    pub fn with_layer(mut self, layer: i32, settings: LayerSettings) -> Self{
+        self.set(layer, settings);
+        self
+    }
+
+    ///Settings of layers that weren't configured, transparent without y sorting unless changed
+    pub fn with_default(mut self, settings: LayerSettings) -> Self{
+        self.default = settings;
+        self
+    }
+
+    pub fn set(&mut self, layer: i32, settings: LayerSettings){
+        match self.layers.iter_mut().find(|(x, _)|*x == layer){
+            Some((_, x)) => *x = settings,
+            None => self.layers.push((layer, settings)),
+        }
+    }
+
+    pub fn get(&self, layer: i32) -> &LayerSettings{
+        self.layers.iter().find(|(x, _)|*x == layer).map_or(&self.default, |(_, x)|x)
+    }
+
+    ///True if any layer, or the default, is drawn with depth testing
+    pub fn any_opaque(&self) -> bool{
+        self.default.opaque || self.layers.iter().any(|(_, x)|x.opaque)
+    }
+}
+
+///
+/// Depth of the sprite drawn at the index out of count in back to front order, between -1 and 1
+/// Later sprites get smaller depths, so with a less depth test they cover the earlier ones whatever order they are drawn in
+pub fn draw_depth(index: usize, count: usize) -> f32{
+    1.0 - 2.0 * (index + 1) as f32 / (count + 1) as f32
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn key(layer: i32, z_index: i32, y: f32, settings: &LayerSettings) -> DrawKey{
+        DrawKey::new(layer, z_index, y, settings, None, 0)
+    }
+
+    #[test]
+    fn layers_come_before_z_index_and_y(){
+        let settings = LayerSettings::transparent().with_y_sort(YSort::Down);
+        let back = key(0, 10, 100.0, &settings);
+        let front = key(1, -10, -100.0, &settings);
+        assert_eq!(back.compare(&front), Ordering::Less);
+
+        let low_z = key(0, 0, 100.0, &settings);
+        let high_z = key(0, 1, -100.0, &settings);
+        assert_eq!(low_z.compare(&high_z), Ordering::Less);
+    }
+
+    #[test]
+    fn y_sorting_follows_the_axis_direction(){
+        let down = LayerSettings::transparent().with_y_sort(YSort::Down);
+        assert_eq!(key(0, 0, 1.0, &down).compare(&key(0, 0, 2.0, &down)), Ordering::Less);
+
+        let up = LayerSettings::transparent().with_y_sort(YSort::Up);
+        assert_eq!(key(0, 0, 1.0, &up).compare(&key(0, 0, 2.0, &up)), Ordering::Greater);
+
+        let disabled = LayerSettings::transparent();
+        assert_eq!(key(0, 0, 1.0, &disabled).compare(&key(0, 0, 2.0, &disabled)), Ordering::Equal);
+    }
+
+    #[test]
+    fn nan_positions_are_drawn_last(){
+        for y_sort in [YSort::Down, YSort::Up]{
+            let settings = LayerSettings::transparent().with_y_sort(y_sort);
+            let nan = key(0, 0, f32::NAN, &settings);
+            for y in [f32::NEG_INFINITY, -1.0, 0.0, 1e30, f32::INFINITY]{
+                assert_eq!(nan.compare(&key(0, 0, y, &settings)), Ordering::Greater, "{:?} {}", y_sort, y);
+            }
+            assert_eq!(nan.compare(&key(1, 0, 0.0, &settings)), Ordering::Less);
+            assert_eq!(key(0, 0, -f32::NAN, &settings).compare(&key(0, 0, f32::INFINITY, &settings)), Ordering::Greater);
+        }
+    }
+
+    #[test]
+    fn ties_are_broken_by_material_and_texture(){
+        let settings = LayerSettings::transparent();
+        let a = DrawKey::new(0, 0, 0.0, &settings, None, 2);
+        let b = DrawKey::new(0, 0, 0.0, &settings, None, 3);
+        assert_eq!(a.compare(&b), Ordering::Less);
+        assert_eq!(a.compare(&a), Ordering::Equal);
+    }
+
+    #[test]
+    fn render_layers_fall_back_to_the_default(){
+        let mut layers = RenderLayers::new().with_layer(2, LayerSettings::opaque());
+        assert!(layers.get(2).opaque);
+        assert_eq!(*layers.get(5), LayerSettings::transparent());
+        assert!(layers.any_opaque());
+
+        layers.set(2, LayerSettings::transparent().with_y_sort(YSort::Up));
+        assert_eq!(layers.get(2).y_sort, YSort::Up);
+        assert!(!layers.any_opaque());
+        assert!(RenderLayers::new().with_default(LayerSettings::opaque()).get(7).opaque);
+    }
+
+    #[test]
+    fn later_sprites_get_smaller_depths(){
+        for count in [1, 2, 7, 1000]{
+            let depths = (0..count).map(|x|draw_depth(x, count)).collect::<Vec<_>>();
+            assert!(depths.windows(2).all(|x|x[1] < x[0]), "{}", count);
+            assert!(depths.iter().all(|x|*x > -1.0 && *x < 1.0), "{}", count);
+        }
+        assert_eq!(draw_depth(0, 1), 0.0);
+    }
+}
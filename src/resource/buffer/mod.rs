@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+///
+/// How often the contents of a buffer are expected to change, a hint for the driver
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum BufferUsage{
+    Static,
+    Dynamic,
+    Stream,
+}
+
+impl BufferUsage{
+    fn gl_enum(&self) -> gl::types::GLenum{
+        match self{
+            Self::Static => gl::STATIC_DRAW,
+            Self::Dynamic => gl::DYNAMIC_DRAW,
+            Self::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+struct RawGlBuffer{
+    id: gl::types::GLuint,
+}
+
+unsafe impl Send for RawGlBuffer{}
+unsafe impl Sync for RawGlBuffer{}
+
+impl RawGlBuffer{
+    fn new() -> Self{
+        let id = unsafe{
+            let mut out = 0;
+            gl::CreateBuffers(1, &mut out);
+            out
+        };
+
+        Self{
+            id
+        }
+    }
+}
+
+impl Drop for RawGlBuffer{
+    fn drop(&mut self) {
+        unsafe{
+            gl::DeleteBuffers(1, &self.id);
+        }
+    }
+}
+
+///
+/// Represents a gl buffer object
+/// It is safe to clone this buffer due to the underlying data being ref counted
+#[derive(Clone)]
+pub struct GlBuffer{
+    raw: Arc<RawGlBuffer>,
+}
+
+impl GlBuffer{
+    pub fn new() -> Self{
+        Self{
+            raw: Arc::new(RawGlBuffer::new())
+        }
+    }
+
+    ///Creates a buffer of the given size in bytes, with undefined contents
+    pub fn with_size(size: usize, usage: BufferUsage) -> Self{
+        let buffer = Self::new();
+        unsafe{
+            gl::NamedBufferData(buffer.raw.id, size as isize, std::ptr::null(), usage.gl_enum());
+        }
+        buffer
+    }
+
+    pub fn id(&self) -> gl::types::GLuint{
+        self.raw.id
+    }
+
+    ///
+    /// Replaces the storage of the buffer with the data
+    pub fn set_data(&self, data: &[u8], usage: BufferUsage){
+        unsafe{
+            gl::NamedBufferData(self.raw.id, data.len() as isize, data.as_ptr() as *const std::ffi::c_void, usage.gl_enum());
+        }
+    }
+
+    ///
+    /// Overwrites part of the buffer starting at the byte offset, the buffer has to be large enough
+    pub fn set_sub_data(&self, offset: usize, data: &[u8]){
+        unsafe{
+            gl::NamedBufferSubData(self.raw.id, offset as isize, data.len() as isize, data.as_ptr() as *const std::ffi::c_void);
+        }
+    }
+
+    ///
+    /// Binds the buffer to an indexed binding point, for example gl::UNIFORM_BUFFER
+    pub fn bind_base(&self, target: gl::types::GLenum, index: u32){
+        unsafe{
+            gl::BindBufferBase(target, index, self.raw.id);
+        }
+    }
+
+    pub fn bind(&self, target: gl::types::GLenum){
+        unsafe{
+            gl::BindBuffer(target, self.raw.id);
+        }
+    }
+}
+
+impl Default for GlBuffer{
+    fn default() -> Self {
+        Self::new()
+    }
+}
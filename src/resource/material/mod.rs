@@ -0,0 +1,337 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use super::{shader::{GlShader, uniform::UniformValue}, texture::GlTexture};
+
+///Slot the texture of the drawn sprite is bound to, material textures are bound to the slots after it
+pub const SPRITE_TEXTURE_SLOT: u32 = 0;
+///Sampler the built-in shaders read the sprite texture from
+pub const SPRITE_TEXTURE_UNIFORM: &str = "u_texture";
+///Number of textures a material can bind, the slots bind_texture accepts after SPRITE_TEXTURE_SLOT
+const MATERIAL_TEXTURE_SLOTS: u32 = 31 - SPRITE_TEXTURE_SLOT;
+
+///
+/// How drawn pixels are combined with the pixels already in the framebuffer
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum BlendMode{
+    ///Blending is disabled, drawn pixels replace the framebuffer
+    Opaque,
+    ///Straight alpha, the color is weighted by the alpha of the drawn pixel
+    #[default]
+    Alpha,
+    ///For colors already multiplied by their alpha
+    Premultiplied,
+    ///Adds the color weighted by its alpha, for glows and particles
+    Additive,
+    ///Multiplies the framebuffer by the drawn color, for shadows and tinting
+    Multiply,
+}
+
+impl BlendMode{
+    ///Sets the gl blend state, the current state isn't checked first
+    pub fn apply(&self){
+        unsafe{
+            match self{
+                Self::Opaque => {
+                    gl::Disable(gl::BLEND);
+                    return;
+                },
+                Self::Alpha => gl::BlendFuncSeparate(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA, gl::ONE, gl::ONE_MINUS_SRC_ALPHA),
+                Self::Premultiplied => gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA),
+                Self::Additive => gl::BlendFunc(gl::SRC_ALPHA, gl::ONE),
+                Self::Multiply => gl::BlendFunc(gl::DST_COLOR, gl::ONE_MINUS_SRC_ALPHA),
+            }
+            gl::Enable(gl::BLEND);
+            gl::BlendEquation(gl::FUNC_ADD);
+        }
+    }
+}
+
+///
+/// Comparison a fragment has to pass against the depth buffer to be drawn
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum DepthFunction{
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl DepthFunction{
+    fn gl_enum(&self) -> gl::types::GLenum{
+        match self{
+            Self::Never => gl::NEVER,
+            Self::Less => gl::LESS,
+            Self::Equal => gl::EQUAL,
+            Self::LessEqual => gl::LEQUAL,
+            Self::Greater => gl::GREATER,
+            Self::NotEqual => gl::NOTEQUAL,
+            Self::GreaterEqual => gl::GEQUAL,
+            Self::Always => gl::ALWAYS,
+        }
+    }
+}
+
+///
+/// Depth testing and writing, both are off by default since sprites are ordered by the order they are drawn in
+/// Gl doesn't write depth while the test is disabled, use DepthFunction::Always to write without testing
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct DepthState{
+    pub test: Option<DepthFunction>,
+    pub write: bool,
+}
+
+impl DepthState{
+    ///Sets the gl depth state, the current state isn't checked first
+    pub fn apply(&self){
+        unsafe{
+            match self.test{
+                Some(function) => {
+                    gl::Enable(gl::DEPTH_TEST);
+                    gl::DepthFunc(function.gl_enum());
+                },
+                None => gl::Disable(gl::DEPTH_TEST),
+            }
+            gl::DepthMask(if self.write { gl::TRUE } else { gl::FALSE });
+        }
+    }
+}
+
+///
+/// A shader along with the textures, uniform values and render state it is drawn with
+/// Textures are bound to the slots after SPRITE_TEXTURE_SLOT in the order they were added and their sampler uniforms are pointed at them,
+/// so a shader can read the sprite from u_texture and extra textures, like a dissolve noise, from their own samplers
+/// Uniforms the material doesn't set keep the value last uploaded to the shader, which is shared with every material using it
+/// Cloning is cheap since shaders and textures are ref counted, clone a material per entity for effects that differ per sprite
+pub struct Material{
+    shader: GlShader,
+    textures: Vec<(String,GlTexture)>,
+    ///Set through a shared reference so effects can be animated on the material resource
+    uniforms: Mutex<HashMap<String,UniformValue>>,
+    blend: BlendMode,
+    depth: DepthState,
+}
+
+impl Clone for Material{
+    fn clone(&self) -> Self {
+        Self{
+            shader: self.shader.clone(),
+            textures: self.textures.clone(),
+            uniforms: Mutex::new(self.uniforms.lock().unwrap().clone()),
+            blend: self.blend,
+            depth: self.depth,
+        }
+    }
+}
+
+impl Material{
+    ///Creates a material drawing with the shader, alpha blending and no depth test
+    pub fn new(shader: GlShader) -> Self{
+        Self{
+            shader,
+            textures: Vec::new(),
+            uniforms: Mutex::new(HashMap::new()),
+            blend: BlendMode::default(),
+            depth: DepthState::default(),
+        }
+    }
+
+    ///
+    /// Binds the texture to the sampler uniform with the name, replacing a texture added under the same name before
+    /// A texture named SPRITE_TEXTURE_UNIFORM replaces the texture of the drawn sprite
+    pub fn with_texture(mut self, name: &str, texture: GlTexture) -> Self{
+        self.set_texture(name, texture);
+        self
+    }
+
+    pub fn with_uniform(self, name: &str, value: impl Into<UniformValue>) -> Self{
+        self.set_uniform(name, value);
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Self{
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_depth(mut self, depth: DepthState) -> Self{
+        self.depth = depth;
+        self
+    }
+
+    pub fn shader(&self) -> &GlShader{
+        &self.shader
+    }
+
+    pub fn blend(&self) -> BlendMode{
+        self.blend
+    }
+
+    pub fn depth(&self) -> DepthState{
+        self.depth
+    }
+
+    pub fn set_texture(&mut self, name: &str, texture: GlTexture){
+        match self.textures.iter_mut().find(|(x, _)|x == name){
+            Some((_, x)) => *x = texture,
+            None => self.textures.push((String::from(name), texture)),
+        }
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&GlTexture>{
+        self.textures.iter().find(|(x, _)|x == name).map(|(_, x)|x)
+    }
+
+    ///The slot the texture with the name is bound to
+    pub fn texture_slot(&self, name: &str) -> Option<u32>{
+        self.textures.iter().position(|(x, _)|x == name).map(|x|SPRITE_TEXTURE_SLOT + 1 + x as u32)
+    }
+
+    ///
+    /// Sets the value uploaded to the uniform whenever the material is bound
+    /// Takes a shared reference so the value can be changed on a material stored in resources, for example to animate a flash
+    pub fn set_uniform(&self, name: &str, value: impl Into<UniformValue>){
+        self.uniforms.lock().unwrap().insert(String::from(name), value.into());
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<UniformValue>{
+        self.uniforms.lock().unwrap().get(name).cloned()
+    }
+
+    ///Stops setting the uniform, it keeps the value it was last set to
+    pub fn remove_uniform(&self, name: &str) -> Option<UniformValue>{
+        self.uniforms.lock().unwrap().remove(name)
+    }
+
+    ///
+    /// Checks that every texture has a sampler uniform and every uniform value matches the type reflected from the shader
+    pub fn validate(&self) -> Result<(),String>{
+        if self.textures.len() > MATERIAL_TEXTURE_SLOTS as usize{
+            return Err(format!("Material has {} textures but only {} slots are available",self.textures.len(),MATERIAL_TEXTURE_SLOTS));
+        }
+        for (name, _) in &self.textures{
+            let input = self.shader.uniform(name).ok_or_else(||format!("Material texture {} has no active sampler uniform in the shader",name))?;
+            if !UniformValue::Sampler(0).matches(input.data_type()){
+                return Err(format!("Material texture {} is bound to a uniform of type {:?}, expected a sampler",name,input.data_type()));
+            }
+        }
+        for (name, value) in self.uniforms.lock().unwrap().iter(){
+            let input = self.shader.uniform(name).ok_or_else(||format!("Material uniform {} has no active uniform in the shader",name))?;
+            if !value.matches(input.data_type()){
+                return Err(format!("Material uniform {} is {:?} but a {:?} value was given",name,input.data_type(),value.data_type()));
+            }
+            if value.len() > input.size() as usize{
+                return Err(format!("Material uniform {} holds {} elements but {} were given",name,input.size(),value.len()));
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Uses the shader, sets the blend and depth state, binds the textures and uploads the uniform values
+    /// Uniforms the shader doesn't have, for example after a reload removed them, are skipped
+    /// u_texture is pointed back at SPRITE_TEXTURE_SLOT unless the material replaces it, since another material may have moved it
+    pub fn bind(&self) -> Result<(),String>{
+        self.shader.bind();
+        self.blend.apply();
+        self.depth.apply();
+        if self.textures.len() > MATERIAL_TEXTURE_SLOTS as usize{
+            return Err(format!("Material has {} textures but only {} slots are available",self.textures.len(),MATERIAL_TEXTURE_SLOTS));
+        }
+        for (i, (name, texture)) in self.textures.iter().enumerate(){
+            let slot = SPRITE_TEXTURE_SLOT + 1 + i as u32;
+            texture.bind_texture(slot);
+            if self.shader.uniform(name).is_some(){
+                self.shader.set_uniform(name, UniformValue::Sampler(slot as i32))?;
+            }
+        }
+        if self.texture(SPRITE_TEXTURE_UNIFORM).is_none() && self.shader.uniform(SPRITE_TEXTURE_UNIFORM).is_some(){
+            self.shader.set_uniform(SPRITE_TEXTURE_UNIFORM, UniformValue::Sampler(SPRITE_TEXTURE_SLOT as i32))?;
+        }
+        for (name, value) in self.uniforms.lock().unwrap().iter(){
+            if self.shader.uniform(name).is_some(){
+                self.shader.set_uniform(name, value.clone())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::resource::shader::ShaderDataType;
+
+    use super::*;
+
+    fn shader() -> GlShader{
+        GlShader::with_reflected_uniforms(&[
+            ("u_texture", ShaderDataType::Sampler2D, 1),
+            ("u_noise", ShaderDataType::Sampler2D, 1),
+            ("u_shadow", ShaderDataType::Other(gl::SAMPLER_2D_SHADOW), 1),
+            ("u_tint", ShaderDataType::Vec4F, 1),
+            ("u_weights", ShaderDataType::Float, 4),
+        ])
+    }
+
+    #[test]
+    fn valid_materials_pass(){
+        let material = Material::new(shader())
+            .with_texture("u_noise", GlTexture::default())
+            .with_texture("u_shadow", GlTexture::default())
+            .with_uniform("u_tint", [1.0, 0.5, 0.5, 1.0])
+            .with_uniform("u_weights", vec![0.25f32;4]);
+        assert_eq!(material.validate(), Ok(()));
+
+        //shorter arrays only set the first elements
+        material.set_uniform("u_weights", vec![1.0f32]);
+        assert_eq!(material.validate(), Ok(()));
+    }
+
+    #[test]
+    fn uniforms_are_checked_against_the_shader(){
+        let material = Material::new(shader()).with_uniform("u_tint", 1.0f32);
+        assert_eq!(material.validate().unwrap_err(), "Material uniform u_tint is Vec4F but a Float value was given");
+
+        let material = Material::new(shader()).with_uniform("u_weights", vec![0.0f32;5]);
+        assert_eq!(material.validate().unwrap_err(), "Material uniform u_weights holds 4 elements but 5 were given");
+
+        let material = Material::new(shader()).with_uniform("u_missing", 1.0f32);
+        assert!(material.validate().unwrap_err().contains("u_missing has no active uniform"));
+        material.remove_uniform("u_missing");
+        assert_eq!(material.validate(), Ok(()));
+    }
+
+    #[test]
+    fn textures_need_a_sampler(){
+        let material = Material::new(shader()).with_texture("u_tint", GlTexture::default());
+        assert_eq!(material.validate().unwrap_err(), "Material texture u_tint is bound to a uniform of type Vec4F, expected a sampler");
+
+        let material = Material::new(shader()).with_texture("u_mask", GlTexture::default());
+        assert!(material.validate().unwrap_err().contains("u_mask has no active sampler uniform"));
+
+        let mut material = Material::new(shader());
+        for i in 0..=MATERIAL_TEXTURE_SLOTS{
+            material.set_texture(&format!("u_extra{}",i), GlTexture::default());
+        }
+        assert!(material.validate().unwrap_err().contains("only 31 slots are available"));
+    }
+
+    #[test]
+    fn textures_take_the_slots_after_the_sprite(){
+        let mut material = Material::new(shader())
+            .with_texture("u_noise", GlTexture::default())
+            .with_texture("u_shadow", GlTexture::default());
+        assert_eq!(material.texture_slot("u_noise"), Some(SPRITE_TEXTURE_SLOT + 1));
+        assert_eq!(material.texture_slot("u_shadow"), Some(SPRITE_TEXTURE_SLOT + 2));
+        assert_eq!(material.texture_slot("u_texture"), None);
+
+        //replacing a texture keeps its slot
+        material.set_texture("u_noise", GlTexture::default());
+        assert_eq!(material.texture_slot("u_noise"), Some(SPRITE_TEXTURE_SLOT + 1));
+        material.set_texture("u_texture", GlTexture::default());
+        assert_eq!(material.texture_slot("u_texture"), Some(SPRITE_TEXTURE_SLOT + 3));
+    }
+}
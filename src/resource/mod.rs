@@ -3,6 +3,10 @@ use std::{any::{Any, TypeId}, collections::HashMap, ops::{Deref, DerefMut}, sync
 pub mod texture;
 pub mod sprite;
 pub mod shader;
+pub mod animation;
+pub mod buffer;
+pub mod material;
+pub mod batch;
 #[derive(Default)]
 pub struct Resources{
     rw_lock: RwLock<()>,
@@ -29,9 +33,21 @@ impl Resources {
             None => panic!("Invalid resource name {}",key)
         }
     }
+    pub fn find_resource_key(&self, key: &str) -> Option<ResourceKey>{
+        let _lock = self.rw_lock.read().unwrap();
+        self.resource_names.get(key).cloned()
+    }
+
+    ///
+    /// Every name a resource has been added under, collected while the lock is held
+    pub fn resource_names(&self) -> Vec<&str>{
+        let _lock = self.rw_lock.read().unwrap();
+        self.resource_names.keys().map(|x|x.as_str()).collect()
+    }
+
     pub fn get_resource<'a, 'b: 'a, T: Sized + 'static>(&'b self, key: &ResourceKey) -> &'a T{
         let _lock = self.rw_lock.read().unwrap();
-        let reference = self.resources.get(&key.class).expect(&format!("could not find group of type {:?}",key.class))[key.id].downcast_ref().unwrap();
+        let reference = self.resources.get(&key.class).unwrap_or_else(||panic!("could not find group of type {:?}",key.class))[key.id].downcast_ref().unwrap();
         reference
     }
 
@@ -39,7 +55,7 @@ impl Resources {
         let _lock = self.rw_lock.write().unwrap();
 
         let class = TypeId::of::<T>();
-        let l = self.resources.get_mut(&class).expect(&format!("could not find group of type {:?}",class));
+        let l = self.resources.get_mut(&class).unwrap_or_else(||panic!("could not find group of type {:?}",class));
         l.push(Box::new(value));
 
         let key = ResourceKey{
@@ -52,6 +68,46 @@ impl Resources {
          key
     }
 
+    ///
+    /// Adds a boxed value stored under T, which lets trait objects be stored and fetched, for example as dyn Sprite
+    /// T has to be registered with register_type::<T>() first
+    pub fn add_boxed_resource<T: ?Sized + 'static>(&mut self, value: Box<T>, name: String) -> ResourceKey{
+        let _lock = self.rw_lock.write().unwrap();
+
+        let class = TypeId::of::<T>();
+        let l = self.resources.get_mut(&class).unwrap_or_else(||panic!("could not find group of type {:?}",class));
+        l.push(Box::new(value));
+
+        let key = ResourceKey{
+            class,
+            id: l.len()-1
+        };
+
+        self.resource_names.insert(name, key.clone());
+
+        key
+    }
+
+    ///
+    /// Fetches a value added with add_boxed_resource::<T>
+    pub fn get_boxed_resource<'a, 'b: 'a, T: ?Sized + 'static>(&'b self, key: &ResourceKey) -> &'a T{
+        let _lock = self.rw_lock.read().unwrap();
+        if key.class != TypeId::of::<T>(){
+            panic!("resource of type {:?} requested as {:?}",key.class,TypeId::of::<T>());
+        }
+        self.resources.get(&key.class).unwrap_or_else(||panic!("could not find group of type {:?}",key.class))[key.id]
+        .downcast_ref::<Box<T>>().map(|x|&**x).unwrap()
+    }
+
+    ///
+    /// Fetches a value added with add_boxed_resource::<T> by name
+    /// Returns None if there is no resource with the name or it wasn't stored as T
+    pub fn find_boxed_resource<'a, 'b: 'a, T: ?Sized + 'static>(&'b self, name: &str) -> Option<&'a T>{
+        let _lock = self.rw_lock.read().unwrap();
+        let key = self.resource_names.get(name).filter(|x|x.is::<T>())?;
+        self.resources.get(&key.class)?.get(key.id)?.downcast_ref::<Box<T>>().map(|x|&**x)
+    }
+
     pub fn register_type<T: ?Sized + 'static>(&mut self) -> bool{
         let class = TypeId::of::<T>();
         let _lock = self.rw_lock.write().unwrap();
@@ -66,8 +122,93 @@ impl Resources {
 }
 
 
-#[derive(Clone,Copy)]
+///
+/// Keys are ordered by type and then by the order their resources were added, so draws can be sorted by them
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
 pub struct ResourceKey{
     class: TypeId,
     id: usize,
 }
+
+impl ResourceKey{
+    ///True if the resource was stored as T
+    pub fn is<T: ?Sized + 'static>(&self) -> bool{
+        self.class == TypeId::of::<T>()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use sprite::{Sprite, SpriteQuad, UvRect};
+    use texture::GlTexture;
+
+    fn quad(texture: &GlTexture, width: f32, height: f32) -> SpriteQuad<'_>{
+        SpriteQuad{
+            texture,
+            uv: UvRect{min_x: 0.0, min_y: 0.0, max_x: 1.0, max_y: 1.0},
+            width,
+            height,
+            pivot_x: 0.0,
+            pivot_y: 0.0,
+            rotated: false,
+            offset_x: 0.0,
+            offset_y: 0.0,
+            source_width: width,
+            source_height: height,
+        }
+    }
+
+    //textures delete themselves through gl when dropped, which needs a context
+    struct Square(&'static GlTexture);
+
+    impl Sprite for Square{
+        fn quad(&self) -> SpriteQuad<'_> {
+            quad(self.0, 8.0, 8.0)
+        }
+    }
+
+    struct Wide(&'static GlTexture);
+
+    impl Sprite for Wide{
+        fn quad(&self) -> SpriteQuad<'_> {
+            quad(self.0, 32.0, 4.0)
+        }
+    }
+
+    fn resources() -> Resources{
+        let mut resources = Resources::new();
+        resources.register_type::<dyn Sprite>();
+        resources.register_type::<u32>();
+        resources.add_boxed_resource::<dyn Sprite>(Box::new(Square(Box::leak(Box::default()))), String::from("square"));
+        resources.add_boxed_resource::<dyn Sprite>(Box::new(Wide(Box::leak(Box::default()))), String::from("wide"));
+        resources.add_resource(7u32, String::from("number"));
+        resources
+    }
+
+    #[test]
+    fn boxed_resources_keep_their_implementation(){
+        let resources = resources();
+        let square = resources.get_boxed_resource::<dyn Sprite>(&resources.get_resource_key("square"));
+        assert_eq!(square.quad().width, 8.0);
+        let wide = resources.find_boxed_resource::<dyn Sprite>("wide").unwrap();
+        assert_eq!((wide.quad().width, wide.quad().height), (32.0, 4.0));
+        assert_eq!(*resources.get_resource::<u32>(&resources.get_resource_key("number")), 7);
+    }
+
+    #[test]
+    fn finding_a_wrong_name_or_type_returns_none(){
+        let resources = resources();
+        assert!(resources.find_boxed_resource::<dyn Sprite>("missing").is_none());
+        assert!(resources.find_boxed_resource::<dyn Sprite>("number").is_none());
+        assert!(resources.find_boxed_resource::<u32>("square").is_none());
+        assert!(resources.find_resource_key("number").is_some_and(|x|x.is::<u32>() && !x.is::<dyn Sprite>()));
+    }
+
+    #[test]
+    #[should_panic(expected = "requested as")]
+    fn getting_a_boxed_resource_as_the_wrong_type_panics(){
+        let resources = resources();
+        resources.get_boxed_resource::<dyn Sprite>(&resources.get_resource_key("number"));
+    }
+}
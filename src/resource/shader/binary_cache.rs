@@ -0,0 +1,205 @@
+use std::{ffi::CStr, path::PathBuf};
+
+use super::{RawGlShader, preprocessor::PreprocessedSource};
+
+const BINARY_MAGIC: &[u8;8] = b"R2DPROG\0";
+
+///
+/// Stores linked program binaries on disk, so later runs can skip compiling and linking
+/// Binaries are keyed by a hash of both preprocessed sources, which include the variant defines,
+/// and the driver vendor, renderer and version, so a driver update never loads a stale binary
+pub struct ProgramBinaryCache{
+    dir: PathBuf,
+    driver: String,
+}
+
+impl ProgramBinaryCache{
+    ///
+    /// Uses dir for the cache files, it is created when the first binary is stored
+    /// Needs a current gl context to identify the driver
+    pub fn new(dir: impl Into<PathBuf>) -> Self{
+        let driver = [gl::VENDOR, gl::RENDERER, gl::VERSION].iter()
+            .map(|x|gl_string(*x))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self{
+            dir: dir.into(),
+            driver,
+        }
+    }
+
+    pub fn dir(&self) -> &PathBuf{
+        &self.dir
+    }
+
+    pub fn key(&self, vertex: &PreprocessedSource, fragment: &PreprocessedSource) -> u64{
+        let mut hash = FNV_OFFSET;
+        for part in [vertex.source(), fragment.source(), &self.driver]{
+            hash = fnv1a(hash, part.as_bytes());
+            //separates the parts, so moving text from one source to the next changes the key
+            hash = fnv1a(hash, &[0xff]);
+        }
+        hash
+    }
+
+    fn path(&self, key: u64) -> PathBuf{
+        self.dir.join(format!("{:016x}.bin",key))
+    }
+
+    ///
+    /// Creates a program from the stored binary, returns None if there is none or the driver rejects it
+    /// Rejected binaries are removed
+    pub(super) fn load(&self, key: u64) -> Option<gl::types::GLuint>{
+        let path = self.path(key);
+        let data = std::fs::read(&path).ok()?;
+        let program = parse_binary(&data).and_then(|(format, binary)|unsafe{
+            let program = gl::CreateProgram();
+            gl::ProgramBinary(program, format, binary.as_ptr() as *const std::ffi::c_void, binary.len() as i32);
+            match RawGlShader::validate_program(program, gl::LINK_STATUS){
+                Ok(()) => Some(program),
+                Err(_) => {
+                    gl::DeleteProgram(program);
+                    None
+                }
+            }
+        });
+        if program.is_none(){
+            let _ = std::fs::remove_file(&path);
+        }
+        program
+    }
+
+    ///
+    /// Stores the binary of a linked program, which has to be linked with the retrievable hint
+    pub(super) fn store(&self, key: u64, program: gl::types::GLuint) -> Result<(),String>{
+        let (format, binary) = unsafe{
+            let mut length = 0;
+            gl::GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut length);
+            if length <= 0{
+                return Err(String::from("The driver provides no program binary"));
+            }
+            let mut binary = vec![0u8;length as usize];
+            let mut written = 0;
+            let mut format = 0;
+            gl::GetProgramBinary(program, length, &mut written, &mut format, binary.as_mut_ptr() as *mut std::ffi::c_void);
+            binary.truncate(written as usize);
+            (format, binary)
+        };
+
+        let mut data = Vec::with_capacity(BINARY_MAGIC.len() + 4 + binary.len());
+        data.extend_from_slice(BINARY_MAGIC);
+        data.extend_from_slice(&format.to_le_bytes());
+        data.extend_from_slice(&binary);
+
+        std::fs::create_dir_all(&self.dir).map_err(|e|format!("Failed to create {}: {}",self.dir.display(),e))?;
+        let path = self.path(key);
+        std::fs::write(&path, data).map_err(|e|format!("Failed to write {}: {}",path.display(),e))
+    }
+
+    ///
+    /// Removes every stored binary
+    pub fn clear(&self) -> Result<(),String>{
+        let entries = match std::fs::read_dir(&self.dir){
+            Ok(x) => x,
+            Err(_) => return Ok(())
+        };
+        for entry in entries.flatten(){
+            let path = entry.path();
+            if path.extension().is_some_and(|x|x == "bin"){
+                std::fs::remove_file(&path).map_err(|e|format!("Failed to remove {}: {}",path.display(),e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_binary(data: &[u8]) -> Option<(gl::types::GLenum,&[u8])>{
+    let rest = data.strip_prefix(BINARY_MAGIC.as_slice())?;
+    if rest.len() <= 4{
+        return None;
+    }
+    let format = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+    Some((format, &rest[4..]))
+}
+
+fn gl_string(name: gl::types::GLenum) -> String{
+    unsafe{
+        let pointer = gl::GetString(name);
+        if pointer.is_null(){
+            String::new()
+        }
+        else{
+            CStr::from_ptr(pointer as *const std::os::raw::c_char).to_string_lossy().into_owned()
+        }
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+///
+/// 64 bit FNV-1a, unlike the std hasher it is stable between runs and compiler versions
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64{
+    for byte in bytes{
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::{loader::vfs::MemoryFs, resource::shader::preprocessor::{ShaderVariant, preprocess_source}};
+
+    fn cache(driver: &str) -> ProgramBinaryCache{
+        ProgramBinaryCache{
+            dir: PathBuf::from("cache"),
+            driver: String::from(driver),
+        }
+    }
+
+    fn source(text: &str, variant: &ShaderVariant) -> PreprocessedSource{
+        preprocess_source(&MemoryFs::new(), "shader.glsl", text, variant).unwrap()
+    }
+
+    #[test]
+    fn fnv1a_matches_the_reference_values(){
+        assert_eq!(fnv1a(FNV_OFFSET, b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(FNV_OFFSET, b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(FNV_OFFSET, b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn key_changes_with_the_source_variant_and_driver(){
+        let none = ShaderVariant::new();
+        let vertex = source("#version 450\nvoid main(){}", &none);
+        let fragment = source("#version 450\nvoid main(){ }", &none);
+        let key = cache("vendor\nrenderer\n4.5").key(&vertex, &fragment);
+
+        assert_eq!(key, cache("vendor\nrenderer\n4.5").key(&vertex, &fragment));
+        assert_ne!(key, cache("vendor\nrenderer\n4.6").key(&vertex, &fragment));
+        assert_ne!(key, cache("vendor\nrenderer\n4.5").key(&source("#version 450\nvoid main(){  }", &none), &fragment));
+        assert_ne!(key, cache("vendor\nrenderer\n4.5").key(&vertex, &source("#version 450\nvoid main(){}", &none)));
+
+        let defined = ShaderVariant::new().with_define("ALPHA_TEST");
+        let variant_key = cache("vendor\nrenderer\n4.5").key(&source("#version 450\nvoid main(){}", &defined), &source("#version 450\nvoid main(){ }", &defined));
+        assert_ne!(key, variant_key);
+    }
+
+    #[test]
+    fn moving_text_between_the_sources_changes_the_key(){
+        let none = ShaderVariant::new();
+        let cache = cache("driver");
+        assert_ne!(cache.key(&source("ab", &none), &source("c", &none)), cache.key(&source("a", &none), &source("bc", &none)));
+    }
+
+    #[test]
+    fn binaries_need_the_magic_and_a_format(){
+        let mut data = BINARY_MAGIC.to_vec();
+        data.extend_from_slice(&7u32.to_le_bytes());
+        assert_eq!(parse_binary(&data), None);
+        data.push(1);
+        assert_eq!(parse_binary(&data), Some((7, [1u8].as_slice())));
+        assert_eq!(parse_binary(&data[1..]), None);
+    }
+}
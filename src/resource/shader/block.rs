@@ -0,0 +1,192 @@
+use std::{marker::PhantomData, mem::size_of};
+
+use crate::resource::buffer::{BufferUsage, GlBuffer};
+
+use super::{GlShader, ShaderDataType};
+
+///Binding point the FrameUniforms block is bound to
+pub const FRAME_UNIFORMS_BINDING: u32 = 0;
+///Name the shared FrameUniforms block is registered under by register_builtin_shaders
+pub const FRAME_UNIFORMS: &str = "render-2d/frame_uniforms";
+
+///
+/// A rust struct mirroring a glsl uniform block declared with layout(std140)
+/// Implement it with uniform_block_layout!, which checks the layout at compile time
+///
+/// # Safety
+/// The struct is uploaded as raw bytes, so it has to be #[repr(C)] without any implicit padding,
+/// with gaps the std140 rules require filled by explicit padding fields
+/// Every member has to sit at the std140 offset listed in MEMBERS and have the std140 size of its type
+pub unsafe trait UniformBlockLayout: Copy + 'static{
+    ///Name of the block in glsl
+    const NAME: &'static str;
+    ///Name, byte offset and type of every member
+    const MEMBERS: &'static [(&'static str,i32,ShaderDataType)];
+}
+
+///
+/// Implements UniformBlockLayout for a #[repr(C)] struct, failing to compile if the layout breaks its safety rules
+/// Every field is listed, members with their glsl name, std140 offset and type, then the explicit padding fields
+///
+/// uniform_block_layout!(Light, "Light", {
+///     position: "u_position", 0, ShaderDataType::Vec3F;
+///     intensity: "u_intensity", 12, ShaderDataType::Float;
+///     color: "u_color", 16, ShaderDataType::Vec3F;
+/// }, padding{_padding});
+#[macro_export]
+macro_rules! uniform_block_layout{
+    ($type: ty, $name: literal, {$($field: ident: $member: literal, $offset: literal, $data_type: expr);* $(;)?} $(, padding{$($padding: ident),* $(,)?})?) => {
+        unsafe impl $crate::resource::shader::block::UniformBlockLayout for $type{
+            const NAME: &'static str = $name;
+            const MEMBERS: &'static [(&'static str,i32,$crate::resource::shader::ShaderDataType)] = &[$(($member, $offset, $data_type)),*];
+        }
+
+        const _: () = {
+            use $crate::resource::shader::block::{field_size, std140_layout};
+            $(
+                assert!(core::mem::offset_of!($type, $field) == $offset, concat!(stringify!($type), ".", stringify!($field), " isn't at offset ", stringify!($offset)));
+                let (size, alignment) = match std140_layout($data_type){
+                    Some(x) => x,
+                    None => panic!(concat!(stringify!($type), ".", stringify!($field), " has a type that can't be in a uniform block")),
+                };
+                assert!($offset % alignment == 0, concat!(stringify!($type), ".", stringify!($field), " isn't aligned by the std140 rules"));
+                assert!(field_size(|x: &$type|&x.$field) == size, concat!(stringify!($type), ".", stringify!($field), " doesn't have the std140 size of its type"));
+            )*
+            let listed = 0 $(+ field_size(|x: &$type|&x.$field))* $($(+ field_size(|x: &$type|&x.$padding))*)?;
+            assert!(listed == core::mem::size_of::<$type>(), concat!(stringify!($type), " has implicit padding or fields that aren't listed"));
+        };
+    };
+}
+
+///Size of the field the accessor returns, used by uniform_block_layout!
+#[doc(hidden)]
+pub const fn field_size<T,F>(_field: fn(&T) -> &F) -> usize{
+    size_of::<F>()
+}
+
+///
+/// Size and base alignment of a uniform block member by the std140 rules, None for types blocks can't hold
+/// Matrices are stored as vec4 columns
+pub const fn std140_layout(data_type: ShaderDataType) -> Option<(usize,usize)>{
+    match data_type{
+        ShaderDataType::Bool | ShaderDataType::Int | ShaderDataType::UInt | ShaderDataType::Float => Some((4, 4)),
+        ShaderDataType::Vec2F | ShaderDataType::Vec2I => Some((8, 8)),
+        ShaderDataType::Vec3F | ShaderDataType::Vec3I => Some((12, 16)),
+        ShaderDataType::Vec4F | ShaderDataType::Vec4I => Some((16, 16)),
+        ShaderDataType::Mat2F => Some((32, 16)),
+        ShaderDataType::Mat3F => Some((48, 16)),
+        ShaderDataType::Mat4F => Some((64, 16)),
+        ShaderDataType::Sampler2D | ShaderDataType::Other(_) => None,
+    }
+}
+
+///
+/// Checks that the block of the shader has exactly the members of the layout, at the same offsets and with the same types
+pub fn validate_block<T: UniformBlockLayout>(shader: &GlShader) -> Result<(),String>{
+    let block = shader.uniform_block(T::NAME).ok_or_else(||format!("Shader has no active uniform block named {}",T::NAME))?;
+    if block.data_size as usize > size_of::<T>(){
+        return Err(format!("Uniform block {} is {} bytes but the rust struct is only {}",T::NAME,block.data_size,size_of::<T>()));
+    }
+    for (name, offset, data_type) in T::MEMBERS{
+        //members unused by the shader may be optimized away, they aren't an error
+        if let Some(member) = block.members.get(*name){
+            if member.offset != *offset || member.data_type != *data_type{
+                return Err(format!("Uniform block member {}.{} is {:?} at offset {}, the rust struct has {:?} at offset {}",T::NAME,name,member.data_type,member.offset,data_type,offset));
+            }
+        }
+    }
+    if let Some(name) = block.members.keys().find(|x|!T::MEMBERS.iter().any(|(m,_,_)|m == x)){
+        return Err(format!("Uniform block member {}.{} has no matching field in the rust struct",T::NAME,name));
+    }
+    Ok(())
+}
+
+///
+/// A uniform buffer holding one T, bound to a fixed binding point
+/// Shaders read it after bind_to links their block to the binding point, so an update reaches every program at once
+pub struct UniformBlock<T: UniformBlockLayout>{
+    buffer: GlBuffer,
+    binding: u32,
+    marker: PhantomData<T>,
+}
+
+impl<T: UniformBlockLayout> UniformBlock<T>{
+    ///
+    /// Creates the buffer with the initial value and binds it to the binding point
+    pub fn new(binding: u32, value: &T) -> Self{
+        let buffer = GlBuffer::new();
+        buffer.set_data(as_bytes(value), BufferUsage::Dynamic);
+        buffer.bind_base(gl::UNIFORM_BUFFER, binding);
+        Self{
+            buffer,
+            binding,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn binding(&self) -> u32{
+        self.binding
+    }
+
+    pub fn buffer(&self) -> &GlBuffer{
+        &self.buffer
+    }
+
+    ///Uploads a new value, visible to every shader bound to the block
+    pub fn set(&self, value: &T){
+        self.buffer.set_sub_data(0, as_bytes(value));
+    }
+
+    ///Binds the buffer to its binding point again, in case something else was bound there
+    pub fn bind(&self){
+        self.buffer.bind_base(gl::UNIFORM_BUFFER, self.binding);
+    }
+
+    ///
+    /// Validates the block of the shader against T and makes it read from this buffer
+    pub fn bind_to(&self, shader: &GlShader) -> Result<(),String>{
+        validate_block::<T>(shader)?;
+        shader.bind_uniform_block(T::NAME, self.binding)
+    }
+}
+
+fn as_bytes<T: UniformBlockLayout>(value: &T) -> &[u8]{
+    //the trait guarantees T has no padding, so every byte is initialized
+    unsafe{std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>())}
+}
+
+///
+/// Data shared by every shader and updated once per frame, matching the glsl block
+/// layout(std140) uniform FrameUniforms{ mat4 u_view_projection; vec2 u_resolution; float u_time; float u_delta_time; };
+#[repr(C)]
+#[derive(Clone,Copy,Debug)]
+pub struct FrameUniforms{
+    ///Column major
+    pub view_projection: [f32;16],
+    pub resolution: [f32;2],
+    pub time: f32,
+    pub delta_time: f32,
+}
+
+impl Default for FrameUniforms{
+    fn default() -> Self {
+        Self{
+            view_projection: [
+                1.0, 0.0, 0.0, 0.0,
+                0.0, 1.0, 0.0, 0.0,
+                0.0, 0.0, 1.0, 0.0,
+                0.0, 0.0, 0.0, 1.0,
+            ],
+            resolution: [0.0, 0.0],
+            time: 0.0,
+            delta_time: 0.0,
+        }
+    }
+}
+
+uniform_block_layout!(FrameUniforms, "FrameUniforms", {
+    view_projection: "u_view_projection", 0, ShaderDataType::Mat4F;
+    resolution: "u_resolution", 64, ShaderDataType::Vec2F;
+    time: "u_time", 72, ShaderDataType::Float;
+    delta_time: "u_delta_time", 76, ShaderDataType::Float;
+});
@@ -0,0 +1,123 @@
+use super::{GlShader, ShaderDataType, block::{FRAME_UNIFORMS_BINDING, FrameUniforms, UniformBlockLayout, validate_block}, uniform::UniformValue};
+
+///Textured quads multiplied by their vertex color
+pub const SPRITE_SHADER: &str = "render-2d/sprite";
+///Untextured quads in their vertex color
+pub const COLOR_SHADER: &str = "render-2d/color";
+///Text from a signed distance field font, the glyph distance is read from alpha
+pub const SDF_TEXT_SHADER: &str = "render-2d/sdf_text";
+///Textured quads blended towards their luminance by u_amount
+pub const GRAYSCALE_SHADER: &str = "render-2d/grayscale";
+///Textured quads with a u_outline_color outline drawn around their opaque pixels
+pub const OUTLINE_SHADER: &str = "render-2d/outline";
+
+///
+/// A shader shipped with the crate, along with the inputs it has to expose
+/// Every built-in program shares the vertex layout a_position (0), a_uv (1) and a_color (2)
+/// and reads the view projection from the FrameUniforms block
+pub struct BuiltinShader{
+    pub name: &'static str,
+    pub vertex: &'static str,
+    pub fragment: &'static str,
+    pub uniforms: &'static [(&'static str,ShaderDataType)],
+    pub attributes: &'static [(&'static str,ShaderDataType)],
+}
+
+const SPRITE_VERTEX: &str = include_str!("builtin/sprite.vert");
+const COLOR_VERTEX: &str = include_str!("builtin/color.vert");
+
+const TEXTURED_ATTRIBUTES: &[(&str,ShaderDataType)] = &[
+    ("a_position", ShaderDataType::Vec3F),
+    ("a_uv", ShaderDataType::Vec2F),
+    ("a_color", ShaderDataType::Vec4F),
+];
+
+pub const BUILTIN_SHADERS: &[BuiltinShader] = &[
+    BuiltinShader{
+        name: SPRITE_SHADER,
+        vertex: SPRITE_VERTEX,
+        fragment: include_str!("builtin/sprite.frag"),
+        uniforms: &[
+            ("u_texture", ShaderDataType::Sampler2D),
+        ],
+        attributes: TEXTURED_ATTRIBUTES,
+    },
+    BuiltinShader{
+        name: COLOR_SHADER,
+        vertex: COLOR_VERTEX,
+        fragment: include_str!("builtin/color.frag"),
+        uniforms: &[],
+        attributes: &[
+            ("a_position", ShaderDataType::Vec3F),
+            ("a_color", ShaderDataType::Vec4F),
+        ],
+    },
+    BuiltinShader{
+        name: SDF_TEXT_SHADER,
+        vertex: SPRITE_VERTEX,
+        fragment: include_str!("builtin/sdf_text.frag"),
+        uniforms: &[
+            ("u_texture", ShaderDataType::Sampler2D),
+            ("u_threshold", ShaderDataType::Float),
+        ],
+        attributes: TEXTURED_ATTRIBUTES,
+    },
+    BuiltinShader{
+        name: GRAYSCALE_SHADER,
+        vertex: SPRITE_VERTEX,
+        fragment: include_str!("builtin/grayscale.frag"),
+        uniforms: &[
+            ("u_texture", ShaderDataType::Sampler2D),
+            ("u_amount", ShaderDataType::Float),
+        ],
+        attributes: TEXTURED_ATTRIBUTES,
+    },
+    BuiltinShader{
+        name: OUTLINE_SHADER,
+        vertex: SPRITE_VERTEX,
+        fragment: include_str!("builtin/outline.frag"),
+        uniforms: &[
+            ("u_texture", ShaderDataType::Sampler2D),
+            ("u_outline_color", ShaderDataType::Vec4F),
+            ("u_outline_size", ShaderDataType::Vec2F),
+        ],
+        attributes: TEXTURED_ATTRIBUTES,
+    },
+];
+
+impl BuiltinShader{
+    ///
+    /// Compiles the program and checks that reflection reports every expected uniform and attribute with its type
+    /// Uniform defaults are set as well, the texture sampler reads slot 0 and FrameUniforms is bound to FRAME_UNIFORMS_BINDING
+    pub fn compile(&self) -> Result<GlShader,String>{
+        let shader = GlShader::from_source(self.vertex, self.fragment).map_err(|e|format!("{} ({})",e,self.name))?;
+        for (name, data_type) in self.uniforms{
+            check_input(self.name, "uniform", name, *data_type, shader.uniform(name).map(|x|x.data_type()))?;
+        }
+        for (name, data_type) in self.attributes{
+            check_input(self.name, "attribute", name, *data_type, shader.attribute(name).map(|x|x.data_type()))?;
+        }
+
+        validate_block::<FrameUniforms>(&shader).map_err(|e|format!("{} ({})",e,self.name))?;
+        shader.bind_uniform_block(FrameUniforms::NAME, FRAME_UNIFORMS_BINDING)?;
+
+        if shader.uniform("u_texture").is_some(){
+            shader.set_uniform("u_texture", UniformValue::Sampler(0))?;
+        }
+        if shader.uniform("u_threshold").is_some(){
+            shader.set_uniform("u_threshold", 0.5)?;
+        }
+        if shader.uniform("u_amount").is_some(){
+            shader.set_uniform("u_amount", 1.0)?;
+        }
+        Ok(shader)
+    }
+}
+
+fn check_input(shader: &str, kind: &str, name: &str, expected: ShaderDataType, found: Option<ShaderDataType>) -> Result<(),String>{
+    match found{
+        Some(x) if x == expected => Ok(()),
+        Some(x) => Err(format!("Built-in shader {} has {} {} of type {:?}, expected {:?}",shader,kind,name,x,expected)),
+        None => Err(format!("Built-in shader {} is missing {} {}",shader,kind,name)),
+    }
+}
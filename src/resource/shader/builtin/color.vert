@@ -0,0 +1,18 @@
+#version 330 core
+
+layout(location = 0) in vec3 a_position;
+layout(location = 2) in vec4 a_color;
+
+layout(std140) uniform FrameUniforms{
+    mat4 u_view_projection;
+    vec2 u_resolution;
+    float u_time;
+    float u_delta_time;
+};
+
+out vec4 v_color;
+
+void main(){
+    v_color = a_color;
+    gl_Position = u_view_projection * vec4(a_position, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+
+in vec2 v_uv;
+in vec4 v_color;
+
+uniform sampler2D u_texture;
+//0 keeps the original colors, 1 is fully gray
+uniform float u_amount;
+
+out vec4 out_color;
+
+void main(){
+    vec4 color = texture(u_texture, v_uv) * v_color;
+    float luma = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    out_color = vec4(mix(color.rgb, vec3(luma), u_amount), color.a);
+}
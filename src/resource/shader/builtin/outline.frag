@@ -0,0 +1,21 @@
+#version 330 core
+
+in vec2 v_uv;
+in vec4 v_color;
+
+uniform sampler2D u_texture;
+uniform vec4 u_outline_color;
+//outline width in uv units, usually a whole number of texels
+uniform vec2 u_outline_size;
+
+out vec4 out_color;
+
+void main(){
+    vec4 color = texture(u_texture, v_uv) * v_color;
+    float neighbours = texture(u_texture, v_uv + vec2(u_outline_size.x, 0.0)).a
+        + texture(u_texture, v_uv - vec2(u_outline_size.x, 0.0)).a
+        + texture(u_texture, v_uv + vec2(0.0, u_outline_size.y)).a
+        + texture(u_texture, v_uv - vec2(0.0, u_outline_size.y)).a;
+    float outline = clamp(neighbours, 0.0, 1.0) * (1.0 - color.a);
+    out_color = mix(color, u_outline_color, outline);
+}
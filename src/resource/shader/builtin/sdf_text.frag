@@ -0,0 +1,17 @@
+#version 330 core
+
+in vec2 v_uv;
+in vec4 v_color;
+
+uniform sampler2D u_texture;
+//distance value of the glyph edge, usually 0.5
+uniform float u_threshold;
+
+out vec4 out_color;
+
+void main(){
+    float distance = texture(u_texture, v_uv).a;
+    float smoothing = fwidth(distance);
+    float alpha = smoothstep(u_threshold - smoothing, u_threshold + smoothing, distance);
+    out_color = vec4(v_color.rgb, v_color.a * alpha);
+}
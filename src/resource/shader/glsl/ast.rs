@@ -0,0 +1,196 @@
+///
+/// Array dimensions of a declaration, None for an unsized dimension
+pub type ArraySizes = Vec<Option<Expr>>;
+
+#[derive(Clone,Debug)]
+pub enum TypeName{
+    Named(String),
+    Struct(StructDecl),
+}
+
+#[derive(Clone,Debug)]
+pub struct TypeSpec{
+    pub name: TypeName,
+    ///Dimensions written after the type, as in float[4] x
+    pub array: ArraySizes,
+    pub line: usize,
+}
+
+#[derive(Clone,Debug)]
+pub struct StructDecl{
+    pub name: Option<String>,
+    pub fields: Vec<MemberDecl>,
+    pub line: usize,
+}
+
+///
+/// A member of a struct or interface block, possibly declaring several names of the same type
+#[derive(Clone,Debug)]
+pub struct MemberDecl{
+    pub qualifiers: Qualifiers,
+    pub ty: TypeSpec,
+    pub names: Vec<(String,ArraySizes)>,
+    pub line: usize,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Storage{
+    Const,
+    In,
+    Out,
+    InOut,
+    Uniform,
+    Buffer,
+}
+
+#[derive(Clone,Debug,Default)]
+pub struct Qualifiers{
+    pub storage: Option<Storage>,
+    ///Identifier and optional value of every layout qualifier, in order
+    pub layout: Vec<(String,Option<Expr>)>,
+    pub interpolation: Option<String>,
+    pub auxiliary: Option<String>,
+    pub invariant: bool,
+    pub precise: bool,
+    pub line: usize,
+}
+
+impl Qualifiers{
+    pub fn is_empty(&self) -> bool{
+        self.storage.is_none() && self.layout.is_empty() && self.interpolation.is_none() && self.auxiliary.is_none() && !self.invariant && !self.precise
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct Declarator{
+    pub name: String,
+    pub array: ArraySizes,
+    pub init: Option<Expr>,
+    pub line: usize,
+}
+
+#[derive(Clone,Debug)]
+pub struct Declaration{
+    pub qualifiers: Qualifiers,
+    pub ty: TypeSpec,
+    pub declarators: Vec<Declarator>,
+}
+
+#[derive(Clone,Debug)]
+pub struct BlockDecl{
+    pub qualifiers: Qualifiers,
+    pub name: String,
+    pub members: Vec<MemberDecl>,
+    ///Instance name and its array dimensions
+    pub instance: Option<(String,ArraySizes)>,
+    pub line: usize,
+}
+
+#[derive(Clone,Debug)]
+pub struct Param{
+    pub qualifiers: Qualifiers,
+    pub ty: TypeSpec,
+    pub name: Option<String>,
+    pub array: ArraySizes,
+    pub line: usize,
+}
+
+#[derive(Clone,Debug)]
+pub struct FunctionDecl{
+    pub return_type: TypeSpec,
+    pub name: String,
+    pub params: Vec<Param>,
+    ///None for a prototype
+    pub body: Option<Vec<Stmt>>,
+    pub line: usize,
+}
+
+#[derive(Clone,Debug)]
+pub enum External{
+    Declaration(Declaration),
+    Block(BlockDecl),
+    Function(FunctionDecl),
+    ///Qualifiers without a declaration, like layout(std140) uniform;
+    DefaultQualifiers(Qualifiers),
+    ///precision and invariant statements, which don't affect validation
+    Ignored,
+}
+
+#[derive(Clone,Debug)]
+pub struct Stmt{
+    pub kind: StmtKind,
+    pub line: usize,
+}
+
+#[derive(Clone,Debug)]
+pub enum StmtKind{
+    Block(Vec<Stmt>),
+    Declaration(Declaration),
+    Expr(Expr),
+    Empty,
+    If{
+        condition: Expr,
+        then: Box<Stmt>,
+        otherwise: Option<Box<Stmt>>,
+    },
+    For{
+        init: Option<Box<Stmt>>,
+        condition: Option<Expr>,
+        step: Option<Expr>,
+        body: Box<Stmt>,
+    },
+    While{
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+    DoWhile{
+        body: Box<Stmt>,
+        condition: Expr,
+    },
+    Switch{
+        value: Expr,
+        body: Vec<Stmt>,
+    },
+    Case(Expr),
+    Default,
+    Break,
+    Continue,
+    Return(Option<Expr>),
+    Discard,
+}
+
+#[derive(Clone,Debug)]
+pub struct Expr{
+    pub kind: ExprKind,
+    pub line: usize,
+}
+
+#[derive(Clone,Debug)]
+pub enum ExprKind{
+    Ident(String),
+    Int(u64),
+    UInt(u64),
+    Float,
+    Bool(bool),
+    ///Prefix operators, including ++ and --
+    Unary(&'static str,Box<Expr>),
+    ///Postfix ++ and --
+    Postfix(&'static str,Box<Expr>),
+    Binary(&'static str,Box<Expr>,Box<Expr>),
+    ///Assignment operator, = or a compound one
+    Assign(&'static str,Box<Expr>,Box<Expr>),
+    Ternary(Box<Expr>,Box<Expr>,Box<Expr>),
+    ///Function calls and constructors, array constructors carry their dimensions
+    Call{
+        name: String,
+        array: Option<ArraySizes>,
+        args: Vec<Expr>,
+    },
+    Index(Box<Expr>,Box<Expr>),
+    Field(Box<Expr>,String),
+    ///x.length()
+    Length(Box<Expr>),
+    Sequence(Box<Expr>,Box<Expr>),
+    ///Brace initializer list
+    List(Vec<Expr>),
+}
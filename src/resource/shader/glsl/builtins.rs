@@ -0,0 +1,217 @@
+use crate::resource::shader::ShaderStage;
+
+///
+/// A family of built-in functions, the texture lookups are checked separately against the sampler table
+/// Signatures are separated by ';' and written as return(parameters) with these placeholders:
+/// genF, genI, genU, genB for a scalar or vector of the component type, vecF, vecI, vecU, vecB for vectors only,
+/// every gen and vec placeholder in a signature has the same component count,
+/// mat for any matrix, matT for its transpose, sqmat for a square matrix and plain type names for themselves
+pub struct BuiltinFunction{
+    pub name: &'static str,
+    pub signatures: &'static str,
+    ///Stage the function is restricted to
+    pub stage: Option<ShaderStage>,
+    pub min_version: u32,
+}
+
+macro_rules! function{
+    ($name: literal, $signatures: expr) => {
+        function!($name, $signatures, None, 330)
+    };
+    ($name: literal, $signatures: expr, $version: literal) => {
+        function!($name, $signatures, None, $version)
+    };
+    ($name: literal, $signatures: expr, $stage: expr, $version: literal) => {
+        BuiltinFunction{
+            name: $name,
+            signatures: $signatures,
+            stage: $stage,
+            min_version: $version,
+        }
+    };
+}
+
+const MIN_MAX: &str = "genF(genF,genF);genF(genF,float);genI(genI,genI);genI(genI,int);genU(genU,genU);genU(genU,uint)";
+const COMPARE: &str = "vecB(vecF,vecF);vecB(vecI,vecI);vecB(vecU,vecU)";
+const EQUAL: &str = "vecB(vecF,vecF);vecB(vecI,vecI);vecB(vecU,vecU);vecB(vecB,vecB)";
+const FRAGMENT: Option<ShaderStage> = Some(ShaderStage::Fragment);
+
+pub const BUILTIN_FUNCTIONS: &[BuiltinFunction] = &[
+    function!("radians", "genF(genF)"),
+    function!("degrees", "genF(genF)"),
+    function!("sin", "genF(genF)"),
+    function!("cos", "genF(genF)"),
+    function!("tan", "genF(genF)"),
+    function!("asin", "genF(genF)"),
+    function!("acos", "genF(genF)"),
+    function!("atan", "genF(genF,genF);genF(genF)"),
+    function!("sinh", "genF(genF)"),
+    function!("cosh", "genF(genF)"),
+    function!("tanh", "genF(genF)"),
+    function!("asinh", "genF(genF)"),
+    function!("acosh", "genF(genF)"),
+    function!("atanh", "genF(genF)"),
+    function!("pow", "genF(genF,genF)"),
+    function!("exp", "genF(genF)"),
+    function!("log", "genF(genF)"),
+    function!("exp2", "genF(genF)"),
+    function!("log2", "genF(genF)"),
+    function!("sqrt", "genF(genF)"),
+    function!("inversesqrt", "genF(genF)"),
+    function!("abs", "genF(genF);genI(genI)"),
+    function!("sign", "genF(genF);genI(genI)"),
+    function!("floor", "genF(genF)"),
+    function!("trunc", "genF(genF)"),
+    function!("round", "genF(genF)"),
+    function!("roundEven", "genF(genF)"),
+    function!("ceil", "genF(genF)"),
+    function!("fract", "genF(genF)"),
+    function!("mod", "genF(genF,float);genF(genF,genF)"),
+    function!("modf", "genF(genF,genF)"),
+    function!("min", MIN_MAX),
+    function!("max", MIN_MAX),
+    function!("clamp", "genF(genF,genF,genF);genF(genF,float,float);genI(genI,genI,genI);genI(genI,int,int);genU(genU,genU,genU);genU(genU,uint,uint)"),
+    function!("mix", "genF(genF,genF,genF);genF(genF,genF,float);genF(genF,genF,genB)"),
+    function!("step", "genF(genF,genF);genF(float,genF)"),
+    function!("smoothstep", "genF(genF,genF,genF);genF(float,float,genF)"),
+    function!("isnan", "genB(genF)"),
+    function!("isinf", "genB(genF)"),
+    function!("floatBitsToInt", "genI(genF)"),
+    function!("floatBitsToUint", "genU(genF)"),
+    function!("intBitsToFloat", "genF(genI)"),
+    function!("uintBitsToFloat", "genF(genU)"),
+    function!("fma", "genF(genF,genF,genF)", 400),
+    function!("frexp", "genF(genF,genI)", 400),
+    function!("ldexp", "genF(genF,genI)", 400),
+    function!("packUnorm2x16", "uint(vec2)", 410),
+    function!("packSnorm2x16", "uint(vec2)", 420),
+    function!("packUnorm4x8", "uint(vec4)", 400),
+    function!("packSnorm4x8", "uint(vec4)", 400),
+    function!("unpackUnorm2x16", "vec2(uint)", 410),
+    function!("unpackSnorm2x16", "vec2(uint)", 420),
+    function!("unpackUnorm4x8", "vec4(uint)", 400),
+    function!("unpackSnorm4x8", "vec4(uint)", 400),
+    function!("packHalf2x16", "uint(vec2)", 420),
+    function!("unpackHalf2x16", "vec2(uint)", 420),
+    function!("length", "float(genF)"),
+    function!("distance", "float(genF,genF)"),
+    function!("dot", "float(genF,genF)"),
+    function!("cross", "vec3(vec3,vec3)"),
+    function!("normalize", "genF(genF)"),
+    function!("faceforward", "genF(genF,genF,genF)"),
+    function!("reflect", "genF(genF,genF)"),
+    function!("refract", "genF(genF,genF,float)"),
+    function!("matrixCompMult", "mat(mat,mat)"),
+    function!("transpose", "matT(mat)"),
+    function!("determinant", "float(sqmat)"),
+    function!("inverse", "sqmat(sqmat)"),
+    function!("lessThan", COMPARE),
+    function!("lessThanEqual", COMPARE),
+    function!("greaterThan", COMPARE),
+    function!("greaterThanEqual", COMPARE),
+    function!("equal", EQUAL),
+    function!("notEqual", EQUAL),
+    function!("any", "bool(vecB)"),
+    function!("all", "bool(vecB)"),
+    function!("not", "vecB(vecB)"),
+    function!("uaddCarry", "genU(genU,genU,genU)", 400),
+    function!("usubBorrow", "genU(genU,genU,genU)", 400),
+    function!("bitfieldExtract", "genI(genI,int,int);genU(genU,int,int)", 400),
+    function!("bitfieldInsert", "genI(genI,genI,int,int);genU(genU,genU,int,int)", 400),
+    function!("bitfieldReverse", "genI(genI);genU(genU)", 400),
+    function!("bitCount", "genI(genI);genI(genU)", 400),
+    function!("findLSB", "genI(genI);genI(genU)", 400),
+    function!("findMSB", "genI(genI);genI(genU)", 400),
+    function!("dFdx", "genF(genF)", FRAGMENT, 330),
+    function!("dFdy", "genF(genF)", FRAGMENT, 330),
+    function!("fwidth", "genF(genF)", FRAGMENT, 330),
+    function!("dFdxFine", "genF(genF)", FRAGMENT, 450),
+    function!("dFdyFine", "genF(genF)", FRAGMENT, 450),
+    function!("dFdxCoarse", "genF(genF)", FRAGMENT, 450),
+    function!("dFdyCoarse", "genF(genF)", FRAGMENT, 450),
+    function!("fwidthFine", "genF(genF)", FRAGMENT, 450),
+    function!("fwidthCoarse", "genF(genF)", FRAGMENT, 450),
+];
+
+///Parameters of built-in functions written through, which have to be l-values
+pub const OUT_PARAMETERS: &[(&str,usize)] = &[
+    ("modf", 1),
+    ("frexp", 1),
+    ("uaddCarry", 2),
+    ("usubBorrow", 2),
+];
+
+///Texture lookup functions, which are checked against the sampler table
+pub const TEXTURE_FUNCTIONS: &[&str] = &[
+    "texture", "textureProj", "textureLod", "textureOffset", "textureLodOffset", "textureGrad", "textureGradOffset",
+    "textureProjLod", "textureProjOffset", "textureSize", "texelFetch", "texelFetchOffset", "textureGather", "textureQueryLevels",
+];
+
+///
+/// A built-in variable, with its type written as glsl
+pub struct BuiltinVariable{
+    pub name: &'static str,
+    pub ty: &'static str,
+    ///Array length, 0 if it isn't an array
+    pub array: u32,
+    pub writable: bool,
+    pub stage: Option<ShaderStage>,
+    pub min_version: u32,
+}
+
+macro_rules! variable{
+    ($name: literal, $ty: literal, $array: literal, $writable: literal, $stage: expr, $version: literal) => {
+        BuiltinVariable{
+            name: $name,
+            ty: $ty,
+            array: $array,
+            writable: $writable,
+            stage: $stage,
+            min_version: $version,
+        }
+    };
+}
+
+const VERTEX: Option<ShaderStage> = Some(ShaderStage::Vertex);
+
+pub const BUILTIN_VARIABLES: &[BuiltinVariable] = &[
+    variable!("gl_VertexID", "int", 0, false, VERTEX, 330),
+    variable!("gl_InstanceID", "int", 0, false, VERTEX, 330),
+    variable!("gl_Position", "vec4", 0, true, VERTEX, 330),
+    variable!("gl_PointSize", "float", 0, true, VERTEX, 330),
+    variable!("gl_ClipDistance", "float", 8, true, VERTEX, 330),
+    variable!("gl_FragCoord", "vec4", 0, false, FRAGMENT, 330),
+    variable!("gl_FrontFacing", "bool", 0, false, FRAGMENT, 330),
+    variable!("gl_PointCoord", "vec2", 0, false, FRAGMENT, 330),
+    variable!("gl_PrimitiveID", "int", 0, false, FRAGMENT, 330),
+    variable!("gl_ClipDistance", "float", 8, false, FRAGMENT, 330),
+    variable!("gl_FragDepth", "float", 0, true, FRAGMENT, 330),
+    variable!("gl_SampleID", "int", 0, false, FRAGMENT, 400),
+    variable!("gl_SamplePosition", "vec2", 0, false, FRAGMENT, 400),
+    variable!("gl_SampleMaskIn", "int", 1, false, FRAGMENT, 400),
+    variable!("gl_SampleMask", "int", 1, true, FRAGMENT, 400),
+    variable!("gl_MaxVertexAttribs", "int", 0, false, None, 330),
+    variable!("gl_MaxVertexUniformComponents", "int", 0, false, None, 330),
+    variable!("gl_MaxFragmentUniformComponents", "int", 0, false, None, 330),
+    variable!("gl_MaxTextureImageUnits", "int", 0, false, None, 330),
+    variable!("gl_MaxVertexTextureImageUnits", "int", 0, false, None, 330),
+    variable!("gl_MaxCombinedTextureImageUnits", "int", 0, false, None, 330),
+    variable!("gl_MaxDrawBuffers", "int", 0, false, None, 330),
+    variable!("gl_MaxClipDistances", "int", 0, false, None, 330),
+];
+
+///
+/// Minimum values the spec guarantees for the gl_Max constants, used when they size arrays
+pub fn constant_value(name: &str) -> Option<i64>{
+    Some(match name{
+        "gl_MaxVertexAttribs" => 16,
+        "gl_MaxVertexUniformComponents" => 1024,
+        "gl_MaxFragmentUniformComponents" => 1024,
+        "gl_MaxTextureImageUnits" => 16,
+        "gl_MaxVertexTextureImageUnits" => 16,
+        "gl_MaxCombinedTextureImageUnits" => 48,
+        "gl_MaxDrawBuffers" => 8,
+        "gl_MaxClipDistances" => 8,
+        _ => return None,
+    })
+}
@@ -0,0 +1,2218 @@
+use std::{collections::{HashMap, HashSet}, rc::Rc};
+
+use crate::resource::shader::{ShaderDataType, ShaderStage};
+
+use super::{
+    Diagnostic, InterfaceBlock, InterfaceVariable, StageInterface,
+    ast::*,
+    builtins::{BUILTIN_FUNCTIONS, BUILTIN_VARIABLES, OUT_PARAMETERS, TEXTURE_FUNCTIONS, constant_value},
+    types::{SamplerType, Scalar, StructType, Type, std140_members},
+};
+
+///Layout qualifiers that take a value
+const VALUED_LAYOUTS: &[&str] = &["location", "index", "binding", "offset", "align"];
+
+///Layout qualifiers newer than the oldest supported version, with the version and the extension that enable them
+const LAYOUT_VERSIONS: &[(&str,u32,&str)] = &[
+    ("binding", 420, "GL_ARB_shading_language_420pack"),
+    ("offset", 440, "GL_ARB_enhanced_layouts"),
+    ("align", 440, "GL_ARB_enhanced_layouts"),
+    ("early_fragment_tests", 420, "GL_ARB_shader_image_load_store"),
+    ("std430", 430, "GL_ARB_shader_storage_buffer_object"),
+];
+
+const BLOCK_LAYOUTS: &[&str] = &["std140", "shared", "packed", "row_major", "column_major", "binding"];
+
+#[derive(Clone)]
+struct Variable{
+    ty: Type,
+    ///What makes the variable read only, as used in errors
+    readonly: Option<&'static str>,
+    constant: bool,
+    ///Value of integer constants, needed for array sizes
+    value: Option<i64>,
+    ///Name of the interface variable or block it belongs to, for tracking which are referenced
+    interface: Option<String>,
+}
+
+#[derive(Default)]
+struct Scope{
+    variables: HashMap<String,Variable>,
+    structs: HashMap<String,Rc<StructType>>,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+enum Access{
+    Value,
+    ReadOnly(String),
+    Writable,
+}
+
+///
+/// The type of an expression and whether it can be assigned to or folded
+#[derive(Clone,Debug)]
+struct Typed{
+    ty: Type,
+    access: Access,
+    constant: bool,
+    value: Option<i64>,
+}
+
+impl Typed{
+    fn value(ty: Type) -> Self{
+        Self{
+            ty,
+            access: Access::Value,
+            constant: false,
+            value: None,
+        }
+    }
+
+    fn constant(ty: Type, value: Option<i64>) -> Self{
+        Self{
+            ty,
+            access: Access::Value,
+            constant: true,
+            value,
+        }
+    }
+}
+
+struct Function{
+    params: Vec<(Type,Option<Storage>)>,
+    return_type: Type,
+    defined: bool,
+}
+
+///
+/// A place in a shader built-in function signature
+enum Pattern{
+    Gen(Scalar),
+    Vec(Scalar),
+    Mat,
+    MatT,
+    SqMat,
+    Fixed(Type),
+}
+
+#[derive(Default)]
+struct Binding{
+    components: Option<u8>,
+    matrix: Option<(u8,u8)>,
+}
+
+struct Checker<'a>{
+    stage: ShaderStage,
+    version: u32,
+    extensions: &'a [String],
+    scopes: Vec<Scope>,
+    functions: HashMap<String,Vec<Function>>,
+    diagnostics: Vec<Diagnostic>,
+    referenced: HashSet<String>,
+    ///User functions called before they were defined, with the line of the first call
+    called: Vec<(String,usize)>,
+    return_type: Option<Type>,
+    ///True for every enclosing loop, false for every enclosing switch
+    breakable: Vec<bool>,
+    default_std140: bool,
+    ///Interface variables along with the variable they were flattened from
+    inputs: Vec<(String,InterfaceVariable)>,
+    outputs: Vec<(String,InterfaceVariable)>,
+    uniforms: Vec<(String,InterfaceVariable)>,
+    blocks: Vec<InterfaceBlock>,
+}
+
+///
+/// Type checks a parsed stage and collects its interface
+pub fn check(externals: &[External], stage: ShaderStage, version: u32, extensions: &[String]) -> Result<StageInterface,Vec<Diagnostic>>{
+    let mut checker = Checker{
+        stage,
+        version,
+        extensions,
+        scopes: vec![Scope::default(), Scope::default()],
+        functions: HashMap::new(),
+        diagnostics: Vec::new(),
+        referenced: HashSet::new(),
+        called: Vec::new(),
+        return_type: None,
+        breakable: Vec::new(),
+        default_std140: false,
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        uniforms: Vec::new(),
+        blocks: Vec::new(),
+    };
+    checker.declare_builtin_variables();
+
+    for external in externals{
+        if let Err(e) = checker.external(external){
+            checker.diagnostics.push(e);
+        }
+    }
+
+    let has_main = checker.functions.get("main").is_some_and(|x|x.iter().any(|x|x.defined));
+    if !has_main{
+        checker.diagnostics.push(Diagnostic::new(0, "the shader has no main function"));
+    }
+    for (name, line) in std::mem::take(&mut checker.called){
+        if !checker.functions.get(&name).is_some_and(|x|x.iter().any(|x|x.defined)){
+            checker.diagnostics.push(Diagnostic::new(line, &format!("function {} is called but never defined",name)));
+        }
+    }
+    if !checker.diagnostics.is_empty(){
+        return Err(checker.diagnostics);
+    }
+
+    checker.check_locations()?;
+    let referenced = checker.referenced;
+    let finish = |variables: Vec<(String,InterfaceVariable)>|variables.into_iter().map(|(root, mut x)|{
+        x.referenced = referenced.contains(&root);
+        x
+    }).collect::<Vec<_>>();
+    let mut blocks = checker.blocks;
+    for block in &mut blocks{
+        block.referenced = referenced.contains(&block.name);
+    }
+    Ok(StageInterface{
+        stage,
+        version,
+        inputs: finish(checker.inputs),
+        outputs: finish(checker.outputs),
+        uniforms: finish(checker.uniforms),
+        uniform_blocks: blocks,
+    })
+}
+
+///
+/// Checks that the outputs of the vertex stage feed the inputs of the fragment stage and that shared uniforms agree
+pub fn link(vertex: &StageInterface, fragment: &StageInterface) -> Vec<String>{
+    let mut errors = Vec::new();
+    for input in &fragment.inputs{
+        match vertex.output(&input.name){
+            Some(output) if output.data_type != input.data_type || output.array_size != input.array_size => {
+                errors.push(format!("{} is {} in the vertex shader but {} in the fragment shader",input.name,describe(output),describe(input)));
+            },
+            Some(_) => {},
+            None if input.referenced => errors.push(format!("fragment input {} is read but the vertex shader has no output with that name",input.name)),
+            None => {},
+        }
+    }
+    for uniform in &fragment.uniforms{
+        if let Some(other) = vertex.uniform(&uniform.name){
+            if other.data_type != uniform.data_type || other.array_size != uniform.array_size{
+                errors.push(format!("uniform {} is {} in the vertex shader but {} in the fragment shader",uniform.name,describe(other),describe(uniform)));
+            }
+        }
+    }
+    for block in &fragment.uniform_blocks{
+        if let Some(other) = vertex.uniform_block(&block.name){
+            let same = other.members.len() == block.members.len() && other.members.iter().zip(&block.members).all(|((a, x), (b, y))|{
+                a == b && x.data_type == y.data_type && x.size == y.size && x.offset == y.offset
+            });
+            if !same{
+                errors.push(format!("uniform block {} has different members in the vertex and fragment shader",block.name));
+            }
+        }
+    }
+    errors
+}
+
+fn describe(variable: &InterfaceVariable) -> String{
+    match variable.array_size{
+        Some(n) => format!("{:?}[{}]",variable.data_type,n),
+        None => format!("{:?}",variable.data_type),
+    }
+}
+
+///
+/// Splits structs and arrays of structs into the names reflection reports, along with their type and array length
+fn flatten(name: &str, ty: &Type, out: &mut Vec<(String,ShaderDataType,Option<u32>)>){
+    match ty{
+        Type::Struct(x) => {
+            for (field, field_type) in &x.fields{
+                flatten(&format!("{}.{}",name,field), field_type, out);
+            }
+        },
+        Type::Array(element, length) if element.contains(&|x|matches!(x, Type::Struct(_))) => {
+            for i in 0..length.unwrap_or(1){
+                flatten(&format!("{}[{}]",name,i), element, out);
+            }
+        },
+        Type::Array(element, length) => {
+            out.push((String::from(name), element.data_type().unwrap_or(ShaderDataType::Other(0)), Some(length.unwrap_or(0))));
+        },
+        x => out.push((String::from(name), x.data_type().unwrap_or(ShaderDataType::Other(0)), None)),
+    }
+}
+
+impl<'a> Checker<'a>{
+    fn has_extension(&self, name: &str) -> bool{
+        self.extensions.iter().any(|x|x == name || x == "all")
+    }
+
+    fn requires(&self, version: u32, extension: Option<&str>, what: &str, line: usize) -> Result<(),Diagnostic>{
+        if self.version >= version || extension.is_some_and(|x|self.has_extension(x)){
+            return Ok(());
+        }
+        let message = match extension{
+            Some(x) => format!("{} requires GLSL {} or the {} extension",what,version,x),
+            None => format!("{} requires GLSL {}",what,version),
+        };
+        Err(Diagnostic::new(line, &message))
+    }
+
+    fn declare_builtin_variables(&mut self){
+        for builtin in BUILTIN_VARIABLES{
+            if builtin.min_version > self.version || builtin.stage.is_some_and(|x|x != self.stage){
+                continue;
+            }
+            let base = Type::from_name(builtin.ty, self.version).unwrap();
+            let ty = if builtin.array > 0{
+                Type::Array(Box::new(base), Some(builtin.array))
+            }
+            else{
+                base
+            };
+            let value = constant_value(builtin.name);
+            self.scopes[0].variables.insert(String::from(builtin.name), Variable{
+                ty,
+                readonly: if builtin.writable{ None } else if value.is_some(){ Some("constant") } else{ Some("built-in input") },
+                constant: value.is_some(),
+                value,
+                interface: None,
+            });
+        }
+    }
+
+    fn push_scope(&mut self){
+        self.scopes.push(Scope::default());
+    }
+
+    fn pop_scope(&mut self){
+        self.scopes.pop();
+    }
+
+    fn is_global(&self) -> bool{
+        self.scopes.len() == 2
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Variable>{
+        self.scopes.iter().rev().find_map(|x|x.variables.get(name))
+    }
+
+    fn lookup_struct(&self, name: &str) -> Option<Rc<StructType>>{
+        self.scopes.iter().rev().find_map(|x|x.structs.get(name)).cloned()
+    }
+
+    fn declare(&mut self, name: &str, variable: Variable, line: usize) -> Result<(),Diagnostic>{
+        if name.starts_with("gl_"){
+            return Err(Diagnostic::new(line, &format!("names starting with gl_ are reserved, {}",name)));
+        }
+        if self.is_global() && self.functions.contains_key(name){
+            return Err(Diagnostic::new(line, &format!("{} is already declared as a function",name)));
+        }
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.variables.contains_key(name) || scope.structs.contains_key(name){
+            return Err(Diagnostic::new(line, &format!("redefinition of {}",name)));
+        }
+        scope.variables.insert(String::from(name), variable);
+        Ok(())
+    }
+
+    fn can_convert_scalar(&self, from: Scalar, to: Scalar) -> bool{
+        from == to || matches!((from, to), (Scalar::Int, Scalar::Float) | (Scalar::UInt, Scalar::Float))
+            || (from == Scalar::Int && to == Scalar::UInt && self.version >= 400)
+    }
+
+    ///
+    /// True if the implicit conversions allow a value of type from where to is expected
+    fn can_convert(&self, from: &Type, to: &Type) -> bool{
+        if from == to{
+            return true;
+        }
+        match (from, to){
+            (Type::Scalar(a), Type::Scalar(b)) => self.can_convert_scalar(*a, *b),
+            (Type::Vector(a, n), Type::Vector(b, m)) => n == m && self.can_convert_scalar(*a, *b),
+            (Type::Array(a, Some(n)), Type::Array(b, None)) => a == b && *n > 0,
+            _ => false,
+        }
+    }
+
+    ///
+    /// The common component type two operands are converted to
+    fn promote(&self, a: Scalar, b: Scalar) -> Option<Scalar>{
+        if self.can_convert_scalar(a, b){
+            Some(b)
+        }
+        else if self.can_convert_scalar(b, a){
+            Some(a)
+        }
+        else{
+            None
+        }
+    }
+
+    fn external(&mut self, external: &External) -> Result<(),Diagnostic>{
+        match external{
+            External::Declaration(x) => self.global_declaration(x),
+            External::Block(x) => self.block(x),
+            External::Function(x) => self.function(x),
+            External::DefaultQualifiers(x) => self.default_qualifiers(x),
+            External::Ignored => Ok(()),
+        }
+    }
+
+    fn default_qualifiers(&mut self, qualifiers: &Qualifiers) -> Result<(),Diagnostic>{
+        match qualifiers.storage{
+            Some(Storage::Uniform) | Some(Storage::Buffer) => {
+                let layout = self.layout(qualifiers, &["std140", "std430", "shared", "packed", "row_major", "column_major"])?;
+                if layout.contains_key("std140"){
+                    self.default_std140 = true;
+                }
+                else if layout.contains_key("shared") || layout.contains_key("packed"){
+                    self.default_std140 = false;
+                }
+                Ok(())
+            },
+            Some(Storage::In) if self.stage == ShaderStage::Fragment => {
+                self.layout(qualifiers, &["early_fragment_tests"])?;
+                Ok(())
+            },
+            _ => Err(Diagnostic::new(qualifiers.line, "qualifiers without a declaration are only allowed for uniform and buffer layouts")),
+        }
+    }
+
+    ///
+    /// Checks the layout qualifiers against the allowed ones, returning their values
+    fn layout(&mut self, qualifiers: &Qualifiers, allowed: &[&str]) -> Result<HashMap<String,Option<u32>>,Diagnostic>{
+        let mut out = HashMap::new();
+        for (name, value) in &qualifiers.layout{
+            let line = qualifiers.line;
+            if !allowed.contains(&name.as_str()){
+                return Err(Diagnostic::new(line, &format!("layout qualifier {} isn't allowed here",name)));
+            }
+            if let Some((_, version, extension)) = LAYOUT_VERSIONS.iter().find(|(x, _, _)|x == name){
+                self.requires(*version, Some(extension), &format!("layout qualifier {}",name), line)?;
+            }
+            let value = match (VALUED_LAYOUTS.contains(&name.as_str()), value){
+                (true, Some(expr)) => {
+                    let typed = self.expr(expr)?;
+                    match typed.value{
+                        Some(x) if typed.constant && x >= 0 && typed.ty.scalar().is_some_and(|x|x.is_integer()) => Some(x as u32),
+                        _ => return Err(Diagnostic::new(line, &format!("layout qualifier {} needs a non negative constant integer",name))),
+                    }
+                },
+                (true, None) => return Err(Diagnostic::new(line, &format!("layout qualifier {} needs a value",name))),
+                (false, Some(_)) => return Err(Diagnostic::new(line, &format!("layout qualifier {} doesn't take a value",name))),
+                (false, None) => None,
+            };
+            out.insert(name.clone(), value);
+        }
+        Ok(out)
+    }
+
+    fn resolve_type(&mut self, spec: &TypeSpec) -> Result<Type,Diagnostic>{
+        let base = match &spec.name{
+            TypeName::Named(name) => match Type::from_name(name, self.version){
+                Some(x) => x,
+                None => match self.lookup_struct(name){
+                    Some(x) => Type::Struct(x),
+                    None if Type::from_name(name, u32::MAX).is_some() => return Err(Diagnostic::new(spec.line, &format!("{} isn't available in GLSL {}",name,self.version))),
+                    None => return Err(Diagnostic::new(spec.line, &format!("unknown type {}",name))),
+                }
+            },
+            TypeName::Struct(decl) => self.struct_decl(decl)?,
+        };
+        self.apply_array(base, &spec.array, spec.line)
+    }
+
+    ///
+    /// Wraps the type in the array dimensions, the first dimension is the outermost
+    fn apply_array(&mut self, base: Type, sizes: &ArraySizes, line: usize) -> Result<Type,Diagnostic>{
+        if sizes.is_empty(){
+            return Ok(base);
+        }
+        if sizes.len() > 1 || base.is_array(){
+            self.requires(430, Some("GL_ARB_arrays_of_arrays"), "arrays of arrays", line)?;
+        }
+        if base == Type::Void{
+            return Err(Diagnostic::new(line, "arrays of void aren't allowed"));
+        }
+        let mut ty = base;
+        for size in sizes.iter().rev(){
+            let length = match size{
+                Some(x) => Some(self.array_size(x)?),
+                None => None,
+            };
+            ty = Type::Array(Box::new(ty), length);
+        }
+        Ok(ty)
+    }
+
+    fn array_size(&mut self, expr: &Expr) -> Result<u32,Diagnostic>{
+        let typed = self.expr(expr)?;
+        if !typed.constant || !typed.ty.is_scalar() || !typed.ty.scalar().is_some_and(|x|x.is_integer()){
+            return Err(Diagnostic::new(expr.line, "array sizes have to be constant integer expressions"));
+        }
+        match typed.value{
+            Some(x) if x > 0 => Ok(x as u32),
+            Some(_) => Err(Diagnostic::new(expr.line, "array sizes have to be greater than zero")),
+            None => Err(Diagnostic::new(expr.line, "array size can't be evaluated, only integer arithmetic on constants is supported")),
+        }
+    }
+
+    fn struct_decl(&mut self, decl: &StructDecl) -> Result<Type,Diagnostic>{
+        let mut fields: Vec<(String,Type)> = Vec::new();
+        for member in &decl.fields{
+            if !member.qualifiers.is_empty(){
+                return Err(Diagnostic::new(member.line, "struct members can't have qualifiers"));
+            }
+            let base = self.resolve_type(&member.ty)?;
+            for (name, array) in &member.names{
+                let ty = self.apply_array(base.clone(), array, member.line)?;
+                if ty == Type::Void{
+                    return Err(Diagnostic::new(member.line, "struct members can't be void"));
+                }
+                if ty.contains(&|x|matches!(x, Type::Array(_, None))){
+                    return Err(Diagnostic::new(member.line, &format!("struct member {} needs an array size",name)));
+                }
+                if fields.iter().any(|(x, _)|x == name){
+                    return Err(Diagnostic::new(member.line, &format!("struct member {} is declared twice",name)));
+                }
+                fields.push((name.clone(), ty));
+            }
+        }
+        let name = decl.name.clone().unwrap_or_default();
+        let ty = Rc::new(StructType{
+            name: name.clone(),
+            fields,
+        });
+        if !name.is_empty(){
+            if name.starts_with("gl_"){
+                return Err(Diagnostic::new(decl.line, &format!("names starting with gl_ are reserved, {}",name)));
+            }
+            let scope = self.scopes.last_mut().unwrap();
+            if scope.structs.contains_key(&name) || scope.variables.contains_key(&name){
+                return Err(Diagnostic::new(decl.line, &format!("redefinition of {}",name)));
+            }
+            scope.structs.insert(name, ty.clone());
+        }
+        Ok(Type::Struct(ty))
+    }
+
+    ///
+    /// Type of a declarator, sized from its initializer if the declaration leaves the size out
+    fn declarator(&mut self, base: &Type, declarator: &Declarator, readonly: Option<&'static str>, require_constant: bool) -> Result<Variable,Diagnostic>{
+        let line = declarator.line;
+        let mut ty = self.apply_array(base.clone(), &declarator.array, line)?;
+        if ty == Type::Void{
+            return Err(Diagnostic::new(line, &format!("{} can't be void",declarator.name)));
+        }
+        let mut constant = false;
+        let mut value = None;
+        if let Some(init) = &declarator.init{
+            let typed = self.initializer(init, &ty)?;
+            if let (Type::Array(_, None), Type::Array(_, Some(_))) = (&ty, &typed.ty){
+                ty = typed.ty.clone();
+            }
+            if !self.can_convert(&typed.ty, &ty){
+                return Err(Diagnostic::new(line, &format!("can't initialize {} of type {} with {}",declarator.name,ty,typed.ty)));
+            }
+            if require_constant && !typed.constant{
+                return Err(Diagnostic::new(line, &format!("{} has to be initialized with a constant expression",declarator.name)));
+            }
+            constant = typed.constant && readonly == Some("constant");
+            value = if constant && ty.scalar().is_some_and(|x|x.is_integer()) && ty.is_scalar(){ typed.value } else{ None };
+        }
+        if let Type::Array(_, None) = ty{
+            return Err(Diagnostic::new(line, &format!("{} needs an array size or an initializer",declarator.name)));
+        }
+        Ok(Variable{
+            ty,
+            readonly,
+            constant,
+            value,
+            interface: None,
+        })
+    }
+
+    ///
+    /// Declares a variable whose declaration has an error anyway, so later uses don't report it as undeclared
+    fn declare_invalid(&mut self, base: &Type, declarator: &Declarator, readonly: Option<&'static str>, error: Diagnostic) -> Diagnostic{
+        let ty = self.apply_array(base.clone(), &declarator.array, declarator.line).unwrap_or_else(|_|base.clone());
+        let _ = self.declare(&declarator.name, Variable{
+            ty,
+            readonly,
+            constant: false,
+            value: None,
+            interface: None,
+        }, declarator.line);
+        error
+    }
+
+    fn initializer(&mut self, init: &Expr, ty: &Type) -> Result<Typed,Diagnostic>{
+        let items = match &init.kind{
+            ExprKind::List(x) => x,
+            _ => return self.expr(init),
+        };
+        self.requires(420, Some("GL_ARB_shading_language_420pack"), "initializer lists", init.line)?;
+        let expected: Vec<Type> = match ty{
+            Type::Array(element, length) => {
+                if length.is_some_and(|x|x as usize != items.len()){
+                    return Err(Diagnostic::new(init.line, &format!("{} needs {} initializers but has {}",ty,length.unwrap(),items.len())));
+                }
+                vec![(**element).clone();items.len()]
+            },
+            Type::Struct(x) => x.fields.iter().map(|(_, t)|t.clone()).collect(),
+            Type::Vector(s, n) => vec![Type::Scalar(*s);*n as usize],
+            Type::Matrix(c, r) => vec![Type::Vector(Scalar::Float, *r);*c as usize],
+            x => vec![x.clone()],
+        };
+        if expected.len() != items.len(){
+            return Err(Diagnostic::new(init.line, &format!("{} needs {} initializers but has {}",ty,expected.len(),items.len())));
+        }
+        let mut constant = true;
+        for (item, item_type) in items.iter().zip(&expected){
+            let typed = self.initializer(item, item_type)?;
+            if !self.can_convert(&typed.ty, item_type){
+                return Err(Diagnostic::new(item.line, &format!("can't initialize {} with {}",item_type,typed.ty)));
+            }
+            constant &= typed.constant;
+        }
+        let ty = match ty{
+            Type::Array(element, None) => Type::Array(element.clone(), Some(items.len() as u32)),
+            x => x.clone(),
+        };
+        Ok(Typed{
+            ty,
+            access: Access::Value,
+            constant,
+            value: None,
+        })
+    }
+
+    fn global_declaration(&mut self, decl: &Declaration) -> Result<(),Diagnostic>{
+        let q = &decl.qualifiers;
+        let base = self.resolve_type(&decl.ty)?;
+
+        //redeclarations of built-in variables, like out float gl_FragDepth, only change qualifiers
+        if decl.declarators.iter().all(|x|x.name.starts_with("gl_")) && !decl.declarators.is_empty(){
+            for declarator in &decl.declarators{
+                if self.lookup(&declarator.name).is_none(){
+                    return Err(Diagnostic::new(declarator.line, &format!("{} isn't a built-in variable of the {} stage",declarator.name,self.stage.name())));
+                }
+            }
+            return Ok(());
+        }
+
+        let storage = q.storage;
+        if q.interpolation.is_some() || q.auxiliary.is_some(){
+            let varying = matches!((storage, self.stage), (Some(Storage::Out), ShaderStage::Vertex) | (Some(Storage::In), ShaderStage::Fragment));
+            if !varying{
+                return Err(Diagnostic::new(q.line, "interpolation qualifiers are only allowed on vertex outputs and fragment inputs"));
+            }
+        }
+
+        let (readonly, allowed_layouts): (Option<&'static str>, &[&str]) = match (storage, self.stage){
+            (None, _) => (None, &[]),
+            (Some(Storage::Const), _) => (Some("constant"), &[]),
+            (Some(Storage::Uniform), _) => (Some("uniform"), &["location", "binding"]),
+            (Some(Storage::In), ShaderStage::Vertex) => (Some("input"), &["location"]),
+            (Some(Storage::Out), ShaderStage::Fragment) => (None, &["location", "index"]),
+            (Some(Storage::In), _) => (Some("input"), &["location"]),
+            (Some(Storage::Out), _) => (None, &["location"]),
+            (Some(Storage::InOut), _) => return Err(Diagnostic::new(q.line, "inout is only allowed on function parameters")),
+            (Some(Storage::Buffer), _) => return Err(Diagnostic::new(q.line, "buffer variables have to be declared in a block")),
+        };
+        let layout = self.layout(q, allowed_layouts)?;
+        let location = layout.get("location").copied().flatten();
+        let binding = layout.get("binding").copied().flatten();
+        if location.is_some(){
+            match (storage, self.stage){
+                (Some(Storage::Uniform), _) => self.requires(430, Some("GL_ARB_explicit_uniform_location"), "uniform locations", q.line)?,
+                (Some(Storage::Out), ShaderStage::Vertex) | (Some(Storage::In), ShaderStage::Fragment) => {
+                    self.requires(410, Some("GL_ARB_separate_shader_objects"), "locations on vertex outputs and fragment inputs", q.line)?
+                },
+                _ => {}
+            }
+        }
+        if binding.is_some() && !base.is_opaque(){
+            return Err(Diagnostic::new(q.line, "binding is only allowed on samplers and blocks"));
+        }
+
+        for declarator in &decl.declarators{
+            let line = declarator.line;
+            //initializers of globals have to be constant, whatever their storage
+            let mut variable = match self.declarator(&base, declarator, readonly, true){
+                Ok(x) => x,
+                Err(e) => return Err(self.declare_invalid(&base, declarator, readonly, e)),
+            };
+            let ty = variable.ty.clone();
+            match storage{
+                Some(Storage::Const) if declarator.init.is_none() => {
+                    return Err(Diagnostic::new(line, &format!("constant {} needs an initializer",declarator.name)));
+                },
+                Some(Storage::In) | Some(Storage::Out) if declarator.init.is_some() => {
+                    return Err(Diagnostic::new(line, &format!("{} can't have an initializer",declarator.name)));
+                },
+                _ => {}
+            }
+            if ty.is_opaque() && storage != Some(Storage::Uniform){
+                return Err(Diagnostic::new(line, &format!("{} is {}, samplers can only be uniforms or function parameters",declarator.name,ty)));
+            }
+            if let Err(e) = self.check_interface_type(storage, &ty, q, &declarator.name, line){
+                return Err(self.declare_invalid(&base, declarator, readonly, e));
+            }
+
+            let interface = match storage{
+                Some(Storage::In) | Some(Storage::Out) | Some(Storage::Uniform) => Some(declarator.name.clone()),
+                _ => None,
+            };
+            if let Some(root) = &interface{
+                let mut flat = Vec::new();
+                flatten(root, &ty, &mut flat);
+                let single = flat.len() == 1;
+                let list = match storage{
+                    Some(Storage::In) => &mut self.inputs,
+                    Some(Storage::Out) => &mut self.outputs,
+                    _ => &mut self.uniforms,
+                };
+                for (name, data_type, array_size) in flat{
+                    list.push((root.clone(), InterfaceVariable{
+                        name,
+                        data_type,
+                        array_size,
+                        location: if single{ location } else{ None },
+                        binding,
+                        referenced: false,
+                    }));
+                }
+            }
+            variable.interface = interface;
+            self.declare(&declarator.name, variable, line)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Types each kind of stage input and output may have
+    fn check_interface_type(&self, storage: Option<Storage>, ty: &Type, q: &Qualifiers, name: &str, line: usize) -> Result<(),Diagnostic>{
+        let has_bool = ty.contains(&|x|x.scalar() == Some(Scalar::Bool));
+        let has_struct = ty.contains(&|x|matches!(x, Type::Struct(_)));
+        let error = |what: &str|Err(Diagnostic::new(line, &format!("{} {} can't be {}",what,name,ty)));
+        match (storage, self.stage){
+            (Some(Storage::In), ShaderStage::Vertex) if has_bool || has_struct => error("vertex input"),
+            (Some(Storage::Out), ShaderStage::Vertex) if has_bool => error("vertex output"),
+            (Some(Storage::In), ShaderStage::Fragment) if has_bool => error("fragment input"),
+            (Some(Storage::In), ShaderStage::Fragment) if ty.contains(&|x|x.scalar().is_some_and(|x|x.is_integer())) && q.interpolation.as_deref() != Some("flat") => {
+                Err(Diagnostic::new(line, &format!("integer fragment input {} has to be declared flat",name)))
+            },
+            (Some(Storage::Out), ShaderStage::Fragment) if has_bool || has_struct || ty.contains(&|x|matches!(x, Type::Matrix(_, _))) => error("fragment output"),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_locations(&self) -> Result<(),Vec<Diagnostic>>{
+        let mut errors = Vec::new();
+        let lists: [(&str,&Vec<(String,InterfaceVariable)>);2] = match self.stage{
+            ShaderStage::Vertex => [("vertex output", &self.outputs), ("vertex output", &self.outputs)],
+            ShaderStage::Fragment => [("fragment output", &self.outputs), ("fragment input", &self.inputs)],
+        };
+        for (what, list) in lists.iter().take(if self.stage == ShaderStage::Vertex{ 1 } else{ 2 }){
+            let mut used: HashMap<u32,&str> = HashMap::new();
+            for (_, variable) in list.iter(){
+                if let Some(location) = variable.location{
+                    let count = variable.array_size.unwrap_or(1).max(1);
+                    for slot in location..location + count{
+                        if let Some(other) = used.insert(slot, &variable.name){
+                            errors.push(Diagnostic::new(0, &format!("{}s {} and {} share location {}",what,other,variable.name,slot)));
+                        }
+                    }
+                }
+            }
+        }
+        if errors.is_empty(){
+            Ok(())
+        }
+        else{
+            Err(errors)
+        }
+    }
+
+    fn block(&mut self, decl: &BlockDecl) -> Result<(),Diagnostic>{
+        let q = &decl.qualifiers;
+        let line = decl.line;
+        if decl.name == "gl_PerVertex"{
+            return Ok(());
+        }
+        let storage = q.storage.unwrap();
+        let layout = match storage{
+            Storage::Uniform => self.layout(q, BLOCK_LAYOUTS)?,
+            Storage::Buffer => {
+                self.requires(430, Some("GL_ARB_shader_storage_buffer_object"), "buffer blocks", line)?;
+                let mut allowed = BLOCK_LAYOUTS.to_vec();
+                allowed.push("std430");
+                self.layout(q, &allowed)?
+            },
+            Storage::In | Storage::Out => {
+                let varying = matches!((storage, self.stage), (Storage::Out, ShaderStage::Vertex) | (Storage::In, ShaderStage::Fragment));
+                if !varying{
+                    return Err(Diagnostic::new(line, &format!("{} blocks aren't allowed in the {} stage",if storage == Storage::In{ "input" } else{ "output" },self.stage.name())));
+                }
+                self.layout(q, &["location"])?
+            },
+            _ => return Err(Diagnostic::new(line, "blocks have to be uniform, buffer, in or out")),
+        };
+        if self.blocks.iter().any(|x|x.name == decl.name) || self.lookup_struct(&decl.name).is_some(){
+            return Err(Diagnostic::new(line, &format!("redefinition of block {}",decl.name)));
+        }
+
+        let mut fields: Vec<(String,Type)> = Vec::new();
+        for member in &decl.members{
+            self.layout(&member.qualifiers, &["row_major", "column_major", "offset", "align"])?;
+            if member.qualifiers.storage.is_some_and(|x|x != storage){
+                return Err(Diagnostic::new(member.line, "block members can't change the storage of the block"));
+            }
+            let base = self.resolve_type(&member.ty)?;
+            for (name, array) in &member.names{
+                let ty = self.apply_array(base.clone(), array, member.line)?;
+                if ty.is_opaque(){
+                    return Err(Diagnostic::new(member.line, &format!("block member {} can't be a sampler",name)));
+                }
+                if storage != Storage::Buffer && ty.contains(&|x|matches!(x, Type::Array(_, None))){
+                    return Err(Diagnostic::new(member.line, &format!("block member {} needs an array size",name)));
+                }
+                if fields.iter().any(|(x, _)|x == name){
+                    return Err(Diagnostic::new(member.line, &format!("block member {} is declared twice",name)));
+                }
+                if matches!(storage, Storage::In | Storage::Out){
+                    self.check_interface_type(Some(storage), &ty, &member.qualifiers, name, member.line)?;
+                }
+                fields.push((name.clone(), ty));
+            }
+        }
+
+        let readonly = match storage{
+            Storage::Uniform => Some("uniform"),
+            Storage::In => Some("input"),
+            _ => None,
+        };
+        match &decl.instance{
+            Some((instance, array)) => {
+                let block_type = Type::Struct(Rc::new(StructType{
+                    name: decl.name.clone(),
+                    fields: fields.clone(),
+                }));
+                let ty = self.apply_array(block_type, array, line)?;
+                self.declare(instance, Variable{
+                    ty,
+                    readonly,
+                    constant: false,
+                    value: None,
+                    interface: Some(decl.name.clone()),
+                }, line)?;
+            },
+            None => {
+                for (name, ty) in &fields{
+                    self.declare(name, Variable{
+                        ty: ty.clone(),
+                        readonly,
+                        constant: false,
+                        value: None,
+                        interface: Some(decl.name.clone()),
+                    }, line)?;
+                }
+            }
+        }
+
+        match storage{
+            Storage::Uniform => {
+                let std140 = layout.contains_key("std140") || (self.default_std140 && !layout.contains_key("shared") && !layout.contains_key("packed"));
+                let (members, data_size) = if std140{
+                    std140_members(&fields)
+                }
+                else{
+                    let (mut members, _) = std140_members(&fields);
+                    for (_, member) in &mut members{
+                        member.offset = -1;
+                        member.array_stride = -1;
+                        member.matrix_stride = -1;
+                    }
+                    (members, -1)
+                };
+                self.blocks.push(InterfaceBlock{
+                    name: decl.name.clone(),
+                    instance: decl.instance.as_ref().map(|(x, _)|x.clone()),
+                    binding: layout.get("binding").copied().flatten(),
+                    std140,
+                    data_size,
+                    members,
+                    referenced: false,
+                });
+            },
+            Storage::In | Storage::Out => {
+                let list = if storage == Storage::In{ &mut self.inputs } else{ &mut self.outputs };
+                for (name, ty) in &fields{
+                    let mut flat = Vec::new();
+                    flatten(&format!("{}.{}",decl.name,name), ty, &mut flat);
+                    for (name, data_type, array_size) in flat{
+                        list.push((decl.name.clone(), InterfaceVariable{
+                            name,
+                            data_type,
+                            array_size,
+                            location: None,
+                            binding: None,
+                            referenced: false,
+                        }));
+                    }
+                }
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_builtin_function(name: &str) -> bool{
+        BUILTIN_FUNCTIONS.iter().any(|x|x.name == name) || TEXTURE_FUNCTIONS.contains(&name) || name == "outerProduct"
+    }
+
+    fn function(&mut self, decl: &FunctionDecl) -> Result<(),Diagnostic>{
+        let line = decl.line;
+        if Self::is_builtin_function(&decl.name){
+            return Err(Diagnostic::new(line, &format!("built-in function {} can't be redefined or overloaded",decl.name)));
+        }
+        if decl.name.starts_with("gl_"){
+            return Err(Diagnostic::new(line, &format!("names starting with gl_ are reserved, {}",decl.name)));
+        }
+        if self.scopes[1].variables.contains_key(&decl.name) || self.scopes[1].structs.contains_key(&decl.name){
+            return Err(Diagnostic::new(line, &format!("{} is already declared as a variable or type",decl.name)));
+        }
+        let return_type = self.resolve_type(&decl.return_type)?;
+        let mut params = Vec::new();
+        for param in &decl.params{
+            if param.qualifiers.storage == Some(Storage::Uniform) || param.qualifiers.storage == Some(Storage::Buffer) || !param.qualifiers.layout.is_empty(){
+                return Err(Diagnostic::new(param.line, "parameters can only be qualified with const, in, out or inout"));
+            }
+            let base = self.resolve_type(&param.ty)?;
+            let ty = self.apply_array(base, &param.array, param.line)?;
+            if ty == Type::Void{
+                return Err(Diagnostic::new(param.line, "parameters can't be void"));
+            }
+            if matches!(ty, Type::Array(_, None)){
+                return Err(Diagnostic::new(param.line, "array parameters need a size"));
+            }
+            if ty.is_opaque() && matches!(param.qualifiers.storage, Some(Storage::Out) | Some(Storage::InOut)){
+                return Err(Diagnostic::new(param.line, "samplers can only be in parameters"));
+            }
+            params.push((ty, param.qualifiers.storage));
+        }
+        if decl.name == "main" && (return_type != Type::Void || !params.is_empty()){
+            return Err(Diagnostic::new(line, "main has to be declared as void main()"));
+        }
+
+        let overloads = self.functions.entry(decl.name.clone()).or_default();
+        let existing = overloads.iter_mut().find(|x|x.params.iter().map(|(t, _)|t).eq(params.iter().map(|(t, _)|t)));
+        match existing{
+            Some(x) => {
+                if x.return_type != return_type{
+                    return Err(Diagnostic::new(line, &format!("function {} is redeclared with a different return type",decl.name)));
+                }
+                if x.params.iter().map(|(_, q)|normalize_direction(*q)).ne(params.iter().map(|(_, q)|normalize_direction(*q))){
+                    return Err(Diagnostic::new(line, &format!("function {} is redeclared with different parameter qualifiers",decl.name)));
+                }
+                if x.defined && decl.body.is_some(){
+                    return Err(Diagnostic::new(line, &format!("redefinition of function {}",decl.name)));
+                }
+                x.defined |= decl.body.is_some();
+            },
+            None => overloads.push(Function{
+                params: params.clone(),
+                return_type: return_type.clone(),
+                defined: decl.body.is_some(),
+            }),
+        }
+
+        let body = match &decl.body{
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        self.push_scope();
+        for (param, (ty, storage)) in decl.params.iter().zip(&params){
+            if let Some(name) = &param.name{
+                let result = self.declare(name, Variable{
+                    ty: ty.clone(),
+                    readonly: if *storage == Some(Storage::Const){ Some("constant parameter") } else{ None },
+                    constant: false,
+                    value: None,
+                    interface: None,
+                }, param.line);
+                if let Err(e) = result{
+                    self.diagnostics.push(e);
+                }
+            }
+        }
+        self.return_type = Some(return_type);
+        //the body shares the scope of the parameters, so it can't redeclare them
+        for statement in body{
+            if let Err(e) = self.statement(statement){
+                self.diagnostics.push(e);
+            }
+        }
+        self.return_type = None;
+        self.pop_scope();
+        Ok(())
+    }
+
+    fn statements(&mut self, statements: &[Stmt]){
+        for statement in statements{
+            if let Err(e) = self.statement(statement){
+                self.diagnostics.push(e);
+            }
+        }
+    }
+
+    fn scoped_statement(&mut self, statement: &Stmt) -> Result<(),Diagnostic>{
+        self.push_scope();
+        let result = self.statement(statement);
+        self.pop_scope();
+        result
+    }
+
+    fn condition(&mut self, expr: &Expr) -> Result<(),Diagnostic>{
+        let typed = self.expr(expr)?;
+        if typed.ty != Type::BOOL{
+            return Err(Diagnostic::new(expr.line, &format!("conditions have to be bool, found {}",typed.ty)));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(),Diagnostic>{
+        let line = statement.line;
+        match &statement.kind{
+            StmtKind::Block(x) => {
+                self.push_scope();
+                self.statements(x);
+                self.pop_scope();
+            },
+            StmtKind::Declaration(x) => self.local_declaration(x)?,
+            StmtKind::Expr(x) => {
+                self.expr(x)?;
+            },
+            StmtKind::Empty => {},
+            StmtKind::If{condition, then, otherwise} => {
+                self.condition(condition)?;
+                if let Err(e) = self.scoped_statement(then){
+                    self.diagnostics.push(e);
+                }
+                if let Some(otherwise) = otherwise{
+                    self.scoped_statement(otherwise)?;
+                }
+            },
+            StmtKind::For{init, condition, step, body} => {
+                self.push_scope();
+                let result = (||{
+                    if let Some(init) = init{
+                        self.statement(init)?;
+                    }
+                    if let Some(condition) = condition{
+                        self.condition(condition)?;
+                    }
+                    if let Some(step) = step{
+                        self.expr(step)?;
+                    }
+                    self.loop_body(body)
+                })();
+                self.pop_scope();
+                result?;
+            },
+            StmtKind::While{condition, body} => {
+                self.condition(condition)?;
+                self.loop_body(body)?;
+            },
+            StmtKind::DoWhile{body, condition} => {
+                self.loop_body(body)?;
+                self.condition(condition)?;
+            },
+            StmtKind::Switch{value, body} => self.switch(value, body)?,
+            StmtKind::Case(_) => return Err(Diagnostic::new(line, "case label outside of a switch")),
+            StmtKind::Default => return Err(Diagnostic::new(line, "default label outside of a switch")),
+            StmtKind::Break => {
+                if self.breakable.is_empty(){
+                    return Err(Diagnostic::new(line, "break outside of a loop or switch"));
+                }
+            },
+            StmtKind::Continue => {
+                if !self.breakable.contains(&true){
+                    return Err(Diagnostic::new(line, "continue outside of a loop"));
+                }
+            },
+            StmtKind::Return(value) => {
+                let expected = self.return_type.clone().unwrap_or(Type::Void);
+                match (value, &expected){
+                    (None, Type::Void) => {},
+                    (None, x) => return Err(Diagnostic::new(line, &format!("the function has to return {}",x))),
+                    (Some(_), Type::Void) => return Err(Diagnostic::new(line, "void functions can't return a value")),
+                    (Some(value), expected) => {
+                        let typed = self.expr(value)?;
+                        if !self.can_convert(&typed.ty, expected){
+                            return Err(Diagnostic::new(line, &format!("returning {} from a function that returns {}",typed.ty,expected)));
+                        }
+                    }
+                }
+            },
+            StmtKind::Discard => {
+                if self.stage != ShaderStage::Fragment{
+                    return Err(Diagnostic::new(line, "discard is only allowed in fragment shaders"));
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn loop_body(&mut self, body: &Stmt) -> Result<(),Diagnostic>{
+        self.breakable.push(true);
+        let result = self.scoped_statement(body);
+        self.breakable.pop();
+        result
+    }
+
+    fn switch(&mut self, value: &Expr, body: &[Stmt]) -> Result<(),Diagnostic>{
+        let typed = self.expr(value)?;
+        if typed.ty != Type::INT && typed.ty != Type::UINT{
+            return Err(Diagnostic::new(value.line, &format!("switch needs an int or uint value, found {}",typed.ty)));
+        }
+        if !matches!(body.first().map(|x|&x.kind), Some(StmtKind::Case(_)) | Some(StmtKind::Default) | None){
+            return Err(Diagnostic::new(body[0].line, "statements in a switch have to follow a case label"));
+        }
+        self.breakable.push(false);
+        self.push_scope();
+        let mut labels = HashSet::new();
+        let mut has_default = false;
+        for statement in body{
+            let result = match &statement.kind{
+                StmtKind::Case(label) => self.expr(label).and_then(|label|{
+                    if !label.constant || label.ty != typed.ty{
+                        return Err(Diagnostic::new(statement.line, &format!("case labels have to be constant {} expressions",typed.ty)));
+                    }
+                    match label.value{
+                        Some(x) if !labels.insert(x) => Err(Diagnostic::new(statement.line, &format!("duplicate case label {}",x))),
+                        _ => Ok(()),
+                    }
+                }),
+                StmtKind::Default if has_default => Err(Diagnostic::new(statement.line, "duplicate default label")),
+                StmtKind::Default => {
+                    has_default = true;
+                    Ok(())
+                },
+                _ => self.statement(statement),
+            };
+            if let Err(e) = result{
+                self.diagnostics.push(e);
+            }
+        }
+        self.pop_scope();
+        self.breakable.pop();
+        Ok(())
+    }
+
+    fn local_declaration(&mut self, decl: &Declaration) -> Result<(),Diagnostic>{
+        let q = &decl.qualifiers;
+        if !q.layout.is_empty() || q.interpolation.is_some() || q.auxiliary.is_some() || q.invariant{
+            return Err(Diagnostic::new(q.line, "local variables can't have layout, interpolation or invariant qualifiers"));
+        }
+        let readonly = match q.storage{
+            None => None,
+            Some(Storage::Const) => Some("constant"),
+            Some(_) => return Err(Diagnostic::new(q.line, "local variables can only be qualified with const")),
+        };
+        let base = self.resolve_type(&decl.ty)?;
+        for declarator in &decl.declarators{
+            let line = declarator.line;
+            let variable = match self.declarator(&base, declarator, readonly, readonly.is_some() && self.version < 420){
+                Ok(x) => x,
+                Err(e) => return Err(self.declare_invalid(&base, declarator, readonly, e)),
+            };
+            if readonly.is_some() && declarator.init.is_none(){
+                return Err(Diagnostic::new(line, &format!("constant {} needs an initializer",declarator.name)));
+            }
+            if variable.ty.is_opaque(){
+                return Err(Diagnostic::new(line, &format!("{} is {}, samplers can only be uniforms or function parameters",declarator.name,variable.ty)));
+            }
+            self.declare(&declarator.name, variable, line)?;
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Typed,Diagnostic>{
+        let line = expr.line;
+        let error = |message: String|Err(Diagnostic::new(line, &message));
+        match &expr.kind{
+            ExprKind::Ident(name) => {
+                let variable = match self.lookup(name){
+                    Some(x) => x.clone(),
+                    None => return error(format!("undeclared identifier {}",name)),
+                };
+                if let Some(interface) = &variable.interface{
+                    self.referenced.insert(interface.clone());
+                }
+                let access = match variable.readonly{
+                    Some(x) => Access::ReadOnly(format!("{} {}",x,name)),
+                    None => Access::Writable,
+                };
+                Ok(Typed{
+                    ty: variable.ty,
+                    access,
+                    constant: variable.constant,
+                    value: variable.value,
+                })
+            },
+            ExprKind::Int(x) | ExprKind::UInt(x) => {
+                if *x > u32::MAX as u64{
+                    return error(format!("integer literal {} doesn't fit in 32 bits",x));
+                }
+                let (ty, value) = match &expr.kind{
+                    ExprKind::Int(_) => (Type::INT, *x as u32 as i32 as i64),
+                    _ => (Type::UINT, *x as i64),
+                };
+                Ok(Typed::constant(ty, Some(value)))
+            },
+            ExprKind::Float => Ok(Typed::constant(Type::FLOAT, None)),
+            ExprKind::Bool(x) => Ok(Typed::constant(Type::BOOL, Some(*x as i64))),
+            ExprKind::Unary(op, operand) => {
+                let typed = self.expr(operand)?;
+                self.unary(op, typed, line)
+            },
+            ExprKind::Postfix(op, operand) => {
+                let typed = self.expr(operand)?;
+                self.increment(op, &typed, line)?;
+                Ok(Typed::value(typed.ty))
+            },
+            ExprKind::Binary(op, left, right) => {
+                let left = self.expr(left)?;
+                let right = self.expr(right)?;
+                self.binary(op, &left, &right, line)
+            },
+            ExprKind::Assign(op, target, value) => {
+                let target = self.expr(target)?;
+                let value = self.expr(value)?;
+                self.writable(&target, line)?;
+                if *op == "="{
+                    if !self.can_convert(&value.ty, &target.ty) || matches!(target.ty, Type::Array(_, None)){
+                        return error(format!("can't assign {} to {}",value.ty,target.ty));
+                    }
+                }
+                else{
+                    let result = self.binary(&op[..op.len() - 1], &target, &value, line)?;
+                    if result.ty != target.ty{
+                        return error(format!("{} {} {} gives {}, which can't be assigned back",target.ty,op,value.ty,result.ty));
+                    }
+                }
+                Ok(Typed::value(target.ty))
+            },
+            ExprKind::Ternary(condition, then, otherwise) => {
+                let condition = self.expr(condition)?;
+                if condition.ty != Type::BOOL{
+                    return error(format!("the condition of ?: has to be bool, found {}",condition.ty));
+                }
+                let then = self.expr(then)?;
+                let otherwise = self.expr(otherwise)?;
+                let ty = if self.can_convert(&otherwise.ty, &then.ty){
+                    then.ty.clone()
+                }
+                else if self.can_convert(&then.ty, &otherwise.ty){
+                    otherwise.ty.clone()
+                }
+                else{
+                    return error(format!("the branches of ?: have different types, {} and {}",then.ty,otherwise.ty));
+                };
+                let constant = condition.constant && then.constant && otherwise.constant;
+                let value = match condition.value{
+                    Some(0) => otherwise.value,
+                    Some(_) => then.value,
+                    None => None,
+                };
+                Ok(Typed{
+                    ty,
+                    access: Access::Value,
+                    constant,
+                    value: if constant{ value } else{ None },
+                })
+            },
+            ExprKind::Call{name, array, args} => self.call(name, array.as_ref(), args, line),
+            ExprKind::Index(base, index) => {
+                let base = self.expr(base)?;
+                let index = self.expr(index)?;
+                self.index(base, index, line)
+            },
+            ExprKind::Field(base, field) => {
+                let base = self.expr(base)?;
+                self.field(base, field, line)
+            },
+            ExprKind::Length(base) => {
+                let base = self.expr(base)?;
+                let value = match &base.ty{
+                    Type::Array(_, Some(n)) => Some(*n as i64),
+                    Type::Array(_, None) => None,
+                    Type::Vector(_, n) => Some(*n as i64),
+                    Type::Matrix(c, _) => Some(*c as i64),
+                    x => return error(format!("length() needs an array, vector or matrix, found {}",x)),
+                };
+                Ok(Typed{
+                    ty: Type::INT,
+                    access: Access::Value,
+                    constant: value.is_some(),
+                    value,
+                })
+            },
+            ExprKind::Sequence(left, right) => {
+                self.expr(left)?;
+                let right = self.expr(right)?;
+                Ok(Typed::value(right.ty))
+            },
+            ExprKind::List(_) => error(String::from("initializer lists are only allowed in declarations")),
+        }
+    }
+
+    fn writable(&self, typed: &Typed, line: usize) -> Result<(),Diagnostic>{
+        match &typed.access{
+            Access::Writable => Ok(()),
+            Access::ReadOnly(x) => Err(Diagnostic::new(line, &format!("can't assign to {}",x))),
+            Access::Value => Err(Diagnostic::new(line, "can't assign to an expression that isn't a variable")),
+        }
+    }
+
+    fn increment(&self, op: &str, typed: &Typed, line: usize) -> Result<(),Diagnostic>{
+        if !typed.ty.is_numeric() || typed.ty.is_array(){
+            return Err(Diagnostic::new(line, &format!("{} needs a numeric operand, found {}",op,typed.ty)));
+        }
+        self.writable(typed, line)
+    }
+
+    fn unary(&self, op: &str, typed: Typed, line: usize) -> Result<Typed,Diagnostic>{
+        let valid = match op{
+            "++" | "--" => {
+                self.increment(op, &typed, line)?;
+                return Ok(Typed::value(typed.ty));
+            },
+            "+" | "-" => typed.ty.is_numeric(),
+            "!" => typed.ty == Type::BOOL,
+            _ => typed.ty.scalar().is_some_and(|x|x.is_integer()) && !matches!(typed.ty, Type::Matrix(_, _)),
+        };
+        if !valid{
+            return Err(Diagnostic::new(line, &format!("operator {} doesn't work on {}",op,typed.ty)));
+        }
+        let value = typed.value.map(|x|match op{
+            "-" => x.wrapping_neg(),
+            "!" => (x == 0) as i64,
+            "~" => !x,
+            _ => x,
+        });
+        Ok(Typed{
+            ty: typed.ty,
+            access: Access::Value,
+            constant: typed.constant,
+            value,
+        })
+    }
+
+    fn binary(&self, op: &str, left: &Typed, right: &Typed, line: usize) -> Result<Typed,Diagnostic>{
+        let mismatch = ||Err(Diagnostic::new(line, &format!("operator {} doesn't work on {} and {}",op,left.ty,right.ty)));
+        let constant = left.constant && right.constant;
+        let ty = match op{
+            "&&" | "||" | "^^" => {
+                if left.ty != Type::BOOL || right.ty != Type::BOOL{
+                    return mismatch();
+                }
+                Type::BOOL
+            },
+            "==" | "!=" => {
+                if !(self.can_convert(&left.ty, &right.ty) || self.can_convert(&right.ty, &left.ty)) || left.ty.is_opaque() || left.ty == Type::Void{
+                    return mismatch();
+                }
+                Type::BOOL
+            },
+            "<" | ">" | "<=" | ">=" => {
+                match (&left.ty, &right.ty){
+                    (Type::Scalar(a), Type::Scalar(b)) if a.is_numeric() && b.is_numeric() && self.promote(*a, *b).is_some() => Type::BOOL,
+                    _ => return mismatch(),
+                }
+            },
+            "<<" | ">>" => {
+                let integers = left.ty.scalar().is_some_and(|x|x.is_integer()) && right.ty.scalar().is_some_and(|x|x.is_integer());
+                if !integers{
+                    return mismatch();
+                }
+                match (&left.ty, &right.ty){
+                    (Type::Scalar(_), Type::Scalar(_)) | (Type::Vector(_, _), Type::Scalar(_)) => left.ty.clone(),
+                    (Type::Vector(_, n), Type::Vector(_, m)) if n == m => left.ty.clone(),
+                    _ => return mismatch(),
+                }
+            },
+            _ => match self.arithmetic(op, &left.ty, &right.ty){
+                Some(x) => x,
+                None => return mismatch(),
+            },
+        };
+
+        let value = match (left.value, right.value){
+            (Some(a), Some(b)) if constant && (ty.scalar().is_some_and(|x|x.is_integer()) || ty == Type::BOOL) && ty.is_scalar() => fold(op, a, b),
+            _ => None,
+        };
+        Ok(Typed{
+            ty,
+            access: Access::Value,
+            constant,
+            value,
+        })
+    }
+
+    ///
+    /// Result type of + - * / % & | ^, None if the operands don't fit together
+    fn arithmetic(&self, op: &str, left: &Type, right: &Type) -> Option<Type>{
+        let scalar = self.promote(left.scalar()?, right.scalar()?)?;
+        let integer_only = matches!(op, "%" | "&" | "|" | "^");
+        if !scalar.is_numeric() || (integer_only && !scalar.is_integer()){
+            return None;
+        }
+        let is_matrix = |x: &Type|matches!(x, Type::Matrix(_, _));
+        if integer_only && (is_matrix(left) || is_matrix(right)){
+            return None;
+        }
+        Some(match (left, right){
+            (Type::Scalar(_), Type::Scalar(_)) => Type::Scalar(scalar),
+            (Type::Scalar(_), x) | (x, Type::Scalar(_)) => x.with_scalar(scalar),
+            (Type::Vector(_, n), Type::Vector(_, m)) if n == m => Type::Vector(scalar, *n),
+            (Type::Matrix(c, r), Type::Vector(_, n)) if op == "*" && c == n => Type::Vector(Scalar::Float, *r),
+            (Type::Vector(_, n), Type::Matrix(c, r)) if op == "*" && r == n => Type::Vector(Scalar::Float, *c),
+            (Type::Matrix(c1, r1), Type::Matrix(c2, r2)) if op == "*" && c1 == r2 => Type::Matrix(*c2, *r1),
+            (Type::Matrix(c1, r1), Type::Matrix(c2, r2)) if op != "*" && c1 == c2 && r1 == r2 => left.clone(),
+            _ => return None,
+        })
+    }
+
+    fn index(&self, base: Typed, index: Typed, line: usize) -> Result<Typed,Diagnostic>{
+        if !index.ty.is_scalar() || !index.ty.scalar().is_some_and(|x|x.is_integer()){
+            return Err(Diagnostic::new(line, &format!("indices have to be int or uint, found {}",index.ty)));
+        }
+        let (element, length) = match &base.ty{
+            Type::Array(element, length) => ((**element).clone(), *length),
+            Type::Vector(s, n) => (Type::Scalar(*s), Some(*n as u32)),
+            Type::Matrix(c, r) => (Type::Vector(Scalar::Float, *r), Some(*c as u32)),
+            x => return Err(Diagnostic::new(line, &format!("{} can't be indexed",x))),
+        };
+        if let Some(value) = index.value{
+            if value < 0 || length.is_some_and(|x|value >= x as i64){
+                return Err(Diagnostic::new(line, &format!("index {} is out of range for {}",value,base.ty)));
+            }
+        }
+        if element.is_opaque() && !index.constant && self.version < 400{
+            return Err(Diagnostic::new(line, "sampler arrays can only be indexed with constants before GLSL 400"));
+        }
+        Ok(Typed{
+            ty: element,
+            access: base.access,
+            constant: base.constant && index.constant,
+            value: None,
+        })
+    }
+
+    fn field(&self, base: Typed, field: &str, line: usize) -> Result<Typed,Diagnostic>{
+        let (scalar, size) = match &base.ty{
+            Type::Struct(x) => {
+                let ty = x.fields.iter().find(|(name, _)|name == field).map(|(_, t)|t.clone())
+                    .ok_or_else(||Diagnostic::new(line, &format!("{} has no member {}",x.name,field)))?;
+                return Ok(Typed{
+                    ty,
+                    access: base.access,
+                    constant: base.constant,
+                    value: None,
+                });
+            },
+            Type::Vector(s, n) => (*s, *n),
+            Type::Scalar(s) if self.version >= 420 => (*s, 1),
+            x => return Err(Diagnostic::new(line, &format!("{} has no fields, can't access .{}",x,field))),
+        };
+
+        const SETS: [&str;3] = ["xyzw", "rgba", "stpq"];
+        let set = SETS.iter().find(|x|field.chars().next().is_some_and(|c|x.contains(c)))
+            .ok_or_else(||Diagnostic::new(line, &format!("invalid swizzle .{}",field)))?;
+        if field.len() > 4{
+            return Err(Diagnostic::new(line, &format!("swizzle .{} has more than 4 components",field)));
+        }
+        let mut seen = Vec::new();
+        for c in field.chars(){
+            let component = set.find(c).ok_or_else(||Diagnostic::new(line, &format!("swizzle .{} mixes component sets",field)))?;
+            if component >= size as usize{
+                return Err(Diagnostic::new(line, &format!("swizzle .{} reads past the {} components of {}",field,size,base.ty)));
+            }
+            seen.push(component);
+        }
+        let repeated = (1..seen.len()).any(|i|seen[..i].contains(&seen[i]));
+        let access = match base.access{
+            Access::Writable if repeated => Access::Value,
+            x => x,
+        };
+        Ok(Typed{
+            ty: Type::vector(scalar, field.len() as u8),
+            access,
+            constant: base.constant,
+            value: if field.len() == 1 && size == 1{ base.value } else{ None },
+        })
+    }
+
+    fn call(&mut self, name: &str, array: Option<&ArraySizes>, args: &[Expr], line: usize) -> Result<Typed,Diagnostic>{
+        let mut typed = Vec::with_capacity(args.len());
+        for arg in args{
+            typed.push(self.expr(arg)?);
+        }
+
+        let constructed = match Type::from_name(name, self.version){
+            Some(x) => Some(x),
+            None => self.lookup_struct(name).map(Type::Struct),
+        };
+        if let Some(ty) = constructed{
+            if let Some(sizes) = array{
+                return self.array_constructor(ty, sizes, &typed, line);
+            }
+            return self.constructor(&ty, &typed, line);
+        }
+        if Type::from_name(name, u32::MAX).is_some(){
+            return Err(Diagnostic::new(line, &format!("{} isn't available in GLSL {}",name,self.version)));
+        }
+
+        if self.functions.contains_key(name){
+            return self.user_call(name, args, &typed, line);
+        }
+        if self.lookup(name).is_some(){
+            return Err(Diagnostic::new(line, &format!("{} is a variable, not a function",name)));
+        }
+        if TEXTURE_FUNCTIONS.contains(&name){
+            return self.texture_call(name, &typed, line);
+        }
+        if name == "outerProduct"{
+            return match (typed.first().map(|x|&x.ty), typed.get(1).map(|x|&x.ty), typed.len()){
+                (Some(Type::Vector(a, c)), Some(Type::Vector(b, r)), 2) if self.can_convert_scalar(*a, Scalar::Float) && self.can_convert_scalar(*b, Scalar::Float) => {
+                    Ok(Typed::value(Type::Matrix(*r, *c)))
+                },
+                _ => Err(Diagnostic::new(line, "outerProduct needs two float vectors")),
+            };
+        }
+        self.builtin_call(name, args, &typed, line)
+    }
+
+    fn constructor(&self, ty: &Type, args: &[Typed], line: usize) -> Result<Typed,Diagnostic>{
+        let error = |message: String|Err(Diagnostic::new(line, &message));
+        if args.is_empty(){
+            return error(format!("constructor {} needs arguments",ty));
+        }
+        let constant = args.iter().all(|x|x.constant);
+        match ty{
+            Type::Void | Type::Sampler(_) | Type::Array(_, _) => return error(format!("{} can't be constructed",ty)),
+            Type::Struct(x) => {
+                if args.len() != x.fields.len(){
+                    return error(format!("{} has {} members but the constructor got {} arguments",x.name,x.fields.len(),args.len()));
+                }
+                for ((field, field_type), arg) in x.fields.iter().zip(args){
+                    if !self.can_convert(&arg.ty, field_type){
+                        return error(format!("member {} of {} is {}, can't construct it from {}",field,x.name,field_type,arg.ty));
+                    }
+                }
+            },
+            _ => {
+                for arg in args{
+                    if arg.ty.components().is_none(){
+                        return error(format!("{} can't be constructed from {}",ty,arg.ty));
+                    }
+                }
+                let needed = ty.components().unwrap();
+                let single_scalar = args.len() == 1 && args[0].ty.is_scalar();
+                let single_matrix = args.len() == 1 && matches!(args[0].ty, Type::Matrix(_, _));
+                if matches!(ty, Type::Matrix(_, _)) && !single_matrix && args.iter().any(|x|matches!(x.ty, Type::Matrix(_, _))){
+                    return error(format!("a matrix argument of {} has to be the only argument",ty));
+                }
+                if ty.is_scalar() && args.len() != 1{
+                    return error(format!("{} takes a single argument",ty));
+                }
+                if !(single_scalar || single_matrix){
+                    let total = args.iter().map(|x|x.ty.components().unwrap()).sum::<u32>();
+                    let before_last = total - args.last().unwrap().ty.components().unwrap();
+                    if total < needed{
+                        return error(format!("{} needs {} components but the arguments have {}",ty,needed,total));
+                    }
+                    if before_last >= needed{
+                        return error(format!("too many arguments for {}",ty));
+                    }
+                }
+            }
+        }
+        let value = match (ty, args[0].value){
+            (Type::Scalar(Scalar::Int), Some(x)) if args[0].ty.is_scalar() => Some(x as i32 as i64),
+            (Type::Scalar(Scalar::UInt), Some(x)) if args[0].ty.is_scalar() => Some(x as u32 as i64),
+            (Type::Scalar(Scalar::Bool), Some(x)) if args[0].ty.is_scalar() => Some((x != 0) as i64),
+            _ => None,
+        };
+        Ok(Typed{
+            ty: ty.clone(),
+            access: Access::Value,
+            constant,
+            value,
+        })
+    }
+
+    fn array_constructor(&mut self, element: Type, sizes: &ArraySizes, args: &[Typed], line: usize) -> Result<Typed,Diagnostic>{
+        let ty = self.apply_array(element, sizes, line)?;
+        let (element, length) = match &ty{
+            Type::Array(element, length) => ((**element).clone(), *length),
+            _ => unreachable!(),
+        };
+        if length.is_some_and(|x|x as usize != args.len()){
+            return Err(Diagnostic::new(line, &format!("{} needs {} arguments but got {}",ty,length.unwrap(),args.len())));
+        }
+        for arg in args{
+            if !self.can_convert(&arg.ty, &element) || matches!(element, Type::Array(_, None)){
+                return Err(Diagnostic::new(line, &format!("elements of {} can't be constructed from {}",ty,arg.ty)));
+            }
+        }
+        Ok(Typed{
+            ty: Type::Array(Box::new(element), Some(args.len() as u32)),
+            access: Access::Value,
+            constant: args.iter().all(|x|x.constant),
+            value: None,
+        })
+    }
+
+    fn user_call(&mut self, name: &str, args: &[Expr], typed: &[Typed], line: usize) -> Result<Typed,Diagnostic>{
+        let overloads = &self.functions[name];
+        let arity = overloads.iter().filter(|x|x.params.len() == typed.len()).collect::<Vec<_>>();
+        let exact = arity.iter().find(|x|x.params.iter().zip(typed).all(|((t, _), a)|*t == a.ty));
+        let function = match exact{
+            Some(x) => x,
+            None => match arity.iter().find(|x|x.params.iter().zip(typed).all(|((t, q), a)|{
+                if matches!(q, Some(Storage::Out) | Some(Storage::InOut)){
+                    self.can_convert(t, &a.ty)
+                }
+                else{
+                    self.can_convert(&a.ty, t)
+                }
+            })){
+                Some(x) => x,
+                None => {
+                    let types = typed.iter().map(|x|x.ty.to_string()).collect::<Vec<_>>().join(", ");
+                    return Err(Diagnostic::new(line, &format!("no overload of {} takes ({})",name,types)));
+                }
+            }
+        };
+        let return_type = function.return_type.clone();
+        let defined = function.defined;
+        for ((_, q), (arg, typed)) in function.params.iter().zip(args.iter().zip(typed)){
+            if matches!(q, Some(Storage::Out) | Some(Storage::InOut)){
+                self.writable(typed, arg.line)?;
+            }
+        }
+        if !defined && !self.called.iter().any(|(x, _)|x == name){
+            self.called.push((String::from(name), line));
+        }
+        Ok(Typed::value(return_type))
+    }
+
+    fn builtin_call(&mut self, name: &str, args: &[Expr], typed: &[Typed], line: usize) -> Result<Typed,Diagnostic>{
+        let function = BUILTIN_FUNCTIONS.iter().find(|x|x.name == name)
+            .ok_or_else(||Diagnostic::new(line, &format!("undeclared function {}",name)))?;
+        self.requires(function.min_version, None, &format!("function {}",name), line)?;
+        if let Some(stage) = function.stage{
+            if stage != self.stage{
+                return Err(Diagnostic::new(line, &format!("{} is only available in {} shaders",name,stage.name())));
+            }
+        }
+
+        let types = typed.iter().map(|x|&x.ty).collect::<Vec<_>>();
+        let result = [false, true].iter().find_map(|convert|{
+            function.signatures.split(';').find_map(|x|self.match_signature(x, &types, *convert))
+        });
+        let ty = match result{
+            Some(x) => x,
+            None => {
+                let types = types.iter().map(|x|x.to_string()).collect::<Vec<_>>().join(", ");
+                return Err(Diagnostic::new(line, &format!("no overload of {} takes ({})",name,types)));
+            }
+        };
+        for (function_name, index) in OUT_PARAMETERS{
+            if *function_name == name{
+                self.writable(&typed[*index], args[*index].line)?;
+            }
+        }
+        let derivative = function.stage.is_some();
+        Ok(Typed{
+            ty,
+            access: Access::Value,
+            constant: !derivative && typed.iter().all(|x|x.constant),
+            value: None,
+        })
+    }
+
+    fn parse_pattern(&self, text: &str) -> Pattern{
+        let scalar = |x: &str|match x{
+            "F" => Scalar::Float,
+            "I" => Scalar::Int,
+            "U" => Scalar::UInt,
+            _ => Scalar::Bool,
+        };
+        if let Some(x) = text.strip_prefix("gen"){
+            return Pattern::Gen(scalar(x));
+        }
+        if let Some(x) = text.strip_prefix("vec").filter(|x|!x.starts_with(|c: char|c.is_ascii_digit())){
+            return Pattern::Vec(scalar(x));
+        }
+        match text{
+            "mat" => Pattern::Mat,
+            "matT" => Pattern::MatT,
+            "sqmat" => Pattern::SqMat,
+            x => Pattern::Fixed(Type::from_name(x, u32::MAX).unwrap()),
+        }
+    }
+
+    ///
+    /// Matches the arguments against a signature like genF(genF,float), returning the result type
+    fn match_signature(&self, signature: &str, args: &[&Type], convert: bool) -> Option<Type>{
+        let (result, params) = signature.split_once('(')?;
+        let params = params.strip_suffix(')')?.split(',').collect::<Vec<_>>();
+        if params.len() != args.len(){
+            return None;
+        }
+        let mut binding = Binding::default();
+        for (param, arg) in params.iter().zip(args){
+            if !self.match_pattern(&self.parse_pattern(param), arg, &mut binding, convert){
+                return None;
+            }
+        }
+        Some(match self.parse_pattern(result){
+            Pattern::Gen(s) => Type::vector(s, binding.components.unwrap_or(1)),
+            Pattern::Vec(s) => Type::Vector(s, binding.components?),
+            Pattern::Mat | Pattern::SqMat => {
+                let (c, r) = binding.matrix?;
+                Type::Matrix(c, r)
+            },
+            Pattern::MatT => {
+                let (c, r) = binding.matrix?;
+                Type::Matrix(r, c)
+            },
+            Pattern::Fixed(x) => x,
+        })
+    }
+
+    fn match_pattern(&self, pattern: &Pattern, arg: &Type, binding: &mut Binding, convert: bool) -> bool{
+        let scalar_fits = |from: Scalar, to: Scalar|from == to || (convert && self.can_convert_scalar(from, to));
+        let mut bind = |n: u8|match binding.components{
+            Some(x) => x == n,
+            None => {
+                binding.components = Some(n);
+                true
+            }
+        };
+        match (pattern, arg){
+            (Pattern::Gen(s), Type::Scalar(a)) => scalar_fits(*a, *s) && bind(1),
+            (Pattern::Gen(s), Type::Vector(a, n)) | (Pattern::Vec(s), Type::Vector(a, n)) => scalar_fits(*a, *s) && bind(*n),
+            (Pattern::Mat, Type::Matrix(c, r)) | (Pattern::SqMat, Type::Matrix(c, r)) => {
+                if matches!(pattern, Pattern::SqMat) && c != r{
+                    return false;
+                }
+                match binding.matrix{
+                    Some(x) => x == (*c, *r),
+                    None => {
+                        binding.matrix = Some((*c, *r));
+                        true
+                    }
+                }
+            },
+            (Pattern::Fixed(x), arg) => x == arg || (convert && self.can_convert(arg, x)),
+            _ => false,
+        }
+    }
+
+    fn texture_call(&self, name: &str, args: &[Typed], line: usize) -> Result<Typed,Diagnostic>{
+        let sampler: &SamplerType = match args.first().map(|x|&x.ty){
+            Some(Type::Sampler(x)) => x,
+            _ => return Err(Diagnostic::new(line, &format!("{} needs a sampler as its first argument",name))),
+        };
+        let error = |message: &str|Err(Diagnostic::new(line, &format!("{} on {} {}",name,sampler.name,message)));
+        let float = |n: u8|Type::vector(Scalar::Float, n);
+        let int = |n: u8|Type::vector(Scalar::Int, n);
+        let is_array = sampler.name.contains("Array");
+        let is_cube = sampler.name.contains("Cube");
+        let is_special = !sampler.mipmapped;
+        //coordinates without the array layer and shadow reference, which offsets and gradients use
+        let spatial = if is_cube{ 3 } else{ sampler.size - is_array as u8 };
+        let texel = if sampler.shadow{ Type::FLOAT } else{ Type::Vector(sampler.result, 4) };
+        let bias_allowed = self.stage == ShaderStage::Fragment && sampler.mipmapped;
+
+        //expected parameters after the sampler, the optional ones are listed separately
+        let (required, optional, result): (Vec<Type>,Vec<Type>,Type) = match name{
+            "texture" => {
+                if is_special && sampler.name != "sampler2DRect"{
+                    return error("isn't allowed, use texelFetch");
+                }
+                (vec![float(sampler.coordinate)], if bias_allowed{ vec![Type::FLOAT] } else{ vec![] }, texel)
+            },
+            "textureProj" | "textureProjLod" | "textureProjOffset" => {
+                if is_array || is_cube || is_special && sampler.name != "sampler2DRect"{
+                    return error("isn't allowed");
+                }
+                let coordinate = if sampler.coordinate < 3{ 3 } else{ 4 };
+                let mut required = vec![float(coordinate)];
+                if name == "textureProjLod"{
+                    required.push(Type::FLOAT);
+                }
+                if name == "textureProjOffset"{
+                    required.push(int(spatial));
+                }
+                (required, if bias_allowed && name != "textureProjLod"{ vec![Type::FLOAT] } else{ vec![] }, texel)
+            },
+            "textureLod" | "textureLodOffset" => {
+                if !sampler.mipmapped || (sampler.shadow && (is_cube || is_array)){
+                    return error("isn't allowed");
+                }
+                let mut required = vec![float(sampler.coordinate), Type::FLOAT];
+                if name == "textureLodOffset"{
+                    if is_cube{
+                        return error("isn't allowed");
+                    }
+                    required.push(int(spatial));
+                }
+                (required, vec![], texel)
+            },
+            "textureOffset" => {
+                if is_cube || (is_special && sampler.name != "sampler2DRect"){
+                    return error("isn't allowed");
+                }
+                (vec![float(sampler.coordinate), int(spatial)], if bias_allowed{ vec![Type::FLOAT] } else{ vec![] }, texel)
+            },
+            "textureGrad" | "textureGradOffset" => {
+                if is_special && sampler.name != "sampler2DRect"{
+                    return error("isn't allowed");
+                }
+                let mut required = vec![float(sampler.coordinate), float(spatial), float(spatial)];
+                if name == "textureGradOffset"{
+                    if is_cube{
+                        return error("isn't allowed");
+                    }
+                    required.push(int(spatial));
+                }
+                (required, vec![], texel)
+            },
+            "textureSize" => {
+                let lod = if sampler.mipmapped{ vec![Type::INT] } else{ vec![] };
+                (lod, vec![], int(sampler.size))
+            },
+            "texelFetch" | "texelFetchOffset" => {
+                if !sampler.fetch{
+                    return error("isn't allowed");
+                }
+                let mut required = vec![int(sampler.size)];
+                if sampler.mipmapped || sampler.name.ends_with("MS"){
+                    required.push(Type::INT);
+                }
+                if name == "texelFetchOffset"{
+                    if sampler.name.ends_with("MS") || sampler.name.ends_with("Buffer"){
+                        return error("isn't allowed");
+                    }
+                    required.push(int(spatial));
+                }
+                (required, vec![], Type::Vector(sampler.result, 4))
+            },
+            "textureGather" => {
+                self.requires(400, Some("GL_ARB_texture_gather"), "textureGather", line)?;
+                if !(sampler.name.contains("2D") || is_cube) || sampler.name.ends_with("MS"){
+                    return error("isn't allowed");
+                }
+                if sampler.shadow{
+                    (vec![float(sampler.coordinate - 1), Type::FLOAT], vec![], Type::Vector(Scalar::Float, 4))
+                }
+                else{
+                    (vec![float(sampler.coordinate)], vec![Type::INT], Type::Vector(sampler.result, 4))
+                }
+            },
+            _ => {
+                self.requires(430, Some("GL_ARB_texture_query_levels"), "textureQueryLevels", line)?;
+                if !sampler.mipmapped{
+                    return error("isn't allowed");
+                }
+                (vec![], vec![], Type::INT)
+            },
+        };
+
+        let given = &args[1..];
+        if given.len() < required.len() || given.len() > required.len() + optional.len(){
+            let expected = required.iter().chain(optional.iter()).map(|x|x.to_string()).collect::<Vec<_>>().join(", ");
+            return error(&format!("takes ({}{}{})",sampler.name,if expected.is_empty(){ "" } else{ ", " },expected));
+        }
+        for (arg, expected) in given.iter().zip(required.iter().chain(optional.iter())){
+            if !self.can_convert(&arg.ty, expected){
+                return error(&format!("expects {} but got {}",expected,arg.ty));
+            }
+        }
+        Ok(Typed::value(result))
+    }
+}
+
+///
+/// in and const in parameters are passed the same way, so redeclarations may differ in const
+fn normalize_direction(storage: Option<Storage>) -> Option<Storage>{
+    match storage{
+        None | Some(Storage::Const) | Some(Storage::In) => None,
+        x => x,
+    }
+}
+
+fn fold(op: &str, a: i64, b: i64) -> Option<i64>{
+    Some(match op{
+        "+" => a.wrapping_add(b),
+        "-" => a.wrapping_sub(b),
+        "*" => a.wrapping_mul(b),
+        "/" if b != 0 => a.wrapping_div(b),
+        "%" if b != 0 => a.wrapping_rem(b),
+        "&" => a & b,
+        "|" => a | b,
+        "^" => a ^ b,
+        "<<" => a.wrapping_shl(b as u32),
+        ">>" => a.wrapping_shr(b as u32),
+        "==" => (a == b) as i64,
+        "!=" => (a != b) as i64,
+        "<" => (a < b) as i64,
+        ">" => (a > b) as i64,
+        "<=" => (a <= b) as i64,
+        ">=" => (a >= b) as i64,
+        "&&" => (a != 0 && b != 0) as i64,
+        "||" => (a != 0 || b != 0) as i64,
+        "^^" => ((a != 0) != (b != 0)) as i64,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn errors(stage: ShaderStage, version: u32, source: &str) -> Vec<(usize,String)>{
+        match super::super::check_stage(&format!("#version {} core\n{}", version, source), stage){
+            Ok(_) => Vec::new(),
+            Err(diagnostics) => diagnostics.into_iter().map(|d|(d.line, d.message)).collect(),
+        }
+    }
+
+    ///Checks that every source reports the expected message
+    fn expect(stage: ShaderStage, version: u32, cases: &[(&str,&str)]){
+        let mut missing = Vec::new();
+        for (source, message) in cases{
+            let errors = errors(stage, version, source);
+            if !errors.iter().any(|(_, m)|m == message){
+                missing.push(format!("{:?}: expected {:?}, got {:?}", source, message, errors));
+            }
+        }
+        assert!(missing.is_empty(), "{}", missing.join("\n"));
+    }
+
+    ///Declarations followed by an empty main
+    fn globals(stage: ShaderStage, version: u32, cases: &[(&str,&str)]){
+        let cases: Vec<(String,&str)> = cases.iter().map(|(source, message)|(format!("{}\nvoid main(){{}}", source), *message)).collect();
+        expect(stage, version, &cases.iter().map(|(s, m)|(s.as_str(), *m)).collect::<Vec<_>>());
+    }
+
+    ///Statements inside main, after optional declarations separated by "|"
+    fn statements(stage: ShaderStage, version: u32, cases: &[(&str,&str)]){
+        let cases: Vec<(String,&str)> = cases.iter().map(|(source, message)|{
+            let (declarations, body) = source.split_once('|').unwrap_or(("", source));
+            (format!("{}\nvoid main(){{ {} }}", declarations, body), *message)
+        }).collect();
+        expect(stage, version, &cases.iter().map(|(s, m)|(s.as_str(), *m)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn valid_shader_reports_its_interface(){
+        let source = "#version 330 core
+struct Light{ vec3 color; float radius; };
+layout(std140) uniform Frame{ mat4 view; vec4 time; };
+uniform Light u_light;
+uniform sampler2D u_textures[2];
+in vec2 v_uv;
+flat in int v_index;
+layout(location = 0) out vec4 o_color;
+float scale(const float x){ return x * u_light.radius; }
+void main(){
+    vec3 color = u_light.color.rgb * scale(2.0);
+    for(int i = 0; i < 2; i++){
+        if(i == v_index) continue;
+        color += texture(u_textures[1], v_uv).xyz;
+    }
+    switch(v_index){
+        case 0: color *= 0.5; break;
+        default: break;
+    }
+    o_color = vec4(color, time.x > 0.0 ? 1.0 : 0.0);
+}";
+        let interface = super::super::check_stage(source, ShaderStage::Fragment).unwrap();
+        let names = |variables: &[InterfaceVariable]|variables.iter().map(|v|v.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&interface.inputs), ["v_uv", "v_index"]);
+        assert_eq!(names(&interface.outputs), ["o_color"]);
+        assert_eq!(names(&interface.uniforms), ["u_light.color", "u_light.radius", "u_textures"]);
+        assert_eq!(interface.uniform_blocks.len(), 1);
+    }
+
+    #[test]
+    fn missing_and_undefined_functions(){
+        assert_eq!(errors(ShaderStage::Fragment, 330, "float x;"), [(0, "the shader has no main function".to_string())]);
+        assert_eq!(
+            errors(ShaderStage::Fragment, 330, "float f();\nvoid main(){ f(); }"),
+            [(3, "function f is called but never defined".to_string())]
+        );
+    }
+
+    #[test]
+    fn declaration_errors(){
+        globals(ShaderStage::Fragment, 330, &[
+            ("float f(){ return 1.0; }\nfloat f;", "f is already declared as a function"),
+            ("float a;\nfloat a;", "redefinition of a"),
+            ("out;", "qualifiers without a declaration are only allowed for uniform and buffer layouts"),
+            ("uniform samplerCubeArray s;", "samplerCubeArray isn't available in GLSL 330"),
+            ("void f(){ struct S{ float a; }; }\nS g;", "unknown type S"),
+            ("float a[2][2];", "arrays of arrays requires GLSL 430 or the GL_ARB_arrays_of_arrays extension"),
+            ("void a[2];", "arrays of void aren't allowed"),
+            ("uniform int n;\nfloat a[n];", "array sizes have to be constant integer expressions"),
+            ("float a[0];", "array sizes have to be greater than zero"),
+            ("float a[int(2.0)];", "array size can't be evaluated, only integer arithmetic on constants is supported"),
+            ("void a;", "a can't be void"),
+            ("const float a = true;", "can't initialize a of type float with bool"),
+            ("uniform float u;\nconst float a = u;", "a has to be initialized with a constant expression"),
+            ("float a[];", "a needs an array size or an initializer"),
+            ("float a[1] = {1.0};", "initializer lists requires GLSL 420 or the GL_ARB_shading_language_420pack extension"),
+        ]);
+        globals(ShaderStage::Fragment, 420, &[
+            ("float a[2] = {1.0};", "float[2] needs 2 initializers but has 1"),
+            ("vec2 v = {1.0};", "vec2 needs 2 initializers but has 1"),
+            ("vec2 v = {1.0, true};", "can't initialize float with bool"),
+        ]);
+    }
+
+    #[test]
+    fn struct_errors(){
+        globals(ShaderStage::Fragment, 330, &[
+            ("struct S{ const float a; };", "struct members can't have qualifiers"),
+            ("struct S{ void a; };", "struct members can't be void"),
+            ("struct S{ float a[]; };", "struct member a needs an array size"),
+            ("struct S{ float a; float a; };", "struct member a is declared twice"),
+            ("struct gl_S{ float a; };", "names starting with gl_ are reserved, gl_S"),
+            ("float S;\nstruct S{ float a; };", "redefinition of S"),
+        ]);
+    }
+
+    #[test]
+    fn layout_errors(){
+        globals(ShaderStage::Fragment, 330, &[
+            ("layout(index = 0) uniform float u;", "layout qualifier index isn't allowed here"),
+            ("layout(location = -1) out vec4 o;", "layout qualifier location needs a non negative constant integer"),
+            ("layout(location) out vec4 o;", "layout qualifier location needs a value"),
+            ("layout(std140 = 1) uniform B{ float x; };", "layout qualifier std140 doesn't take a value"),
+            ("layout(binding = 0) uniform sampler2D t;", "layout qualifier binding requires GLSL 420 or the GL_ARB_shading_language_420pack extension"),
+            ("layout(location = 0) uniform float u;", "uniform locations requires GLSL 430 or the GL_ARB_explicit_uniform_location extension"),
+            (
+                "layout(location = 0) in vec2 v;",
+                "locations on vertex outputs and fragment inputs requires GLSL 410 or the GL_ARB_separate_shader_objects extension",
+            ),
+            ("uniform B{ layout(location = 0) float x; };", "layout qualifier location isn't allowed here"),
+        ]);
+        globals(ShaderStage::Fragment, 420, &[
+            ("layout(binding = 0) uniform float u;", "binding is only allowed on samplers and blocks"),
+        ]);
+    }
+
+    #[test]
+    fn global_variable_errors(){
+        globals(ShaderStage::Fragment, 330, &[
+            ("out float gl_Nope;", "gl_Nope isn't a built-in variable of the fragment stage"),
+            ("flat uniform int u;", "interpolation qualifiers are only allowed on vertex outputs and fragment inputs"),
+            ("inout float x;", "inout is only allowed on function parameters"),
+            ("buffer float x;", "buffer variables have to be declared in a block"),
+            ("const float c;", "constant c needs an initializer"),
+            ("in float v = 1.0;", "v can't have an initializer"),
+            ("sampler2D s;", "s is sampler2D, samplers can only be uniforms or function parameters"),
+            ("in bool b;", "fragment input b can't be bool"),
+            ("in int i;", "integer fragment input i has to be declared flat"),
+            ("out mat4 m;", "fragment output m can't be mat4"),
+        ]);
+        globals(ShaderStage::Vertex, 330, &[
+            ("in bool b;", "vertex input b can't be bool"),
+            ("out bool b;", "vertex output b can't be bool"),
+        ]);
+        assert!(errors(ShaderStage::Fragment, 330, "layout(location = 0) out vec4 a;\nlayout(location = 0) out vec4 b;\nvoid main(){}")
+            .contains(&(0, "fragment outputs a and b share location 0".to_string())));
+    }
+
+    #[test]
+    fn block_errors(){
+        globals(ShaderStage::Vertex, 330, &[
+            ("in B{ float x; };", "input blocks aren't allowed in the vertex stage"),
+        ]);
+        globals(ShaderStage::Fragment, 330, &[
+            ("out B{ float x; };", "output blocks aren't allowed in the fragment stage"),
+            ("const B{ float x; };", "blocks have to be uniform, buffer, in or out"),
+            ("buffer B{ float x; };", "buffer blocks requires GLSL 430 or the GL_ARB_shader_storage_buffer_object extension"),
+            ("uniform B{ float x; };\nuniform B{ float y; };", "redefinition of block B"),
+            ("uniform B{ in float x; };", "block members can't change the storage of the block"),
+            ("uniform B{ sampler2D s; };", "block member s can't be a sampler"),
+            ("uniform B{ float x[]; };", "block member x needs an array size"),
+            ("uniform B{ float x; float x; };", "block member x is declared twice"),
+        ]);
+    }
+
+    #[test]
+    fn function_errors(){
+        globals(ShaderStage::Fragment, 330, &[
+            ("float sin(float x){ return x; }", "built-in function sin can't be redefined or overloaded"),
+            ("void gl_f(){}", "names starting with gl_ are reserved, gl_f"),
+            ("float f;\nvoid f(){}", "f is already declared as a variable or type"),
+            ("void f(uniform float x){}", "parameters can only be qualified with const, in, out or inout"),
+            ("void f(float x, void y){}", "parameters can't be void"),
+            ("void f(float x[]){}", "array parameters need a size"),
+            ("void f(out sampler2D s){}", "samplers can only be in parameters"),
+            ("float f();\nint f(){ return 1; }", "function f is redeclared with a different return type"),
+            ("void f(float x);\nvoid f(out float x){ x = 1.0; }", "function f is redeclared with different parameter qualifiers"),
+            ("void f(){}\nvoid f(){}", "redefinition of function f"),
+            ("float f(){ return; }", "the function has to return float"),
+            ("float f(){ return true; }", "returning bool from a function that returns float"),
+        ]);
+        expect(ShaderStage::Fragment, 330, &[
+            ("int main(){ return 0; }", "main has to be declared as void main()"),
+            ("float dx(){ return dFdxFine(1.0); }\nvoid main(){}", "function dFdxFine requires GLSL 450"),
+        ]);
+    }
+
+    #[test]
+    fn statement_errors(){
+        statements(ShaderStage::Fragment, 330, &[
+            ("if(1){}", "conditions have to be bool, found int"),
+            ("while(1.0){}", "conditions have to be bool, found float"),
+            ("case 1:", "case label outside of a switch"),
+            ("default:", "default label outside of a switch"),
+            ("break;", "break outside of a loop or switch"),
+            ("switch(1){ default: continue; }", "continue outside of a loop"),
+            ("return 1;", "void functions can't return a value"),
+            ("switch(1.0){}", "switch needs an int or uint value, found float"),
+            ("int x; switch(x){ x = 1; case 1: break; }", "statements in a switch have to follow a case label"),
+            ("int x; switch(x){ case x: break; }", "case labels have to be constant int expressions"),
+            ("switch(1){ case 1: case 1: break; }", "duplicate case label 1"),
+            ("switch(1){ default: default: break; }", "duplicate default label"),
+            ("layout(location = 0) float x;", "local variables can't have layout, interpolation or invariant qualifiers"),
+            ("uniform float x;", "local variables can only be qualified with const"),
+            ("const float c;", "constant c needs an initializer"),
+            ("sampler2D s;", "s is sampler2D, samplers can only be uniforms or function parameters"),
+            ("float gl_x;", "names starting with gl_ are reserved, gl_x"),
+        ]);
+        statements(ShaderStage::Vertex, 330, &[
+            ("discard;", "discard is only allowed in fragment shaders"),
+        ]);
+    }
+
+    #[test]
+    fn expression_errors(){
+        statements(ShaderStage::Fragment, 330, &[
+            ("x = 1;", "undeclared identifier x"),
+            ("int x = 4294967296;", "integer literal 4294967296 doesn't fit in 32 bits"),
+            ("float x; x = true;", "can't assign bool to float"),
+            ("int i; i += 1.0;", "int += float gives float, which can't be assigned back"),
+            ("float x = 1 ? 1.0 : 2.0;", "the condition of ?: has to be bool, found int"),
+            ("float x = true ? 1.0 : true;", "the branches of ?: have different types, float and bool"),
+            ("float f; int n = f.length();", "length() needs an array, vector or matrix, found float"),
+            ("gl_FragCoord = vec4(1.0);", "can't assign to built-in input gl_FragCoord"),
+            ("1 = 2;", "can't assign to an expression that isn't a variable"),
+            ("bool b; b++;", "++ needs a numeric operand, found bool"),
+            ("bool b = -true;", "operator - doesn't work on bool"),
+            ("float f = ~1.0;", "operator ~ doesn't work on float"),
+            ("bool b = !1;", "operator ! doesn't work on int"),
+            ("bool b = true && 1;", "operator && doesn't work on bool and int"),
+            ("bool b = 1.0 < vec2(1.0);", "operator < doesn't work on float and vec2"),
+            ("int i = 1 << 1.0;", "operator << doesn't work on int and float"),
+            ("vec2 v = vec2(1.0) + vec3(1.0);", "operator + doesn't work on vec2 and vec3"),
+            ("bool b = vec2(1.0) == 1.0;", "operator == doesn't work on vec2 and float"),
+        ]);
+    }
+
+    #[test]
+    fn index_and_field_errors(){
+        statements(ShaderStage::Fragment, 330, &[
+            ("float a[2]; float x = a[1.0];", "indices have to be int or uint, found float"),
+            ("float f; float x = f[0];", "float can't be indexed"),
+            ("float a[2]; float x = a[2];", "index 2 is out of range for float[2]"),
+            (
+                "uniform sampler2D s[2];|int i = 0; vec4 c = texture(s[i], vec2(0.0));",
+                "sampler arrays can only be indexed with constants before GLSL 400",
+            ),
+            ("struct S{ float a; }; S s; float x = s.b;", "S has no member b"),
+            ("float f; float x = f.x;", "float has no fields, can't access .x"),
+            ("vec2 v; float x = v.k;", "invalid swizzle .k"),
+            ("vec4 v; vec4 w = v.xyzwx;", "swizzle .xyzwx has more than 4 components"),
+            ("vec2 v; vec2 w = v.xg;", "swizzle .xg mixes component sets"),
+            ("vec2 v; float x = v.z;", "swizzle .z reads past the 2 components of vec2"),
+        ]);
+    }
+
+    #[test]
+    fn constructor_errors(){
+        statements(ShaderStage::Fragment, 330, &[
+            ("vec4 c = samplerCubeArray(1.0);", "samplerCubeArray isn't available in GLSL 330"),
+            ("vec2 v = vec2();", "constructor vec2 needs arguments"),
+            ("void(1);", "void can't be constructed"),
+            ("struct S{ float a; float b; }; S s = S(1.0);", "S has 2 members but the constructor got 1 arguments"),
+            ("struct S{ float a; float b; }; S s = S(1.0, true);", "member b of S is float, can't construct it from bool"),
+            ("uniform sampler2D t;|vec2 v = vec2(t);", "vec2 can't be constructed from sampler2D"),
+            ("mat2 m = mat2(mat2(1.0), 1.0);", "a matrix argument of mat2 has to be the only argument"),
+            ("float f = float(1.0, 2.0);", "float takes a single argument"),
+            ("vec3 v = vec3(1.0, 2.0);", "vec3 needs 3 components but the arguments have 2"),
+            ("vec2 v = vec2(1.0, 2.0, 3.0);", "too many arguments for vec2"),
+            ("float a[2] = float[2](1.0);", "float[2] needs 2 arguments but got 1"),
+            ("float a[1] = float[1](true);", "elements of float[1] can't be constructed from bool"),
+        ]);
+    }
+
+    #[test]
+    fn call_errors(){
+        statements(ShaderStage::Fragment, 330, &[
+            ("float f; f(1.0);", "f is a variable, not a function"),
+            ("mat2 m = outerProduct(1.0, 2.0);", "outerProduct needs two float vectors"),
+            ("float f(float x){ return x; }|f(true);", "no overload of f takes (bool)"),
+            ("void g(out float x){ x = 1.0; }|g(1.0);", "can't assign to an expression that isn't a variable"),
+            ("foo(1.0);", "undeclared function foo"),
+            ("float x = sin(true);", "no overload of sin takes (bool)"),
+            ("float x = modf(1.0, 2.0);", "can't assign to an expression that isn't a variable"),
+        ]);
+        statements(ShaderStage::Vertex, 330, &[
+            ("float x = dFdx(1.0);", "dFdx is only available in fragment shaders"),
+        ]);
+    }
+
+    #[test]
+    fn texture_call_errors(){
+        let samplers = "uniform sampler2D t;\nuniform sampler1D t1;\nuniform samplerCube cube;\nuniform sampler2DMS ms;|";
+        let cases: Vec<(String,&str)> = [
+            ("vec4 c = texture(1.0, vec2(0.0));", "texture needs a sampler as its first argument"),
+            ("vec4 c = texture(ms, vec2(0.0));", "texture on sampler2DMS isn't allowed, use texelFetch"),
+            ("vec4 c = textureProj(cube, vec4(0.0));", "textureProj on samplerCube isn't allowed"),
+            ("vec4 c = textureLod(ms, vec2(0.0), 0.0);", "textureLod on sampler2DMS isn't allowed"),
+            ("vec4 c = textureOffset(cube, vec3(0.0), ivec3(0));", "textureOffset on samplerCube isn't allowed"),
+            ("vec4 c = texelFetch(cube, ivec3(0), 0);", "texelFetch on samplerCube isn't allowed"),
+            ("vec4 c = textureGather(t, vec2(0.0));", "textureGather requires GLSL 400 or the GL_ARB_texture_gather extension"),
+            ("int n = textureQueryLevels(t);", "textureQueryLevels requires GLSL 430 or the GL_ARB_texture_query_levels extension"),
+            ("vec4 c = texture(t);", "texture on sampler2D takes (sampler2D, vec2, float)"),
+            ("vec4 c = texture(t, 1.0);", "texture on sampler2D expects vec2 but got float"),
+        ].iter().map(|(body, message)|(format!("{}{}", samplers, body), *message)).collect();
+        statements(ShaderStage::Fragment, 330, &cases.iter().map(|(s, m)|(s.as_str(), *m)).collect::<Vec<_>>());
+        statements(ShaderStage::Fragment, 430, &[
+            (
+                "uniform sampler1D t1;|vec4 c = textureGather(t1, 0.0);",
+                "textureGather on sampler1D isn't allowed",
+            ),
+            (
+                "uniform sampler2DMS ms;|int n = textureQueryLevels(ms);",
+                "textureQueryLevels on sampler2DMS isn't allowed",
+            ),
+        ]);
+    }
+}
@@ -0,0 +1,640 @@
+use std::collections::HashMap;
+
+use super::{Diagnostic, lexer::{Token, TokenKind, strip_comments, tokenize_line}};
+
+///Versions the validator accepts, every one of them as a core or compatibility profile
+pub const SUPPORTED_VERSIONS: &[u32] = &[330, 400, 410, 420, 430, 440, 450, 460];
+
+struct Macro{
+    params: Option<Vec<String>>,
+    body: Vec<Token>,
+}
+
+struct Conditional{
+    ///True if the lines of the current branch are compiled
+    active: bool,
+    ///True if any branch so far was taken
+    taken: bool,
+    parent_active: bool,
+    seen_else: bool,
+    line: usize,
+}
+
+///
+/// The tokens of a shader after directives are applied and macros expanded
+pub struct DirectiveOutput{
+    pub tokens: Vec<Token>,
+    pub version: u32,
+    pub extensions: Vec<String>,
+}
+
+///
+/// Runs the directives of comment free source, expanding macros and dropping lines of inactive conditionals
+/// Includes have already been resolved by the shader preprocessor
+pub fn run(source: &str) -> Result<DirectiveOutput,Vec<Diagnostic>>{
+    let source = strip_comments(source).map_err(|e|vec![e])?;
+    let mut state = State{
+        macros: HashMap::new(),
+        conditionals: Vec::new(),
+        version: None,
+        extensions: Vec::new(),
+        tokens: Vec::new(),
+        diagnostics: Vec::new(),
+        seen_content: false,
+    };
+    state.define_builtin("GL_core_profile", "1");
+
+    let lines = join_continued_lines(&source);
+    for (index, text) in lines.iter().enumerate(){
+        let line = index + 1;
+        let trimmed = text.trim_start();
+        let result = if let Some(directive) = trimmed.strip_prefix('#'){
+            state.directive(directive, line)
+        }
+        else if state.is_active() && !trimmed.is_empty(){
+            state.code(text, line)
+        }
+        else{
+            Ok(())
+        };
+        if let Err(e) = result{
+            state.diagnostics.push(e);
+        }
+    }
+
+    if let Some(open) = state.conditionals.last(){
+        state.diagnostics.push(Diagnostic::new(open.line, "unterminated conditional directive, missing #endif"));
+    }
+    let version = match state.version{
+        Some(x) => x,
+        None => {
+            state.diagnostics.insert(0, Diagnostic::new(1, &format!("missing #version directive, at least {} is required",SUPPORTED_VERSIONS[0])));
+            0
+        }
+    };
+    if !state.diagnostics.is_empty(){
+        return Err(state.diagnostics);
+    }
+
+    let end = lines.len().max(1);
+    state.tokens.push(Token{
+        kind: TokenKind::Eof,
+        line: end,
+    });
+    Ok(DirectiveOutput{
+        tokens: state.tokens,
+        version,
+        extensions: state.extensions,
+    })
+}
+
+///
+/// Joins lines ending in a backslash with the next line, which is left empty so line numbers stay the same
+fn join_continued_lines(source: &str) -> Vec<String>{
+    let mut out = Vec::new();
+    //index of the first line of a continuation and the text joined so far
+    let mut pending: Option<(usize,String)> = None;
+    for line in source.split('\n'){
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let (text, continued) = match line.strip_suffix('\\'){
+            Some(x) => (x, true),
+            None => (line, false),
+        };
+        out.push(String::new());
+        match &mut pending{
+            Some((_, joined)) => {
+                joined.push(' ');
+                joined.push_str(text);
+            },
+            None => pending = Some((out.len() - 1, String::from(text))),
+        }
+        if !continued{
+            let (first, joined) = pending.take().unwrap();
+            out[first] = joined;
+        }
+    }
+    if let Some((first, joined)) = pending{
+        out[first] = joined;
+    }
+    out
+}
+
+struct State{
+    macros: HashMap<String,Macro>,
+    conditionals: Vec<Conditional>,
+    version: Option<u32>,
+    extensions: Vec<String>,
+    tokens: Vec<Token>,
+    diagnostics: Vec<Diagnostic>,
+    seen_content: bool,
+}
+
+impl State{
+    fn define_builtin(&mut self, name: &str, value: &str){
+        let body = tokenize_line(value, 0).unwrap_or_default();
+        self.macros.insert(String::from(name), Macro{
+            params: None,
+            body,
+        });
+    }
+
+    fn is_active(&self) -> bool{
+        self.conditionals.last().is_none_or(|x|x.active)
+    }
+
+    fn code(&mut self, text: &str, line: usize) -> Result<(),Diagnostic>{
+        self.seen_content = true;
+        let tokens = tokenize_line(text, line)?;
+        let expanded = self.expand(&tokens, &mut Vec::new(), line)?;
+        self.tokens.extend(expanded);
+        Ok(())
+    }
+
+    fn directive(&mut self, text: &str, line: usize) -> Result<(),Diagnostic>{
+        let text = text.trim();
+        let (name, rest) = match text.find(|c: char|c.is_whitespace() || c == '('){
+            Some(x) if !text[..x].is_empty() => (&text[..x], text[x..].trim()),
+            _ => (text, ""),
+        };
+
+        //conditionals are tracked even inside inactive branches, so nesting stays balanced
+        match name{
+            "if" | "ifdef" | "ifndef" => {
+                let parent_active = self.is_active();
+                let condition = if parent_active{
+                    match name{
+                        "if" => self.evaluate(rest, line).map(|x|x != 0),
+                        "ifdef" => macro_name(rest, line).map(|x|self.macros.contains_key(x)),
+                        _ => macro_name(rest, line).map(|x|!self.macros.contains_key(x)),
+                    }
+                }
+                else{
+                    Ok(false)
+                };
+                //an invalid condition still opens the conditional, so its #endif isn't reported as well
+                let active = *condition.as_ref().unwrap_or(&false);
+                self.conditionals.push(Conditional{
+                    active: parent_active && active,
+                    taken: active,
+                    parent_active,
+                    seen_else: false,
+                    line,
+                });
+                return condition.map(|_|());
+            },
+            "elif" => {
+                let (parent_active, taken, seen_else) = match self.conditionals.last(){
+                    Some(x) => (x.parent_active, x.taken, x.seen_else),
+                    None => return Err(Diagnostic::new(line, "#elif without #if")),
+                };
+                if seen_else{
+                    return Err(Diagnostic::new(line, "#elif after #else"));
+                }
+                let condition = parent_active && !taken && self.evaluate(rest, line)? != 0;
+                let current = self.conditionals.last_mut().unwrap();
+                current.active = condition;
+                current.taken |= condition;
+                return Ok(());
+            },
+            "else" => {
+                let current = self.conditionals.last_mut().ok_or_else(||Diagnostic::new(line, "#else without #if"))?;
+                if current.seen_else{
+                    return Err(Diagnostic::new(line, "#else after #else"));
+                }
+                current.seen_else = true;
+                current.active = current.parent_active && !current.taken;
+                current.taken = true;
+                return Ok(());
+            },
+            "endif" => {
+                self.conditionals.pop().ok_or_else(||Diagnostic::new(line, "#endif without #if"))?;
+                return Ok(());
+            },
+            _ => {}
+        }
+
+        if !self.is_active(){
+            return Ok(());
+        }
+
+        match name{
+            "" => Ok(()),
+            //a directive before it was already reported as misplacing #version
+            "version" if self.version == Some(0) => Ok(()),
+            "version" => {
+                if self.version.is_some() || self.seen_content{
+                    return Err(Diagnostic::new(line, "#version has to be the first directive of the shader"));
+                }
+                //an invalid version is reported once, not as missing as well
+                self.version = Some(0);
+                let mut parts = rest.split_whitespace();
+                let version = parts.next().and_then(|x|x.parse::<u32>().ok())
+                    .ok_or_else(||Diagnostic::new(line, "expected a version number"))?;
+                let profile = parts.next().unwrap_or("core");
+                if profile == "es"{
+                    return Err(Diagnostic::new(line, "GLSL ES shaders are not supported"));
+                }
+                if profile != "core" && profile != "compatibility"{
+                    return Err(Diagnostic::new(line, &format!("unknown profile {}",profile)));
+                }
+                if !SUPPORTED_VERSIONS.contains(&version){
+                    return Err(Diagnostic::new(line, &format!("unsupported GLSL version {}, supported versions are {}",version,
+                        SUPPORTED_VERSIONS.iter().map(|x|x.to_string()).collect::<Vec<_>>().join(", "))));
+                }
+                self.version = Some(version);
+                self.define_builtin("__VERSION__", &version.to_string());
+                Ok(())
+            },
+            _ if self.version.is_none() => {
+                self.seen_content = true;
+                self.version = Some(0);
+                Err(Diagnostic::new(line, "#version has to be the first directive of the shader"))
+            },
+            "define" => self.define(rest, line),
+            "undef" => {
+                self.macros.remove(macro_name(rest, line)?);
+                Ok(())
+            },
+            "extension" => {
+                let (extension, behaviour) = rest.split_once(':').ok_or_else(||Diagnostic::new(line, "expected #extension name : behavior"))?;
+                let behaviour = behaviour.trim();
+                if !matches!(behaviour, "require" | "enable" | "warn" | "disable"){
+                    return Err(Diagnostic::new(line, &format!("unknown extension behavior {}",behaviour)));
+                }
+                if behaviour != "disable"{
+                    self.extensions.push(String::from(extension.trim()));
+                }
+                Ok(())
+            },
+            "pragma" | "line" => Ok(()),
+            "error" => Err(Diagnostic::new(line, &format!("#error {}",rest))),
+            x => Err(Diagnostic::new(line, &format!("unknown directive #{}",x))),
+        }
+    }
+
+    fn define(&mut self, rest: &str, line: usize) -> Result<(),Diagnostic>{
+        let name_end = rest.find(|c: char|!(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        if name.is_empty() || name.starts_with(|c: char|c.is_ascii_digit()){
+            return Err(Diagnostic::new(line, "expected a macro name"));
+        }
+        if name.starts_with("GL_") || name.contains("__"){
+            return Err(Diagnostic::new(line, &format!("macro names starting with GL_ or containing __ are reserved, {}",name)));
+        }
+
+        let rest = &rest[name_end..];
+        //a parameter list has to follow the name directly, without whitespace
+        let (params, body) = match rest.strip_prefix('('){
+            Some(x) => {
+                let (list, body) = x.split_once(')').ok_or_else(||Diagnostic::new(line, "unterminated macro parameter list"))?;
+                let params = list.split(',').map(|x|String::from(x.trim())).filter(|x|!x.is_empty()).collect::<Vec<_>>();
+                (Some(params), body)
+            },
+            None => (None, rest),
+        };
+        let body = tokenize_line(body, line)?;
+        if body.iter().any(|x|x.is("#") || x.is("##")){
+            return Err(Diagnostic::new(line, "token pasting is not supported"));
+        }
+        self.macros.insert(String::from(name), Macro{
+            params,
+            body,
+        });
+        Ok(())
+    }
+
+    ///
+    /// Expands macros in the tokens, active holds the macros being expanded so recursive macros stop
+    fn expand(&self, tokens: &[Token], active: &mut Vec<String>, line: usize) -> Result<Vec<Token>,Diagnostic>{
+        let mut out = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len(){
+            let token = &tokens[i];
+            i += 1;
+            let name = match token.ident(){
+                Some(x) => x,
+                None => {
+                    out.push(token.clone());
+                    continue;
+                }
+            };
+            if name == "__LINE__"{
+                out.push(Token{
+                    kind: TokenKind::Int(line as u64),
+                    line,
+                });
+                continue;
+            }
+            if name == "__FILE__"{
+                out.push(Token{
+                    kind: TokenKind::Int(0),
+                    line,
+                });
+                continue;
+            }
+            let definition = match self.macros.get(name){
+                Some(x) if !active.iter().any(|x|x == name) => x,
+                _ => {
+                    out.push(token.clone());
+                    continue;
+                }
+            };
+
+            let body = match &definition.params{
+                None => definition.body.clone(),
+                Some(params) => {
+                    if !tokens.get(i).is_some_and(|x|x.is("(")){
+                        //a function like macro name without arguments is left alone
+                        out.push(token.clone());
+                        continue;
+                    }
+                    let (args, end) = collect_arguments(tokens, i, line)?;
+                    i = end;
+                    let args = if params.is_empty() && args.len() == 1 && args[0].is_empty(){
+                        Vec::new()
+                    }
+                    else{
+                        args
+                    };
+                    if args.len() != params.len(){
+                        return Err(Diagnostic::new(line, &format!("macro {} expects {} arguments but got {}",name,params.len(),args.len())));
+                    }
+                    let mut expanded_args = Vec::with_capacity(args.len());
+                    for arg in &args{
+                        expanded_args.push(self.expand(arg, active, line)?);
+                    }
+                    let mut body = Vec::new();
+                    for x in &definition.body{
+                        match x.ident().and_then(|x|params.iter().position(|p|p == x)){
+                            Some(p) => body.extend(expanded_args[p].iter().cloned()),
+                            None => body.push(x.clone()),
+                        }
+                    }
+                    body
+                }
+            };
+
+            let body = body.into_iter().map(|mut x|{
+                x.line = line;
+                x
+            }).collect::<Vec<_>>();
+            active.push(String::from(name));
+            let expanded = self.expand(&body, active, line);
+            active.pop();
+            out.extend(expanded?);
+        }
+        Ok(out)
+    }
+
+    fn evaluate(&self, text: &str, line: usize) -> Result<i64,Diagnostic>{
+        let tokens = tokenize_line(text, line)?;
+        //defined has to be resolved before expansion, so the macro names aren't replaced
+        let mut resolved = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len(){
+            if tokens[i].ident() == Some("defined"){
+                let (name, end) = match (tokens.get(i + 1), tokens.get(i + 2), tokens.get(i + 3)){
+                    (Some(open), Some(name), Some(close)) if open.is("(") && close.is(")") => (name.ident(), i + 4),
+                    (Some(name), _, _) => (name.ident(), i + 2),
+                    _ => (None, i + 1),
+                };
+                let name = name.ok_or_else(||Diagnostic::new(line, "expected a macro name after defined"))?;
+                resolved.push(Token{
+                    kind: TokenKind::Int(self.macros.contains_key(name) as u64),
+                    line,
+                });
+                i = end;
+            }
+            else{
+                resolved.push(tokens[i].clone());
+                i += 1;
+            }
+        }
+        let expanded = self.expand(&resolved, &mut Vec::new(), line)?;
+        if expanded.is_empty(){
+            return Err(Diagnostic::new(line, "expected an expression"));
+        }
+        let mut parser = ConditionParser{
+            tokens: &expanded,
+            position: 0,
+            line,
+        };
+        let value = parser.parse(0)?;
+        if parser.position != expanded.len(){
+            return Err(Diagnostic::new(line, "unexpected tokens after the expression"));
+        }
+        Ok(value)
+    }
+}
+
+fn macro_name(text: &str, line: usize) -> Result<&str,Diagnostic>{
+    let name = text.split_whitespace().next().unwrap_or("");
+    if name.is_empty() || !name.chars().all(|c|c.is_ascii_alphanumeric() || c == '_'){
+        return Err(Diagnostic::new(line, "expected a macro name"));
+    }
+    Ok(name)
+}
+
+///
+/// Collects the comma separated arguments of a macro call starting at the opening parenthesis
+/// Returns the arguments and the index after the closing parenthesis
+fn collect_arguments(tokens: &[Token], open: usize, line: usize) -> Result<(Vec<Vec<Token>>,usize),Diagnostic>{
+    let mut args = vec![Vec::new()];
+    let mut depth = 0;
+    let mut i = open + 1;
+    while i < tokens.len(){
+        let token = &tokens[i];
+        i += 1;
+        if token.is("("){
+            depth += 1;
+        }
+        else if token.is(")"){
+            if depth == 0{
+                return Ok((args, i));
+            }
+            depth -= 1;
+        }
+        else if token.is(",") && depth == 0{
+            args.push(Vec::new());
+            continue;
+        }
+        args.last_mut().unwrap().push(token.clone());
+    }
+    Err(Diagnostic::new(line, "unterminated macro arguments, they have to be on a single line"))
+}
+
+///
+/// Precedence climbing over the integer expressions allowed in #if
+struct ConditionParser<'a>{
+    tokens: &'a [Token],
+    position: usize,
+    line: usize,
+}
+
+const BINARY_PRECEDENCE: &[(&str,u8)] = &[
+    ("||", 1), ("&&", 2), ("|", 3), ("^", 4), ("&", 5), ("==", 6), ("!=", 6),
+    ("<", 7), (">", 7), ("<=", 7), (">=", 7), ("<<", 8), (">>", 8), ("+", 9), ("-", 9), ("*", 10), ("/", 10), ("%", 10),
+];
+
+impl<'a> ConditionParser<'a>{
+    fn error(&self, message: &str) -> Diagnostic{
+        Diagnostic::new(self.line, message)
+    }
+
+    fn parse(&mut self, min_precedence: u8) -> Result<i64,Diagnostic>{
+        let mut left = self.unary()?;
+        while let Some(TokenKind::Punct(operator)) = self.tokens.get(self.position).map(|x|&x.kind){
+            let operator = *operator;
+            let precedence = match BINARY_PRECEDENCE.iter().find(|(op,_)|*op == operator){
+                Some((_, p)) if *p > min_precedence => *p,
+                _ => break,
+            };
+            self.position += 1;
+            let right = self.parse(precedence)?;
+            left = match operator{
+                "||" => ((left != 0) || (right != 0)) as i64,
+                "&&" => ((left != 0) && (right != 0)) as i64,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => return Err(self.error("division by zero in #if")),
+                "/" => left / right,
+                _ => left % right,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64,Diagnostic>{
+        let token = self.tokens.get(self.position).ok_or_else(||self.error("expected an expression"))?;
+        self.position += 1;
+        match &token.kind{
+            TokenKind::Int(x) | TokenKind::UInt(x) => Ok(*x as i64),
+            TokenKind::Punct("(") => {
+                let value = self.parse(0)?;
+                if !self.tokens.get(self.position).is_some_and(|x|x.is(")")){
+                    return Err(self.error("expected ')'"));
+                }
+                self.position += 1;
+                Ok(value)
+            },
+            TokenKind::Punct("-") => Ok(self.unary()?.wrapping_neg()),
+            TokenKind::Punct("+") => self.unary(),
+            TokenKind::Punct("!") => Ok((self.unary()? == 0) as i64),
+            TokenKind::Punct("~") => Ok(!self.unary()?),
+            TokenKind::Ident(x) => Err(self.error(&format!("undefined identifier {} in #if",x))),
+            _ => Err(self.error("expected an integer expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn errors(source: &str) -> Vec<(usize,String)>{
+        match run(source){
+            Ok(_) => Vec::new(),
+            Err(e) => e.into_iter().map(|x|(x.line, x.message)).collect(),
+        }
+    }
+
+    ///The single error of a shader starting with #version 330 on line 1
+    fn error(body: &str) -> (usize,String){
+        let errors = errors(&format!("#version 330\n{}",body));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        errors.into_iter().next().unwrap()
+    }
+
+    fn message(body: &str) -> String{
+        error(body).1
+    }
+
+    #[test]
+    fn macros_are_expanded_and_inactive_lines_dropped(){
+        let source = "#version 450 compatibility\n#define SCALE(x) ((x) * FACTOR)\n#define FACTOR 2\n#if defined(FACTOR) && __VERSION__ >= 450\nint a = SCALE(3);\n#elif 1\nint b;\n#else\nint c;\n#endif\n#extension GL_ARB_foo : enable\n#extension GL_ARB_bar : disable";
+        let output = run(source).unwrap();
+        let text = output.tokens.iter().map(|x|match &x.kind{
+            TokenKind::Ident(x) => x.clone(),
+            TokenKind::Int(x) => x.to_string(),
+            TokenKind::Punct(x) => String::from(*x),
+            _ => String::new(),
+        }).collect::<Vec<_>>().join(" ");
+        assert_eq!(text, "int a = ( ( 3 ) * 2 ) ; ");
+        assert_eq!(output.version, 450);
+        assert_eq!(output.extensions, ["GL_ARB_foo"]);
+        assert_eq!(output.tokens[0].line, 5);
+    }
+
+    #[test]
+    fn version_errors(){
+        assert_eq!(errors("int a;"), vec![(1, String::from("missing #version directive, at least 330 is required"))]);
+        assert_eq!(errors("#define A\n#version 330"), vec![(1, String::from("#version has to be the first directive of the shader"))]);
+        assert_eq!(error("#version 330"), (2, String::from("#version has to be the first directive of the shader")));
+        assert!(errors("int a;\n#version 330").contains(&(2, String::from("#version has to be the first directive of the shader"))));
+        assert_eq!(errors("#version core"), vec![(1, String::from("expected a version number"))]);
+        assert_eq!(errors("#version 300 es"), vec![(1, String::from("GLSL ES shaders are not supported"))]);
+        assert_eq!(errors("#version 330 legacy"), vec![(1, String::from("unknown profile legacy"))]);
+        assert_eq!(errors("#version 120")[0].1, "unsupported GLSL version 120, supported versions are 330, 400, 410, 420, 430, 440, 450, 460");
+    }
+
+    #[test]
+    fn conditional_errors(){
+        assert_eq!(error("#if 1\n#ifdef A\n#endif"), (2, String::from("unterminated conditional directive, missing #endif")));
+        assert_eq!(message("#elif 1"), "#elif without #if");
+        assert_eq!(message("#if 1\n#else\n#elif 1\n#endif"), "#elif after #else");
+        assert_eq!(message("#else"), "#else without #if");
+        assert_eq!(message("#if 1\n#else\n#else\n#endif"), "#else after #else");
+        assert_eq!(message("#endif"), "#endif without #if");
+        //an invalid condition still opens its conditional
+        assert_eq!(error("#ifdef\nint a;\n#endif"), (2, String::from("expected a macro name")));
+    }
+
+    #[test]
+    fn condition_errors(){
+        assert_eq!(message("#if defined\n#endif"), "expected a macro name after defined");
+        assert_eq!(message("#if\n#endif"), "expected an expression");
+        assert_eq!(message("#if 1 2\n#endif"), "unexpected tokens after the expression");
+        assert_eq!(message("#if 1 / (2 - 2)\n#endif"), "division by zero in #if");
+        assert_eq!(message("#if (1 + 2\n#endif"), "expected ')'");
+        assert_eq!(message("#if 1 +\n#endif"), "expected an expression");
+        assert_eq!(message("#if UNDEFINED\n#endif"), "undefined identifier UNDEFINED in #if");
+        assert_eq!(message("#if 1.0\n#endif"), "expected an integer expression");
+    }
+
+    #[test]
+    fn directive_errors(){
+        assert_eq!(message("#extension GL_ARB_foo"), "expected #extension name : behavior");
+        assert_eq!(message("#extension GL_ARB_foo : maybe"), "unknown extension behavior maybe");
+        assert_eq!(error("\n#error broken shader"), (3, String::from("#error broken shader")));
+        assert_eq!(message("#include \"a.glsl\""), "unknown directive #include");
+        assert_eq!(message("#undef"), "expected a macro name");
+    }
+
+    #[test]
+    fn define_errors(){
+        assert_eq!(message("#define 1A"), "expected a macro name");
+        assert_eq!(message("#define GL_FOO 1"), "macro names starting with GL_ or containing __ are reserved, GL_FOO");
+        assert_eq!(message("#define A__B 1"), "macro names starting with GL_ or containing __ are reserved, A__B");
+        assert_eq!(message("#define F(a, b a"), "unterminated macro parameter list");
+        assert_eq!(message("#define F(a) a ## 1"), "token pasting is not supported");
+        assert_eq!(message("#define F(a, b) a\nint x = F(1);"), "macro F expects 2 arguments but got 1");
+        assert_eq!(message("#define F(a) a\nint x = F(1;"), "unterminated macro arguments, they have to be on a single line");
+    }
+
+    #[test]
+    fn inactive_branches_are_not_checked(){
+        assert!(errors("#version 330\n#if 0\n#error unused\n#unknown\n#define GL_X\nint x = $;\n#endif").is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+use super::Diagnostic;
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum TokenKind{
+    Ident(String),
+    Int(u64),
+    UInt(u64),
+    Float(f64),
+    Punct(&'static str),
+    Eof,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Token{
+    pub kind: TokenKind,
+    ///1 based line in the preprocessed source
+    pub line: usize,
+}
+
+impl Token{
+    pub fn is(&self, punct: &str) -> bool{
+        matches!(&self.kind, TokenKind::Punct(x) if *x == punct)
+    }
+
+    pub fn ident(&self) -> Option<&str>{
+        match &self.kind{
+            TokenKind::Ident(x) => Some(x),
+            _ => None
+        }
+    }
+}
+
+///Longest first, so the lexer always takes the longest operator
+const PUNCTUATORS: &[&str] = &[
+    "<<=", ">>=",
+    "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "^^", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "##",
+    "(", ")", "[", "]", "{", "}", ".", ",", ";", ":", "?", "+", "-", "*", "/", "%", "=", "<", ">", "!", "~", "&", "|", "^", "#",
+];
+
+///
+/// Replaces comments with spaces, keeping newlines so line numbers stay the same
+pub fn strip_comments(source: &str) -> Result<String,Diagnostic>{
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next(){
+        match (c, chars.peek()){
+            ('/', Some('/')) => {
+                while let Some(x) = chars.peek(){
+                    if *x == '\n'{
+                        break;
+                    }
+                    chars.next();
+                }
+                out.push(' ');
+            },
+            ('/', Some('*')) => {
+                let start = line;
+                chars.next();
+                let mut closed = false;
+                while let Some(x) = chars.next(){
+                    if x == '\n'{
+                        line += 1;
+                        out.push('\n');
+                    }
+                    else if x == '*' && chars.peek() == Some(&'/'){
+                        chars.next();
+                        closed = true;
+                        break;
+                    }
+                }
+                if !closed{
+                    return Err(Diagnostic::new(start, "unterminated block comment"));
+                }
+                out.push(' ');
+            },
+            ('\n', _) => {
+                line += 1;
+                out.push('\n');
+            },
+            _ => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+///
+/// Splits a single line without comments into tokens
+pub fn tokenize_line(text: &str, line: usize) -> Result<Vec<Token>,Diagnostic>{
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len(){
+        let c = bytes[i];
+        if c.is_ascii_whitespace(){
+            i += 1;
+        }
+        else if c.is_ascii_alphabetic() || c == b'_'{
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_'){
+                i += 1;
+            }
+            out.push(Token{
+                kind: TokenKind::Ident(String::from(&text[start..i])),
+                line,
+            });
+        }
+        else if c.is_ascii_digit() || (c == b'.' && bytes.get(i + 1).is_some_and(|x|x.is_ascii_digit())){
+            let (kind, end) = lex_number(text, i).map_err(|e|Diagnostic::new(line, &e))?;
+            out.push(Token{
+                kind,
+                line,
+            });
+            i = end;
+        }
+        else{
+            let punct = PUNCTUATORS.iter().find(|x|text[i..].starts_with(**x))
+                .ok_or_else(||Diagnostic::new(line, &format!("unexpected character '{}'",&text[i..].chars().next().unwrap())))?;
+            out.push(Token{
+                kind: TokenKind::Punct(punct),
+                line,
+            });
+            i += punct.len();
+        }
+    }
+    Ok(out)
+}
+
+fn lex_number(text: &str, start: usize) -> Result<(TokenKind,usize),String>{
+    let bytes = text.as_bytes();
+    let mut i = start;
+
+    if bytes[i] == b'0' && matches!(bytes.get(i + 1), Some(b'x') | Some(b'X')){
+        i += 2;
+        let digits = i;
+        while i < bytes.len() && bytes[i].is_ascii_hexdigit(){
+            i += 1;
+        }
+        let value = u64::from_str_radix(&text[digits..i], 16).map_err(|_|format!("invalid hexadecimal literal {}",&text[start..i]))?;
+        return Ok(integer_suffix(bytes, i, value));
+    }
+
+    while i < bytes.len() && bytes[i].is_ascii_digit(){
+        i += 1;
+    }
+    let mut is_float = false;
+    if i < bytes.len() && bytes[i] == b'.'{
+        is_float = true;
+        i += 1;
+        while i < bytes.len() && bytes[i].is_ascii_digit(){
+            i += 1;
+        }
+    }
+    if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E'){
+        let mut j = i + 1;
+        if j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-'){
+            j += 1;
+        }
+        if j < bytes.len() && bytes[j].is_ascii_digit(){
+            is_float = true;
+            i = j;
+            while i < bytes.len() && bytes[i].is_ascii_digit(){
+                i += 1;
+            }
+        }
+    }
+
+    if is_float || matches!(bytes.get(i), Some(b'f') | Some(b'F')){
+        let value = text[start..i].parse::<f64>().map_err(|_|format!("invalid floating point literal {}",&text[start..i]))?;
+        if matches!(bytes.get(i), Some(b'f') | Some(b'F')){
+            i += 1;
+        }
+        else if text[i..].starts_with("lf") || text[i..].starts_with("LF"){
+            return Err(String::from("double precision literals are not supported"));
+        }
+        return Ok((TokenKind::Float(value), i));
+    }
+
+    let digits = &text[start..i];
+    let value = if digits.len() > 1 && digits.starts_with('0'){
+        u64::from_str_radix(&digits[1..], 8).map_err(|_|format!("invalid octal literal {}",digits))?
+    }
+    else{
+        digits.parse::<u64>().map_err(|_|format!("invalid integer literal {}",digits))?
+    };
+    Ok(integer_suffix(bytes, i, value))
+}
+
+fn integer_suffix(bytes: &[u8], i: usize, value: u64) -> (TokenKind,usize){
+    match bytes.get(i){
+        Some(b'u') | Some(b'U') => (TokenKind::UInt(value), i + 1),
+        _ => (TokenKind::Int(value), i),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<TokenKind>{
+        tokenize_line(text, 1).unwrap().into_iter().map(|x|x.kind).collect()
+    }
+
+    fn error(text: &str) -> String{
+        tokenize_line(text, 7).map(|_|()).unwrap_err().message
+    }
+
+    #[test]
+    fn comments_become_spaces_and_keep_lines(){
+        assert_eq!(strip_comments("a // b\n/* c\nd */e").unwrap(), "a  \n\n e");
+        assert_eq!(strip_comments("a\n/* open\n\n"), Err(Diagnostic::new(2, "unterminated block comment")));
+    }
+
+    #[test]
+    fn numbers_and_operators(){
+        assert_eq!(kinds("x+=0x1Fu<<=010"), vec![
+            TokenKind::Ident(String::from("x")), TokenKind::Punct("+="), TokenKind::UInt(31), TokenKind::Punct("<<="), TokenKind::Int(8),
+        ]);
+        assert_eq!(kinds(".5 1e3 2.f 3F 4e"), vec![
+            TokenKind::Float(0.5), TokenKind::Float(1000.0), TokenKind::Float(2.0), TokenKind::Float(3.0), TokenKind::Int(4), TokenKind::Ident(String::from("e")),
+        ]);
+    }
+
+    #[test]
+    fn invalid_tokens_are_reported_at_their_line(){
+        assert_eq!(tokenize_line("a = $;", 7), Err(Diagnostic::new(7, "unexpected character '$'")));
+        assert_eq!(error("0x"), "invalid hexadecimal literal 0x");
+        assert_eq!(error("0x10000000000000000"), "invalid hexadecimal literal 0x10000000000000000");
+        assert_eq!(error("1.0lf"), "double precision literals are not supported");
+        assert_eq!(error("09"), "invalid octal literal 09");
+        assert_eq!(error("99999999999999999999"), "invalid integer literal 99999999999999999999");
+    }
+}
@@ -0,0 +1,307 @@
+use crate::loader::vfs::VirtualFs;
+
+use super::{BlockMember, ShaderDataType, ShaderStage, preprocessor::{PreprocessedSource, ShaderVariant, preprocess}};
+
+mod lexer;
+mod directives;
+mod ast;
+mod parser;
+mod types;
+mod builtins;
+mod checker;
+
+pub use directives::SUPPORTED_VERSIONS;
+
+///
+/// An error at a 1 based line of the preprocessed source
+#[derive(Clone,Debug,PartialEq)]
+pub struct Diagnostic{
+    pub line: usize,
+    pub message: String,
+}
+
+impl Diagnostic{
+    pub fn new(line: usize, message: &str) -> Self{
+        Self{
+            line,
+            message: String::from(message),
+        }
+    }
+}
+
+///
+/// A uniform, input or output declared by a stage
+#[derive(Clone,Debug,PartialEq)]
+pub struct InterfaceVariable{
+    ///Struct uniforms are flattened into "name.field" like reflection reports them
+    pub name: String,
+    pub data_type: ShaderDataType,
+    ///Length of arrays
+    pub array_size: Option<u32>,
+    pub location: Option<u32>,
+    pub binding: Option<u32>,
+    ///True if a function of the stage reads or writes it, only referenced variables are active after linking
+    pub referenced: bool,
+}
+
+///
+/// A uniform block declared by a stage
+#[derive(Clone,Debug)]
+pub struct InterfaceBlock{
+    pub name: String,
+    pub instance: Option<String>,
+    pub binding: Option<u32>,
+    ///Offsets are only known for layout(std140) blocks, the others have offsets and a size of -1
+    pub std140: bool,
+    pub data_size: i32,
+    pub members: Vec<(String,BlockMember)>,
+    pub referenced: bool,
+}
+
+impl InterfaceBlock{
+    pub fn member(&self, name: &str) -> Option<&BlockMember>{
+        self.members.iter().find(|(x, _)|x == name).map(|(_, x)|x)
+    }
+}
+
+///
+/// Everything a stage exchanges with the application and the other stage
+#[derive(Clone,Debug)]
+pub struct StageInterface{
+    pub stage: ShaderStage,
+    pub version: u32,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    pub uniforms: Vec<InterfaceVariable>,
+    pub uniform_blocks: Vec<InterfaceBlock>,
+}
+
+impl StageInterface{
+    pub fn input(&self, name: &str) -> Option<&InterfaceVariable>{
+        self.inputs.iter().find(|x|x.name == name)
+    }
+
+    pub fn output(&self, name: &str) -> Option<&InterfaceVariable>{
+        self.outputs.iter().find(|x|x.name == name)
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&InterfaceVariable>{
+        self.uniforms.iter().find(|x|x.name == name)
+    }
+
+    pub fn uniform_block(&self, name: &str) -> Option<&InterfaceBlock>{
+        self.uniform_blocks.iter().find(|x|x.name == name)
+    }
+}
+
+///
+/// The interfaces of both stages of a program that passed validation
+#[derive(Clone,Debug)]
+pub struct ProgramInterface{
+    pub vertex: StageInterface,
+    pub fragment: StageInterface,
+}
+
+impl ProgramInterface{
+    ///
+    /// The vertex inputs, which reflection reports as attributes
+    pub fn attributes(&self) -> &[InterfaceVariable]{
+        &self.vertex.inputs
+    }
+
+    ///
+    /// The uniforms of both stages, a uniform declared by both is listed once
+    /// It is referenced if either stage references it
+    pub fn uniforms(&self) -> Vec<InterfaceVariable>{
+        let mut out = self.vertex.uniforms.clone();
+        for uniform in &self.fragment.uniforms{
+            match out.iter_mut().find(|x|x.name == uniform.name){
+                Some(x) => x.referenced |= uniform.referenced,
+                None => out.push(uniform.clone()),
+            }
+        }
+        out
+    }
+
+    ///
+    /// The uniform blocks of both stages, a block declared by both is listed once
+    pub fn uniform_blocks(&self) -> Vec<InterfaceBlock>{
+        let mut out = self.vertex.uniform_blocks.clone();
+        for block in &self.fragment.uniform_blocks{
+            match out.iter_mut().find(|x|x.name == block.name){
+                Some(x) => x.referenced |= block.referenced,
+                None => out.push(block.clone()),
+            }
+        }
+        out
+    }
+}
+
+///
+/// Parses and type checks both stages without a gl context, then checks that they link
+/// Includes are resolved exactly like load_shader_variant_from_fs resolves them, errors point at the original files and lines
+pub fn validate_program(fs: &dyn VirtualFs, vertex_path: &str, fragment_path: &str, variant: &ShaderVariant) -> Result<ProgramInterface,String>{
+    let vertex = preprocess(fs, vertex_path, variant)?;
+    let fragment = preprocess(fs, fragment_path, variant)?;
+    validate_preprocessed(&vertex, &fragment)
+}
+
+///
+/// Same as validate_program for sources that are already preprocessed
+pub fn validate_preprocessed(vertex: &PreprocessedSource, fragment: &PreprocessedSource) -> Result<ProgramInterface,String>{
+    let v = validate_stage(vertex, ShaderStage::Vertex);
+    let f = validate_stage(fragment, ShaderStage::Fragment);
+    let (vertex_interface, fragment_interface) = match (v, f){
+        (Ok(v), Ok(f)) => (v, f),
+        (Err(v), Err(f)) => return Err(format!("{}{}",v,f)),
+        (Err(e), _) | (_, Err(e)) => return Err(e),
+    };
+
+    let errors = checker::link(&vertex_interface, &fragment_interface);
+    if !errors.is_empty(){
+        let root = fragment.files().next().filter(|x|!x.is_empty()).unwrap_or("program");
+        let mut out = String::from("Failed to link program:\n");
+        for error in errors{
+            out.push_str(&format!("{}: {}\n",root,error));
+        }
+        return Err(out);
+    }
+    Ok(ProgramInterface{
+        vertex: vertex_interface,
+        fragment: fragment_interface,
+    })
+}
+
+///
+/// Validates a single stage, returning what it declares
+pub fn validate_stage(source: &PreprocessedSource, stage: ShaderStage) -> Result<StageInterface,String>{
+    check_stage(source.source(), stage).map_err(|e|{
+        let mut out = format!("Failed to validate {} shader:\n",stage.name());
+        for diagnostic in e{
+            match source.original_line(diagnostic.line){
+                Some(("", number, text)) => out.push_str(&format!("line {}: error: {}\n    {}\n",number,diagnostic.message,text.trim())),
+                Some((file, number, text)) => out.push_str(&format!("{}:{}: error: {}\n    {}\n",file,number,diagnostic.message,text.trim())),
+                None => out.push_str(&format!("error: {}\n",diagnostic.message)),
+            }
+        }
+        out
+    })
+}
+
+fn check_stage(source: &str, stage: ShaderStage) -> Result<StageInterface,Vec<Diagnostic>>{
+    let output = directives::run(source)?;
+    let externals = parser::parse(&output.tokens).map_err(|e|vec![e])?;
+    checker::check(&externals, stage, output.version, &output.extensions)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::{loader::vfs::MemoryFs, resource::shader::{block::{FrameUniforms, UniformBlockLayout}, builtin::BUILTIN_SHADERS}};
+
+    const VERTEX: &str = "#version 330 core
+layout(location = 0) in vec3 a_position;
+out vec2 v_uv;
+void main(){
+    v_uv = a_position.xy;
+    gl_Position = vec4(a_position, 1.0);
+}
+";
+
+    fn program(vertex: &str, fragment: &str) -> Result<ProgramInterface,String>{
+        validate_preprocessed(&PreprocessedSource::from_plain("test.vert", vertex), &PreprocessedSource::from_plain("test.frag", fragment))
+    }
+
+    fn fragment(body: &str) -> String{
+        format!("#version 330 core\nin vec2 v_uv;\nout vec4 o_color;\n{}\n",body)
+    }
+
+    #[test]
+    fn builtin_shaders_pass(){
+        for shader in BUILTIN_SHADERS{
+            let vertex = PreprocessedSource::from_plain(shader.name, shader.vertex);
+            let fragment = PreprocessedSource::from_plain(shader.name, shader.fragment);
+            let interface = validate_preprocessed(&vertex, &fragment).unwrap_or_else(|e|panic!("{}: {}",shader.name,e));
+            for (name, data_type) in shader.attributes{
+                assert_eq!(interface.vertex.input(name).map(|x|x.data_type), Some(*data_type), "{} {}", shader.name, name);
+            }
+            let uniforms = interface.uniforms();
+            for (name, data_type) in shader.uniforms{
+                assert_eq!(uniforms.iter().find(|x|x.name == *name).map(|x|x.data_type), Some(*data_type), "{} {}", shader.name, name);
+            }
+        }
+    }
+
+    #[test]
+    fn frame_uniforms_match_the_std140_offsets_of_the_rust_struct(){
+        let shader = &BUILTIN_SHADERS[0];
+        let interface = program(shader.vertex, shader.fragment).unwrap();
+        let blocks = interface.uniform_blocks();
+        let block = blocks.iter().find(|x|x.name == FrameUniforms::NAME).unwrap();
+        assert!(block.std140);
+        assert_eq!(block.data_size as usize, std::mem::size_of::<FrameUniforms>());
+        assert_eq!(block.members.len(), FrameUniforms::MEMBERS.len());
+        for (name, offset, data_type) in FrameUniforms::MEMBERS{
+            let member = block.member(name).unwrap_or_else(||panic!("missing {}",name));
+            assert_eq!((member.offset, member.data_type), (*offset, *data_type), "{}", name);
+        }
+    }
+
+    #[test]
+    fn type_mismatches_are_reported_at_the_original_line_through_includes(){
+        let fs = MemoryFs::new()
+            .with_file("test.vert", VERTEX.as_bytes().to_vec())
+            .with_file("test.frag", fragment("#include \"lib/color.glsl\"\nvoid main(){ o_color = tint(); }").into_bytes())
+            .with_file("lib/color.glsl", b"// helpers\nvec4 tint(){\n    vec3 color = 1;\n    return vec4(color, 1.0);\n}".to_vec());
+
+        let error = validate_program(&fs, "test.vert", "test.frag", &ShaderVariant::new()).unwrap_err();
+        assert!(error.contains("lib/color.glsl:3: error:"), "{}", error);
+        assert!(error.contains("vec3 color = 1;"), "{}", error);
+    }
+
+    #[test]
+    fn undeclared_identifiers_are_reported_at_the_original_line_through_includes(){
+        let fs = MemoryFs::new()
+            .with_file("test.vert", VERTEX.as_bytes().to_vec())
+            .with_file("test.frag", fragment("#include \"a.glsl\"\nvoid main(){ o_color = shade(); }").into_bytes())
+            .with_file("a.glsl", b"#include \"b.glsl\"\nvec4 shade(){ return base(); }".to_vec())
+            .with_file("b.glsl", b"\n\nvec4 base(){ return vec4(missing_value); }".to_vec());
+
+        let error = validate_program(&fs, "test.vert", "test.frag", &ShaderVariant::new()).unwrap_err();
+        assert!(error.contains("b.glsl:3: error:"), "{}", error);
+        assert!(error.contains("missing_value"), "{}", error);
+    }
+
+    #[test]
+    fn errors_of_both_stages_are_reported(){
+        let vertex = VERTEX.replace("v_uv = a_position.xy;", "v_uv = undeclared;");
+        let error = program(&vertex, &fragment("void main(){ o_color = vec4(other); }")).unwrap_err();
+        assert!(error.contains("test.vert:5: error:") && error.contains("undeclared"), "{}", error);
+        assert!(error.contains("test.frag:4: error:") && error.contains("other"), "{}", error);
+    }
+
+    #[test]
+    fn varying_type_mismatches_fail_to_link(){
+        let error = program(VERTEX, &fragment("void main(){ o_color = vec4(v_uv, 1.0); }").replace("in vec2 v_uv", "in vec3 v_uv")).unwrap_err();
+        assert!(error.contains("Failed to link program"), "{}", error);
+        assert!(error.contains("v_uv is Vec2F in the vertex shader but Vec3F in the fragment shader"), "{}", error);
+    }
+
+    #[test]
+    fn inputs_without_a_vertex_output_fail_to_link(){
+        let error = program(VERTEX, &fragment("in vec4 v_color;\nvoid main(){ o_color = v_color; }")).unwrap_err();
+        assert!(error.contains("fragment input v_color is read but the vertex shader has no output with that name"), "{}", error);
+
+        //unused inputs are removed by the linker
+        assert!(program(VERTEX, &fragment("in vec4 v_color;\nvoid main(){ o_color = vec4(v_uv, 0.0, 1.0); }")).is_ok());
+    }
+
+    #[test]
+    fn matching_programs_report_their_interface(){
+        let interface = program(VERTEX, &fragment("uniform sampler2D u_texture;\nvoid main(){ o_color = texture(u_texture, v_uv); }")).unwrap();
+        assert_eq!(interface.attributes().len(), 1);
+        assert_eq!(interface.vertex.input("a_position").unwrap().location, Some(0));
+        assert_eq!(interface.fragment.uniform("u_texture").unwrap().data_type, ShaderDataType::Sampler2D);
+        assert!(interface.fragment.output("o_color").is_some());
+    }
+}
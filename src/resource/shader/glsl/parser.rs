@@ -0,0 +1,996 @@
+use std::collections::HashSet;
+
+use super::{Diagnostic, ast::*, lexer::{Token, TokenKind}, types::Type};
+
+///Words reserved by glsl that can't name variables, functions or types
+const RESERVED: &[&str] = &[
+    "attribute", "const", "uniform", "varying", "buffer", "shared", "coherent", "volatile", "restrict", "readonly", "writeonly",
+    "layout", "centroid", "flat", "smooth", "noperspective", "patch", "sample", "subroutine", "break", "continue", "do", "for",
+    "while", "switch", "case", "default", "if", "else", "in", "out", "inout", "true", "false", "invariant", "precise", "discard",
+    "return", "lowp", "mediump", "highp", "precision", "struct", "common", "partition", "active", "asm", "class", "union", "enum",
+    "typedef", "template", "this", "resource", "goto", "inline", "noinline", "public", "static", "extern", "external", "interface",
+    "long", "short", "half", "fixed", "unsigned", "superp", "input", "output", "filter", "sizeof", "cast", "namespace", "using",
+    "double", "dvec2", "dvec3", "dvec4", "dmat2", "dmat3", "dmat4",
+];
+
+const ASSIGNMENT_OPERATORS: &[&str] = &["=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "|=", "^="];
+
+const BINARY_PRECEDENCE: &[(&str,u8)] = &[
+    ("||", 1), ("^^", 2), ("&&", 3), ("|", 4), ("^", 5), ("&", 6), ("==", 7), ("!=", 7),
+    ("<", 8), (">", 8), ("<=", 8), (">=", 8), ("<<", 9), (">>", 9), ("+", 10), ("-", 10), ("*", 11), ("/", 11), ("%", 11),
+];
+
+///
+/// Parses the tokens of a shader, which have to end in an Eof token, into external declarations
+/// Parsing stops at the first syntax error
+pub fn parse(tokens: &[Token]) -> Result<Vec<External>,Diagnostic>{
+    let mut parser = Parser{
+        tokens,
+        position: 0,
+        structs: HashSet::new(),
+    };
+    let mut out = Vec::new();
+    while !matches!(parser.peek().kind, TokenKind::Eof){
+        out.push(parser.external()?);
+    }
+    Ok(out)
+}
+
+struct Parser<'a>{
+    tokens: &'a [Token],
+    position: usize,
+    ///Names of the declared structs, which parse like built-in type names
+    structs: HashSet<String>,
+}
+
+impl<'a> Parser<'a>{
+    fn peek(&self) -> &'a Token{
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> &'a Token{
+        let index = (self.position + offset).min(self.tokens.len() - 1);
+        &self.tokens[index]
+    }
+
+    fn next(&mut self) -> &'a Token{
+        let token = self.peek();
+        if self.position < self.tokens.len() - 1{
+            self.position += 1;
+        }
+        token
+    }
+
+    fn line(&self) -> usize{
+        self.peek().line
+    }
+
+    fn at(&self, punct: &str) -> bool{
+        self.peek().is(punct)
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool{
+        self.peek().ident() == Some(keyword)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool{
+        if self.at(punct){
+            self.next();
+            true
+        }
+        else{
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool{
+        if self.at_keyword(keyword){
+            self.next();
+            true
+        }
+        else{
+            false
+        }
+    }
+
+    fn error(&self, message: &str) -> Diagnostic{
+        Diagnostic::new(self.line(), message)
+    }
+
+    fn unexpected(&self, expected: &str) -> Diagnostic{
+        let found = match &self.peek().kind{
+            TokenKind::Ident(x) => format!("'{}'",x),
+            TokenKind::Int(x) | TokenKind::UInt(x) => x.to_string(),
+            TokenKind::Float(x) => x.to_string(),
+            TokenKind::Punct(x) => format!("'{}'",x),
+            TokenKind::Eof => String::from("end of file"),
+        };
+        self.error(&format!("expected {} but found {}",expected,found))
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(),Diagnostic>{
+        if self.eat(punct){
+            Ok(())
+        }
+        else{
+            Err(self.unexpected(&format!("'{}'",punct)))
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<String,Diagnostic>{
+        match self.peek().ident(){
+            Some(x) if RESERVED.contains(&x) || self.is_type_name(x) => Err(self.error(&format!("'{}' is reserved and can't be used as a name",x))),
+            Some(x) => {
+                self.next();
+                Ok(String::from(x))
+            },
+            None => Err(self.unexpected("a name")),
+        }
+    }
+
+    fn is_type_name(&self, name: &str) -> bool{
+        Type::from_name(name, u32::MAX).is_some() || self.structs.contains(name)
+    }
+
+    fn at_type(&self) -> bool{
+        self.peek().ident().is_some_and(|x|x == "struct" || self.is_type_name(x))
+    }
+
+    fn at_qualifier(&self) -> bool{
+        matches!(self.peek().ident(), Some(
+            "const" | "in" | "out" | "inout" | "uniform" | "buffer" | "layout" | "flat" | "smooth" | "noperspective"
+            | "centroid" | "sample" | "patch" | "invariant" | "precise" | "lowp" | "mediump" | "highp" | "attribute" | "varying"
+        ))
+    }
+
+    fn external(&mut self) -> Result<External,Diagnostic>{
+        let line = self.line();
+        if self.eat(";"){
+            return Ok(External::Ignored);
+        }
+        if self.eat_keyword("precision"){
+            self.precision_statement()?;
+            return Ok(External::Ignored);
+        }
+
+        let qualifiers = self.qualifiers()?;
+        if !qualifiers.is_empty() && self.eat(";"){
+            return Ok(External::DefaultQualifiers(qualifiers));
+        }
+        //invariant gl_Position; redeclares a built-in output
+        if qualifiers.invariant && qualifiers.storage.is_none() && !self.at_type(){
+            loop{
+                self.next();
+                if !self.eat(","){
+                    break;
+                }
+            }
+            self.expect(";")?;
+            return Ok(External::Ignored);
+        }
+        if qualifiers.storage.is_some() && self.peek().ident().is_some() && !self.at_type() && self.peek_at(1).is("{"){
+            return Ok(External::Block(self.block(qualifiers, line)?));
+        }
+
+        let ty = self.type_spec()?;
+        if self.eat(";"){
+            return Ok(External::Declaration(Declaration{
+                qualifiers,
+                ty,
+                declarators: Vec::new(),
+            }));
+        }
+        let name_line = self.line();
+        let name = self.expect_name()?;
+        if self.eat("("){
+            if qualifiers.storage.is_some() || !qualifiers.layout.is_empty(){
+                return Err(Diagnostic::new(line, "functions can't have storage or layout qualifiers"));
+            }
+            return Ok(External::Function(self.function(ty, name, line)?));
+        }
+        Ok(External::Declaration(self.declaration_rest(qualifiers, ty, name, name_line)?))
+    }
+
+    fn precision_statement(&mut self) -> Result<(),Diagnostic>{
+        if !matches!(self.next().ident(), Some("lowp" | "mediump" | "highp")){
+            return Err(self.error("expected a precision qualifier"));
+        }
+        if !self.at_type(){
+            return Err(self.unexpected("a type"));
+        }
+        self.next();
+        self.expect(";")
+    }
+
+    fn qualifiers(&mut self) -> Result<Qualifiers,Diagnostic>{
+        let mut out = Qualifiers{
+            line: self.line(),
+            ..Qualifiers::default()
+        };
+        while let Some(word) = self.peek().ident(){
+            let storage = match word{
+                "const" => Some(Storage::Const),
+                "in" => Some(Storage::In),
+                "out" => Some(Storage::Out),
+                "inout" => Some(Storage::InOut),
+                "uniform" => Some(Storage::Uniform),
+                "buffer" => Some(Storage::Buffer),
+                _ => None,
+            };
+            if let Some(storage) = storage{
+                if out.storage.is_some(){
+                    return Err(self.error("only one storage qualifier is allowed"));
+                }
+                out.storage = Some(storage);
+                self.next();
+                continue;
+            }
+            match word{
+                "attribute" | "varying" => return Err(self.error(&format!("'{}' isn't available in core profiles, use in and out",word))),
+                "layout" => {
+                    self.next();
+                    self.expect("(")?;
+                    loop{
+                        let name = match self.next().ident(){
+                            Some(x) => String::from(x),
+                            None => return Err(self.error("expected a layout qualifier")),
+                        };
+                        let value = if self.eat("="){
+                            Some(self.conditional()?)
+                        }
+                        else{
+                            None
+                        };
+                        out.layout.push((name, value));
+                        if !self.eat(","){
+                            break;
+                        }
+                    }
+                    self.expect(")")?;
+                },
+                "flat" | "smooth" | "noperspective" => {
+                    if out.interpolation.is_some(){
+                        return Err(self.error("only one interpolation qualifier is allowed"));
+                    }
+                    out.interpolation = Some(String::from(word));
+                    self.next();
+                },
+                "centroid" | "sample" | "patch" => {
+                    out.auxiliary = Some(String::from(word));
+                    self.next();
+                },
+                "invariant" => {
+                    out.invariant = true;
+                    self.next();
+                },
+                "precise" => {
+                    out.precise = true;
+                    self.next();
+                },
+                "lowp" | "mediump" | "highp" => {
+                    self.next();
+                },
+                _ => break,
+            }
+        }
+        Ok(out)
+    }
+
+    fn array_sizes(&mut self) -> Result<ArraySizes,Diagnostic>{
+        let mut out = Vec::new();
+        while self.eat("["){
+            if self.eat("]"){
+                out.push(None);
+            }
+            else{
+                out.push(Some(self.expression()?));
+                self.expect("]")?;
+            }
+        }
+        Ok(out)
+    }
+
+    fn type_spec(&mut self) -> Result<TypeSpec,Diagnostic>{
+        let line = self.line();
+        let name = if self.eat_keyword("struct"){
+            TypeName::Struct(self.struct_decl(line)?)
+        }
+        else{
+            match self.peek().ident(){
+                Some(x) if self.is_type_name(x) => {
+                    self.next();
+                    TypeName::Named(String::from(x))
+                },
+                Some(x) if RESERVED.contains(&x) => return Err(self.error(&format!("'{}' is reserved",x))),
+                Some(x) => return Err(self.error(&format!("unknown type {}",x))),
+                None => return Err(self.unexpected("a type")),
+            }
+        };
+        let array = self.array_sizes()?;
+        Ok(TypeSpec{
+            name,
+            array,
+            line,
+        })
+    }
+
+    fn struct_decl(&mut self, line: usize) -> Result<StructDecl,Diagnostic>{
+        let name = if self.at("{"){
+            None
+        }
+        else{
+            Some(self.expect_name()?)
+        };
+        self.expect("{")?;
+        let fields = self.members()?;
+        if fields.is_empty(){
+            return Err(Diagnostic::new(line, "structs need at least one member"));
+        }
+        if let Some(name) = &name{
+            self.structs.insert(name.clone());
+        }
+        Ok(StructDecl{
+            name,
+            fields,
+            line,
+        })
+    }
+
+    ///
+    /// Members of a struct or block, up to and including the closing brace
+    fn members(&mut self) -> Result<Vec<MemberDecl>,Diagnostic>{
+        let mut out = Vec::new();
+        while !self.eat("}"){
+            if matches!(self.peek().kind, TokenKind::Eof){
+                return Err(self.unexpected("'}'"));
+            }
+            let line = self.line();
+            let qualifiers = self.qualifiers()?;
+            let ty = self.type_spec()?;
+            let mut names = Vec::new();
+            loop{
+                let name = self.expect_name()?;
+                let array = self.array_sizes()?;
+                names.push((name, array));
+                if !self.eat(","){
+                    break;
+                }
+            }
+            self.expect(";")?;
+            out.push(MemberDecl{
+                qualifiers,
+                ty,
+                names,
+                line,
+            });
+        }
+        Ok(out)
+    }
+
+    fn block(&mut self, qualifiers: Qualifiers, line: usize) -> Result<BlockDecl,Diagnostic>{
+        let name = self.expect_name()?;
+        self.expect("{")?;
+        let members = self.members()?;
+        let instance = if self.at(";"){
+            None
+        }
+        else{
+            let instance = self.expect_name()?;
+            Some((instance, self.array_sizes()?))
+        };
+        self.expect(";")?;
+        Ok(BlockDecl{
+            qualifiers,
+            name,
+            members,
+            instance,
+            line,
+        })
+    }
+
+    fn function(&mut self, return_type: TypeSpec, name: String, line: usize) -> Result<FunctionDecl,Diagnostic>{
+        let mut params = Vec::new();
+        //f(void) declares no parameters
+        if self.at_keyword("void") && self.peek_at(1).is(")"){
+            self.next();
+        }
+        if !self.at(")"){
+            loop{
+                let line = self.line();
+                let qualifiers = self.qualifiers()?;
+                let ty = self.type_spec()?;
+                let (name, array) = if self.peek().ident().is_some(){
+                    (Some(self.expect_name()?), self.array_sizes()?)
+                }
+                else{
+                    (None, Vec::new())
+                };
+                params.push(Param{
+                    qualifiers,
+                    ty,
+                    name,
+                    array,
+                    line,
+                });
+                if !self.eat(","){
+                    break;
+                }
+            }
+        }
+        self.expect(")")?;
+        let body = if self.eat(";"){
+            None
+        }
+        else{
+            self.expect("{")?;
+            Some(self.statements_until_brace()?)
+        };
+        Ok(FunctionDecl{
+            return_type,
+            name,
+            params,
+            body,
+            line,
+        })
+    }
+
+    fn declaration_rest(&mut self, qualifiers: Qualifiers, ty: TypeSpec, first: String, first_line: usize) -> Result<Declaration,Diagnostic>{
+        let mut declarators = Vec::new();
+        let mut name = first;
+        let mut name_line = first_line;
+        loop{
+            let array = self.array_sizes()?;
+            let init = if self.eat("="){
+                Some(self.initializer()?)
+            }
+            else{
+                None
+            };
+            declarators.push(Declarator{
+                name,
+                array,
+                init,
+                line: name_line,
+            });
+            if !self.eat(","){
+                break;
+            }
+            name_line = self.line();
+            name = self.expect_name()?;
+        }
+        self.expect(";")?;
+        Ok(Declaration{
+            qualifiers,
+            ty,
+            declarators,
+        })
+    }
+
+    fn initializer(&mut self) -> Result<Expr,Diagnostic>{
+        let line = self.line();
+        if self.eat("{"){
+            let mut items = Vec::new();
+            loop{
+                items.push(self.initializer()?);
+                if !self.eat(","){
+                    break;
+                }
+                //a trailing comma is allowed
+                if self.at("}"){
+                    break;
+                }
+            }
+            self.expect("}")?;
+            return Ok(Expr{
+                kind: ExprKind::List(items),
+                line,
+            });
+        }
+        self.assignment()
+    }
+
+    fn statements_until_brace(&mut self) -> Result<Vec<Stmt>,Diagnostic>{
+        let mut out = Vec::new();
+        while !self.eat("}"){
+            if matches!(self.peek().kind, TokenKind::Eof){
+                return Err(self.unexpected("'}'"));
+            }
+            out.push(self.statement()?);
+        }
+        Ok(out)
+    }
+
+    ///
+    /// True if the statement starting here declares variables, a type followed by a name
+    fn at_declaration(&self) -> bool{
+        if self.at_qualifier() || self.at_keyword("struct"){
+            return true;
+        }
+        if !self.at_type(){
+            return false;
+        }
+        let mut offset = 1;
+        //skip array dimensions written after the type
+        while self.peek_at(offset).is("["){
+            let mut depth = 0;
+            loop{
+                let token = self.peek_at(offset);
+                if matches!(token.kind, TokenKind::Eof){
+                    return false;
+                }
+                offset += 1;
+                if token.is("["){
+                    depth += 1;
+                }
+                else if token.is("]"){
+                    depth -= 1;
+                    if depth == 0{
+                        break;
+                    }
+                }
+            }
+        }
+        self.peek_at(offset).ident().is_some()
+    }
+
+    fn statement(&mut self) -> Result<Stmt,Diagnostic>{
+        let line = self.line();
+        let kind = match self.peek().ident(){
+            Some("if") => {
+                self.next();
+                self.expect("(")?;
+                let condition = self.expression()?;
+                self.expect(")")?;
+                let then = Box::new(self.statement()?);
+                let otherwise = if self.eat_keyword("else"){
+                    Some(Box::new(self.statement()?))
+                }
+                else{
+                    None
+                };
+                StmtKind::If{
+                    condition,
+                    then,
+                    otherwise,
+                }
+            },
+            Some("for") => {
+                self.next();
+                self.expect("(")?;
+                let init = if self.eat(";"){
+                    None
+                }
+                else{
+                    Some(Box::new(self.simple_statement()?))
+                };
+                let condition = if self.at(";"){
+                    None
+                }
+                else{
+                    Some(self.expression()?)
+                };
+                self.expect(";")?;
+                let step = if self.at(")"){
+                    None
+                }
+                else{
+                    Some(self.expression()?)
+                };
+                self.expect(")")?;
+                StmtKind::For{
+                    init,
+                    condition,
+                    step,
+                    body: Box::new(self.statement()?),
+                }
+            },
+            Some("while") => {
+                self.next();
+                self.expect("(")?;
+                let condition = self.expression()?;
+                self.expect(")")?;
+                StmtKind::While{
+                    condition,
+                    body: Box::new(self.statement()?),
+                }
+            },
+            Some("do") => {
+                self.next();
+                let body = Box::new(self.statement()?);
+                if !self.eat_keyword("while"){
+                    return Err(self.unexpected("'while'"));
+                }
+                self.expect("(")?;
+                let condition = self.expression()?;
+                self.expect(")")?;
+                self.expect(";")?;
+                StmtKind::DoWhile{
+                    body,
+                    condition,
+                }
+            },
+            Some("switch") => {
+                self.next();
+                self.expect("(")?;
+                let value = self.expression()?;
+                self.expect(")")?;
+                self.expect("{")?;
+                StmtKind::Switch{
+                    value,
+                    body: self.statements_until_brace()?,
+                }
+            },
+            Some("case") => {
+                self.next();
+                let value = self.expression()?;
+                self.expect(":")?;
+                StmtKind::Case(value)
+            },
+            Some("default") => {
+                self.next();
+                self.expect(":")?;
+                StmtKind::Default
+            },
+            Some("break") => {
+                self.next();
+                self.expect(";")?;
+                StmtKind::Break
+            },
+            Some("continue") => {
+                self.next();
+                self.expect(";")?;
+                StmtKind::Continue
+            },
+            Some("discard") => {
+                self.next();
+                self.expect(";")?;
+                StmtKind::Discard
+            },
+            Some("return") => {
+                self.next();
+                let value = if self.at(";"){
+                    None
+                }
+                else{
+                    Some(self.expression()?)
+                };
+                self.expect(";")?;
+                StmtKind::Return(value)
+            },
+            Some("precision") => {
+                self.next();
+                self.precision_statement()?;
+                StmtKind::Empty
+            },
+            _ if self.eat("{") => StmtKind::Block(self.statements_until_brace()?),
+            _ => return self.simple_statement(),
+        };
+        Ok(Stmt{
+            kind,
+            line,
+        })
+    }
+
+    ///
+    /// A declaration, expression or empty statement, including the semicolon
+    fn simple_statement(&mut self) -> Result<Stmt,Diagnostic>{
+        let line = self.line();
+        let kind = if self.eat(";"){
+            StmtKind::Empty
+        }
+        else if self.at_declaration(){
+            let qualifiers = self.qualifiers()?;
+            let ty = self.type_spec()?;
+            if self.eat(";"){
+                StmtKind::Declaration(Declaration{
+                    qualifiers,
+                    ty,
+                    declarators: Vec::new(),
+                })
+            }
+            else{
+                let name_line = self.line();
+                let name = self.expect_name()?;
+                StmtKind::Declaration(self.declaration_rest(qualifiers, ty, name, name_line)?)
+            }
+        }
+        else{
+            let expr = self.expression()?;
+            self.expect(";")?;
+            StmtKind::Expr(expr)
+        };
+        Ok(Stmt{
+            kind,
+            line,
+        })
+    }
+
+    fn expression(&mut self) -> Result<Expr,Diagnostic>{
+        let mut left = self.assignment()?;
+        while self.at(","){
+            let line = self.line();
+            self.next();
+            let right = self.assignment()?;
+            left = Expr{
+                kind: ExprKind::Sequence(Box::new(left), Box::new(right)),
+                line,
+            };
+        }
+        Ok(left)
+    }
+
+    fn assignment(&mut self) -> Result<Expr,Diagnostic>{
+        let left = self.conditional()?;
+        let operator = match &self.peek().kind{
+            TokenKind::Punct(x) if ASSIGNMENT_OPERATORS.contains(x) => *x,
+            _ => return Ok(left),
+        };
+        let line = self.line();
+        self.next();
+        let right = self.assignment()?;
+        Ok(Expr{
+            kind: ExprKind::Assign(operator, Box::new(left), Box::new(right)),
+            line,
+        })
+    }
+
+    fn conditional(&mut self) -> Result<Expr,Diagnostic>{
+        let condition = self.binary(0)?;
+        if !self.at("?"){
+            return Ok(condition);
+        }
+        let line = self.line();
+        self.next();
+        let then = self.expression()?;
+        self.expect(":")?;
+        let otherwise = self.assignment()?;
+        Ok(Expr{
+            kind: ExprKind::Ternary(Box::new(condition), Box::new(then), Box::new(otherwise)),
+            line,
+        })
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr,Diagnostic>{
+        let mut left = self.unary()?;
+        while let TokenKind::Punct(operator) = &self.peek().kind{
+            let operator = *operator;
+            let precedence = match BINARY_PRECEDENCE.iter().find(|(op, _)|*op == operator){
+                Some((_, p)) if *p > min_precedence => *p,
+                _ => break,
+            };
+            let line = self.line();
+            self.next();
+            let right = self.binary(precedence)?;
+            left = Expr{
+                kind: ExprKind::Binary(operator, Box::new(left), Box::new(right)),
+                line,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr,Diagnostic>{
+        let line = self.line();
+        let operator = match &self.peek().kind{
+            TokenKind::Punct(x @ ("++" | "--" | "+" | "-" | "!" | "~")) => *x,
+            _ => return self.postfix(),
+        };
+        self.next();
+        let operand = self.unary()?;
+        Ok(Expr{
+            kind: ExprKind::Unary(operator, Box::new(operand)),
+            line,
+        })
+    }
+
+    fn postfix(&mut self) -> Result<Expr,Diagnostic>{
+        let mut expr = self.primary()?;
+        loop{
+            let line = self.line();
+            let kind = if self.eat("["){
+                let index = self.expression()?;
+                self.expect("]")?;
+                ExprKind::Index(Box::new(expr), Box::new(index))
+            }
+            else if self.eat("."){
+                let field = match self.next().ident(){
+                    Some(x) => String::from(x),
+                    None => return Err(Diagnostic::new(line, "expected a field name after '.'")),
+                };
+                if field == "length" && self.at("("){
+                    self.next();
+                    self.expect(")")?;
+                    ExprKind::Length(Box::new(expr))
+                }
+                else{
+                    ExprKind::Field(Box::new(expr), field)
+                }
+            }
+            else if self.at("++") || self.at("--"){
+                let operator = if self.next().is("++"){ "++" } else{ "--" };
+                ExprKind::Postfix(operator, Box::new(expr))
+            }
+            else{
+                break;
+            };
+            expr = Expr{
+                kind,
+                line,
+            };
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr,Diagnostic>{
+        let line = self.line();
+        let token = self.peek();
+        let kind = match &token.kind{
+            TokenKind::Int(x) => ExprKind::Int(*x),
+            TokenKind::UInt(x) => ExprKind::UInt(*x),
+            TokenKind::Float(_) => ExprKind::Float,
+            TokenKind::Punct("(") => {
+                self.next();
+                let expr = self.expression()?;
+                self.expect(")")?;
+                return Ok(expr);
+            },
+            TokenKind::Ident(x) if x == "true" || x == "false" => ExprKind::Bool(x == "true"),
+            TokenKind::Ident(x) if self.is_type_name(x) => {
+                self.next();
+                let array = if self.at("["){
+                    Some(self.array_sizes()?)
+                }
+                else{
+                    None
+                };
+                if !self.at("("){
+                    return Err(self.unexpected("'(' after a type name"));
+                }
+                let args = self.arguments()?;
+                return Ok(Expr{
+                    kind: ExprKind::Call{
+                        name: x.clone(),
+                        array,
+                        args,
+                    },
+                    line,
+                });
+            },
+            TokenKind::Ident(x) if RESERVED.contains(&x.as_str()) => return Err(self.error(&format!("unexpected '{}'",x))),
+            TokenKind::Ident(x) => {
+                self.next();
+                if self.at("("){
+                    let args = self.arguments()?;
+                    return Ok(Expr{
+                        kind: ExprKind::Call{
+                            name: x.clone(),
+                            array: None,
+                            args,
+                        },
+                        line,
+                    });
+                }
+                return Ok(Expr{
+                    kind: ExprKind::Ident(x.clone()),
+                    line,
+                });
+            },
+            _ => return Err(self.unexpected("an expression")),
+        };
+        self.next();
+        Ok(Expr{
+            kind,
+            line,
+        })
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Expr>,Diagnostic>{
+        self.expect("(")?;
+        let mut args = Vec::new();
+        if self.at_keyword("void") && self.peek_at(1).is(")"){
+            self.next();
+        }
+        if !self.at(")"){
+            loop{
+                args.push(self.assignment()?);
+                if !self.eat(","){
+                    break;
+                }
+            }
+        }
+        self.expect(")")?;
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::{*, super::lexer::tokenize_line};
+
+    fn tokens(source: &str) -> Vec<Token>{
+        let mut out = Vec::new();
+        for (i, line) in source.lines().enumerate(){
+            out.extend(tokenize_line(line, i + 1).unwrap());
+        }
+        out.push(Token{
+            kind: TokenKind::Eof,
+            line: source.lines().count().max(1),
+        });
+        out
+    }
+
+    fn error(source: &str) -> (usize,String){
+        let error = parse(&tokens(source)).unwrap_err();
+        (error.line, error.message)
+    }
+
+    fn message(source: &str) -> String{
+        error(source).1
+    }
+
+    #[test]
+    fn parses_declarations_functions_and_statements(){
+        let source = "precision highp float;
+layout(std140, binding = 1) uniform Lights{ vec4 colors[4]; } lights;
+struct Light{ vec3 position; float radius; };
+flat out int v_index;
+invariant gl_Position;
+float falloff(Light light, float distance);
+void main(void){
+    int total = 0;
+    for(int i = 0; i < 4; i++){ if(i == 2) continue; else total += i; }
+    while(total > 0) total--;
+    do{ total++; } while(total < 2);
+    switch(total){ case 1: break; default: discard; }
+    float values[2] = {1.0, 2.0,};
+    total = total > 1 ? int(values.length()) : -total;
+    return;
+}";
+        let externals = parse(&tokens(source)).unwrap();
+        assert_eq!(externals.len(), 7);
+        assert!(matches!(&externals[1], External::Block(x) if x.name == "Lights" && x.instance.as_ref().is_some_and(|x|x.0 == "lights")));
+        assert!(matches!(&externals[5], External::Function(x) if x.name == "falloff" && x.params.len() == 2 && x.body.is_none()));
+        assert!(matches!(&externals[6], External::Function(x) if x.params.is_empty() && x.body.as_ref().is_some_and(|x|x.len() == 8)));
+    }
+
+    #[test]
+    fn name_errors(){
+        assert_eq!(message("float class;"), "'class' is reserved and can't be used as a name");
+        assert_eq!(message("float vec3;"), "'vec3' is reserved and can't be used as a name");
+        assert_eq!(message("float 1;"), "expected a name but found 1");
+        assert_eq!(message("void f(){ a. + 1; }"), "expected a field name after '.'");
+    }
+
+    #[test]
+    fn qualifier_errors(){
+        assert_eq!(error("\nuniform float f(){ return 1.0; }"), (2, String::from("functions can't have storage or layout qualifiers")));
+        assert_eq!(message("precision float;"), "expected a precision qualifier");
+        assert_eq!(message("precision highp foo;"), "expected a type but found 'foo'");
+        assert_eq!(message("in out float x;"), "only one storage qualifier is allowed");
+        assert_eq!(message("attribute vec3 a;"), "'attribute' isn't available in core profiles, use in and out");
+        assert_eq!(message("varying vec3 a;"), "'varying' isn't available in core profiles, use in and out");
+        assert_eq!(message("layout(1) in vec3 a;"), "expected a layout qualifier");
+        assert_eq!(message("flat smooth in vec3 a;"), "only one interpolation qualifier is allowed");
+    }
+
+    #[test]
+    fn type_errors(){
+        assert_eq!(message("long x;"), "'long' is reserved");
+        assert_eq!(message("foo x;"), "unknown type foo");
+        assert_eq!(message("1;"), "expected a type but found 1");
+        assert_eq!(error("float a;\nstruct S{\n};"), (2, String::from("structs need at least one member")));
+        assert_eq!(message("struct S{ float a;"), "expected '}' but found end of file");
+    }
+
+    #[test]
+    fn statement_and_expression_errors(){
+        assert_eq!(error("void main(){\n    int a = 1;"), (2, String::from("expected '}' but found end of file")));
+        assert_eq!(error("float a\nfloat b;"), (2, String::from("expected ';' but found 'float'")));
+        assert_eq!(message("void f(){ do{} until(true); }"), "expected 'while' but found 'until'");
+        assert_eq!(message("void f(){ x = vec3; }"), "expected '(' after a type name but found ';'");
+        assert_eq!(message("void f(){ x = class; }"), "unexpected 'class'");
+        assert_eq!(message("void f(){ x = ; }"), "expected an expression but found ';'");
+        assert_eq!(message("void f(){ x = (1 + 2; }"), "expected ')' but found ';'");
+        assert_eq!(message("void f(){ x = a ? 1; }"), "expected ':' but found ';'");
+    }
+}
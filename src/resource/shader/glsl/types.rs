@@ -0,0 +1,380 @@
+use std::{fmt, rc::Rc};
+
+use crate::resource::shader::{BlockMember, ShaderDataType};
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Scalar{
+    Bool,
+    Int,
+    UInt,
+    Float,
+}
+
+impl Scalar{
+    fn prefix(&self) -> &'static str{
+        match self{
+            Self::Bool => "b",
+            Self::Int => "i",
+            Self::UInt => "u",
+            Self::Float => "",
+        }
+    }
+
+    fn name(&self) -> &'static str{
+        match self{
+            Self::Bool => "bool",
+            Self::Int => "int",
+            Self::UInt => "uint",
+            Self::Float => "float",
+        }
+    }
+
+    pub fn is_integer(&self) -> bool{
+        matches!(self, Self::Int | Self::UInt)
+    }
+
+    pub fn is_numeric(&self) -> bool{
+        !matches!(self, Self::Bool)
+    }
+}
+
+///
+/// An opaque sampler type along with the coordinates its lookup functions take
+#[derive(Debug,PartialEq,Eq)]
+pub struct SamplerType{
+    pub name: &'static str,
+    pub gl_type: gl::types::GLenum,
+    ///Scalar type of the fetched texel
+    pub result: Scalar,
+    ///Components of the coordinate passed to texture, including the array layer and shadow reference
+    pub coordinate: u8,
+    ///Components of the size returned by textureSize and of the texelFetch coordinate
+    pub size: u8,
+    pub shadow: bool,
+    ///False for rectangle, buffer and multisample textures, which have no mipmaps
+    pub mipmapped: bool,
+    ///True if texelFetch works on it
+    pub fetch: bool,
+    pub min_version: u32,
+}
+
+macro_rules! sampler{
+    ($name: literal, $gl: expr, $result: ident, $coordinate: literal, $size: literal, $shadow: literal, $mipmapped: literal, $fetch: literal, $version: literal) => {
+        SamplerType{
+            name: $name,
+            gl_type: $gl,
+            result: Scalar::$result,
+            coordinate: $coordinate,
+            size: $size,
+            shadow: $shadow,
+            mipmapped: $mipmapped,
+            fetch: $fetch,
+            min_version: $version,
+        }
+    };
+}
+
+pub const SAMPLERS: &[SamplerType] = &[
+    sampler!("sampler1D", gl::SAMPLER_1D, Float, 1, 1, false, true, true, 330),
+    sampler!("sampler2D", gl::SAMPLER_2D, Float, 2, 2, false, true, true, 330),
+    sampler!("sampler3D", gl::SAMPLER_3D, Float, 3, 3, false, true, true, 330),
+    sampler!("samplerCube", gl::SAMPLER_CUBE, Float, 3, 2, false, true, false, 330),
+    sampler!("sampler1DArray", gl::SAMPLER_1D_ARRAY, Float, 2, 2, false, true, true, 330),
+    sampler!("sampler2DArray", gl::SAMPLER_2D_ARRAY, Float, 3, 3, false, true, true, 330),
+    sampler!("samplerCubeArray", gl::SAMPLER_CUBE_MAP_ARRAY, Float, 4, 3, false, true, false, 400),
+    sampler!("sampler2DRect", gl::SAMPLER_2D_RECT, Float, 2, 2, false, false, true, 330),
+    sampler!("samplerBuffer", gl::SAMPLER_BUFFER, Float, 1, 1, false, false, true, 330),
+    sampler!("sampler2DMS", gl::SAMPLER_2D_MULTISAMPLE, Float, 2, 2, false, false, true, 330),
+    sampler!("sampler1DShadow", gl::SAMPLER_1D_SHADOW, Float, 3, 1, true, true, false, 330),
+    sampler!("sampler2DShadow", gl::SAMPLER_2D_SHADOW, Float, 3, 2, true, true, false, 330),
+    sampler!("samplerCubeShadow", gl::SAMPLER_CUBE_SHADOW, Float, 4, 2, true, true, false, 330),
+    sampler!("sampler2DArrayShadow", gl::SAMPLER_2D_ARRAY_SHADOW, Float, 4, 3, true, true, false, 330),
+    sampler!("isampler1D", gl::INT_SAMPLER_1D, Int, 1, 1, false, true, true, 330),
+    sampler!("isampler2D", gl::INT_SAMPLER_2D, Int, 2, 2, false, true, true, 330),
+    sampler!("isampler3D", gl::INT_SAMPLER_3D, Int, 3, 3, false, true, true, 330),
+    sampler!("isamplerCube", gl::INT_SAMPLER_CUBE, Int, 3, 2, false, true, false, 330),
+    sampler!("isampler2DArray", gl::INT_SAMPLER_2D_ARRAY, Int, 3, 3, false, true, true, 330),
+    sampler!("isamplerBuffer", gl::INT_SAMPLER_BUFFER, Int, 1, 1, false, false, true, 330),
+    sampler!("usampler1D", gl::UNSIGNED_INT_SAMPLER_1D, UInt, 1, 1, false, true, true, 330),
+    sampler!("usampler2D", gl::UNSIGNED_INT_SAMPLER_2D, UInt, 2, 2, false, true, true, 330),
+    sampler!("usampler3D", gl::UNSIGNED_INT_SAMPLER_3D, UInt, 3, 3, false, true, true, 330),
+    sampler!("usamplerCube", gl::UNSIGNED_INT_SAMPLER_CUBE, UInt, 3, 2, false, true, false, 330),
+    sampler!("usampler2DArray", gl::UNSIGNED_INT_SAMPLER_2D_ARRAY, UInt, 3, 3, false, true, true, 330),
+    sampler!("usamplerBuffer", gl::UNSIGNED_INT_SAMPLER_BUFFER, UInt, 1, 1, false, false, true, 330),
+];
+
+#[derive(Debug,PartialEq)]
+pub struct StructType{
+    pub name: String,
+    pub fields: Vec<(String,Type)>,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum Type{
+    Void,
+    Scalar(Scalar),
+    Vector(Scalar,u8),
+    ///Columns and rows, always float
+    Matrix(u8,u8),
+    Sampler(&'static SamplerType),
+    Struct(Rc<StructType>),
+    ///Element type and length, None if the length isn't known at compile time
+    Array(Box<Type>,Option<u32>),
+}
+
+impl Type{
+    pub const BOOL: Type = Type::Scalar(Scalar::Bool);
+    pub const INT: Type = Type::Scalar(Scalar::Int);
+    pub const UINT: Type = Type::Scalar(Scalar::UInt);
+    pub const FLOAT: Type = Type::Scalar(Scalar::Float);
+
+    ///
+    /// A scalar for one component, a vector otherwise
+    pub fn vector(scalar: Scalar, components: u8) -> Self{
+        if components == 1{
+            Self::Scalar(scalar)
+        }
+        else{
+            Self::Vector(scalar, components)
+        }
+    }
+
+    ///
+    /// Looks up a built-in type by its glsl name
+    pub fn from_name(name: &str, version: u32) -> Option<Self>{
+        let scalar = match name{
+            "void" => return Some(Self::Void),
+            "bool" => return Some(Self::BOOL),
+            "int" => return Some(Self::INT),
+            "uint" => return Some(Self::UINT),
+            "float" => return Some(Self::FLOAT),
+            _ => None,
+        };
+        if scalar.is_some(){
+            return scalar;
+        }
+        if let Some(sampler) = SAMPLERS.iter().find(|x|x.name == name && x.min_version <= version){
+            return Some(Self::Sampler(sampler));
+        }
+        if let Some(size) = name.strip_prefix("mat"){
+            let (columns, rows) = match size.split_once('x'){
+                Some((c, r)) => (c, r),
+                None => (size, size),
+            };
+            return match (matrix_size(columns), matrix_size(rows)){
+                (Some(c), Some(r)) => Some(Self::Matrix(c, r)),
+                _ => None,
+            };
+        }
+        let (scalar, rest) = match name.as_bytes().first(){
+            Some(b'b') => (Scalar::Bool, &name[1..]),
+            Some(b'i') => (Scalar::Int, &name[1..]),
+            Some(b'u') => (Scalar::UInt, &name[1..]),
+            _ => (Scalar::Float, name),
+        };
+        let size = rest.strip_prefix("vec").and_then(matrix_size)?;
+        Some(Self::Vector(scalar, size))
+    }
+
+    pub fn scalar(&self) -> Option<Scalar>{
+        match self{
+            Self::Scalar(x) | Self::Vector(x, _) => Some(*x),
+            Self::Matrix(_, _) => Some(Scalar::Float),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Number of scalar components of scalars, vectors and matrices
+    pub fn components(&self) -> Option<u32>{
+        match self{
+            Self::Scalar(_) => Some(1),
+            Self::Vector(_, n) => Some(*n as u32),
+            Self::Matrix(c, r) => Some(*c as u32 * *r as u32),
+            _ => None,
+        }
+    }
+
+    pub fn is_scalar(&self) -> bool{
+        matches!(self, Self::Scalar(_))
+    }
+
+    pub fn is_numeric(&self) -> bool{
+        self.scalar().is_some_and(|x|x.is_numeric())
+    }
+
+    pub fn is_array(&self) -> bool{
+        matches!(self, Self::Array(_, _))
+    }
+
+    pub fn with_scalar(&self, scalar: Scalar) -> Self{
+        match self{
+            Self::Scalar(_) => Self::Scalar(scalar),
+            Self::Vector(_, n) => Self::Vector(scalar, *n),
+            x => x.clone(),
+        }
+    }
+
+    ///
+    /// True if the type is or contains the predicate
+    pub fn contains(&self, predicate: &dyn Fn(&Type) -> bool) -> bool{
+        if predicate(self){
+            return true;
+        }
+        match self{
+            Self::Array(x, _) => x.contains(predicate),
+            Self::Struct(x) => x.fields.iter().any(|(_, t)|t.contains(predicate)),
+            _ => false,
+        }
+    }
+
+    pub fn is_opaque(&self) -> bool{
+        self.contains(&|x|matches!(x, Type::Sampler(_)))
+    }
+
+    ///
+    /// The gl type enum reflection reports for a variable of this type, None for arrays and structs
+    pub fn gl_type(&self) -> Option<gl::types::GLenum>{
+        Some(match self{
+            Self::Scalar(Scalar::Bool) => gl::BOOL,
+            Self::Scalar(Scalar::Int) => gl::INT,
+            Self::Scalar(Scalar::UInt) => gl::UNSIGNED_INT,
+            Self::Scalar(Scalar::Float) => gl::FLOAT,
+            Self::Vector(s, n) => {
+                let table = match s{
+                    Scalar::Bool => [gl::BOOL_VEC2, gl::BOOL_VEC3, gl::BOOL_VEC4],
+                    Scalar::Int => [gl::INT_VEC2, gl::INT_VEC3, gl::INT_VEC4],
+                    Scalar::UInt => [gl::UNSIGNED_INT_VEC2, gl::UNSIGNED_INT_VEC3, gl::UNSIGNED_INT_VEC4],
+                    Scalar::Float => [gl::FLOAT_VEC2, gl::FLOAT_VEC3, gl::FLOAT_VEC4],
+                };
+                table[*n as usize - 2]
+            },
+            Self::Matrix(c, r) => match (c, r){
+                (2, 2) => gl::FLOAT_MAT2,
+                (2, 3) => gl::FLOAT_MAT2x3,
+                (2, 4) => gl::FLOAT_MAT2x4,
+                (3, 2) => gl::FLOAT_MAT3x2,
+                (3, 3) => gl::FLOAT_MAT3,
+                (3, 4) => gl::FLOAT_MAT3x4,
+                (4, 2) => gl::FLOAT_MAT4x2,
+                (4, 3) => gl::FLOAT_MAT4x3,
+                _ => gl::FLOAT_MAT4,
+            },
+            Self::Sampler(x) => x.gl_type,
+            _ => return None,
+        })
+    }
+
+    pub fn data_type(&self) -> Option<ShaderDataType>{
+        self.gl_type().map(ShaderDataType::from_gl_type)
+    }
+
+    ///
+    /// Base alignment and size in bytes under the std140 rules
+    pub fn std140(&self) -> (u32,u32){
+        match self{
+            Self::Scalar(_) => (4, 4),
+            Self::Vector(_, 2) => (8, 8),
+            Self::Vector(_, 3) => (16, 12),
+            Self::Vector(_, _) => (16, 16),
+            //matrices are stored like arrays of their column vectors
+            Self::Matrix(c, _) => (16, 16 * *c as u32),
+            Self::Array(element, length) => {
+                let (alignment, size) = element.std140();
+                let stride = round_up(size, alignment.max(16));
+                (alignment.max(16), stride * length.unwrap_or(1))
+            },
+            Self::Struct(x) => {
+                let mut alignment = 16;
+                let mut size = 0;
+                for (_, field) in &x.fields{
+                    let (a, s) = field.std140();
+                    alignment = alignment.max(a);
+                    size = round_up(size, a) + s;
+                }
+                (alignment, round_up(size, alignment))
+            },
+            Self::Void | Self::Sampler(_) => (0, 0),
+        }
+    }
+}
+
+fn matrix_size(text: &str) -> Option<u8>{
+    match text{
+        "2" => Some(2),
+        "3" => Some(3),
+        "4" => Some(4),
+        _ => None,
+    }
+}
+
+pub fn round_up(value: u32, alignment: u32) -> u32{
+    if alignment == 0{
+        value
+    }
+    else{
+        value.div_ceil(alignment) * alignment
+    }
+}
+
+///
+/// Lays out the members of a std140 block, structs are flattened into "member.field" names like reflection reports them
+/// Returns the members and the size of the block
+pub fn std140_members(members: &[(String,Type)]) -> (Vec<(String,BlockMember)>,i32){
+    let mut out = Vec::new();
+    let mut offset = 0;
+    for (name, member) in members{
+        offset = layout_member(name, member, offset, &mut out);
+    }
+    (out, round_up(offset, 16) as i32)
+}
+
+fn layout_member(name: &str, member: &Type, offset: u32, out: &mut Vec<(String,BlockMember)>) -> u32{
+    let (alignment, size) = member.std140();
+    let offset = round_up(offset, alignment);
+    match member{
+        Type::Struct(x) => {
+            let mut field_offset = offset;
+            for (field, field_type) in &x.fields{
+                field_offset = layout_member(&format!("{}.{}",name,field), field_type, field_offset, out);
+            }
+        },
+        Type::Array(element, length) if matches!(**element, Type::Struct(_)) => {
+            let stride = round_up(element.std140().1, alignment);
+            for i in 0..length.unwrap_or(1){
+                layout_member(&format!("{}[{}]",name,i), element, offset + i * stride, out);
+            }
+        },
+        _ => {
+            let (element, length, array_stride) = match member{
+                Type::Array(element, length) => (&**element, length.unwrap_or(1) as i32, round_up(element.std140().1, alignment) as i32),
+                x => (x, 1, 0),
+            };
+            let matrix_stride = if matches!(element, Type::Matrix(_, _)){ 16 } else{ 0 };
+            out.push((String::from(name), BlockMember{
+                offset: offset as i32,
+                data_type: element.data_type().unwrap_or(ShaderDataType::Other(0)),
+                size: length,
+                array_stride,
+                matrix_stride,
+            }));
+        }
+    }
+    offset + size
+}
+
+impl fmt::Display for Type{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            Self::Void => write!(f, "void"),
+            Self::Scalar(x) => write!(f, "{}", x.name()),
+            Self::Vector(x, n) => write!(f, "{}vec{}", x.prefix(), n),
+            Self::Matrix(c, r) if c == r => write!(f, "mat{}", c),
+            Self::Matrix(c, r) => write!(f, "mat{}x{}", c, r),
+            Self::Sampler(x) => write!(f, "{}", x.name),
+            Self::Struct(x) => write!(f, "{}", x.name),
+            Self::Array(x, Some(n)) => write!(f, "{}[{}]", x, n),
+            Self::Array(x, None) => write!(f, "{}[]", x),
+        }
+    }
+}
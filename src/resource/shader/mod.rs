@@ -1,127 +1,588 @@
-use std::{collections::HashMap, ffi::CString, ops::Deref, rc::Rc};
+use std::{collections::HashMap, sync::{Arc, Mutex, RwLock}};
 
+pub mod uniform;
+pub mod preprocessor;
+pub mod builtin;
+pub mod block;
+pub mod binary_cache;
+pub mod glsl;
+
+use binary_cache::ProgramBinaryCache;
+use preprocessor::PreprocessedSource;
+use uniform::UniformValue;
+
+///
+/// Type of a shader uniform or attribute, mapped from the gl type enum
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
 pub enum ShaderDataType{
+    Bool,
     Int,
+    UInt,
     Float,
     Vec2F,
     Vec3F,
     Vec4F,
+    Vec2I,
+    Vec3I,
+    Vec4I,
     Mat2F,
     Mat3F,
-    Mat4F
+    Mat4F,
+    Sampler2D,
+    ///A type without a dedicated variant, holding the gl enum
+    Other(gl::types::GLenum),
 }
 
+impl ShaderDataType{
+    pub fn from_gl_type(gl_type: gl::types::GLenum) -> Self{
+        match gl_type{
+            gl::BOOL => Self::Bool,
+            gl::INT => Self::Int,
+            gl::UNSIGNED_INT => Self::UInt,
+            gl::FLOAT => Self::Float,
+            gl::FLOAT_VEC2 => Self::Vec2F,
+            gl::FLOAT_VEC3 => Self::Vec3F,
+            gl::FLOAT_VEC4 => Self::Vec4F,
+            gl::INT_VEC2 => Self::Vec2I,
+            gl::INT_VEC3 => Self::Vec3I,
+            gl::INT_VEC4 => Self::Vec4I,
+            gl::FLOAT_MAT2 => Self::Mat2F,
+            gl::FLOAT_MAT3 => Self::Mat3F,
+            gl::FLOAT_MAT4 => Self::Mat4F,
+            gl::SAMPLER_2D => Self::Sampler2D,
+            x => Self::Other(x),
+        }
+    }
+}
+
+///
+/// A stage of a shader program
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum ShaderStage{
+    Vertex,
+    Fragment,
+}
+
+impl ShaderStage{
+    pub fn name(&self) -> &'static str{
+        match self{
+            Self::Vertex => "vertex",
+            Self::Fragment => "fragment",
+        }
+    }
+
+    fn gl_enum(&self) -> gl::types::GLenum{
+        match self{
+            Self::Vertex => gl::VERTEX_SHADER,
+            Self::Fragment => gl::FRAGMENT_SHADER,
+        }
+    }
+}
+
+///
+/// An active uniform or attribute of a linked program
+#[derive(Clone,Debug)]
 pub struct ShaderInput{
-    id: i32,
+    location: i32,
     data_type: ShaderDataType,
+    ///Number of elements, larger than 1 for arrays
+    size: i32,
+}
+
+impl ShaderInput{
+    pub fn location(&self) -> i32{
+        self.location
+    }
+
+    pub fn data_type(&self) -> ShaderDataType{
+        self.data_type
+    }
+
+    pub fn size(&self) -> i32{
+        self.size
+    }
+}
+
+///
+/// A member of a uniform block with its std140 layout
+#[derive(Clone,Debug)]
+pub struct BlockMember{
+    pub offset: i32,
+    pub data_type: ShaderDataType,
+    ///Number of elements, larger than 1 for arrays
+    pub size: i32,
+    pub array_stride: i32,
+    pub matrix_stride: i32,
+}
+
+///
+/// An active uniform block of a linked program
+#[derive(Clone,Debug)]
+pub struct UniformBlockInfo{
+    pub index: u32,
+    ///Size of the block in bytes
+    pub data_size: i32,
+    pub members: HashMap<String,BlockMember>,
 }
 
 struct RawGlShader{
     id: gl::types::GLuint,
     uniforms: HashMap<String,ShaderInput>,
     attributes: HashMap<String,ShaderInput>,
+    uniform_blocks: HashMap<String,UniformBlockInfo>,
+    ///Binding point of every uniform block bound so far, restored after a reload
+    block_bindings: Mutex<HashMap<String,u32>>,
+    ///Last value uploaded to each uniform, by name so it can be restored after a reload
+    uniform_cache: Mutex<HashMap<String,UniformValue>>,
 }
 
+unsafe impl Send for RawGlShader{}
+unsafe impl Sync for RawGlShader{}
+
 impl RawGlShader{
-    fn from_source(vertex: &str, fragment: &str) -> Result<Self,String>{
-        let v = Self::create_shader(vertex, gl::VERTEX_SHADER)?;
-        let f = Self::create_shader(fragment, gl::FRAGMENT_SHADER)?;
+    fn from_source(vertex: &PreprocessedSource, fragment: &PreprocessedSource) -> Result<Self,String>{
+        Self::compile(vertex, fragment, false).map(Self::from_program)
+    }
 
+    ///
+    /// Compiles and links a program, retrievable asks the driver to keep the program binary around for ProgramBinaryCache
+    fn compile(vertex: &PreprocessedSource, fragment: &PreprocessedSource, retrievable: bool) -> Result<gl::types::GLuint,String>{
+        let v = Self::create_shader(vertex, ShaderStage::Vertex)?;
+        let f = match Self::create_shader(fragment, ShaderStage::Fragment){
+            Ok(x) => x,
+            Err(e) => {
+                unsafe{gl::DeleteShader(v);}
+                return Err(e);
+            }
+        };
 
+        let program = Self::create_program(v, f, retrievable);
+        unsafe{
+            gl::DeleteShader(v);
+            gl::DeleteShader(f);
+        }
+        program
+    }
 
-        Ok(Self{
-            id: 0,
-            uniforms: HashMap::new(),
-            attributes: HashMap::new(),
-        })
+    ///
+    /// Takes ownership of a linked program and reflects its inputs
+    fn from_program(id: gl::types::GLuint) -> Self{
+        let (uniforms, attributes, uniform_blocks) = unsafe{
+            (Self::reflect_uniforms(id), Self::reflect_attributes(id), Self::reflect_uniform_blocks(id))
+        };
+        Self{
+            id,
+            uniforms,
+            attributes,
+            uniform_blocks,
+            block_bindings: Mutex::new(HashMap::new()),
+            uniform_cache: Mutex::new(HashMap::new()),
+        }
     }
 
-    fn create_program(vertex: gl::types::GLuint, fragment: gl::types::GLuint) -> Result<gl::types::GLuint,String>{
+    fn create_program(vertex: gl::types::GLuint, fragment: gl::types::GLuint, retrievable: bool) -> Result<gl::types::GLuint,String>{
         unsafe{
             let program = gl::CreateProgram();
+            if retrievable{
+                gl::ProgramParameteri(program, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as i32);
+            }
 
             gl::AttachShader(program, vertex);
             gl::AttachShader(program,fragment);
             gl::LinkProgram(program);
-            Self::validate_program(program, gl::LINK_STATUS);
-            
+            let linked = Self::validate_program(program, gl::LINK_STATUS);
+            gl::DetachShader(program, vertex);
+            gl::DetachShader(program, fragment);
 
-            
-            let uniform_count = {
-                let mut out = 0;
-                gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut out);
-                out
-            } as u32;
-
-            for i in 0..uniform_count{
-                let name = {
-                    let mut len = 0;
-                    gl::GetActiveUniformBlockiv(program, i, gl::UNIFORM_NAME_LENGTH, &mut len);
-                    let mut buf = vec![0;len as usize];
-                    let mut _outlen = 0;
-                    gl::GetActiveUniformName(program,i,len,&mut _outlen, buf.as_mut_ptr());
-                    String::from(CString::from_raw(buf.as_mut_ptr()).to_str().unwrap())
+            if let Err(e) = linked{
+                gl::DeleteProgram(program);
+                return Err(format!("Failed to link shader program:\n{}",e));
+            }
+            Ok(program)
+        }
+    }
+
+    unsafe fn reflect_uniforms(program: gl::types::GLuint) -> HashMap<String,ShaderInput>{
+        let mut count = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+        let mut max_length = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+
+        let mut out = HashMap::new();
+        for i in 0..count as u32{
+            let mut buf = vec![0u8;max_length.max(1) as usize];
+            let mut length = 0;
+            let mut size = 0;
+            let mut gl_type = 0;
+            gl::GetActiveUniform(program, i, buf.len() as i32, &mut length, &mut size, &mut gl_type, buf.as_mut_ptr() as *mut gl::types::GLchar);
+            let location = gl::GetUniformLocation(program, buf.as_ptr() as *const gl::types::GLchar);
+            //members of uniform blocks have no location
+            if location < 0{
+                continue;
+            }
+            out.insert(input_name(&buf[..length as usize]), ShaderInput{
+                location,
+                data_type: ShaderDataType::from_gl_type(gl_type),
+                size,
+            });
+        }
+        out
+    }
+
+    unsafe fn reflect_attributes(program: gl::types::GLuint) -> HashMap<String,ShaderInput>{
+        let mut count = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut count);
+        let mut max_length = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_length);
+
+        let mut out = HashMap::new();
+        for i in 0..count as u32{
+            let mut buf = vec![0u8;max_length.max(1) as usize];
+            let mut length = 0;
+            let mut size = 0;
+            let mut gl_type = 0;
+            gl::GetActiveAttrib(program, i, buf.len() as i32, &mut length, &mut size, &mut gl_type, buf.as_mut_ptr() as *mut gl::types::GLchar);
+            let location = gl::GetAttribLocation(program, buf.as_ptr() as *const gl::types::GLchar);
+            //built in inputs like gl_VertexID have no location
+            if location < 0{
+                continue;
+            }
+            out.insert(input_name(&buf[..length as usize]), ShaderInput{
+                location,
+                data_type: ShaderDataType::from_gl_type(gl_type),
+                size,
+            });
+        }
+        out
+    }
+
+    unsafe fn reflect_uniform_blocks(program: gl::types::GLuint) -> HashMap<String,UniformBlockInfo>{
+        let mut count = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCKS, &mut count);
+        let mut max_length = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH, &mut max_length);
+        let mut max_member_length = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_member_length);
+
+        let mut out = HashMap::new();
+        for index in 0..count as u32{
+            let mut buf = vec![0u8;max_length.max(1) as usize];
+            let mut length = 0;
+            gl::GetActiveUniformBlockName(program, index, buf.len() as i32, &mut length, buf.as_mut_ptr() as *mut gl::types::GLchar);
+            let name = input_name(&buf[..length as usize]);
+
+            let mut data_size = 0;
+            gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut data_size);
+            let mut member_count = 0;
+            gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_ACTIVE_UNIFORMS, &mut member_count);
+            let mut indices = vec![0i32;member_count as usize];
+            if member_count > 0{
+                gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES, indices.as_mut_ptr());
+            }
+            let indices = indices.into_iter().map(|x|x as u32).collect::<Vec<_>>();
+
+            let query = |property: gl::types::GLenum|{
+                let mut values = vec![0i32;indices.len()];
+                if !indices.is_empty(){
+                    gl::GetActiveUniformsiv(program, indices.len() as i32, indices.as_ptr(), property, values.as_mut_ptr());
+                }
+                values
+            };
+            let offsets = query(gl::UNIFORM_OFFSET);
+            let types = query(gl::UNIFORM_TYPE);
+            let sizes = query(gl::UNIFORM_SIZE);
+            let array_strides = query(gl::UNIFORM_ARRAY_STRIDE);
+            let matrix_strides = query(gl::UNIFORM_MATRIX_STRIDE);
+
+            let mut members = HashMap::new();
+            for (i, member) in indices.iter().enumerate(){
+                let mut buf = vec![0u8;max_member_length.max(1) as usize];
+                let mut length = 0;
+                gl::GetActiveUniformName(program, *member, buf.len() as i32, &mut length, buf.as_mut_ptr() as *mut gl::types::GLchar);
+                //members of blocks with an instance name are reported as 'Block.member'
+                let member_name = input_name(&buf[..length as usize]);
+                let member_name = match member_name.strip_prefix(&format!("{}.",name)){
+                    Some(x) => String::from(x),
+                    None => member_name,
                 };
+                members.insert(member_name, BlockMember{
+                    offset: offsets[i],
+                    data_type: ShaderDataType::from_gl_type(types[i] as gl::types::GLenum),
+                    size: sizes[i],
+                    array_stride: array_strides[i],
+                    matrix_stride: matrix_strides[i],
+                });
             }
-            
 
-            Ok(program)
+            out.insert(name, UniformBlockInfo{
+                index,
+                data_size,
+                members,
+            });
         }
+        out
     }
 
-    fn create_shader(source: &str, shader_type: gl::types::GLenum) -> Result<gl::types::GLuint,String>{
+    fn create_shader(source: &PreprocessedSource, stage: ShaderStage) -> Result<gl::types::GLuint,String>{
         unsafe{
-            let shader = gl::CreateShader(shader_type);
-            gl::ShaderSource(shader,1,source.as_ptr() as *const *const i8,&(source.len() as i32));
+            let shader = gl::CreateShader(stage.gl_enum());
+            let text = source.source();
+            let pointer = text.as_ptr() as *const gl::types::GLchar;
+            gl::ShaderSource(shader,1,&pointer,&(text.len() as i32));
             gl::CompileShader(shader);
-            Self::validate_shader(shader, gl::COMPILE_STATUS)?;
+            if let Err(e) = Self::validate_shader(shader, gl::COMPILE_STATUS){
+                gl::DeleteShader(shader);
+                return Err(format!("Failed to compile {} shader:\n{}",stage.name(),annotate_log(&e, source)));
+            }
             Ok(shader)
         }
     }
 
     fn validate_shader(shader: gl::types::GLuint, target: gl::types::GLenum) -> Result<(),String>{
-        
         unsafe{
             let mut ok = 0;
             gl::GetShaderiv(shader, target, &mut ok);
             if ok != gl::TRUE as i32{
                 let mut len = 0;
                 gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
-                let mut buf = vec![0;len as usize];
+                let mut buf = vec![0u8;len.max(1) as usize];
                 let mut out = 0;
-                gl::GetShaderInfoLog(shader,len,&mut out,buf.as_mut_ptr());
-                let cstring = std::ffi::CString::from_raw(buf.as_mut_ptr());
-                Err(String::from(cstring.to_string_lossy()))
-            }   
-            else{                
+                gl::GetShaderInfoLog(shader,buf.len() as i32,&mut out,buf.as_mut_ptr() as *mut gl::types::GLchar);
+                Err(String::from_utf8_lossy(&buf[..out as usize]).into_owned())
+            }
+            else{
                 Ok(())
-            }     
+            }
         }
     }
 
     fn validate_program(program: gl::types::GLuint, target: gl::types::GLenum) -> Result<(),String>{
-        
         unsafe{
             let mut ok = 0;
             gl::GetProgramiv(program, target, &mut ok);
             if ok != gl::TRUE as i32{
                 let mut len = 0;
                 gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
-                let mut buf = vec![0;len as usize];
+                let mut buf = vec![0u8;len.max(1) as usize];
                 let mut out = 0;
-                gl::GetProgramInfoLog(program,len,&mut out,buf.as_mut_ptr());
-                let cstring = std::ffi::CString::from_raw(buf.as_mut_ptr());
-                Err(String::from(cstring.to_string_lossy()))
-            }   
-            else{                
+                gl::GetProgramInfoLog(program,buf.len() as i32,&mut out,buf.as_mut_ptr() as *mut gl::types::GLchar);
+                Err(String::from_utf8_lossy(&buf[..out as usize]).into_owned())
+            }
+            else{
                 Ok(())
-            }     
+            }
         }
     }
 }
 
+impl RawGlShader{
+    fn bind_uniform_block(&self, name: &str, binding: u32) -> Result<(),String>{
+        let block = self.uniform_blocks.get(name).ok_or_else(||format!("Shader has no active uniform block named {}",name))?;
+        unsafe{
+            gl::UniformBlockBinding(self.id, block.index, binding);
+        }
+        self.block_bindings.lock().unwrap().insert(String::from(name), binding);
+        Ok(())
+    }
+
+    fn set_uniform(&self, name: &str, value: UniformValue) -> Result<(),String>{
+        let input = self.uniforms.get(name).ok_or_else(||format!("Shader has no active uniform named {}",name))?;
+        if !value.matches(input.data_type){
+            return Err(format!("Uniform {} is {:?} but a {:?} value was given",name,input.data_type,value.data_type()));
+        }
+        if value.len() > input.size as usize{
+            return Err(format!("Uniform {} holds {} elements but {} were given",name,input.size,value.len()));
+        }
+
+        let mut cache = self.uniform_cache.lock().unwrap();
+        if cache.get(name) == Some(&value){
+            return Ok(());
+        }
+        value.upload(self.id, input.location);
+        cache.insert(String::from(name), value);
+        Ok(())
+    }
+}
+
+impl Drop for RawGlShader{
+    fn drop(&mut self) {
+        //0 is never a linked program
+        if self.id == 0{
+            return;
+        }
+        unsafe{
+            gl::DeleteProgram(self.id);
+        }
+    }
+}
+
+///
+/// Arrays are reported as 'name[0]', they are stored under their plain name
+fn input_name(bytes: &[u8]) -> String{
+    let name = String::from_utf8_lossy(bytes);
+    String::from(name.strip_suffix("[0]").unwrap_or(&name))
+}
+
+///
+/// Extracts the source line number from an info log line
+/// Handles the common driver formats '0:12(5): error', 'ERROR: 0:12: ...' and '0(12) : error'
+pub fn log_line_number(line: &str) -> Option<usize>{
+    let line = line.trim_start();
+    let line = line.strip_prefix("ERROR: ").or_else(||line.strip_prefix("WARNING: ")).unwrap_or(line);
+    let rest = line.trim_start_matches(|c: char|c.is_ascii_digit());
+    if rest.len() == line.len(){
+        return None;
+    }
+    let digits = match rest.chars().next()?{
+        ':' | '(' => &rest[1..],
+        _ => return None
+    };
+    let end = digits.find(|c: char|!c.is_ascii_digit()).unwrap_or(digits.len());
+    digits[..end].parse().ok()
+}
+
+///
+/// Prefixes every log line that refers to a source line with the file and line it originally came from, and its text
+fn annotate_log(log: &str, source: &PreprocessedSource) -> String{
+    let mut out = String::new();
+    for line in log.lines().filter(|x|!x.trim().is_empty()){
+        match log_line_number(line).and_then(|x|source.original_line(x)){
+            Some(("", number, text)) => out.push_str(&format!("line {}: {}\n    {}\n",number,line.trim(),text.trim())),
+            Some((file, number, text)) => out.push_str(&format!("{}:{}: {}\n    {}\n",file,number,line.trim(),text.trim())),
+            None => out.push_str(&format!("{}\n",line.trim())),
+        }
+    }
+    out
+}
+
+///
+/// Represents a linked gl shader program
+/// It is safe to clone this shader due to the underlying program being ref counted
+/// Reloading swaps the program for every clone
+#[derive(Clone)]
 pub struct GlShader{
-    raw: Rc<RawGlShader>
+    raw: Arc<RwLock<RawGlShader>>
 }
 
+impl GlShader{
+    ///
+    /// A shader reporting the uniforms as (name, type, size) without a gl program, for testing code that only reads the reflection
+    #[cfg(test)]
+    pub(crate) fn with_reflected_uniforms(uniforms: &[(&str,ShaderDataType,i32)]) -> Self{
+        let uniforms = uniforms.iter().enumerate().map(|(location, (name, data_type, size))|(String::from(*name), ShaderInput{
+            location: location as i32,
+            data_type: *data_type,
+            size: *size,
+        })).collect();
+        Self{
+            raw: Arc::new(RwLock::new(RawGlShader{
+                id: 0,
+                uniforms,
+                attributes: HashMap::new(),
+                uniform_blocks: HashMap::new(),
+                block_bindings: Mutex::new(HashMap::new()),
+                uniform_cache: Mutex::new(HashMap::new()),
+            }))
+        }
+    }
+
+    ///
+    /// Compiles and links the program, errors contain the failing stage and the offending source lines
+    pub fn from_source(vertex: &str, fragment: &str) -> Result<Self,String>{
+        Self::from_preprocessed(&PreprocessedSource::from_plain("", vertex), &PreprocessedSource::from_plain("", fragment))
+    }
+
+    ///
+    /// Same as from_source, but errors point at the files and lines the preprocessed source came from
+    pub fn from_preprocessed(vertex: &PreprocessedSource, fragment: &PreprocessedSource) -> Result<Self,String>{
+        Ok(Self{
+            raw: Arc::new(RwLock::new(RawGlShader::from_source(vertex, fragment)?))
+        })
+    }
+
+    ///
+    /// Same as from_preprocessed, but a program binary stored by an earlier run is used when the cache has one
+    /// Binaries the driver rejects are removed and the sources are compiled instead, storing the new binary
+    pub fn from_preprocessed_cached(vertex: &PreprocessedSource, fragment: &PreprocessedSource, cache: &ProgramBinaryCache) -> Result<Self,String>{
+        let key = cache.key(vertex, fragment);
+        let id = match cache.load(key){
+            Some(x) => x,
+            None => {
+                let id = RawGlShader::compile(vertex, fragment, true)?;
+                //a failed write only costs the next run a compile
+                let _ = cache.store(key, id);
+                id
+            }
+        };
+        Ok(Self{
+            raw: Arc::new(RwLock::new(RawGlShader::from_program(id)))
+        })
+    }
+
+    ///
+    /// Compiles the new sources and swaps them in for every clone of this shader
+    /// Uniform values set before are restored by name where the new program has a uniform of the same type
+    /// If compiling or linking fails the current program stays active and the error is returned
+    pub fn reload(&self, vertex: &PreprocessedSource, fragment: &PreprocessedSource) -> Result<(),String>{
+        let program = RawGlShader::from_source(vertex, fragment)?;
+        let mut raw = self.raw.write().unwrap();
+        let values = std::mem::take(&mut *raw.uniform_cache.lock().unwrap());
+        for (name, value) in values{
+            //uniforms that were removed or changed type are dropped
+            let _ = program.set_uniform(&name, value);
+        }
+        let bindings = std::mem::take(&mut *raw.block_bindings.lock().unwrap());
+        for (name, binding) in bindings{
+            let _ = program.bind_uniform_block(&name, binding);
+        }
+        *raw = program;
+        Ok(())
+    }
+
+    pub fn id(&self) -> gl::types::GLuint{
+        self.raw.read().unwrap().id
+    }
+
+    pub fn bind(&self){
+        unsafe{
+            gl::UseProgram(self.id());
+        }
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<ShaderInput>{
+        self.raw.read().unwrap().uniforms.get(name).cloned()
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<ShaderInput>{
+        self.raw.read().unwrap().attributes.get(name).cloned()
+    }
+
+    pub fn uniforms(&self) -> Vec<(String,ShaderInput)>{
+        self.raw.read().unwrap().uniforms.iter().map(|(k,v)|(k.clone(),v.clone())).collect()
+    }
+
+    pub fn attributes(&self) -> Vec<(String,ShaderInput)>{
+        self.raw.read().unwrap().attributes.iter().map(|(k,v)|(k.clone(),v.clone())).collect()
+    }
+
+    pub fn uniform_block(&self, name: &str) -> Option<UniformBlockInfo>{
+        self.raw.read().unwrap().uniform_blocks.get(name).cloned()
+    }
+
+    pub fn uniform_blocks(&self) -> Vec<(String,UniformBlockInfo)>{
+        self.raw.read().unwrap().uniform_blocks.iter().map(|(k,v)|(k.clone(),v.clone())).collect()
+    }
+
+    ///
+    /// Makes the uniform block read from the buffer bound to the binding point, see UniformBlock
+    pub fn bind_uniform_block(&self, name: &str, binding: u32) -> Result<(),String>{
+        self.raw.read().unwrap().bind_uniform_block(name, binding)
+    }
 
+    ///
+    /// Sets the uniform, checking the value against the reflected type and array size
+    /// Setting the same value as the last call is skipped, the program doesn't have to be bound
+    pub fn set_uniform(&self, name: &str, value: impl Into<UniformValue>) -> Result<(),String>{
+        self.raw.read().unwrap().set_uniform(name, value.into())
+    }
+}
@@ -0,0 +1,846 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::loader::vfs::VirtualFs;
+
+use super::{GlShader, binary_cache::ProgramBinaryCache};
+
+///
+/// A set of defines injected into both stages of a shader, identifying one compiled variant
+/// Defines are kept sorted so the same set always produces the same key
+#[derive(Clone,Debug,Default,PartialEq,Eq,Hash)]
+pub struct ShaderVariant{
+    defines: Vec<(String,Option<String>)>,
+}
+
+impl ShaderVariant{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    ///Adds a define without a value, for example USE_NORMAL_MAP
+    pub fn with_define(self, name: &str) -> Self{
+        self.with_entry(name, None)
+    }
+
+    ///Adds a define with a value, for example MAX_LIGHTS 8
+    pub fn with_value(self, name: &str, value: &str) -> Self{
+        self.with_entry(name, Some(String::from(value)))
+    }
+
+    fn with_entry(mut self, name: &str, value: Option<String>) -> Self{
+        match self.defines.binary_search_by(|(x,_)|x.as_str().cmp(name)){
+            Ok(i) => self.defines[i].1 = value,
+            Err(i) => self.defines.insert(i, (String::from(name), value)),
+        }
+        self
+    }
+
+    pub fn defines(&self) -> impl Iterator<Item = (&str,Option<&str>)>{
+        self.defines.iter().map(|(k,v)|(k.as_str(),v.as_deref()))
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.defines.is_empty()
+    }
+
+    ///A readable key like 'ALPHA_TEST,MAX_LIGHTS=8'
+    pub fn key(&self) -> String{
+        self.defines.iter()
+            .map(|(k,v)|match v{
+                Some(v) => format!("{}={}",k,v),
+                None => k.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+///Name the injected define lines are reported under
+const VARIANT_FILE: &str = "<variant>";
+
+///
+/// Shader source with includes resolved and defines injected
+/// Every line remembers the file and line it came from, so compile errors can point at the original source
+#[derive(Clone,Debug,Default)]
+pub struct PreprocessedSource{
+    source: String,
+    files: Vec<String>,
+    ///File index and 1 based line for every output line
+    lines: Vec<(usize,usize)>,
+    text: Vec<String>,
+}
+
+impl PreprocessedSource{
+    ///
+    /// Wraps source that doesn't need preprocessing, lines map to themselves
+    pub fn from_plain(file: &str, source: &str) -> Self{
+        let mut out = Self::default();
+        let file = out.add_file(file);
+        for (i, line) in source.lines().enumerate(){
+            out.push_line(line, file, i + 1);
+        }
+        out
+    }
+
+    pub fn source(&self) -> &str{
+        &self.source
+    }
+
+    ///
+    /// Every file that contributed to the source, the root file first
+    pub fn files(&self) -> impl Iterator<Item = &str>{
+        self.files.iter().map(|x|x.as_str()).filter(|x|*x != VARIANT_FILE)
+    }
+
+    ///
+    /// Returns the file, line and text a 1 based line of the output came from
+    pub fn original_line(&self, line: usize) -> Option<(&str,usize,&str)>{
+        let index = line.checked_sub(1)?;
+        let (file, original) = *self.lines.get(index)?;
+        Some((&self.files[file], original, &self.text[index]))
+    }
+
+    fn add_file(&mut self, file: &str) -> usize{
+        self.files.push(String::from(file));
+        self.files.len() - 1
+    }
+
+    fn push_line(&mut self, line: &str, file: usize, original: usize){
+        self.source.push_str(line);
+        self.source.push('\n');
+        self.lines.push((file, original));
+        self.text.push(String::from(line));
+    }
+}
+
+///
+/// Preprocesses the file at the path, resolving '#include "file"' relative to the including file
+/// The defines of the variant are injected after the '#version' line
+/// Conditionals are tracked so includes in branches known to be skipped, and in comments, are left alone
+/// Files are only included once by the branches known to be compiled, so shared code doesn't need include guards
+pub fn preprocess(fs: &dyn VirtualFs, path: &str, variant: &ShaderVariant) -> Result<PreprocessedSource,String>{
+    let source = read_source(fs, path)?;
+    preprocess_source(fs, path, &source, variant)
+}
+
+///
+/// Same as preprocess, but the source of the root file is given, path is used for resolving includes and errors
+pub fn preprocess_source(fs: &dyn VirtualFs, path: &str, source: &str, variant: &ShaderVariant) -> Result<PreprocessedSource,String>{
+    let mut out = PreprocessedSource::default();
+    let mut state = State::default();
+
+    //the version directive has to stay the first statement, defines go right after it
+    let mut lines = source.lines().enumerate().peekable();
+    let root = out.add_file(path);
+    while let Some((i, line)) = lines.peek(){
+        let trimmed = line.trim();
+        if let Some(version) = trimmed.strip_prefix("#version"){
+            if let Some(number) = version.split_whitespace().next().filter(|x|x.parse::<u32>().is_ok()){
+                state.defines.insert(String::from("__VERSION__"), Define::Value(String::from(number)));
+            }
+            out.push_line(line, root, i + 1);
+            lines.next();
+            break;
+        }
+        if !trimmed.is_empty() && !trimmed.starts_with("//"){
+            break;
+        }
+        out.push_line(line, root, i + 1);
+        lines.next();
+    }
+
+    let defines = out.add_file(VARIANT_FILE);
+    for (i, (name, value)) in variant.defines().enumerate(){
+        let line = match value{
+            Some(value) => format!("#define {} {}",name,value),
+            None => format!("#define {}",name),
+        };
+        out.push_line(&line, defines, i + 1);
+        state.defines.insert(String::from(name), Define::Value(String::from(value.unwrap_or(""))));
+    }
+
+    state.stack.push(String::from(path));
+    state.included.push(String::from(path));
+    process_lines(fs, path, root, lines, &mut out, &mut state)?;
+    Ok(out)
+}
+
+///
+/// Whether the lines of a conditional branch are compiled, as far as the preprocessor can tell
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+enum Branch{
+    Active,
+    Inactive,
+    ///The condition depends on something only the compiler knows, like the GL_ macros of its extensions
+    Unknown,
+}
+
+impl Branch{
+    fn and(self, other: Branch) -> Branch{
+        match (self, other){
+            (Self::Inactive, _) | (_, Self::Inactive) => Self::Inactive,
+            (Self::Active, Self::Active) => Self::Active,
+            _ => Self::Unknown,
+        }
+    }
+
+    fn or(self, other: Branch) -> Branch{
+        match (self, other){
+            (Self::Active, _) | (_, Self::Active) => Self::Active,
+            (Self::Inactive, Self::Inactive) => Self::Inactive,
+            _ => Self::Unknown,
+        }
+    }
+
+    fn not(self) -> Branch{
+        match self{
+            Self::Active => Self::Inactive,
+            Self::Inactive => Self::Active,
+            Self::Unknown => Self::Unknown,
+        }
+    }
+
+    fn from_value(value: Option<i64>) -> Branch{
+        match value{
+            Some(0) => Self::Inactive,
+            Some(_) => Self::Active,
+            None => Self::Unknown,
+        }
+    }
+}
+
+struct Conditional{
+    branch: Branch,
+    parent: Branch,
+    ///Whether any branch so far was taken
+    taken: Branch,
+}
+
+enum Define{
+    ///An object-like macro and its body
+    Value(String),
+    ///A function-like macro, which conditions aren't evaluated through
+    Function,
+    ///Defined or undefined in a branch that may not be compiled
+    Unknown,
+}
+
+#[derive(Default)]
+struct State{
+    ///Files currently being included, used to detect cycles
+    stack: Vec<String>,
+    ///Every file included by a branch known to be compiled
+    included: Vec<String>,
+    conditionals: Vec<Conditional>,
+    defines: HashMap<String,Define>,
+}
+
+impl State{
+    fn branch(&self) -> Branch{
+        self.conditionals.last().map_or(Branch::Active, |x|x.branch)
+    }
+
+    ///
+    /// Applies a conditional or define directive, malformed ones are left for the compiler to report
+    fn directive(&mut self, directive: &str, rest: &str){
+        let parent = self.branch();
+        match directive{
+            "if" | "ifdef" | "ifndef" => {
+                let condition = match directive{
+                    "if" => self.condition(rest),
+                    "ifdef" => self.is_defined(macro_name(rest)),
+                    _ => self.is_defined(macro_name(rest)).not(),
+                };
+                self.conditionals.push(Conditional{
+                    branch: parent.and(condition),
+                    parent,
+                    taken: condition,
+                });
+            },
+            "elif" => {
+                let condition = self.condition(rest);
+                if let Some(current) = self.conditionals.last_mut(){
+                    current.branch = current.parent.and(current.taken.not()).and(condition);
+                    current.taken = current.taken.or(condition);
+                }
+            },
+            "else" => {
+                if let Some(current) = self.conditionals.last_mut(){
+                    current.branch = current.parent.and(current.taken.not());
+                    current.taken = Branch::Active;
+                }
+            },
+            "endif" => {
+                self.conditionals.pop();
+            },
+            "define" | "undef" if parent != Branch::Inactive => {
+                let rest = rest.trim_start();
+                let name = macro_name(rest);
+                if name.is_empty(){
+                    return;
+                }
+                let body = &rest[name.len()..];
+                let define = if parent == Branch::Unknown{
+                    Define::Unknown
+                }
+                else if directive == "undef"{
+                    self.defines.remove(name);
+                    return;
+                }
+                else if body.starts_with('('){
+                    Define::Function
+                }
+                else{
+                    Define::Value(String::from(body.split("//").next().unwrap().trim()))
+                };
+                self.defines.insert(String::from(name), define);
+            },
+            _ => {}
+        }
+    }
+
+    fn is_defined(&self, name: &str) -> Branch{
+        match self.defines.get(name){
+            Some(Define::Unknown) => Branch::Unknown,
+            Some(_) => Branch::Active,
+            None if is_compiler_name(name) => Branch::Unknown,
+            None => Branch::Inactive,
+        }
+    }
+
+    fn condition(&self, text: &str) -> Branch{
+        let value = tokenize(text).and_then(|x|evaluate(&self.expand(x, 0)));
+        Branch::from_value(value)
+    }
+
+    ///
+    /// Replaces defined() and object-like macros, identifiers nothing is known about become 0 like in C
+    fn expand(&self, tokens: Vec<Token>, depth: usize) -> Vec<Token>{
+        let mut out = Vec::new();
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next(){
+            match token{
+                Token::Ident(x) if x == "defined" => {
+                    //the operand of defined isn't expanded
+                    let open = tokens.next_if_eq(&Token::Punct("(")).is_some();
+                    let value = match tokens.next(){
+                        Some(Token::Ident(name)) => self.is_defined(&name),
+                        _ => return vec![Token::Unknown],
+                    };
+                    if open && tokens.next_if_eq(&Token::Punct(")")).is_none(){
+                        return vec![Token::Unknown];
+                    }
+                    out.push(match value{
+                        Branch::Active => Token::Number(1),
+                        Branch::Inactive => Token::Number(0),
+                        Branch::Unknown => Token::Unknown,
+                    });
+                },
+                Token::Ident(x) => match self.defines.get(&x){
+                    Some(Define::Value(body)) if depth < MAX_EXPANSION_DEPTH => match tokenize(body){
+                        Some(body) => out.extend(self.expand(body, depth + 1)),
+                        None => out.push(Token::Unknown),
+                    },
+                    None if !is_compiler_name(&x) => out.push(Token::Number(0)),
+                    _ => out.push(Token::Unknown),
+                },
+                x => out.push(x),
+            }
+        }
+        out
+    }
+}
+
+///Deepest macro expansion evaluated in a condition, deeper ones are likely self referencing
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+///
+/// The compiler defines GL_ macros for its profile and extensions, and reserves names containing '__'
+fn is_compiler_name(name: &str) -> bool{
+    name.starts_with("GL_") || name.contains("__")
+}
+
+fn macro_name(text: &str) -> &str{
+    let text = text.trim_start();
+    let end = text.find(|c: char|!(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(text.len());
+    &text[..end]
+}
+
+#[derive(Clone,Debug,PartialEq)]
+enum Token{
+    Number(i64),
+    Ident(String),
+    Punct(&'static str),
+    ///A value only the compiler knows
+    Unknown,
+}
+
+///Longer operators come first, so they are matched before their prefixes
+const PUNCTUATION: &[&str] = &["&&", "||", "==", "!=", "<=", ">=", "<<", ">>", "(", ")", "!", "~", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^"];
+
+fn tokenize(text: &str) -> Option<Vec<Token>>{
+    let mut rest = text.split("//").next().unwrap().trim_start();
+    let mut out = Vec::new();
+    while let Some(c) = rest.chars().next(){
+        let end = if c.is_ascii_digit(){
+            let end = rest.find(|c: char|!c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+            out.push(Token::Number(parse_number(&rest[..end])?));
+            end
+        }
+        else if c.is_ascii_alphabetic() || c == '_'{
+            let end = rest.find(|c: char|!(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            out.push(Token::Ident(String::from(&rest[..end])));
+            end
+        }
+        else{
+            let punct = PUNCTUATION.iter().find(|x|rest.starts_with(**x))?;
+            out.push(Token::Punct(punct));
+            punct.len()
+        };
+        rest = rest[end..].trim_start();
+    }
+    Some(out)
+}
+
+fn parse_number(text: &str) -> Option<i64>{
+    let text = text.trim_end_matches(['u', 'U']);
+    match text.strip_prefix("0x").or_else(||text.strip_prefix("0X")){
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None if text.len() > 1 && text.starts_with('0') => i64::from_str_radix(&text[1..], 8).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+///
+/// Evaluates an expanded condition, None if it is malformed or depends on an unknown value
+/// && and || short circuit, so a known operand can decide them on its own
+fn evaluate(tokens: &[Token]) -> Option<i64>{
+    let mut evaluator = Evaluator{
+        tokens,
+        position: 0,
+    };
+    match evaluator.parse(1){
+        Ok(x) if evaluator.position == tokens.len() => x,
+        _ => None,
+    }
+}
+
+struct Evaluator<'a>{
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Evaluator<'_>{
+    fn parse(&mut self, min_precedence: u8) -> Result<Option<i64>,()>{
+        let mut left = self.unary()?;
+        while let Some(Token::Punct(op)) = self.tokens.get(self.position){
+            let precedence = match binary_precedence(op){
+                Some(x) if x >= min_precedence => x,
+                _ => break,
+            };
+            self.position += 1;
+            let right = self.parse(precedence + 1)?;
+            left = apply(op, left, right);
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Option<i64>,()>{
+        let token = self.tokens.get(self.position).ok_or(())?;
+        self.position += 1;
+        match token{
+            Token::Number(x) => Ok(Some(*x)),
+            Token::Unknown => Ok(None),
+            Token::Punct("(") => {
+                let value = self.parse(1)?;
+                if self.tokens.get(self.position) != Some(&Token::Punct(")")){
+                    return Err(());
+                }
+                self.position += 1;
+                Ok(value)
+            },
+            Token::Punct("!") => Ok(self.unary()?.map(|x|(x == 0) as i64)),
+            Token::Punct("~") => Ok(self.unary()?.map(|x|!x)),
+            Token::Punct("-") => Ok(self.unary()?.map(|x|x.wrapping_neg())),
+            Token::Punct("+") => self.unary(),
+            _ => Err(()),
+        }
+    }
+}
+
+fn binary_precedence(op: &str) -> Option<u8>{
+    Some(match op{
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | ">" | "<=" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+fn apply(op: &str, left: Option<i64>, right: Option<i64>) -> Option<i64>{
+    match (op, left, right){
+        ("&&", Some(0), _) | ("&&", _, Some(0)) => Some(0),
+        ("||", Some(x), _) | ("||", _, Some(x)) if x != 0 => Some(1),
+        (_, Some(a), Some(b)) => match op{
+            "&&" | "||" => Some((a != 0 || b != 0) as i64),
+            "|" => Some(a | b),
+            "^" => Some(a ^ b),
+            "&" => Some(a & b),
+            "==" => Some((a == b) as i64),
+            "!=" => Some((a != b) as i64),
+            "<" => Some((a < b) as i64),
+            ">" => Some((a > b) as i64),
+            "<=" => Some((a <= b) as i64),
+            ">=" => Some((a >= b) as i64),
+            "<<" => u32::try_from(b).ok().and_then(|b|a.checked_shl(b)),
+            ">>" => u32::try_from(b).ok().and_then(|b|a.checked_shr(b)),
+            "+" => Some(a.wrapping_add(b)),
+            "-" => Some(a.wrapping_sub(b)),
+            "*" => Some(a.wrapping_mul(b)),
+            "/" => a.checked_div(b),
+            "%" => a.checked_rem(b),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+///
+/// Returns true if a block comment is still open at the end of the line
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool{
+    let mut rest = line;
+    loop{
+        if in_comment{
+            match rest.find("*/"){
+                Some(x) => {
+                    rest = &rest[x + 2..];
+                    in_comment = false;
+                },
+                None => return true,
+            }
+        }
+        else{
+            match (rest.find("/*"), rest.find("//")){
+                (Some(block), line) if line.is_none_or(|x|block < x) => {
+                    rest = &rest[block + 2..];
+                    in_comment = true;
+                },
+                _ => return false,
+            }
+        }
+    }
+}
+
+fn process_lines<'a>(fs: &dyn VirtualFs, path: &str, file: usize, lines: impl Iterator<Item = (usize,&'a str)>, out: &mut PreprocessedSource, state: &mut State) -> Result<(),String>{
+    let mut in_comment = false;
+    for (i, line) in lines{
+        //lines starting inside a block comment can't hold directives
+        let commented = in_comment;
+        in_comment = ends_in_comment(line, in_comment);
+        if commented{
+            out.push_line(line, file, i + 1);
+        }
+        else{
+            process_line(fs, path, file, line, i + 1, out, state)?;
+        }
+    }
+    Ok(())
+}
+
+fn process_line(fs: &dyn VirtualFs, path: &str, file: usize, line: &str, number: usize, out: &mut PreprocessedSource, state: &mut State) -> Result<(),String>{
+    let include = match parse_include(line){
+        //the compiler skips the include along with the rest of the branch
+        Some(_) if state.branch() == Branch::Inactive => None,
+        Some(x) => Some(x.map_err(|e|format!("{} at {}:{}",e,path,number))?),
+        None => {
+            if let Some(directive) = line.trim().strip_prefix('#'){
+                let directive = directive.trim_start();
+                let name = macro_name(directive);
+                state.directive(name, &directive[name.len()..]);
+            }
+            None
+        }
+    };
+    let include = match include{
+        Some(x) => x,
+        None => {
+            out.push_line(line, file, number);
+            return Ok(());
+        }
+    };
+
+    let include_path = resolve_path(path, include);
+    if state.stack.contains(&include_path){
+        return Err(format!("Circular include of {} at {}:{}",include_path,path,number));
+    }
+    if state.included.contains(&include_path){
+        return Ok(());
+    }
+
+    let source = read_source(fs, &include_path).map_err(|e|format!("{} (included at {}:{})",e,path,number))?;
+    //a branch that may be skipped doesn't count, the file could be needed again later
+    if state.branch() == Branch::Active{
+        state.included.push(include_path.clone());
+    }
+    state.stack.push(include_path.clone());
+    let include_file = out.add_file(&include_path);
+    process_lines(fs, &include_path, include_file, source.lines().enumerate(), out, state)?;
+    state.stack.pop();
+    Ok(())
+}
+
+///
+/// Returns None if the line isn't an include directive, otherwise the quoted path
+fn parse_include(line: &str) -> Option<Result<&str,String>>{
+    let rest = line.trim().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim();
+    let path = rest.strip_prefix('"').and_then(|x|x.split_once('"')).map(|(path, tail)|(path, tail.trim()));
+    Some(match path{
+        Some((path, tail)) if !path.is_empty() && (tail.is_empty() || tail.starts_with("//")) => Ok(path),
+        _ => Err(String::from("Invalid include, expected #include \"file\"")),
+    })
+}
+
+///
+/// Resolves an include relative to the directory of the including file, handling '.' and '..' segments
+fn resolve_path(from: &str, include: &str) -> String{
+    let mut segments = Vec::new();
+    if !include.starts_with('/'){
+        segments.extend(from.split('/').filter(|x|!x.is_empty()));
+        segments.pop();
+    }
+    for segment in include.split('/'){
+        match segment{
+            "" | "." => {},
+            ".." => {
+                segments.pop();
+            },
+            x => segments.push(x),
+        }
+    }
+    segments.join("/")
+}
+
+fn read_source(fs: &dyn VirtualFs, path: &str) -> Result<String,String>{
+    String::from_utf8(fs.read(path)?).map_err(|_|format!("Invalid shader source {}: not utf8",path))
+}
+
+///
+/// A vertex and fragment shader pair compiled on demand for every variant it is requested with
+/// Compiled variants are cached by their key, so requesting the same defines again reuses the program
+pub struct ShaderVariants{
+    vertex_path: String,
+    fragment_path: String,
+    compiled: Mutex<HashMap<ShaderVariant,GlShader>>,
+    binary_cache: Option<ProgramBinaryCache>,
+}
+
+impl ShaderVariants{
+    pub fn new(vertex_path: &str, fragment_path: &str) -> Self{
+        Self{
+            vertex_path: String::from(vertex_path),
+            fragment_path: String::from(fragment_path),
+            compiled: Mutex::new(HashMap::new()),
+            binary_cache: None,
+        }
+    }
+
+    ///Loads and stores the linked programs of the variants in the cache, see ProgramBinaryCache
+    pub fn with_binary_cache(mut self, cache: ProgramBinaryCache) -> Self{
+        self.binary_cache = Some(cache);
+        self
+    }
+
+    ///
+    /// Returns the program for the variant, preprocessing and compiling it from the filesystem the first time
+    pub fn get(&self, fs: &dyn VirtualFs, variant: &ShaderVariant) -> Result<GlShader,String>{
+        if let Some(shader) = self.compiled.lock().unwrap().get(variant){
+            return Ok(shader.clone());
+        }
+
+        let vertex = preprocess(fs, &self.vertex_path, variant)?;
+        let fragment = preprocess(fs, &self.fragment_path, variant)?;
+        let shader = match &self.binary_cache{
+            Some(cache) => GlShader::from_preprocessed_cached(&vertex, &fragment, cache),
+            None => GlShader::from_preprocessed(&vertex, &fragment),
+        }.map_err(|e|format!("{} (variant '{}')",e,variant.key()))?;
+        self.compiled.lock().unwrap().insert(variant.clone(), shader.clone());
+        Ok(shader)
+    }
+
+    ///Returns the program for the variant if it has been compiled already
+    pub fn get_compiled(&self, variant: &ShaderVariant) -> Option<GlShader>{
+        self.compiled.lock().unwrap().get(variant).cloned()
+    }
+
+    pub fn compiled_count(&self) -> usize{
+        self.compiled.lock().unwrap().len()
+    }
+
+    ///Drops every compiled variant, they are recompiled the next time they are requested
+    pub fn clear(&self){
+        self.compiled.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::loader::vfs::MemoryFs;
+
+    fn file(fs: MemoryFs, path: &str, source: &str) -> MemoryFs{
+        fs.with_file(path, source.as_bytes().to_vec())
+    }
+
+    ///The output line holding the text, 1 based
+    fn line_of(source: &PreprocessedSource, text: &str) -> usize{
+        source.source().lines().position(|x|x == text).unwrap() + 1
+    }
+
+    #[test]
+    fn nested_includes_are_resolved_relative_to_the_including_file(){
+        let fs = file(MemoryFs::new(), "shaders/main.frag", "#version 450\n#include \"lib/light.glsl\"\nvoid main(){}");
+        let fs = file(fs, "shaders/lib/light.glsl", "#include \"../common/math.glsl\"\nfloat light(){ return pi(); }");
+        let fs = file(fs, "shaders/common/math.glsl", "float pi(){ return 3.14; }");
+
+        let out = preprocess(&fs, "shaders/main.frag", &ShaderVariant::new()).unwrap();
+        assert_eq!(out.source(), "#version 450\nfloat pi(){ return 3.14; }\nfloat light(){ return pi(); }\nvoid main(){}\n");
+        assert_eq!(out.files().collect::<Vec<_>>(), ["shaders/main.frag", "shaders/lib/light.glsl", "shaders/common/math.glsl"]);
+    }
+
+    #[test]
+    fn files_are_only_included_once(){
+        let fs = file(MemoryFs::new(), "main.vert", "#include \"a.glsl\"\n#include \"b.glsl\"\n#include \"./a.glsl\"\nvoid main(){}");
+        let fs = file(fs, "a.glsl", "#include \"common.glsl\"\nfloat a;");
+        let fs = file(fs, "b.glsl", "#include \"common.glsl\"\nfloat b;");
+        let fs = file(fs, "common.glsl", "float common;");
+
+        let out = preprocess(&fs, "main.vert", &ShaderVariant::new()).unwrap();
+        assert_eq!(out.source(), "float common;\nfloat a;\nfloat b;\nvoid main(){}\n");
+    }
+
+    #[test]
+    fn circular_includes_are_an_error(){
+        let fs = file(MemoryFs::new(), "main.vert", "#include \"a.glsl\"");
+        let fs = file(fs, "a.glsl", "\n#include \"b.glsl\"");
+        let fs = file(fs, "b.glsl", "#include \"a.glsl\"");
+
+        let error = preprocess(&fs, "main.vert", &ShaderVariant::new()).unwrap_err();
+        assert!(error.contains("Circular include of a.glsl at b.glsl:1"), "{}", error);
+
+        let fs = file(MemoryFs::new(), "self.glsl", "#include \"self.glsl\"");
+        assert!(preprocess(&fs, "self.glsl", &ShaderVariant::new()).is_err());
+    }
+
+    #[test]
+    fn missing_includes_report_where_they_were_included(){
+        let fs = file(MemoryFs::new(), "main.vert", "#version 450\n\n#include \"missing.glsl\"");
+        let error = preprocess(&fs, "main.vert", &ShaderVariant::new()).unwrap_err();
+        assert!(error.contains("included at main.vert:3"), "{}", error);
+    }
+
+    fn shared_in_both_branches() -> MemoryFs{
+        let fs = file(MemoryFs::new(), "main.vert", "#version 450\n#ifdef A\n#include \"x.glsl\"\n#else\n#include \"x.glsl\"\n#endif\nvoid main(){}");
+        file(fs, "x.glsl", "float x;")
+    }
+
+    #[test]
+    fn an_include_in_both_branches_is_kept_when_the_first_is_taken(){
+        let out = preprocess(&shared_in_both_branches(), "main.vert", &ShaderVariant::new().with_define("A")).unwrap();
+        assert_eq!(out.source(), "#version 450\n#define A\n#ifdef A\nfloat x;\n#else\n#include \"x.glsl\"\n#endif\nvoid main(){}\n");
+    }
+
+    #[test]
+    fn an_include_in_both_branches_is_kept_when_the_else_is_taken(){
+        let out = preprocess(&shared_in_both_branches(), "main.vert", &ShaderVariant::new()).unwrap();
+        assert_eq!(out.source(), "#version 450\n#ifdef A\n#include \"x.glsl\"\n#else\nfloat x;\n#endif\nvoid main(){}\n");
+        assert_eq!(out.original_line(5), Some(("x.glsl", 1, "float x;")));
+    }
+
+    #[test]
+    fn includes_in_skipped_branches_may_be_missing(){
+        let source = "#version 450\n#if defined(USE_LIGHTS) && COUNT > 2\n#include \"missing.glsl\"\n#elif 0\n#include \"missing.glsl\"\n#endif\nvoid main(){}";
+        let fs = file(MemoryFs::new(), "main.vert", source);
+        assert!(preprocess(&fs, "main.vert", &ShaderVariant::new()).is_ok());
+        assert!(preprocess(&fs, "main.vert", &ShaderVariant::new().with_define("USE_LIGHTS").with_value("COUNT", "2")).is_ok());
+        let error = preprocess(&fs, "main.vert", &ShaderVariant::new().with_define("USE_LIGHTS").with_value("COUNT", "(1 + 2)")).unwrap_err();
+        assert!(error.contains("included at main.vert:3"), "{}", error);
+    }
+
+    #[test]
+    fn defines_in_the_source_decide_branches(){
+        let source = "#define USE_X\n#ifdef USE_X\n#include \"x.glsl\"\n#endif\n#undef USE_X\n#ifdef USE_X\n#include \"missing.glsl\"\n#endif\n#ifndef USE_X\nfloat y;\n#endif";
+        let fs = file(file(MemoryFs::new(), "main.vert", source), "x.glsl", "float x;");
+        let out = preprocess(&fs, "main.vert", &ShaderVariant::new()).unwrap();
+        assert!(out.source().contains("float x;\n"));
+
+        //skipped defines don't count
+        let fs = file(MemoryFs::new(), "main.vert", "#if 0\n#define B\n#endif\n#ifdef B\n#include \"missing.glsl\"\n#endif");
+        assert!(preprocess(&fs, "main.vert", &ShaderVariant::new()).is_ok());
+    }
+
+    #[test]
+    fn includes_in_branches_only_the_compiler_knows_are_resolved(){
+        let fs = file(MemoryFs::new(), "main.vert", "#version 450\n#ifdef GL_ES\n#include \"a.glsl\"\n#endif\n#include \"a.glsl\"");
+        let fs = file(fs, "a.glsl", "float a;");
+        //either branch could be compiled, so the file is included by both
+        let out = preprocess(&fs, "main.vert", &ShaderVariant::new()).unwrap();
+        assert_eq!(out.source(), "#version 450\n#ifdef GL_ES\nfloat a;\n#endif\nfloat a;\n");
+
+        let fs = file(MemoryFs::new(), "main.vert", "#version 450\n#if __VERSION__ >= 400 || GL_EXT_foo\n#include \"a.glsl\"\n#endif");
+        assert!(preprocess(&fs, "main.vert", &ShaderVariant::new()).unwrap_err().contains("included at main.vert:3"));
+        let fs = file(MemoryFs::new(), "main.vert", "#version 330\n#if __VERSION__ >= 400 && GL_EXT_foo\n#include \"missing.glsl\"\n#endif");
+        assert!(preprocess(&fs, "main.vert", &ShaderVariant::new()).is_ok());
+    }
+
+    #[test]
+    fn includes_in_comments_are_left_alone(){
+        let source = "#version 450\n/* old lighting\n#include \"missing.glsl\"\n*/\n// #include \"missing.glsl\"\nvoid main(){} /* a\n#if 0 */\n#include \"x.glsl\"";
+        let fs = file(file(MemoryFs::new(), "main.vert", source), "x.glsl", "float x;");
+        let out = preprocess(&fs, "main.vert", &ShaderVariant::new()).unwrap();
+        assert_eq!(out.source(), "#version 450\n/* old lighting\n#include \"missing.glsl\"\n*/\n// #include \"missing.glsl\"\nvoid main(){} /* a\n#if 0 */\nfloat x;\n");
+    }
+
+    #[test]
+    fn conditions_are_evaluated_like_c(){
+        let state = State::default();
+        let value = |text: &str|tokenize(text).and_then(|x|evaluate(&state.expand(x, 0)));
+        assert_eq!(value("1 + 2 * 3 == 7"), Some(1));
+        assert_eq!(value("(1 + 2) * 3 - 0x10 % 5"), Some(8));
+        assert_eq!(value("!UNDEFINED && -1 < 010"), Some(1));
+        assert_eq!(value("1 << 4 | 1 ^ 3 & 2"), Some(19));
+        assert_eq!(value("GL_ES || 1"), Some(1));
+        assert_eq!(value("GL_ES && 0"), Some(0));
+        assert_eq!(value("GL_ES + 1"), None);
+        assert_eq!(value("1 / 0"), None);
+        assert_eq!(value("(1"), None);
+        assert_eq!(value("1 2"), None);
+        assert_eq!(value("defined(A"), None);
+        assert_eq!(value(""), None);
+    }
+
+    #[test]
+    fn lines_map_back_through_two_includes(){
+        let fs = file(MemoryFs::new(), "main.frag", "#version 450\n// header\n#include \"a.glsl\"\nvoid main(){}");
+        let fs = file(fs, "a.glsl", "float a;\n\n#include \"b.glsl\"\nfloat after_b;");
+        let fs = file(fs, "b.glsl", "// b\nfloat b;");
+        let variant = ShaderVariant::new().with_define("USE_B").with_value("COUNT", "2");
+
+        let out = preprocess(&fs, "main.frag", &variant).unwrap();
+        assert_eq!(out.original_line(1), Some(("main.frag", 1, "#version 450")));
+        assert_eq!(out.original_line(line_of(&out, "#define COUNT 2")), Some(("<variant>", 1, "#define COUNT 2")));
+        assert_eq!(out.original_line(line_of(&out, "#define USE_B")), Some(("<variant>", 2, "#define USE_B")));
+        assert_eq!(out.original_line(line_of(&out, "float a;")), Some(("a.glsl", 1, "float a;")));
+        assert_eq!(out.original_line(line_of(&out, "float b;")), Some(("b.glsl", 2, "float b;")));
+        assert_eq!(out.original_line(line_of(&out, "float after_b;")), Some(("a.glsl", 4, "float after_b;")));
+        assert_eq!(out.original_line(line_of(&out, "void main(){}")), Some(("main.frag", 4, "void main(){}")));
+        assert_eq!(out.original_line(0), None);
+        assert_eq!(out.original_line(out.source().lines().count() + 1), None);
+    }
+}
@@ -0,0 +1,230 @@
+use super::ShaderDataType;
+
+///
+/// A value that can be uploaded to a shader uniform
+/// Matrices are column major, array variants set consecutive elements starting at the first
+#[derive(Clone,Debug,PartialEq)]
+pub enum UniformValue{
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Float(f32),
+    Vec2F([f32;2]),
+    Vec3F([f32;3]),
+    Vec4F([f32;4]),
+    Vec2I([i32;2]),
+    Vec3I([i32;3]),
+    Vec4I([i32;4]),
+    Mat2F([f32;4]),
+    Mat3F([f32;9]),
+    Mat4F([f32;16]),
+    ///Texture slot a sampler reads from
+    Sampler(i32),
+    IntArray(Vec<i32>),
+    FloatArray(Vec<f32>),
+    Vec2FArray(Vec<[f32;2]>),
+    Vec3FArray(Vec<[f32;3]>),
+    Vec4FArray(Vec<[f32;4]>),
+    Mat4FArray(Vec<[f32;16]>),
+    SamplerArray(Vec<i32>),
+}
+
+impl UniformValue{
+    ///The shader type the value is meant for, arrays report their element type
+    pub fn data_type(&self) -> ShaderDataType{
+        match self{
+            Self::Bool(_) => ShaderDataType::Bool,
+            Self::Int(_) | Self::IntArray(_) => ShaderDataType::Int,
+            Self::UInt(_) => ShaderDataType::UInt,
+            Self::Float(_) | Self::FloatArray(_) => ShaderDataType::Float,
+            Self::Vec2F(_) | Self::Vec2FArray(_) => ShaderDataType::Vec2F,
+            Self::Vec3F(_) | Self::Vec3FArray(_) => ShaderDataType::Vec3F,
+            Self::Vec4F(_) | Self::Vec4FArray(_) => ShaderDataType::Vec4F,
+            Self::Vec2I(_) => ShaderDataType::Vec2I,
+            Self::Vec3I(_) => ShaderDataType::Vec3I,
+            Self::Vec4I(_) => ShaderDataType::Vec4I,
+            Self::Mat2F(_) => ShaderDataType::Mat2F,
+            Self::Mat3F(_) => ShaderDataType::Mat3F,
+            Self::Mat4F(_) | Self::Mat4FArray(_) => ShaderDataType::Mat4F,
+            Self::Sampler(_) | Self::SamplerArray(_) => ShaderDataType::Sampler2D,
+        }
+    }
+
+    ///Number of array elements the value sets
+    pub fn len(&self) -> usize{
+        match self{
+            Self::IntArray(x) | Self::SamplerArray(x) => x.len(),
+            Self::FloatArray(x) => x.len(),
+            Self::Vec2FArray(x) => x.len(),
+            Self::Vec3FArray(x) => x.len(),
+            Self::Vec4FArray(x) => x.len(),
+            Self::Mat4FArray(x) => x.len(),
+            _ => 1
+        }
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    ///
+    /// Returns true if the value can be uploaded to a uniform of the type
+    /// Samplers of any kind accept texture slots
+    pub fn matches(&self, data_type: ShaderDataType) -> bool{
+        match (self.data_type(), data_type){
+            (ShaderDataType::Sampler2D, ShaderDataType::Other(x)) => is_sampler(x),
+            (a, b) => a == b
+        }
+    }
+
+    pub(super) fn upload(&self, program: gl::types::GLuint, location: i32){
+        unsafe{
+            match self{
+                Self::Bool(x) => gl::ProgramUniform1i(program, location, *x as i32),
+                Self::Int(x) | Self::Sampler(x) => gl::ProgramUniform1i(program, location, *x),
+                Self::UInt(x) => gl::ProgramUniform1ui(program, location, *x),
+                Self::Float(x) => gl::ProgramUniform1f(program, location, *x),
+                Self::Vec2F(x) => gl::ProgramUniform2fv(program, location, 1, x.as_ptr()),
+                Self::Vec3F(x) => gl::ProgramUniform3fv(program, location, 1, x.as_ptr()),
+                Self::Vec4F(x) => gl::ProgramUniform4fv(program, location, 1, x.as_ptr()),
+                Self::Vec2I(x) => gl::ProgramUniform2iv(program, location, 1, x.as_ptr()),
+                Self::Vec3I(x) => gl::ProgramUniform3iv(program, location, 1, x.as_ptr()),
+                Self::Vec4I(x) => gl::ProgramUniform4iv(program, location, 1, x.as_ptr()),
+                Self::Mat2F(x) => gl::ProgramUniformMatrix2fv(program, location, 1, gl::FALSE, x.as_ptr()),
+                Self::Mat3F(x) => gl::ProgramUniformMatrix3fv(program, location, 1, gl::FALSE, x.as_ptr()),
+                Self::Mat4F(x) => gl::ProgramUniformMatrix4fv(program, location, 1, gl::FALSE, x.as_ptr()),
+                Self::IntArray(x) | Self::SamplerArray(x) => gl::ProgramUniform1iv(program, location, x.len() as i32, x.as_ptr()),
+                Self::FloatArray(x) => gl::ProgramUniform1fv(program, location, x.len() as i32, x.as_ptr()),
+                Self::Vec2FArray(x) => gl::ProgramUniform2fv(program, location, x.len() as i32, x.as_ptr() as *const f32),
+                Self::Vec3FArray(x) => gl::ProgramUniform3fv(program, location, x.len() as i32, x.as_ptr() as *const f32),
+                Self::Vec4FArray(x) => gl::ProgramUniform4fv(program, location, x.len() as i32, x.as_ptr() as *const f32),
+                Self::Mat4FArray(x) => gl::ProgramUniformMatrix4fv(program, location, x.len() as i32, gl::FALSE, x.as_ptr() as *const f32),
+            }
+        }
+    }
+}
+
+fn is_sampler(gl_type: gl::types::GLenum) -> bool{
+    matches!(gl_type,
+        gl::SAMPLER_1D | gl::SAMPLER_3D | gl::SAMPLER_CUBE | gl::SAMPLER_2D_ARRAY | gl::SAMPLER_2D_SHADOW
+        | gl::SAMPLER_2D_MULTISAMPLE | gl::SAMPLER_BUFFER | gl::INT_SAMPLER_2D | gl::UNSIGNED_INT_SAMPLER_2D)
+}
+
+impl From<bool> for UniformValue{
+    fn from(x: bool) -> Self {
+        Self::Bool(x)
+    }
+}
+
+impl From<i32> for UniformValue{
+    fn from(x: i32) -> Self {
+        Self::Int(x)
+    }
+}
+
+impl From<u32> for UniformValue{
+    fn from(x: u32) -> Self {
+        Self::UInt(x)
+    }
+}
+
+impl From<f32> for UniformValue{
+    fn from(x: f32) -> Self {
+        Self::Float(x)
+    }
+}
+
+impl From<[f32;2]> for UniformValue{
+    fn from(x: [f32;2]) -> Self {
+        Self::Vec2F(x)
+    }
+}
+
+impl From<[f32;3]> for UniformValue{
+    fn from(x: [f32;3]) -> Self {
+        Self::Vec3F(x)
+    }
+}
+
+impl From<[f32;4]> for UniformValue{
+    fn from(x: [f32;4]) -> Self {
+        Self::Vec4F(x)
+    }
+}
+
+impl From<[i32;2]> for UniformValue{
+    fn from(x: [i32;2]) -> Self {
+        Self::Vec2I(x)
+    }
+}
+
+impl From<[i32;3]> for UniformValue{
+    fn from(x: [i32;3]) -> Self {
+        Self::Vec3I(x)
+    }
+}
+
+impl From<[i32;4]> for UniformValue{
+    fn from(x: [i32;4]) -> Self {
+        Self::Vec4I(x)
+    }
+}
+
+impl From<[f32;16]> for UniformValue{
+    fn from(x: [f32;16]) -> Self {
+        Self::Mat4F(x)
+    }
+}
+
+impl From<Vec<f32>> for UniformValue{
+    fn from(x: Vec<f32>) -> Self {
+        Self::FloatArray(x)
+    }
+}
+
+impl From<Vec<i32>> for UniformValue{
+    fn from(x: Vec<i32>) -> Self {
+        Self::IntArray(x)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn values_match_their_own_type_only(){
+        assert!(UniformValue::Float(1.0).matches(ShaderDataType::Float));
+        assert!(!UniformValue::Float(1.0).matches(ShaderDataType::Int));
+        assert!(!UniformValue::Int(1).matches(ShaderDataType::UInt));
+        assert!(!UniformValue::Int(1).matches(ShaderDataType::Bool));
+        assert!(!UniformValue::Vec3F([0.0;3]).matches(ShaderDataType::Vec4F));
+        assert!(!UniformValue::Vec2I([0;2]).matches(ShaderDataType::Vec2F));
+        assert!(UniformValue::from([0.0f32;16]).matches(ShaderDataType::Mat4F));
+        assert!(!UniformValue::Mat3F([0.0;9]).matches(ShaderDataType::Mat4F));
+    }
+
+    #[test]
+    fn texture_slots_match_any_sampler(){
+        assert!(UniformValue::Sampler(0).matches(ShaderDataType::Sampler2D));
+        assert!(UniformValue::Sampler(0).matches(ShaderDataType::Other(gl::SAMPLER_CUBE)));
+        assert!(UniformValue::SamplerArray(vec![1, 2]).matches(ShaderDataType::Other(gl::INT_SAMPLER_2D)));
+        assert!(!UniformValue::Sampler(0).matches(ShaderDataType::Other(gl::DOUBLE)));
+        assert!(!UniformValue::Sampler(0).matches(ShaderDataType::Int));
+        //a plain int is not a texture slot, even though both upload the same way
+        assert!(!UniformValue::Int(0).matches(ShaderDataType::Sampler2D));
+        assert!(!UniformValue::Int(0).matches(ShaderDataType::Other(gl::SAMPLER_CUBE)));
+    }
+
+    #[test]
+    fn arrays_report_their_element_type_and_length(){
+        let value = UniformValue::from(vec![1.0f32, 2.0, 3.0]);
+        assert!(value.matches(ShaderDataType::Float));
+        assert_eq!(value.len(), 3);
+        assert_eq!(UniformValue::Vec4FArray(vec![[0.0;4];2]).len(), 2);
+        assert_eq!(UniformValue::Mat4FArray(vec![[0.0;16];5]).data_type(), ShaderDataType::Mat4F);
+        assert!(UniformValue::IntArray(Vec::new()).is_empty());
+        assert_eq!(UniformValue::Vec4F([0.0;4]).len(), 1);
+        assert_eq!(UniformValue::Mat4F([0.0;16]).len(), 1);
+    }
+}
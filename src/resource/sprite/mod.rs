@@ -1,6 +1,203 @@
 use super::texture::GlTexture;
 
 pub mod uv_sprite;
+pub mod nine_slice;
+
+///
+/// Texture coordinates of a rect in a texture
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct UvRect{
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+///
+/// Everything needed to draw a sprite as a single textured quad
+/// Sizes and offsets are in pixels, the pivot is relative to the source size where (0,0) is the top left corner
+#[derive(Clone,Copy)]
+pub struct SpriteQuad<'a>{
+    pub texture: &'a GlTexture,
+    ///The area of the texture holding the pixels, as they are stored in the texture
+    pub uv: UvRect,
+    ///Size of the drawn pixels, after trimming but before any rotation in the texture
+    pub width: f32,
+    pub height: f32,
+    pub pivot_x: f32,
+    pub pivot_y: f32,
+    ///True if the pixels are stored rotated 90 degrees clockwise in the texture
+    pub rotated: bool,
+    ///Position of the drawn pixels inside the source image, non zero for trimmed sprites
+    pub offset_x: f32,
+    pub offset_y: f32,
+    ///Size of the image before trimming
+    pub source_width: f32,
+    pub source_height: f32,
+}
+
+impl<'a> SpriteQuad<'a>{
+    ///
+    /// Texture coordinates for the top left, top right, bottom right and bottom left corners of the drawn quad
+    pub fn uv_corners(&self) -> [[f32;2];4]{
+        uv_corners(&self.uv, self.rotated)
+    }
+
+    ///
+    /// The area covered by the drawn pixels relative to the pivot, as (min_x, min_y, max_x, max_y) in pixels
+    /// Useful for placing the quad and for culling
+    pub fn bounds(&self) -> [f32;4]{
+        let min_x = self.offset_x - self.pivot_x * self.source_width;
+        let min_y = self.offset_y - self.pivot_y * self.source_height;
+        [min_x, min_y, min_x + self.width, min_y + self.height]
+    }
+}
+
+///
+/// A quad positioned inside a sprite drawn at a specific size
+/// Positions are in pixels relative to the top left corner of the drawn sprite
+#[derive(Clone,Copy)]
+pub struct PlacedQuad<'a>{
+    pub texture: &'a GlTexture,
+    pub uv: UvRect,
+    pub rotated: bool,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl<'a> PlacedQuad<'a>{
+    ///
+    /// Texture coordinates for the top left, top right, bottom right and bottom left corners, in the same order as SpriteQuad::uv_corners
+    pub fn uv_corners(&self) -> [[f32;2];4]{
+        uv_corners(&self.uv, self.rotated)
+    }
+
+    ///The top left, top right, bottom right and bottom left corners in pixels
+    pub fn corners(&self) -> [[f32;2];4]{
+        let (max_x, max_y) = (self.x + self.width, self.y + self.height);
+        [[self.x, self.y], [max_x, self.y], [max_x, max_y], [self.x, max_y]]
+    }
+}
+
+fn uv_corners(uv: &UvRect, rotated: bool) -> [[f32;2];4]{
+    let UvRect{min_x, min_y, max_x, max_y} = *uv;
+    if rotated{
+        //the source top left corner ends up at the top right of the rotated pixels
+        [[max_x, min_y], [max_x, max_y], [min_x, max_y], [min_x, min_y]]
+    }
+    else{
+        [[min_x, min_y], [max_x, min_y], [max_x, max_y], [min_x, max_y]]
+    }
+}
+
 pub trait Sprite {
-    fn fill_sprite<'a, 'b: 'a>(&'b self, out: &mut [f32]) -> &'a GlTexture;
+    fn quad(&self) -> SpriteQuad<'_>;
+
+    ///
+    /// Adds the quads making up the sprite when drawn with the given size in pixels
+    /// By default the single quad of the sprite is scaled from the source size, so trimmed pixels keep their place
+    fn quads_at_size<'a>(&'a self, width: f32, height: f32, out: &mut Vec<PlacedQuad<'a>>){
+        let quad = self.quad();
+        let scale_x = if quad.source_width > 0.0 { width / quad.source_width } else { 0.0 };
+        let scale_y = if quad.source_height > 0.0 { height / quad.source_height } else { 0.0 };
+        out.push(PlacedQuad{
+            texture: quad.texture,
+            uv: quad.uv,
+            rotated: quad.rotated,
+            x: quad.offset_x * scale_x,
+            y: quad.offset_y * scale_y,
+            width: quad.width * scale_x,
+            height: quad.height * scale_y,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    ///A sprite over a whole texture, using the default quads_at_size
+    struct TestSprite{
+        texture: &'static GlTexture,
+        rotated: bool,
+        offset: [f32;2],
+        size: [f32;2],
+        source: [f32;2],
+    }
+
+    impl TestSprite{
+        fn new(width: f32, height: f32) -> Self{
+            Self{
+                //textures delete themselves through gl when dropped, which needs a context
+                texture: Box::leak(Box::default()),
+                rotated: false,
+                offset: [0.0, 0.0],
+                size: [width, height],
+                source: [width, height],
+            }
+        }
+    }
+
+    impl Sprite for TestSprite{
+        fn quad(&self) -> SpriteQuad<'_> {
+            SpriteQuad{
+                texture: self.texture,
+                uv: UvRect{min_x: 0.25, min_y: 0.5, max_x: 0.75, max_y: 1.0},
+                width: self.size[0],
+                height: self.size[1],
+                pivot_x: 0.5,
+                pivot_y: 0.5,
+                rotated: self.rotated,
+                offset_x: self.offset[0],
+                offset_y: self.offset[1],
+                source_width: self.source[0],
+                source_height: self.source[1],
+            }
+        }
+    }
+
+    #[test]
+    fn placed_quads_use_the_corner_order_of_the_sprite_quad(){
+        for rotated in [false, true]{
+            let sprite = TestSprite{
+                rotated,
+                ..TestSprite::new(4.0, 2.0)
+            };
+            let mut quads = Vec::new();
+            sprite.quads_at_size(4.0, 2.0, &mut quads);
+            assert_eq!(quads.len(), 1);
+            assert_eq!(quads[0].uv_corners(), sprite.quad().uv_corners());
+            assert_eq!(quads[0].rotated, rotated);
+        }
+    }
+
+    #[test]
+    fn rotated_corners_start_at_the_top_right_of_the_stored_pixels(){
+        let uv = UvRect{min_x: 0.0, min_y: 0.0, max_x: 1.0, max_y: 1.0};
+        assert_eq!(uv_corners(&uv, false), [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        assert_eq!(uv_corners(&uv, true), [[1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]]);
+    }
+
+    #[test]
+    fn trimmed_pixels_keep_their_place_when_scaled(){
+        let sprite = TestSprite{
+            offset: [2.0, 1.0],
+            source: [8.0, 4.0],
+            ..TestSprite::new(4.0, 2.0)
+        };
+        let mut quads = Vec::new();
+        sprite.quads_at_size(16.0, 4.0, &mut quads);
+        let quad = &quads[0];
+        assert_eq!([quad.x, quad.y, quad.width, quad.height], [4.0, 1.0, 8.0, 2.0]);
+        assert_eq!(quad.corners(), [[4.0, 1.0], [12.0, 1.0], [12.0, 3.0], [4.0, 3.0]]);
+
+        //at the source size the quad covers the same area as the bounds, relative to the top left corner
+        quads.clear();
+        sprite.quads_at_size(8.0, 4.0, &mut quads);
+        let [min_x, min_y, max_x, max_y] = sprite.quad().bounds();
+        assert_eq!(quads[0].corners()[0], [min_x + 4.0, min_y + 2.0]);
+        assert_eq!(quads[0].corners()[2], [max_x + 4.0, max_y + 2.0]);
+    }
 }
@@ -0,0 +1,305 @@
+use crate::resource::texture::GlTexture;
+
+use super::{PlacedQuad, Sprite, SpriteQuad, UvRect, uv_sprite::UvSprite};
+
+///
+/// How the stretchable parts of a nine slice sprite fill their area
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum SliceMode{
+    Stretch,
+    ///Repeats the source pixels, cutting off the last repetition
+    Tile,
+}
+
+///
+/// Widths of the fixed borders of a nine slice sprite in pixels
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct NineSliceInsets{
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl NineSliceInsets{
+    pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self{
+        Self{
+            left,
+            top,
+            right,
+            bottom
+        }
+    }
+}
+
+///
+/// Everything needed to turn a sprite into a nine slice sprite, as loaded from a sidecar file or a .9.png
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct NineSliceDefinition{
+    pub insets: NineSliceInsets,
+    pub center: SliceMode,
+    pub edges: SliceMode,
+}
+
+impl NineSliceDefinition{
+    pub fn new(insets: NineSliceInsets) -> Self{
+        Self{
+            insets,
+            center: SliceMode::Stretch,
+            edges: SliceMode::Stretch,
+        }
+    }
+}
+
+///
+/// A sprite split into three columns and rows, where the corners keep their size
+/// and the edges and center fill the remaining space when drawn at an arbitrary size
+#[derive(Clone)]
+pub struct NineSliceSprite{
+    sprite: UvSprite,
+    insets: NineSliceInsets,
+    center: SliceMode,
+    edges: SliceMode,
+}
+
+impl NineSliceSprite{
+    ///
+    /// Creates a nine slice sprite over the region of the sprite, which can't be rotated or trimmed in its texture
+    /// since the insets are measured on the whole source image
+    pub fn new(sprite: UvSprite, insets: NineSliceInsets) -> Result<Self,String>{
+        let quad = sprite.quad();
+        if quad.rotated{
+            return Err(String::from("Nine slice sprites can't use rotated atlas regions"));
+        }
+        if quad.offset_x != 0.0 || quad.offset_y != 0.0 || quad.width != quad.source_width || quad.height != quad.source_height{
+            return Err(String::from("Nine slice sprites can't use trimmed atlas regions"));
+        }
+        if insets.left + insets.right > quad.width || insets.top + insets.bottom > quad.height{
+            return Err(format!("Nine slice insets {:?} don't fit in a {}x{} sprite",insets,quad.width,quad.height));
+        }
+        Ok(Self{
+            sprite,
+            insets,
+            center: SliceMode::Stretch,
+            edges: SliceMode::Stretch,
+        })
+    }
+
+    pub fn from_definition(sprite: UvSprite, definition: &NineSliceDefinition) -> Result<Self,String>{
+        Ok(Self::new(sprite, definition.insets)?
+            .with_center_mode(definition.center)
+            .with_edge_mode(definition.edges))
+    }
+
+    pub fn with_center_mode(mut self, mode: SliceMode) -> Self{
+        self.center = mode;
+        self
+    }
+
+    pub fn with_edge_mode(mut self, mode: SliceMode) -> Self{
+        self.edges = mode;
+        self
+    }
+
+    pub fn insets(&self) -> NineSliceInsets{
+        self.insets
+    }
+
+    pub fn sprite(&self) -> &UvSprite{
+        &self.sprite
+    }
+}
+
+///
+/// Splits a length into the start border, middle and end border, shrinking the borders if they don't fit
+fn split(size: f32, start: f32, end: f32) -> [f32;3]{
+    if start + end > size && start + end > 0.0{
+        let scale = size / (start + end);
+        [start * scale, 0.0, end * scale]
+    }
+    else{
+        [start, size - start - end, end]
+    }
+}
+
+impl Sprite for NineSliceSprite{
+    fn quad(&self) -> SpriteQuad<'_> {
+        self.sprite.quad()
+    }
+
+    fn quads_at_size<'a>(&'a self, width: f32, height: f32, out: &mut Vec<PlacedQuad<'a>>) {
+        let quad = self.sprite.quad();
+        let insets = &self.insets;
+        let uv = quad.uv;
+        let u_per_px = (uv.max_x - uv.min_x) / quad.width;
+        let v_per_px = (uv.max_y - uv.min_y) / quad.height;
+
+        let source_columns = [insets.left, quad.width - insets.left - insets.right, insets.right];
+        let source_rows = [insets.top, quad.height - insets.top - insets.bottom, insets.bottom];
+        let columns = split(width, insets.left, insets.right);
+        let rows = split(height, insets.top, insets.bottom);
+
+        let mut y = 0.0;
+        let mut v = uv.min_y;
+        for row in 0..3{
+            let mut x = 0.0;
+            let mut u = uv.min_x;
+            for column in 0..3{
+                let mode = match (row == 1, column == 1){
+                    (true, true) => self.center,
+                    (false, false) => SliceMode::Stretch,
+                    _ => self.edges,
+                };
+                let source = UvRect{
+                    min_x: u,
+                    min_y: v,
+                    max_x: u + source_columns[column] * u_per_px,
+                    max_y: v + source_rows[row] * v_per_px,
+                };
+                emit_cell(out, quad.texture, source, [source_columns[column], source_rows[row]], [x, y, columns[column], rows[row]], mode);
+                x += columns[column];
+                u = source.max_x;
+            }
+            y += rows[row];
+            v += source_rows[row] * v_per_px;
+        }
+    }
+}
+
+///Tiled cells needing more quads than this are stretched instead, which keeps tiny sources from producing endless quads
+const MAX_CELL_TILES: f32 = 4096.0;
+
+///
+/// Fills the target rect (x, y, width, height) with the source uvs of the given pixel size
+fn emit_cell<'a>(out: &mut Vec<PlacedQuad<'a>>, texture: &'a GlTexture, source: UvRect, source_size: [f32;2], target: [f32;4], mode: SliceMode){
+    let [x, y, width, height] = target;
+    if width <= 0.0 || height <= 0.0 || source_size[0] <= 0.0 || source_size[1] <= 0.0{
+        return;
+    }
+
+    let tiles = (width / source_size[0]).ceil() * (height / source_size[1]).ceil();
+    match mode{
+        SliceMode::Tile if tiles <= MAX_CELL_TILES => {
+            let mut ty = 0.0;
+            while ty < height{
+                let tile_height = source_size[1].min(height - ty);
+                let mut tx = 0.0;
+                while tx < width{
+                    let tile_width = source_size[0].min(width - tx);
+                    out.push(PlacedQuad{
+                        texture,
+                        uv: UvRect{
+                            min_x: source.min_x,
+                            min_y: source.min_y,
+                            max_x: source.min_x + (source.max_x - source.min_x) * tile_width / source_size[0],
+                            max_y: source.min_y + (source.max_y - source.min_y) * tile_height / source_size[1],
+                        },
+                        rotated: false,
+                        x: x + tx,
+                        y: y + ty,
+                        width: tile_width,
+                        height: tile_height,
+                    });
+                    tx += source_size[0];
+                }
+                ty += source_size[1];
+            }
+        },
+        _ => out.push(PlacedQuad{
+            texture,
+            uv: source,
+            rotated: false,
+            x,
+            y,
+            width,
+            height,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool{
+        (a - b).abs() < 1e-5
+    }
+
+    //a 30x30 sprite over the whole texture with 10 pixel borders
+    fn sprite() -> NineSliceSprite{
+        let sprite = UvSprite::new(0.0, 0.0, 1.0, 1.0, GlTexture::default()).with_size(30.0, 30.0);
+        NineSliceSprite::new(sprite, NineSliceInsets::new(10.0, 10.0, 10.0, 10.0)).unwrap()
+    }
+
+    fn rects(quads: &[PlacedQuad]) -> Vec<[f32;4]>{
+        quads.iter().map(|x|[x.x, x.y, x.width, x.height]).collect()
+    }
+
+    #[test]
+    fn corners_keep_their_size(){
+        let sprite = sprite();
+        let mut quads = Vec::new();
+        sprite.quads_at_size(100.0, 50.0, &mut quads);
+        assert_eq!(rects(&quads), vec![
+            [0.0, 0.0, 10.0, 10.0], [10.0, 0.0, 80.0, 10.0], [90.0, 0.0, 10.0, 10.0],
+            [0.0, 10.0, 10.0, 30.0], [10.0, 10.0, 80.0, 30.0], [90.0, 10.0, 10.0, 30.0],
+            [0.0, 40.0, 10.0, 10.0], [10.0, 40.0, 80.0, 10.0], [90.0, 40.0, 10.0, 10.0],
+        ]);
+        //the bottom right corner samples the last third of the texture
+        let uv = quads[8].uv;
+        assert!(close(uv.min_x, 2.0 / 3.0) && close(uv.min_y, 2.0 / 3.0) && close(uv.max_x, 1.0) && close(uv.max_y, 1.0), "{:?}", uv);
+    }
+
+    #[test]
+    fn borders_shrink_when_they_dont_fit(){
+        let sprite = sprite();
+        let mut quads = Vec::new();
+        sprite.quads_at_size(10.0, 40.0, &mut quads);
+        //the middle column has no width left and is skipped
+        assert_eq!(rects(&quads), vec![
+            [0.0, 0.0, 5.0, 10.0], [5.0, 0.0, 5.0, 10.0],
+            [0.0, 10.0, 5.0, 20.0], [5.0, 10.0, 5.0, 20.0],
+            [0.0, 30.0, 5.0, 10.0], [5.0, 30.0, 5.0, 10.0],
+        ]);
+        assert_eq!(split(10.0, 10.0, 30.0), [2.5, 0.0, 7.5]);
+        assert_eq!(split(0.0, 0.0, 0.0), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn tiles_cut_the_last_repetition(){
+        let sprite = sprite().with_center_mode(SliceMode::Tile).with_edge_mode(SliceMode::Tile);
+        let mut quads = Vec::new();
+        sprite.quads_at_size(35.0, 30.0, &mut quads);
+        assert_eq!(quads.len(), 12);
+
+        //the top edge is 15 pixels wide, a whole tile and half of one
+        let top = quads.iter().filter(|x|x.y == 0.0 && x.x >= 10.0 && x.x < 25.0).collect::<Vec<_>>();
+        assert_eq!(top.iter().map(|x|[x.x, x.width]).collect::<Vec<_>>(), vec![[10.0, 10.0], [20.0, 5.0]]);
+        assert!(close(top[0].uv.min_x, 1.0 / 3.0) && close(top[0].uv.max_x, 2.0 / 3.0));
+        assert!(close(top[1].uv.min_x, 1.0 / 3.0) && close(top[1].uv.max_x, 0.5));
+        assert!(close(top[1].uv.min_y, 0.0) && close(top[1].uv.max_y, 1.0 / 3.0));
+    }
+
+    #[test]
+    fn tiny_tile_sources_are_stretched(){
+        let texture = GlTexture::default();
+        let source = UvRect{min_x: 0.0, min_y: 0.0, max_x: 0.001, max_y: 0.001};
+        let mut quads = Vec::new();
+        emit_cell(&mut quads, &texture, source, [0.001, 0.001], [0.0, 0.0, 100.0, 100.0], SliceMode::Tile);
+        assert_eq!(rects(&quads), vec![[0.0, 0.0, 100.0, 100.0]]);
+        assert_eq!(quads[0].uv, source);
+
+        quads.clear();
+        emit_cell(&mut quads, &texture, source, [1.0, 1.0], [0.0, 0.0, 64.0, 64.0], SliceMode::Tile);
+        assert_eq!(quads.len(), 4096);
+    }
+
+    #[test]
+    fn rejects_regions_the_insets_dont_describe(){
+        let sprite = || UvSprite::new(0.0, 0.0, 1.0, 1.0, GlTexture::default()).with_size(30.0, 30.0);
+        let insets = NineSliceInsets::new(10.0, 10.0, 10.0, 10.0);
+        assert!(NineSliceSprite::new(sprite().with_rotated(true), insets).is_err());
+        assert!(NineSliceSprite::new(sprite().with_source(1.0, 0.0, 32.0, 30.0), insets).is_err());
+        assert!(NineSliceSprite::new(sprite(), NineSliceInsets::new(20.0, 0.0, 11.0, 0.0)).is_err());
+    }
+}
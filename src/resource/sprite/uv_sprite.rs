@@ -1,35 +1,99 @@
 use crate::resource::texture::GlTexture;
 
-use super::Sprite;
+use super::{Sprite, SpriteQuad, UvRect};
 
-#[derive(Default)]
+#[derive(Clone,Default)]
 pub struct UvSprite{
     min_x: f32,
     min_y: f32,
     max_x: f32,
     max_y: f32,
+    width: f32,
+    height: f32,
+    pivot_x: f32,
+    pivot_y: f32,
+    rotated: bool,
+    offset_x: f32,
+    offset_y: f32,
+    source_width: f32,
+    source_height: f32,
     texture: GlTexture
 }
 
 impl UvSprite{
+    ///
+    /// Creates a sprite with a centered pivot, the pixel size should be set with with_size
     pub fn new(min_x: f32, min_y: f32, max_x: f32, max_y: f32, texture: GlTexture) -> Self{
         Self{
             min_x,
             max_x,
             max_y, 
             min_y,
-            texture
+            pivot_x: 0.5,
+            pivot_y: 0.5,
+            texture,
+            ..Default::default()
         }
     }
-}
 
-impl Sprite for UvSprite{
-    fn fill_sprite<'a, 'b: 'a>(&'b self, out: &mut [f32]) -> &'a GlTexture {
-        out[0] = self.min_x;
-        out[1] = self.min_y;
-        out[2] = self.max_x;
-        out[3] = self.max_y;
+    ///Sets the size in pixels, the source size is set to the same
+    pub fn with_size(mut self, width: f32, height: f32) -> Self{
+        self.width = width;
+        self.height = height;
+        self.source_width = width;
+        self.source_height = height;
+        self
+    }
+
+    ///Sets the pivot relative to the source size, (0,0) is the top left and (1,1) the bottom right corner
+    pub fn with_pivot(mut self, pivot_x: f32, pivot_y: f32) -> Self{
+        self.pivot_x = pivot_x;
+        self.pivot_y = pivot_y;
+        self
+    }
+
+    pub fn with_rotated(mut self, rotated: bool) -> Self{
+        self.rotated = rotated;
+        self
+    }
+
+    ///Describes a trimmed sprite, the drawn pixels are at the offset inside an image of the source size
+    pub fn with_source(mut self, offset_x: f32, offset_y: f32, source_width: f32, source_height: f32) -> Self{
+        self.offset_x = offset_x;
+        self.offset_y = offset_y;
+        self.source_width = source_width;
+        self.source_height = source_height;
+        self
+    }
 
+    pub fn uv(&self) -> UvRect{
+        UvRect{
+            min_x: self.min_x,
+            min_y: self.min_y,
+            max_x: self.max_x,
+            max_y: self.max_y,
+        }
+    }
+
+    pub fn texture(&self) -> &GlTexture{
         &self.texture
     }
-}
\ No newline at end of file
+}
+
+impl Sprite for UvSprite{
+    fn quad(&self) -> SpriteQuad<'_> {
+        SpriteQuad{
+            texture: &self.texture,
+            uv: self.uv(),
+            width: self.width,
+            height: self.height,
+            pivot_x: self.pivot_x,
+            pivot_y: self.pivot_y,
+            rotated: self.rotated,
+            offset_x: self.offset_x,
+            offset_y: self.offset_y,
+            source_width: self.source_width,
+            source_height: self.source_height,
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use crate::resource::sprite::uv_sprite::UvSprite;
+
+use super::GlTexture;
+
+///
+/// A rectangle in pixel coordinates inside a texture atlas, with the origin at the top left corner
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,Hash)]
+pub struct AtlasRect{
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasRect{
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self{
+        Self{
+            x,
+            y,
+            width,
+            height
+        }
+    }
+
+    pub fn area(&self) -> u32{
+        self.width * self.height
+    }
+}
+
+///
+/// Information about how the images of an atlas were packed
+#[derive(Clone,Copy,Debug,Default)]
+pub struct AtlasStats{
+    ///Number of named images in the atlas
+    pub image_count: usize,
+    ///Number of images with distinct pixels, only these take up space in the texture
+    pub unique_count: usize,
+    ///Pixels that would have been packed if duplicate images weren't shared
+    pub saved_pixels: usize,
+    ///Total number of pixels in all pages of the atlas
+    pub atlas_pixels: usize,
+}
+
+///
+/// Where a named image ended up in an atlas
+/// Images can be trimmed of transparent borders and rotated 90 degrees clockwise when packed,
+/// the source fields describe the image as it was before packing
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct AtlasRegion{
+    ///Index of the atlas page containing the image
+    pub page: usize,
+    ///The packed pixels, if rotated the width and height are swapped compared to the source
+    pub rect: AtlasRect,
+    pub rotated: bool,
+    ///Offset of the packed pixels inside the source image, non zero when the image was trimmed
+    pub offset_x: u32,
+    pub offset_y: u32,
+    pub source_width: u32,
+    pub source_height: u32,
+}
+
+impl AtlasRegion{
+    ///A region for an image packed as is on the given page
+    pub fn new(page: usize, rect: AtlasRect) -> Self{
+        Self{
+            page,
+            rect,
+            rotated: false,
+            offset_x: 0,
+            offset_y: 0,
+            source_width: rect.width,
+            source_height: rect.height,
+        }
+    }
+
+    pub fn is_trimmed(&self) -> bool{
+        let (width, height) = if self.rotated { (self.rect.height, self.rect.width) } else { (self.rect.width, self.rect.height) };
+        width != self.source_width || height != self.source_height
+    }
+}
+
+///
+/// A single texture of an atlas, large atlases are split into several pages
+pub struct AtlasPage{
+    pub texture: GlTexture,
+    pub width: u32,
+    pub height: u32,
+    ///Textures sharing the layout of this page, such as normal or emissive maps, by channel name
+    pub companions: HashMap<String,GlTexture>,
+}
+
+///
+/// Creates a sprite covering the rect of a texture with the given size
+/// The uvs are inset slightly so neighbouring images don't bleed in when sampling
+pub(crate) fn create_uv_sprite(texture: &GlTexture, width: u32, height: u32, rect: &AtlasRect) -> UvSprite{
+    let fwidth = width as f32;
+    let fheight = height as f32;
+
+    let half_pixel_x = 1.0/(fwidth*16.0);
+    let half_pixel_y = 1.0/(fheight*16.0);
+
+    let min_x = (rect.x as f32)/fwidth;
+    let min_y = (rect.y as f32)/fheight;
+    let max_x = min_x + (rect.width as f32)/fwidth;
+    let max_y = min_y + (rect.height as f32)/fheight;
+
+    UvSprite::new(min_x + half_pixel_x, min_y + half_pixel_y, max_x - half_pixel_x, max_y - half_pixel_y, texture.clone())
+    .with_size(rect.width as f32, rect.height as f32)
+}
+
+struct AtlasEntry{
+    region: AtlasRegion,
+    sprite: UvSprite,
+}
+
+///
+/// One or more textures containing several packed images, each of which can be looked up by name
+/// either as a sprite or as a pixel rect inside the texture
+pub struct TextureAtlas{
+    pages: Vec<AtlasPage>,
+    entries: HashMap<String,AtlasEntry>,
+    stats: AtlasStats,
+}
+
+impl TextureAtlas{
+    pub fn new(texture: GlTexture, width: u32, height: u32) -> Self{
+        Self{
+            pages: vec![AtlasPage{
+                texture,
+                width,
+                height,
+                companions: HashMap::new(),
+            }],
+            entries: HashMap::new(),
+            stats: Default::default(),
+        }
+    }
+
+    ///
+    /// Creates an atlas spread over several textures
+    pub fn from_pages(pages: Vec<AtlasPage>) -> Self{
+        Self{
+            pages,
+            entries: HashMap::new(),
+            stats: Default::default(),
+        }
+    }
+
+    ///
+    /// Adds a named region to the first page of the atlas, the uv coordinates of the sprite are computed from the rect
+    pub fn insert(&mut self, name: String, rect: AtlasRect) -> &UvSprite{
+        self.insert_region(name, AtlasRegion::new(0, rect))
+    }
+
+    ///
+    /// Adds a named region to the atlas, the uv coordinates of the sprite are computed from the rect of the region
+    pub fn insert_region(&mut self, name: String, region: AtlasRegion) -> &UvSprite{
+        let sprite = self.create_sprite(&region);
+        self.entries.insert(name.clone(), AtlasEntry{
+            region,
+            sprite
+        });
+        &self.entries[&name].sprite
+    }
+
+    fn create_sprite(&self, region: &AtlasRegion) -> UvSprite{
+        let page = &self.pages[region.page];
+        let sprite = create_uv_sprite(&page.texture, page.width, page.height, &region.rect);
+        let (width, height) = if region.rotated { (region.rect.height, region.rect.width) } else { (region.rect.width, region.rect.height) };
+        sprite
+        .with_size(width as f32, height as f32)
+        .with_rotated(region.rotated)
+        .with_source(region.offset_x as f32, region.offset_y as f32, region.source_width as f32, region.source_height as f32)
+    }
+
+    ///
+    /// returns the texture of the first page
+    pub fn texture(&self) -> &GlTexture{
+        &self.pages[0].texture
+    }
+
+    ///
+    /// returns the size of the first page in pixels as (width, height)
+    pub fn size(&self) -> (u32,u32){
+        (self.pages[0].width, self.pages[0].height)
+    }
+
+    pub fn pages(&self) -> &[AtlasPage]{
+        &self.pages
+    }
+
+    ///
+    /// returns the texture of a companion channel for the page, for example the normal map of the page
+    pub fn companion(&self, channel: &str, page: usize) -> Option<&GlTexture>{
+        self.pages.get(page).and_then(|x|x.companions.get(channel))
+    }
+
+    pub fn page_count(&self) -> usize{
+        self.pages.len()
+    }
+
+    pub fn stats(&self) -> &AtlasStats{
+        &self.stats
+    }
+
+    pub fn set_stats(&mut self, stats: AtlasStats){
+        self.stats = stats;
+    }
+
+    pub fn len(&self) -> usize{
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool{
+        self.entries.contains_key(name)
+    }
+
+    pub fn get_sprite(&self, name: &str) -> Option<&UvSprite>{
+        self.entries.get(name).map(|x|&x.sprite)
+    }
+
+    pub fn get_rect(&self, name: &str) -> Option<AtlasRect>{
+        self.entries.get(name).map(|x|x.region.rect)
+    }
+
+    pub fn get_region(&self, name: &str) -> Option<AtlasRegion>{
+        self.entries.get(name).map(|x|x.region)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str>{
+        self.entries.keys().map(|x|x.as_str())
+    }
+
+    pub fn sprites(&self) -> impl Iterator<Item = (&str,&UvSprite)>{
+        self.entries.iter().map(|(k,v)|(k.as_str(),&v.sprite))
+    }
+
+    pub fn rects(&self) -> impl Iterator<Item = (&str,AtlasRect)>{
+        self.entries.iter().map(|(k,v)|(k.as_str(),v.region.rect))
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = (&str,AtlasRegion)>{
+        self.entries.iter().map(|(k,v)|(k.as_str(),v.region))
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+
+use lodepng::{Bitmap, RGBA};
+
+use crate::{loader::texture::into_raw_bytes, resource::sprite::uv_sprite::UvSprite};
+
+use super::{GlTexture, atlas::{AtlasRect, create_uv_sprite}};
+
+///
+/// Identifies an image inserted into a DynamicAtlas
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub struct DynamicAtlasHandle(u32);
+
+///
+/// A horizontal strip of the atlas holding images of similar height
+struct Shelf{
+    y: u32,
+    height: u32,
+    ///Free spans of the shelf as (x, width), sorted by x
+    free: Vec<(u32,u32)>,
+}
+
+impl Shelf{
+    fn new(y: u32, height: u32, width: u32) -> Self{
+        Self{
+            y,
+            height,
+            free: vec![(0, width)],
+        }
+    }
+
+    fn fits(&self, width: u32) -> bool{
+        self.free.iter().any(|(_,w)|*w >= width)
+    }
+
+    fn allocate(&mut self, width: u32) -> Option<u32>{
+        let index = self.free.iter().position(|(_,w)|*w >= width)?;
+        let (x, w) = self.free[index];
+        if w == width{
+            self.free.remove(index);
+        }
+        else{
+            self.free[index] = (x + width, w - width);
+        }
+        Some(x)
+    }
+
+    fn release(&mut self, x: u32, width: u32){
+        let index = self.free.iter().position(|(fx,_)|*fx > x).unwrap_or(self.free.len());
+        self.free.insert(index, (x, width));
+
+        //merge with the following and preceding spans
+        if index + 1 < self.free.len() && self.free[index].0 + self.free[index].1 == self.free[index + 1].0{
+            self.free[index].1 += self.free[index + 1].1;
+            self.free.remove(index + 1);
+        }
+        if index > 0 && self.free[index - 1].0 + self.free[index - 1].1 == self.free[index].0{
+            self.free[index - 1].1 += self.free[index].1;
+            self.free.remove(index);
+        }
+    }
+
+    fn is_empty(&self, width: u32) -> bool{
+        self.free.len() == 1 && self.free[0] == (0, width)
+    }
+}
+
+///
+/// Shelf allocator handing out rects of a fixed size area
+struct ShelfAllocator{
+    width: u32,
+    height: u32,
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfAllocator{
+    fn new(width: u32, height: u32) -> Self{
+        Self{
+            width,
+            height,
+            shelves: Vec::new(),
+        }
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<AtlasRect>{
+        if width > self.width || height > self.height{
+            return None;
+        }
+
+        //the shelf wasting the least height wins, shelves much taller than the image are skipped
+        let best = self.shelves.iter()
+            .enumerate()
+            .filter(|(_,x)|x.height >= height && x.height <= height + height / 2 + 1)
+            .filter(|(_,x)|x.fits(width))
+            .min_by_key(|(_,x)|x.height - height)
+            .map(|(i,_)|i);
+
+        if let Some(index) = best{
+            let shelf = &mut self.shelves[index];
+            let x = shelf.allocate(width).unwrap();
+            return Some(AtlasRect::new(x, shelf.y, width, height));
+        }
+
+        let top = self.shelves.last().map_or(0, |x|x.y + x.height);
+        if top + height > self.height{
+            return None;
+        }
+        let mut shelf = Shelf::new(top, height, self.width);
+        let x = shelf.allocate(width).unwrap();
+        self.shelves.push(shelf);
+        Some(AtlasRect::new(x, top, width, height))
+    }
+
+    fn release(&mut self, rect: &AtlasRect){
+        if let Some(shelf) = self.shelves.iter_mut().find(|s|s.y == rect.y){
+            shelf.release(rect.x, rect.width);
+        }
+        //empty shelves at the top can be given back to new shelves of any height
+        while self.shelves.last().is_some_and(|x|x.is_empty(self.width)){
+            self.shelves.pop();
+        }
+    }
+}
+
+struct DynamicEntry{
+    rect: AtlasRect,
+    sprite: UvSprite,
+}
+
+///Called with every image that moved during compaction and its new sprite
+pub type CompactCallback = Box<dyn FnMut(DynamicAtlasHandle, &UvSprite)>;
+
+///
+/// A texture atlas that images can be added to and removed from after creation
+/// Pixels are uploaded with sub region updates, a copy is kept on the cpu so the atlas can be compacted
+pub struct DynamicAtlas{
+    texture: GlTexture,
+    width: u32,
+    height: u32,
+    padding: u32,
+    allocator: ShelfAllocator,
+    entries: HashMap<DynamicAtlasHandle,DynamicEntry>,
+    next_handle: u32,
+    pixels: Vec<RGBA>,
+    freed_pixels: u32,
+    on_compact: Option<CompactCallback>,
+}
+
+impl DynamicAtlas{
+    pub fn new(width: u32, height: u32) -> Self{
+        let pixels = vec![RGBA{..Default::default()};(width*height) as usize];
+        let texture = GlTexture::from_data(width, height, into_raw_bytes(pixels.clone()));
+        Self{
+            texture,
+            width,
+            height,
+            padding: 1,
+            allocator: ShelfAllocator::new(width, height),
+            entries: HashMap::new(),
+            next_handle: 0,
+            pixels,
+            freed_pixels: 0,
+            on_compact: None,
+        }
+    }
+
+    ///Transparent pixels kept between images, defaults to 1
+    pub fn with_padding(mut self, padding: u32) -> Self{
+        self.padding = padding;
+        self
+    }
+
+    ///
+    /// Sets a callback invoked for every image that moved during compaction, with its new sprite
+    /// Holders of sprites from this atlas should refresh their uvs from it
+    pub fn set_compact_callback(&mut self, callback: impl FnMut(DynamicAtlasHandle, &UvSprite) + 'static){
+        self.on_compact = Some(Box::new(callback));
+    }
+
+    pub fn texture(&self) -> &GlTexture{
+        &self.texture
+    }
+
+    pub fn size(&self) -> (u32,u32){
+        (self.width, self.height)
+    }
+
+    pub fn len(&self) -> usize{
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.entries.is_empty()
+    }
+
+    ///
+    /// Adds the image to the atlas, compacting it first if there is no room left
+    pub fn insert(&mut self, image: &Bitmap<RGBA>) -> Result<(DynamicAtlasHandle,UvSprite),String>{
+        if image.buffer.len() != image.width * image.height{
+            return Err(format!("Invalid image, expected {} pixels but got {}",image.width * image.height,image.buffer.len()));
+        }
+
+        let (width, height) = (image.width as u32, image.height as u32);
+        let rect = match self.allocate(width, height){
+            Some(x) => x,
+            None => {
+                if self.freed_pixels > 0{
+                    self.compact();
+                }
+                self.allocate(width, height).ok_or_else(||format!("No room for a {}x{} image in the dynamic atlas",width,height))?
+            }
+        };
+
+        //the padding is cleared as well, the space may still hold pixels of a freed image
+        let padded = self.padded(&rect);
+        let mut pixels = vec![RGBA{..Default::default()};padded.area() as usize];
+        for py in 0..height as usize{
+            let row = py * width as usize;
+            pixels[py * padded.width as usize..][..width as usize].copy_from_slice(&image.buffer[row..row + width as usize]);
+        }
+        self.write_pixels(&padded, &pixels);
+        self.texture.set_sub_data(padded.x, padded.y, padded.width, padded.height, &into_raw_bytes(pixels));
+
+        let handle = DynamicAtlasHandle(self.next_handle);
+        self.next_handle += 1;
+        let sprite = create_uv_sprite(&self.texture, self.width, self.height, &rect);
+        self.entries.insert(handle, DynamicEntry{
+            rect,
+            sprite: sprite.clone()
+        });
+        Ok((handle, sprite))
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<AtlasRect>{
+        let (padded_width, padded_height) = padded_size(width, height, self.padding, self.width, self.height);
+        let padded = self.allocator.allocate(padded_width, padded_height)?;
+        Some(AtlasRect::new(padded.x, padded.y, width, height))
+    }
+
+    ///The rect with its padding, which is cut at the atlas edge
+    fn padded(&self, rect: &AtlasRect) -> AtlasRect{
+        AtlasRect::new(
+            rect.x,
+            rect.y,
+            (rect.width + self.padding).min(self.width - rect.x),
+            (rect.height + self.padding).min(self.height - rect.y)
+        )
+    }
+
+    fn write_pixels(&mut self, rect: &AtlasRect, pixels: &[RGBA]){
+        for py in 0..rect.height{
+            let src = (py * rect.width) as usize;
+            let dst = (rect.x + (rect.y + py) * self.width) as usize;
+            self.pixels[dst..dst + rect.width as usize].copy_from_slice(&pixels[src..src + rect.width as usize]);
+        }
+    }
+
+    ///
+    /// Releases the space used by the image, returns false if the handle was already freed
+    /// The pixels stay in the texture until the space is reused
+    pub fn free(&mut self, handle: DynamicAtlasHandle) -> bool{
+        match self.entries.remove(&handle){
+            Some(entry) => {
+                let padded = self.padded(&entry.rect);
+                self.allocator.release(&padded);
+                self.freed_pixels += padded.area();
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn get_sprite(&self, handle: DynamicAtlasHandle) -> Option<&UvSprite>{
+        self.entries.get(&handle).map(|x|&x.sprite)
+    }
+
+    pub fn get_rect(&self, handle: DynamicAtlasHandle) -> Option<AtlasRect>{
+        self.entries.get(&handle).map(|x|x.rect)
+    }
+
+    ///
+    /// Fraction of the atlas freed since the last compaction, useful to decide when to call compact
+    pub fn fragmentation(&self) -> f32{
+        self.freed_pixels as f32 / (self.width * self.height) as f32
+    }
+
+    ///
+    /// Compacts the atlas if the freed fraction is at least the threshold, returns true if it did
+    pub fn compact_if_fragmented(&mut self, threshold: f32) -> bool{
+        if self.fragmentation() >= threshold{
+            self.compact()
+        }
+        else{
+            false
+        }
+    }
+
+    ///
+    /// Repacks every live image from scratch, reclaiming the space of freed images
+    /// The compact callback is invoked for every image that moved
+    /// Returns false, leaving the atlas untouched, if the images can't be repacked
+    pub fn compact(&mut self) -> bool{
+        let rects = self.entries.iter().map(|(k,v)|(*k, v.rect)).collect::<Vec<_>>();
+        let (allocator, moved) = match repack(self.width, self.height, self.padding, rects){
+            Some(x) => x,
+            None => return false
+        };
+
+        let mut pixels = vec![RGBA{..Default::default()};(self.width*self.height) as usize];
+        for (_, old, new) in &moved{
+            for py in 0..old.height{
+                let src = (old.x + (old.y + py) * self.width) as usize;
+                let dst = (new.x + (new.y + py) * self.width) as usize;
+                pixels[dst..dst + old.width as usize].copy_from_slice(&self.pixels[src..src + old.width as usize]);
+            }
+        }
+
+        self.pixels = pixels;
+        self.allocator = allocator;
+        self.freed_pixels = 0;
+        self.texture.set_sub_data(0, 0, self.width, self.height, &into_raw_bytes(self.pixels.clone()));
+
+        for (handle, old, new) in moved{
+            let sprite = create_uv_sprite(&self.texture, self.width, self.height, &new);
+            let entry = self.entries.get_mut(&handle).unwrap();
+            entry.rect = new;
+            entry.sprite = sprite;
+            if old != new{
+                if let Some(callback) = &mut self.on_compact{
+                    callback(handle, &entry.sprite);
+                }
+            }
+        }
+        true
+    }
+}
+
+///
+/// Size an image takes in the allocator, the padding is dropped where it would not fit in the atlas
+fn padded_size(width: u32, height: u32, padding: u32, atlas_width: u32, atlas_height: u32) -> (u32,u32){
+    ((width + padding).min(atlas_width), (height + padding).min(atlas_height))
+}
+
+///An image's handle with its rect before and after repacking
+type Placement = (DynamicAtlasHandle,AtlasRect,AtlasRect);
+
+///
+/// Packs the rects into a fresh allocator, tallest first, returning the placement of each image
+fn repack(width: u32, height: u32, padding: u32, mut rects: Vec<(DynamicAtlasHandle,AtlasRect)>) -> Option<(ShelfAllocator,Vec<Placement>)>{
+    rects.sort_by(|a,b|b.1.height.cmp(&a.1.height).then(b.1.width.cmp(&a.1.width)).then(a.0.0.cmp(&b.0.0)));
+
+    let mut allocator = ShelfAllocator::new(width, height);
+    let mut moved = Vec::with_capacity(rects.len());
+    for (handle, rect) in rects{
+        let (padded_width, padded_height) = padded_size(rect.width, rect.height, padding, width, height);
+        let x = allocator.allocate(padded_width, padded_height)?;
+        moved.push((handle, rect, AtlasRect::new(x.x, x.y, rect.width, rect.height)));
+    }
+    Some((allocator, moved))
+}
+
+#[cfg(test)]
+mod tests{
+    use std::mem::ManuallyDrop;
+
+    use super::*;
+
+    //the texture is never uploaded to or dropped, so no gl context is needed
+    fn atlas(width: u32, height: u32, padding: u32) -> ManuallyDrop<DynamicAtlas>{
+        ManuallyDrop::new(DynamicAtlas{
+            texture: GlTexture::default(),
+            width,
+            height,
+            padding,
+            allocator: ShelfAllocator::new(width, height),
+            entries: HashMap::new(),
+            next_handle: 0,
+            pixels: vec![RGBA{..Default::default()};(width*height) as usize],
+            freed_pixels: 0,
+            on_compact: None,
+        })
+    }
+
+    fn image(width: usize, height: usize) -> Bitmap<RGBA>{
+        Bitmap{
+            buffer: vec![RGBA{r: 255, g: 255, b: 255, a: 255};width*height],
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn released_spans_merge_with_their_neighbours(){
+        let mut shelf = Shelf::new(0, 4, 10);
+        assert_eq!(shelf.allocate(3), Some(0));
+        assert_eq!(shelf.allocate(3), Some(3));
+        assert_eq!(shelf.allocate(3), Some(6));
+        assert_eq!(shelf.free, vec![(9, 1)]);
+
+        shelf.release(0, 3);
+        assert_eq!(shelf.free, vec![(0, 3), (9, 1)]);
+        shelf.release(6, 3);
+        assert_eq!(shelf.free, vec![(0, 3), (6, 4)]);
+        assert!(!shelf.fits(5));
+
+        //the middle span joins both sides
+        shelf.release(3, 3);
+        assert_eq!(shelf.free, vec![(0, 10)]);
+        assert!(shelf.is_empty(10));
+    }
+
+    #[test]
+    fn empty_top_shelves_are_given_back(){
+        let mut allocator = ShelfAllocator::new(16, 16);
+        let bottom = allocator.allocate(8, 4).unwrap();
+        let top = allocator.allocate(8, 8).unwrap();
+        assert_eq!(allocator.shelves.len(), 2);
+
+        //a freed shelf below a used one stays
+        allocator.release(&bottom);
+        assert_eq!(allocator.shelves.len(), 2);
+
+        //freeing the top pops every empty shelf, the space then fits a taller image
+        allocator.release(&top);
+        assert!(allocator.shelves.is_empty());
+        assert_eq!(allocator.allocate(16, 16), Some(AtlasRect::new(0, 0, 16, 16)));
+    }
+
+    #[test]
+    fn allocation_fails_when_full(){
+        let mut allocator = ShelfAllocator::new(8, 8);
+        assert!(allocator.allocate(9, 1).is_none());
+        assert!(allocator.allocate(8, 6).is_some());
+        assert!(allocator.allocate(4, 4).is_none());
+        assert!(allocator.allocate(4, 2).is_some());
+    }
+
+    #[test]
+    fn padding_is_cut_at_the_atlas_edge(){
+        let mut atlas = atlas(8, 8, 2);
+        let rect = atlas.allocate(8, 8).unwrap();
+        assert_eq!(rect, AtlasRect::new(0, 0, 8, 8));
+        assert_eq!(atlas.padded(&rect), rect);
+
+        let mut atlas = self::atlas(8, 8, 2);
+        let rect = atlas.allocate(7, 3).unwrap();
+        assert_eq!(atlas.padded(&rect), AtlasRect::new(0, 0, 8, 5));
+        //the padded rect is what goes back to the allocator
+        let padded = atlas.padded(&rect);
+        atlas.allocator.release(&padded);
+        assert!(atlas.allocator.shelves.is_empty());
+    }
+
+    #[test]
+    fn insert_fails_when_full(){
+        let mut atlas = atlas(8, 8, 0);
+        atlas.allocate(8, 8).unwrap();
+        let error = atlas.insert(&image(1, 1)).err().unwrap();
+        assert!(error.contains("No room for a 1x1 image"), "{}", error);
+        assert!(atlas.is_empty());
+
+        let error = atlas.insert(&Bitmap{buffer: Vec::new(), width: 2, height: 2}).err().unwrap();
+        assert!(error.contains("expected 4 pixels"), "{}", error);
+    }
+
+    #[test]
+    fn repacking_only_moves_images_after_a_gap(){
+        let a = DynamicAtlasHandle(0);
+        let b = DynamicAtlasHandle(1);
+        let c = DynamicAtlasHandle(2);
+        //b was freed from between a and c
+        let rects = vec![
+            (c, AtlasRect::new(9, 0, 4, 4)),
+            (a, AtlasRect::new(0, 0, 4, 4)),
+        ];
+        let (allocator, placed) = repack(16, 16, 1, rects).unwrap();
+        assert_eq!(placed, vec![
+            (a, AtlasRect::new(0, 0, 4, 4), AtlasRect::new(0, 0, 4, 4)),
+            (c, AtlasRect::new(9, 0, 4, 4), AtlasRect::new(5, 0, 4, 4)),
+        ]);
+        let moved = placed.iter().filter(|(_,old,new)|old != new).map(|(x,_,_)|*x).collect::<Vec<_>>();
+        assert_eq!(moved, vec![c]);
+        assert!(!moved.contains(&b));
+        assert_eq!(allocator.shelves.len(), 1);
+        assert_eq!(allocator.shelves[0].free, vec![(10, 6)]);
+
+        //images that no longer fit leave the atlas untouched
+        assert!(repack(4, 4, 1, vec![(a, AtlasRect::new(0, 0, 4, 4)), (b, AtlasRect::new(0, 0, 1, 1))]).is_none());
+    }
+}
@@ -1,5 +1,8 @@
 use std::{rc::Rc, sync::Arc};
 
+pub mod atlas;
+pub mod dynamic_atlas;
+
 #[derive(Default)]
 struct RawGlTexture{
     id: gl::types::GLuint,
@@ -18,7 +21,7 @@ impl RawGlTexture {
 
         unsafe{
             gl::BindTexture(gl::TEXTURE_2D,id);
-            gl::TexImage2D(gl::TEXTURE_2D,0,gl::RGBA as i32, width as i32, height as i32,0,gl::RGBA,gl::FLOAT,data.as_ptr() as *const std::ffi::c_void);
+            gl::TexImage2D(gl::TEXTURE_2D,0,gl::RGBA as i32, width as i32, height as i32,0,gl::RGBA,gl::UNSIGNED_BYTE,data.as_ptr() as *const std::ffi::c_void);
             
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
@@ -44,16 +47,27 @@ impl RawGlTexture {
     pub fn set_data(&self, width: u32, height: u32, data: Vec<u8>){
         unsafe{
             gl::BindTexture(gl::TEXTURE_2D,self.id);
-            gl::TexImage2D(gl::TEXTURE_2D,0,gl::RGBA as i32, width as i32, height as i32,0,gl::RGBA,gl::FLOAT,data.as_ptr() as *const std::ffi::c_void);
+            gl::TexImage2D(gl::TEXTURE_2D,0,gl::RGBA as i32, width as i32, height as i32,0,gl::RGBA,gl::UNSIGNED_BYTE,data.as_ptr() as *const std::ffi::c_void);
             
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
         }
     }
+
+    pub fn set_sub_data(&self, x: u32, y: u32, width: u32, height: u32, data: &[u8]){
+        unsafe{
+            gl::BindTexture(gl::TEXTURE_2D,self.id);
+            gl::TexSubImage2D(gl::TEXTURE_2D,0,x as i32,y as i32,width as i32,height as i32,gl::RGBA,gl::UNSIGNED_BYTE,data.as_ptr() as *const std::ffi::c_void);
+        }
+    }
 }
 
 impl Drop for RawGlTexture {
     fn drop(&mut self) {
+        //a default texture never created one, 0 is gl's reserved name
+        if self.id == 0{
+            return;
+        }
         unsafe {
             gl::DeleteTextures(1, &mut self.id);
         }
@@ -77,7 +91,12 @@ impl GlTexture{
     }
 
     pub fn create_empty() -> Self{
-        Self::from_raw(RawGlTexture::empty())   
+        Self::from_raw(RawGlTexture::empty())
+    }
+
+    ///The gl name of the texture, shared by every clone
+    pub fn id(&self) -> gl::types::GLuint{
+        self.raw.id
     }
 
     pub fn from_data(width: u32, height: u32, data: Vec<u8>) -> Self {
@@ -88,6 +107,17 @@ impl GlTexture{
         self.raw.set_data(width, height, data);
     }
 
+    ///
+    /// Replaces a region of the texture, data has to contain width*height rgba pixels
+    pub fn set_sub_data(&mut self, x: u32, y: u32, width: u32, height: u32, data: &[u8]){
+        #[cfg(debug_assertions)]{
+            if data.len() != (width*height*4) as usize{
+                panic!("Expected {} bytes of pixel data but got {}",width*height*4,data.len())
+            }
+        }
+        self.raw.set_sub_data(x, y, width, height, data);
+    }
+
     pub fn bind_texture(&self, slot: u32){
         #[cfg(debug_assertions)]{
             if slot > 31{
@@ -1,19 +1,397 @@
-use ecs_core::{components::Transform, data::storage::Storage, join::{create_iterator, create_iterator_2}, shred::{Read, System}};
+use std::ops::Range;
 
-use crate::{components::SpriteFilter, resource::Resources};
+use ecs_core::{components::Transform, data::storage::Storage, join::{create_iterator_2, create_iterator_mut_2}, shred::{Read, System, Write}};
 
-pub struct SpriteDrawSystem;
+use crate::{components::{AnimatedSprite, SpriteFilter, camera::{Camera2D, ScreenSize}}, loader::sprite::get_sprite, resource::{ResourceKey, Resources, animation::SpriteAnimation, batch::{SpriteBatch, SpriteVertex, order::{DrawKey, RenderLayers, draw_depth}}, material::{BlendMode, DepthFunction, DepthState, Material, SPRITE_TEXTURE_SLOT, SPRITE_TEXTURE_UNIFORM}, shader::{GlShader, block::{FRAME_UNIFORMS, FrameUniforms, UniformBlock}, builtin::SPRITE_SHADER, uniform::UniformValue}, sprite::{PlacedQuad, Sprite}, texture::GlTexture}, window::{WINDOW_RESOURCE, WindowResource}};
 
+///
+/// Draws every entity with a SpriteFilter and a Transform as textured quads
+/// Uses the built-in sprite shader unless another one is set, see loader::shader::register_builtin_shaders
+/// Sprites are ordered by their DrawKey using the RenderLayers world resource,
+/// consecutive sprites with the same texture and material are drawn with a single call
+pub struct SpriteDrawSystem{
+    shader: String,
+    ///Created on the first run, since it needs a gl context
+    batch: Option<SpriteBatch>,
+    draw_calls: usize,
+    ///Materials whose bind failed, so each one is only recorded once
+    failed_materials: Vec<ResourceKey>,
+    ///Bind errors not taken yet, see take_errors
+    errors: Vec<(ResourceKey,String)>,
+}
+
+impl Default for SpriteDrawSystem{
+    fn default() -> Self {
+        Self{
+            shader: String::from(SPRITE_SHADER),
+            batch: None,
+            draw_calls: 0,
+            failed_materials: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl SpriteDrawSystem{
+    pub fn new() -> Self{
+        Default::default()
+    }
+
+    ///Draws with the shader registered under the name instead, it has to accept the built-in sprite vertex layout
+    pub fn with_shader(mut self, name: &str) -> Self{
+        self.shader = String::from(name);
+        self
+    }
+
+    ///Number of draw calls the last run issued
+    pub fn draw_calls(&self) -> usize{
+        self.draw_calls
+    }
+
+    ///
+    /// Materials that failed to bind since the last call, with their error
+    /// Every material is reported once, its sprites keep drawing with the shader of the system
+    pub fn take_errors(&mut self) -> Vec<(ResourceKey,String)>{
+        std::mem::take(&mut self.errors)
+    }
+
+    fn record_failure(&mut self, key: ResourceKey, error: String){
+        if !self.failed_materials.contains(&key){
+            self.failed_materials.push(key);
+            self.errors.push((key, error));
+        }
+    }
+
+    ///
+    /// Binds the material, or the shader of the system with alpha blending for sprites without one
+    /// A material that fails to bind is recorded once and its sprites fall back to the shader of the system
+    /// Opaque layers override the blend and depth state of the material, drawing without blending
+    /// and with a less test that writes depth, since blending would show the sprites rejected behind
+    fn bind_state(&mut self, shader: &GlShader, material: Option<(ResourceKey,&Material)>, texture: &GlTexture, opaque: bool){
+        let bound = match material{
+            Some((key, material)) => match material.bind(){
+                Ok(()) => true,
+                Err(e) => {
+                    self.record_failure(key, e);
+                    false
+                }
+            },
+            None => false,
+        };
+        if !bound{
+            shader.bind();
+            BlendMode::Alpha.apply();
+            DepthState::default().apply();
+            //a material may have pointed the sampler of the shared program at one of its own slots
+            if shader.uniform(SPRITE_TEXTURE_UNIFORM).is_some(){
+                let _ = shader.set_uniform(SPRITE_TEXTURE_UNIFORM, UniformValue::Sampler(SPRITE_TEXTURE_SLOT as i32));
+            }
+        }
+        if opaque{
+            BlendMode::Opaque.apply();
+            DepthState{
+                test: Some(DepthFunction::Less),
+                write: true,
+            }.apply();
+        }
+        texture.bind_texture(SPRITE_TEXTURE_SLOT);
+    }
+}
+
+///A sprite waiting to be sorted and drawn
+struct DrawItem{
+    key: DrawKey,
+    opaque: bool,
+    ///The quads of the sprite in the shared list, placed relative to its top left corner
+    quads: Range<usize>,
+    ///Position of the top left corner relative to the pivot
+    origin: [f32;2],
+    matrix: [f32;6],
+}
+
+///
+/// The transform as a 2d affine matrix in the column major order [a, b, c, d, x, y]
+/// Scale is applied first, then the rotation in radians and then the translation
+fn transform_matrix(transform: &Transform) -> [f32;6]{
+    affine_matrix([transform.position[0], transform.position[1]], transform.rotation, transform.scale)
+}
+
+fn affine_matrix(position: [f32;2], rotation: f32, [scale_x, scale_y]: [f32;2]) -> [f32;6]{
+    let (sin, cos) = rotation.sin_cos();
+    [cos * scale_x, sin * scale_x, -sin * scale_y, cos * scale_y, position[0], position[1]]
+}
+
+fn apply_matrix(matrix: &[f32;6], [x, y]: [f32;2]) -> [f32;2]{
+    [matrix[0] * x + matrix[2] * y + matrix[4], matrix[1] * x + matrix[3] * y + matrix[5]]
+}
+
+///Vertex color of sprites, the texture is drawn unchanged
+const WHITE: [f32;4] = [1.0, 1.0, 1.0, 1.0];
+
+///
+/// The corners of a placed quad moved by the origin and then transformed by the matrix
+fn quad_vertices(quad: &PlacedQuad, origin: [f32;2], matrix: &[f32;6], depth: f32) -> [SpriteVertex;4]{
+    let positions = quad.corners();
+    let uvs = quad.uv_corners();
+    let mut out = [SpriteVertex::default();4];
+    for (i, vertex) in out.iter_mut().enumerate(){
+        let [x, y] = apply_matrix(matrix, [positions[i][0] + origin[0], positions[i][1] + origin[1]]);
+        *vertex = SpriteVertex{
+            position: [x, y, depth],
+            uv: uvs[i],
+            color: WHITE,
+        };
+    }
+    out
+}
 
 impl<'a> System<'a> for SpriteDrawSystem{
-    type SystemData = (Read<'a, Storage<SpriteFilter>>, Read<'a, Storage<Transform>>, Read<'a, Resources>);
+    type SystemData = (Read<'a, Storage<SpriteFilter>>, Read<'a, Storage<Transform>>, Read<'a, Resources>, Read<'a, RenderLayers>);
+
+    fn run(&mut self, (sprites, transforms, render_resources, layers): Self::SystemData) {
+        self.draw_calls = 0;
+        let shader = match render_resources.find_resource_key(&self.shader){
+            Some(x) if x.is::<GlShader>() => render_resources.get_resource::<GlShader>(&x),
+            _ => return
+        };
+
+        let mut items = Vec::new();
+        let mut quads = Vec::new();
+        for (filter, transform) in create_iterator_2(&sprites,&transforms){
+            let sprite = match filter.sprite(){
+                Some(x) if x.is::<dyn Sprite>() => get_sprite(&render_resources, &x),
+                _ => continue
+            };
+            let material = filter.material().filter(|x|x.is::<Material>());
+            let quad = sprite.quad();
+            let [width, height] = filter.size().unwrap_or([quad.source_width, quad.source_height]);
+            let start = quads.len();
+            sprite.quads_at_size(width, height, &mut quads);
+            let settings = layers.get(filter.layer());
+            items.push(DrawItem{
+                key: DrawKey::new(filter.layer(), filter.z_index(), transform.position[1], settings, material, quad.texture.id()),
+                opaque: settings.opaque,
+                quads: start..quads.len(),
+                origin: [-quad.pivot_x * width, -quad.pivot_y * height],
+                matrix: transform_matrix(transform),
+            });
+        }
+        //stable, so sprites with equal keys keep the order of the storage
+        items.sort_by(|a, b|a.key.compare(&b.key));
 
-    fn run(&mut self, (sprites, transforms, render_resources): Self::SystemData) {
-        
-        
+        //opaque layers are drawn front to back, their depth still comes from the back to front position
+        let count = items.len();
+        let mut order = (0..count).collect::<Vec<_>>();
+        let mut start = 0;
+        while start < count{
+            let layer = items[start].key.layer;
+            let end = items[start..].iter().position(|x|x.key.layer != layer).map_or(count, |x|start + x);
+            if items[start].opaque{
+                order[start..end].reverse();
+            }
+            start = end;
+        }
 
-        for x in create_iterator_2(&sprites,&transforms){
-            
+        if layers.any_opaque(){
+            unsafe{
+                gl::DepthMask(gl::TRUE);
+                gl::Clear(gl::DEPTH_BUFFER_BIT);
+            }
         }
+
+        let mut batch = self.batch.take().unwrap_or_default();
+        //material, texture and opacity the quads in the batch are drawn with
+        let mut current = None;
+        for index in order{
+            let item = &items[index];
+            let depth = draw_depth(index, count);
+            for quad in &quads[item.quads.clone()]{
+                let state = (item.key.material, quad.texture.id(), item.opaque);
+                if current != Some(state){
+                    batch.flush();
+                    let material = item.key.material.map(|x|(x, render_resources.get_resource::<Material>(&x)));
+                    self.bind_state(shader, material, quad.texture, item.opaque);
+                    current = Some(state);
+                }
+                batch.push_quad(quad_vertices(quad, item.origin, &item.matrix, depth));
+            }
+        }
+        batch.flush();
+        self.draw_calls = batch.take_draw_calls();
+        self.batch = Some(batch);
+    }
+}
+
+///
+/// Seconds elapsed since the previous frame, systems that depend on time read it from the world
+#[derive(Default,Clone,Copy)]
+pub struct FrameTime{
+    pub delta: f32,
+}
+
+///
+/// Advances every AnimatedSprite and points its SpriteFilter at the current frame
+pub struct AnimationSystem;
+
+impl<'a> System<'a> for AnimationSystem{
+    type SystemData = (Write<'a, Storage<AnimatedSprite>>, Write<'a, Storage<SpriteFilter>>, Read<'a, Resources>, Read<'a, FrameTime>);
+
+    fn run(&mut self, (mut animations, mut sprites, render_resources, time): Self::SystemData) {
+        for (animation, sprite) in create_iterator_mut_2(&mut animations, &mut sprites){
+            let clip = render_resources.get_resource::<SpriteAnimation>(&animation.clip());
+            if let Some(frame) = animation.advance(clip, time.delta){
+                sprite.set_sprite(Some(frame));
+            }
+        }
+    }
+}
+
+///
+/// Uploads the FrameUniforms world resource to the shared uniform block once per frame
+/// The time members are filled in from FrameTime, the rest is left as set by the camera or the user
+#[derive(Default)]
+pub struct FrameUniformsSystem{
+    time: f32,
+}
+
+impl<'a> System<'a> for FrameUniformsSystem{
+    type SystemData = (Read<'a, FrameUniforms>, Read<'a, Resources>, Read<'a, FrameTime>);
+
+    fn run(&mut self, (uniforms, render_resources, time): Self::SystemData) {
+        let block = match render_resources.find_resource_key(FRAME_UNIFORMS){
+            Some(x) if x.is::<UniformBlock<FrameUniforms>>() => render_resources.get_resource::<UniformBlock<FrameUniforms>>(&x),
+            _ => return
+        };
+
+        self.time += time.delta;
+        let mut value = *uniforms;
+        value.time = self.time;
+        value.delta_time = time.delta;
+        block.set(&value);
+    }
+}
+
+
+///
+/// Points the FrameUniforms world resource at the Camera2D world resource and sets the gl viewport
+/// Runs every frame so the projection follows the window size, it has to run before the FrameUniformsSystem
+pub struct CameraSystem;
+
+impl<'a> System<'a> for CameraSystem{
+    type SystemData = (Read<'a, Camera2D>, Write<'a, FrameUniforms>, Read<'a, Resources>);
+
+    fn run(&mut self, (camera, mut uniforms, render_resources): Self::SystemData) {
+        let screen = match render_resources.find_resource_key(WINDOW_RESOURCE){
+            Some(x) if x.is::<WindowResource>() => ScreenSize::from_window(render_resources.get_resource::<WindowResource>(&x)),
+            _ => return
+        };
+
+        let [x, y, width, height] = camera.viewport_rect(&screen);
+        uniforms.view_projection = camera.view_projection(&screen);
+        uniforms.resolution = [width, height];
+        unsafe{
+            //gl measures the viewport from the bottom of the window
+            gl::Viewport(x.round() as i32, (screen.height - y - height).round() as i32, width.round() as i32, height.round() as i32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::resource::sprite::UvRect;
+
+    fn assert_near(a: [f32;2], b: [f32;2]){
+        assert!((a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    ///Textures delete themselves through gl when dropped, which needs a context
+    fn texture() -> &'static GlTexture{
+        Box::leak(Box::default())
+    }
+
+    #[test]
+    fn identity_matrix_keeps_points(){
+        let matrix = affine_matrix([0.0, 0.0], 0.0, [1.0, 1.0]);
+        assert_eq!(matrix, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(apply_matrix(&matrix, [3.0, -4.0]), [3.0, -4.0]);
+    }
+
+    #[test]
+    fn matrix_scales_then_rotates_then_translates(){
+        let matrix = affine_matrix([10.0, 20.0], FRAC_PI_2, [2.0, 3.0]);
+        //(1, 0) scaled to (2, 0), rotated a quarter turn to (0, 2)
+        assert_near(apply_matrix(&matrix, [1.0, 0.0]), [10.0, 22.0]);
+        //(0, 1) scaled to (0, 3), rotated to (-3, 0)
+        assert_near(apply_matrix(&matrix, [0.0, 1.0]), [7.0, 20.0]);
+        assert_near(apply_matrix(&matrix, [0.0, 0.0]), [10.0, 20.0]);
+    }
+
+    #[test]
+    fn matrix_matches_composing_the_steps(){
+        let (position, rotation, scale) = ([-5.0, 7.5], 0.7, [1.5, -0.5]);
+        let matrix = affine_matrix(position, rotation, scale);
+        let (sin, cos) = f32::sin_cos(rotation);
+        for point in [[1.0, 2.0], [-3.0, 0.25], [0.0, -8.0]]{
+            let [x, y] = [point[0] * scale[0], point[1] * scale[1]];
+            let expected = [x * cos - y * sin + position[0], x * sin + y * cos + position[1]];
+            assert_near(apply_matrix(&matrix, point), expected);
+        }
+    }
+
+    #[test]
+    fn quad_vertices_are_offset_by_the_origin_before_the_transform(){
+        let quad = PlacedQuad{
+            texture: texture(),
+            uv: UvRect{min_x: 0.0, min_y: 0.0, max_x: 0.5, max_y: 0.25},
+            rotated: false,
+            x: 2.0,
+            y: 0.0,
+            width: 4.0,
+            height: 2.0,
+        };
+        let matrix = affine_matrix([100.0, 50.0], 0.0, [2.0, 2.0]);
+        let vertices = quad_vertices(&quad, [-4.0, -1.0], &matrix, 0.5);
+
+        let positions = vertices.map(|x|[x.position[0], x.position[1]]);
+        assert_eq!(positions, [[96.0, 48.0], [104.0, 48.0], [104.0, 52.0], [96.0, 52.0]]);
+        assert_eq!(vertices.map(|x|x.uv), quad.uv_corners());
+        assert!(vertices.iter().all(|x|x.position[2] == 0.5 && x.color == WHITE));
+    }
+
+    #[test]
+    fn rotated_quads_sample_the_rotated_corners(){
+        let quad = PlacedQuad{
+            texture: texture(),
+            uv: UvRect{min_x: 0.0, min_y: 0.0, max_x: 1.0, max_y: 1.0},
+            rotated: true,
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        };
+        let vertices = quad_vertices(&quad, [0.0, 0.0], &affine_matrix([0.0, 0.0], 0.0, [1.0, 1.0]), 0.0);
+        assert_eq!(vertices[0].uv, [1.0, 0.0]);
+        assert_eq!(vertices[3].uv, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn material_failures_are_taken_once(){
+        let mut resources = Resources::new();
+        resources.register_type::<u32>();
+        let first = resources.add_resource(1u32, String::from("first"));
+        let second = resources.add_resource(2u32, String::from("second"));
+
+        let mut system = SpriteDrawSystem::new();
+        system.record_failure(first, String::from("missing uniform"));
+        system.record_failure(first, String::from("missing uniform"));
+        system.record_failure(second, String::from("wrong type"));
+        assert_eq!(system.take_errors(), [(first, String::from("missing uniform")), (second, String::from("wrong type"))]);
+        assert!(system.take_errors().is_empty());
+
+        system.record_failure(second, String::from("wrong type"));
+        assert!(system.take_errors().is_empty());
     }
-}
\ No newline at end of file
+}
@@ -15,12 +15,28 @@ pub struct WindowConfiguration{
 
 }
 
+///Name the window is added to resources under
+pub const WINDOW_RESOURCE: &str = "window_resource";
+
 pub struct WindowResource{
     event_loop: EventLoop<()>,
     window: Window,
     ctx: GlContext
 }
 
+impl WindowResource{
+    ///Current size of the drawable area in physical pixels
+    pub fn size(&self) -> [u32;2]{
+        let size = self.window.inner_size();
+        [size.width, size.height]
+    }
+
+    ///Physical pixels per logical pixel of the monitor the window is on
+    pub fn scale_factor(&self) -> f64{
+        self.window.scale_factor()
+    }
+}
+
 pub fn initialize_threaded_window(resources: &mut Resources, config: WindowConfiguration){
    
     
@@ -45,9 +61,10 @@ pub fn initialize_threaded_window(resources: &mut Resources, config: WindowConfi
             }
         }
 
+        resources.register_type::<WindowResource>();
         resources.add_resource(WindowResource{
             ctx,
             event_loop,
             window
-        }, "window_resource".into());
+        }, WINDOW_RESOURCE.into());
 }
\ No newline at end of file